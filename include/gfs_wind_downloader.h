@@ -0,0 +1,94 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#ifndef GFS_WIND_DOWNLOADER_H
+#define GFS_WIND_DOWNLOADER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// `gwd_parser_next()` result: a message was returned.
+#define GWD_MESSAGE 1
+
+// `gwd_parser_next()` result: more bytes must be pushed.
+#define GWD_NEED_MORE 0
+
+// `gwd_parser_next()` result: invalid data, see `gwd_parser_error()`.
+#define GWD_ERROR -1
+
+// Parameters kept by a filter.
+typedef struct GwdFilter GwdFilter;
+
+// Streaming GRIB2 message splitter.
+typedef struct GwdParser GwdParser;
+
+// A complete GRIB2 message, owned by the caller until `gwd_message_free()`.
+typedef struct GwdMessage {
+  uint8_t *data;
+  size_t len;
+} GwdMessage;
+
+// Create a parser; free it with `gwd_parser_free()`.
+struct GwdParser *gwd_parser_new(void);
+
+// Free a parser.
+//
+// # Safety
+// `parser` must come from `gwd_parser_new()` (or be NULL) and not be used afterwards.
+void gwd_parser_free(struct GwdParser *parser);
+
+// Append `len` bytes of the stream.
+//
+// # Safety
+// `parser` must be a live parser and `data` point to `len` readable bytes.
+void gwd_parser_push(struct GwdParser *parser, const uint8_t *data, size_t len);
+
+// Extract the next complete message into `message`: returns `GWD_MESSAGE`,
+// `GWD_NEED_MORE`, or `GWD_ERROR` for a message without its end marker
+// (the parser can go on with the next one).
+//
+// # Safety
+// `parser` must be a live parser and `message` point to a writable `GwdMessage`.
+int gwd_parser_next(struct GwdParser *parser, struct GwdMessage *message);
+
+// Description of the last `GWD_ERROR`, valid until the next error or
+// `gwd_parser_free()`; NULL if none.
+//
+// # Safety
+// `parser` must be a live parser.
+const char *gwd_parser_error(const struct GwdParser *parser);
+
+// Bytes of an incomplete message still buffered; non-zero at the end of the
+// stream means it was cut short.
+//
+// # Safety
+// `parser` must be a live parser.
+size_t gwd_parser_pending(const struct GwdParser *parser);
+
+// Free the data of a message returned by `gwd_parser_next()`.
+//
+// # Safety
+// `message` must hold data from `gwd_parser_next()` not freed yet.
+void gwd_message_free(struct GwdMessage *message);
+
+// Create a filter keeping the messages with any of the comma-separated
+// parameters (NCEP short names or discipline.category.number, e.g.
+// "UGRD,VGRD"); NULL if a parameter is unknown. Free it with `gwd_filter_free()`.
+//
+// # Safety
+// `params` must be a NUL-terminated string.
+struct GwdFilter *gwd_filter_new(const char *params);
+
+// Free a filter.
+//
+// # Safety
+// `filter` must come from `gwd_filter_new()` (or be NULL) and not be used afterwards.
+void gwd_filter_free(struct GwdFilter *filter);
+
+// Whether a message has a product with one of the filter's parameters.
+//
+// # Safety
+// `filter` must be a live filter and `data` point to `len` readable bytes.
+bool gwd_filter_matches(const struct GwdFilter *filter, const uint8_t *data, size_t len);
+
+#endif  /* GFS_WIND_DOWNLOADER_H */
@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use ndarray::Array2;
+
+use crate::chunk::{rechunk, DEFAULT_CHUNK_SIZE};
+use crate::compression::{decode, Compression};
+use crate::error::{Error, Result};
+use crate::field::Decoder;
+use crate::filter::{Filter, KeptMessage, Origin};
+use crate::grib::{Grib2StreamParser, Level, ProductTime};
+use crate::param::Param;
+
+/// Description of a decoded message, from its first product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageMeta {
+    pub param: Param,
+    pub level: Option<Level>,
+    /// Reference and valid times, when decodable.
+    pub time: Option<ProductTime>,
+    /// Where the message was read from the source.
+    pub origin: Origin,
+    /// Indices of the filter's routes matching the message.
+    pub routes: Vec<usize>,
+}
+
+/// A kept message and its values, of shape `(nj, ni)` with NaN for missing points.
+pub type DecodedMessage = (MessageMeta, Array2<f32>);
+
+/// Filter a GRIB2 byte stream read from `url` and decode the messages kept, so a
+/// service can use the grids as they arrive instead of reading them back from S3.
+///
+/// Each chunk is parsed, filtered and decoded on the blocking thread pool by one
+/// `Decoder`, whose buffers are reused from message to message. The stream ends
+/// after the first error (truncated stream, invalid message).
+pub fn decoded_messages<S>(
+    filter: Filter,
+    url: &str,
+    stream: S,
+) -> impl Stream<Item = Result<DecodedMessage>>
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    struct State {
+        stream: Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>,
+        filter: Filter,
+        url: String,
+        parser: Grib2StreamParser,
+        decoder: Decoder,
+        /// Bytes handed to the parser.
+        parsed: u64,
+        ready: VecDeque<Result<DecodedMessage>>,
+        done: bool,
+    }
+
+    let state = State {
+        stream: Box::pin(rechunk(stream, DEFAULT_CHUNK_SIZE)),
+        filter,
+        url: url.to_string(),
+        parser: Grib2StreamParser::new(),
+        decoder: Decoder::default(),
+        parsed: 0,
+        ready: VecDeque::new(),
+        done: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.ready.pop_front() {
+                return Some((item, state));
+            }
+            if state.done {
+                return None;
+            }
+            let truncated = |received| Error::Truncated {
+                url: state.url.clone(),
+                received,
+                expected: None,
+            };
+            match state.stream.next().await {
+                Some(Ok(chunk)) => {
+                    state.parsed += chunk.len() as u64;
+                    let filter = state.filter.clone();
+                    let (parser, mut decoder) = (state.parser, state.decoder);
+                    let (parser, decoder, decoded, error) =
+                        tokio::task::spawn_blocking(move || {
+                            let filtered = filter.filter_chunk(parser, &chunk);
+                            let decoded: Vec<_> = filtered
+                                .kept
+                                .into_iter()
+                                .map(|msg| decode_kept(&mut decoder, msg))
+                                .collect();
+                            (filtered.parser, decoder, decoded, filtered.error)
+                        })
+                        .await
+                        .expect("GRIB2 decoding panicked");
+                    state.parser = parser;
+                    state.decoder = decoder;
+                    for item in decoded {
+                        state.done |= item.is_err();
+                        state.ready.push_back(item);
+                        if state.done {
+                            break;
+                        }
+                    }
+                    if let Some(e) = error.filter(|_| !state.done) {
+                        state.ready.push_back(Err(e));
+                        state.done = true;
+                    }
+                }
+                Some(Err(_)) => {
+                    state.ready.push_back(Err(truncated(state.parsed)));
+                    state.done = true;
+                }
+                None => {
+                    if state.parser.pending() > 0 {
+                        state.ready.push_back(Err(truncated(state.parsed)));
+                    }
+                    state.done = true;
+                }
+            }
+        }
+    })
+}
+
+/// Decode a kept message into its description and grid.
+fn decode_kept(decoder: &mut Decoder, msg: KeptMessage) -> Result<DecodedMessage> {
+    let field = decoder.decode(&msg.bytes).map_err(|e| match e {
+        Error::ParseError { reason, .. } => Error::ParseError {
+            offset: msg.origin.offset,
+            reason,
+        },
+        e => e,
+    })?;
+    let values = Array2::from_shape_vec((field.nj, field.ni), field.values).map_err(|e| {
+        Error::ParseError {
+            offset: msg.origin.offset,
+            reason: e.to_string(),
+        }
+    })?;
+    let first = msg.products.first();
+    let meta = MessageMeta {
+        param: first.map_or(Param::new(255, 255, 255), Param::of),
+        level: first.and_then(|p| p.level),
+        time: first.and_then(|p| p.time),
+        origin: msg.origin,
+        routes: msg.routes,
+    };
+    Ok((meta, values))
+}
+
+/// Download a source file and stream its decoded messages (`decoded_messages()`),
+/// decompressing it as its extension tells.
+pub async fn fetch_decoded(
+    http: &reqwest::Client,
+    url: &str,
+    filter: Filter,
+) -> Result<impl Stream<Item = Result<DecodedMessage>>> {
+    let response = http
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| Error::SourceUnavailable {
+            url: url.to_string(),
+            status: None,
+            reason: e.to_string(),
+        })?;
+    if !response.status().is_success() {
+        return Err(Error::from_status(url, response.status()));
+    }
+    let stream = response.bytes_stream().map_err(io::Error::other);
+    let stream = decode(stream, Compression::from_url(url), Default::default());
+    Ok(decoded_messages(filter, url, stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::route::Route;
+    use crate::testing::{assemble, grid_section, product_section, section};
+
+    /// GRIB2 message of parameter 0.2.`number` on a 2x1 grid, simple packing of
+    /// the values 1 and 2.
+    fn message(number: u8) -> Vec<u8> {
+        let mut sect5 = section(5, 21);
+        sect5[5..9].copy_from_slice(&2u32.to_be_bytes());
+        sect5[11..15].copy_from_slice(&1f32.to_be_bytes());
+        sect5[19] = 8;
+        let sect7 = [0, 0, 0, 7, 7, 0, 1];
+        assemble(
+            0,
+            &[
+                &grid_section(2, 1),
+                &product_section(2, number),
+                &sect5,
+                &sect7,
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoded_messages() {
+        let routes: Arc<[Route]> = vec!["VGRD=v/".parse::<Route>().unwrap()].into();
+        let bytes = [message(2), message(3), message(3)].concat();
+        let chunks: Vec<io::Result<Bytes>> = bytes
+            .chunks(50)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        let decoded: Vec<_> = decoded_messages(
+            Filter::new(routes.clone()),
+            "test",
+            futures::stream::iter(chunks),
+        )
+        .collect()
+        .await;
+        assert_eq!(decoded.len(), 2);
+        let (meta, values) = decoded[1].as_ref().unwrap();
+        assert_eq!(meta.param, "VGRD".parse().unwrap());
+        assert_eq!(meta.origin.index, 3);
+        assert_eq!(meta.routes, [0]);
+        assert_eq!(values.shape(), [1, 2]);
+        assert_eq!(values[[0, 1]], 2.0);
+
+        // A stream ending inside a message
+        let cut = futures::stream::iter([Ok(Bytes::from(bytes[..200].to_vec()))]);
+        let decoded: Vec<_> = decoded_messages(Filter::new(routes), "test", cut)
+            .collect()
+            .await;
+        assert!(matches!(
+            decoded.last(),
+            Some(Err(Error::Truncated { received: 200, .. }))
+        ));
+    }
+}
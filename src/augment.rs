@@ -0,0 +1,152 @@
+use crate::filter::KeptMessage;
+use crate::idx::IdxEntry;
+use crate::manifest::{Manifest, MessageEntry};
+use crate::param::Param;
+use crate::route::Route;
+
+/// Parameters of a route missing from the manifest of an object it produced,
+/// e.g. after `GUST` was added to it (`--augment`).
+pub fn missing_params(route: &Route, manifest: &Manifest) -> Vec<Param> {
+    let mut missing: Vec<Param> = route
+        .params
+        .iter()
+        .filter(|&&param| !manifest.messages.iter().any(|m| entry_param(m) == param))
+        .copied()
+        .collect();
+    missing.sort();
+    missing.dedup();
+    missing
+}
+
+/// Whether the manifest only lists parameters of the route, so that once
+/// augmented the object is what the route produces today.
+pub fn only_route_params(route: &Route, manifest: &Manifest) -> bool {
+    manifest
+        .messages
+        .iter()
+        .all(|m| route.params.contains(&entry_param(m)))
+}
+
+fn entry_param(entry: &MessageEntry) -> Param {
+    Param::new(entry.discipline, entry.category, entry.number)
+}
+
+/// Byte ranges `[start, end)` of a file holding the messages of its `.idx`
+/// inventory with a `wanted` parameter, adjacent messages merged into one range;
+/// an `end` of `None` runs to the end of the file. Parameters with an unknown
+/// name are not fetched.
+pub fn wanted_ranges(
+    entries: &[IdxEntry],
+    wanted: impl Fn(Param) -> bool,
+) -> Vec<(u64, Option<u64>)> {
+    // (number, offset, wanted) of each message, whatever its products
+    let mut messages: Vec<(u64, u64, bool)> = Vec::new();
+    for entry in entries {
+        let kept = entry.param.is_some_and(&wanted);
+        match messages.last_mut() {
+            Some((number, _, any)) if *number == entry.message => *any |= kept,
+            _ => messages.push((entry.message, entry.offset, kept)),
+        }
+    }
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for &(_, offset, kept) in &messages {
+        match (kept, start) {
+            (true, None) => start = Some(offset),
+            (false, Some(first)) => {
+                ranges.push((first, Some(offset)));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges.extend(start.map(|first| (first, None)));
+    ranges
+}
+
+/// Append messages to an archived object and its manifest.
+pub fn append(object: &mut Vec<u8>, manifest: &mut Manifest, messages: &[&KeptMessage]) {
+    for msg in messages {
+        let offset = object.len() as u64;
+        for product in &msg.products {
+            manifest.push(
+                offset,
+                msg.bytes.len() as u64,
+                product,
+                msg.sha256.as_deref(),
+            );
+        }
+        object.extend_from_slice(&msg.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Origin;
+    use crate::grib::ProductInfo;
+    use crate::idx::parse_idx;
+
+    fn product(number: u8) -> ProductInfo {
+        ProductInfo {
+            discipline: 0,
+            template: 0,
+            category: 2,
+            number,
+            time: None,
+            level: None,
+        }
+    }
+
+    #[test]
+    fn test_augment_with_added_params() {
+        let route: Route = "UGRD,VGRD,GUST=wind/".parse().unwrap();
+        let mut manifest = Manifest::new("https://example.com/f000", "wind/a.grb2");
+        manifest.push(0, 10, &product(2), None);
+        manifest.push(10, 10, &product(3), None);
+        let missing = missing_params(&route, &manifest);
+        assert_eq!(missing, [Param::new(0, 2, 22)]);
+        assert!(only_route_params(&route, &manifest));
+
+        let idx = "1:0:d=2020010100:PRMSL:mean sea level:anl:\n\
+                   2:100:d=2020010100:GUST:surface:anl:\n\
+                   3.1:250:d=2020010100:UGRD:10 m above ground:anl:\n\
+                   3.2:250:d=2020010100:VGRD:10 m above ground:anl:\n\
+                   4:400:d=2020010100:GUST:surface:1 hour fcst:\n\
+                   5:520:d=2020010100:TMP:2 m above ground:anl:\n\
+                   6:600:d=2020010100:GUST:surface:2 hour fcst:\n";
+        let entries = parse_idx(idx).unwrap();
+        let gust = |param| missing.contains(&param);
+        assert_eq!(
+            wanted_ranges(&entries, gust),
+            [(100, Some(250)), (400, Some(520)), (600, None)]
+        );
+        // Adjacent messages share a range, a wanted second product takes the message
+        let wind = |param: Param| param == Param::new(0, 2, 3) || param == Param::new(0, 2, 22);
+        assert_eq!(
+            wanted_ranges(&entries, wind),
+            [(100, Some(520)), (600, None)]
+        );
+        assert!(wanted_ranges(&entries, |_| false).is_empty());
+
+        let gust = KeptMessage {
+            bytes: vec![7; 6],
+            products: vec![product(22)],
+            origin: Origin {
+                index: 2,
+                offset: 100,
+                length: 6,
+            },
+            routes: vec![0],
+            sha256: None,
+        };
+        let mut object = vec![0; 20];
+        append(&mut object, &mut manifest, &[&gust]);
+        assert_eq!(object.len(), 26);
+        assert_eq!(manifest.messages[2].offset, 20);
+        assert!(missing_params(&route, &manifest).is_empty());
+
+        let narrower: Route = "UGRD=wind/".parse().unwrap();
+        assert!(!only_route_params(&narrower, &manifest));
+    }
+}
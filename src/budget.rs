@@ -0,0 +1,66 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Returned instead of processing a file once the budget is spent.
+#[derive(Debug)]
+pub struct BudgetExhausted;
+
+impl fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "download budget exhausted")
+    }
+}
+
+impl std::error::Error for BudgetExhausted {}
+
+/// Run-wide download budget.
+///
+/// Bytes are counted as they arrive from the source. Once the limit is
+/// reached no new cycle is started; cycles already in flight are allowed
+/// to finish so that no partial object is left behind.
+pub struct Budget {
+    max_bytes: Option<u64>,
+    downloaded: AtomicU64,
+}
+
+impl Budget {
+    pub fn new(max_bytes: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            downloaded: AtomicU64::new(0),
+        }
+    }
+
+    /// Account for bytes received from the source.
+    pub fn record(&self, bytes: u64) {
+        self.downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Total bytes downloaded so far.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Whether the byte limit has been reached.
+    pub fn exhausted(&self) -> bool {
+        self.max_bytes.is_some_and(|max| self.downloaded() >= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_exhaustion() {
+        let budget = Budget::new(Some(100));
+        budget.record(60);
+        assert!(!budget.exhausted());
+        budget.record(40);
+        assert!(budget.exhausted());
+
+        let unlimited = Budget::new(None);
+        unlimited.record(u64::MAX / 2);
+        assert!(!unlimited.exhausted());
+    }
+}
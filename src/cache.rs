@@ -0,0 +1,166 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::cycle::Task;
+use crate::param::Param;
+use crate::route::Route;
+
+/// Local copies of the messages kept from each task (`--cache-dir`), so a re-run
+/// (e.g. to another bucket) uploads them without downloading the sources again.
+///
+/// A task's file is keyed by its cycle, its source URLs, the routed parameters and
+/// the transforms of the kept messages (`--bbox`, `--quantize`, `--sample`), so
+/// changing any of them misses the cache; route prefixes, bucket and key templates
+/// don't matter.
+pub struct Cache {
+    dir: PathBuf,
+    /// Routed parameters, sorted, then the transforms, e.g.
+    /// `UGRD,VGRD;bbox=-10,35,30,60`.
+    filter: String,
+}
+
+impl Cache {
+    /// Cache in `dir` (created if missing) for the parameters of `routes`.
+    pub fn open(dir: &Path, routes: &[Route]) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        let mut params: Vec<Param> = routes.iter().flat_map(|r| r.params.clone()).collect();
+        params.sort();
+        params.dedup();
+        let params: Vec<_> = params.iter().map(Param::to_string).collect();
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            filter: params.join(","),
+        })
+    }
+
+    /// Key the files by a transform of the kept messages too, e.g. `bbox` and
+    /// `-10,35,30,60`: the cached messages are stored transformed.
+    pub fn with_transform(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.filter.push_str(&format!(";{name}={value}"));
+        self
+    }
+
+    /// File holding the kept messages of `task` read from `sources`, e.g.
+    /// `gfs/2020010100_f000_<hash>.grb2`.
+    pub fn path(&self, task: &Task, sources: &[String]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.filter);
+        for source in sources {
+            hasher.update(b"\n");
+            hasher.update(source);
+        }
+        let hash = format!("{:x}", hasher.finalize());
+        self.dir.join(task.cycle.model.to_string()).join(format!(
+            "{}_f{:03}_{}.grb2",
+            task.cycle.time.format("%Y%m%d%H"),
+            task.forecast_hour,
+            &hash[..16]
+        ))
+    }
+
+    /// Start writing the cache file at `path`; it only appears once committed.
+    pub fn create(&self, path: &Path) -> Result<CacheWriter> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let tmp = path.with_extension("grb2.tmp");
+        let file = File::create(&tmp)
+            .with_context(|| format!("Failed to create cache file {}", tmp.display()))?;
+        Ok(CacheWriter {
+            file: BufWriter::new(file),
+            tmp,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// Cache file being written; removed unless committed.
+pub struct CacheWriter {
+    file: BufWriter<File>,
+    tmp: PathBuf,
+    path: PathBuf,
+}
+
+impl CacheWriter {
+    /// Append a kept message.
+    pub fn write(&mut self, msg: &[u8]) -> Result<()> {
+        self.file
+            .write_all(msg)
+            .with_context(|| format!("Failed to write cache file {}", self.tmp.display()))
+    }
+
+    /// Move the complete file into place.
+    pub fn commit(mut self) -> Result<()> {
+        self.file
+            .flush()
+            .and_then(|()| fs::rename(&self.tmp, &self.path))
+            .with_context(|| format!("Failed to write cache file {}", self.path.display()))
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        // Gone already once committed
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::Cycle;
+    use crate::model::Model;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_cache_keys_and_commit() {
+        let dir = std::env::temp_dir().join(format!("gfs-cache-test-{}", std::process::id()));
+        let routes = |s: &str| -> Vec<Route> { s.split(' ').map(|r| r.parse().unwrap()).collect() };
+        let task = Task {
+            cycle: Cycle {
+                model: Model::Gfs,
+                time: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            },
+            forecast_hour: 3,
+        };
+        let sources = vec!["https://example.com/gfs.2020010106.f003.grib2".to_string()];
+
+        let cache = Cache::open(&dir, &routes("UGRD,VGRD=wind/ GUST=gust/")).unwrap();
+        let path = cache.path(&task, &sources);
+        assert!(path.starts_with(dir.join("gfs")));
+        assert!(path.to_str().unwrap().contains("2020010106_f003_"));
+
+        // Same parameters under other prefixes share the files, other parameters don't
+        let moved = Cache::open(&dir, &routes("GUST,VGRD,UGRD=")).unwrap();
+        assert_eq!(moved.path(&task, &sources), path);
+        let other = Cache::open(&dir, &routes("UGRD=")).unwrap();
+        assert_ne!(other.path(&task, &sources), path);
+        // Cropped messages aren't those of the whole grid
+        let cropped = Cache::open(&dir, &routes("UGRD,VGRD=wind/ GUST=gust/"))
+            .unwrap()
+            .with_transform("bbox", "-10,35,30,60");
+        assert_ne!(cropped.path(&task, &sources), path);
+
+        let mut writer = cache.create(&path).unwrap();
+        writer.write(b"GRIB").unwrap();
+        drop(writer);
+        assert!(!path.exists());
+
+        let mut writer = cache.create(&path).unwrap();
+        writer.write(b"GRIB").unwrap();
+        writer.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"GRIB");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
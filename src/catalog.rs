@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Hex SHA-256 digest of a message, the catalog key.
+pub fn message_hash(msg: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(msg))
+}
+
+/// Where the bytes of an uploaded message live.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Object key.
+    pub key: String,
+    /// Byte offset of the message in the object.
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// One line of the catalog file: the messages of a completed object.
+#[derive(Debug, Serialize, Deserialize)]
+struct ObjectRecord {
+    key: String,
+    messages: Vec<MessageRecord>,
+    /// Messages of the object stored in other objects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    references: Vec<ReferenceRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MessageRecord {
+    sha256: String,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReferenceRecord {
+    sha256: String,
+    stored_in: String,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Default)]
+struct Index {
+    by_hash: HashMap<String, Location>,
+    /// Hashes stored in each object, to forget them when the object is rewritten.
+    by_key: HashMap<String, Vec<String>>,
+    /// Messages each object stores in other objects, which rewriting those must keep.
+    references: HashMap<String, Vec<ReferenceRecord>>,
+}
+
+impl Index {
+    fn apply(&mut self, record: ObjectRecord) {
+        // A rewritten object replaces everything previously stored under its key
+        for hash in self.by_key.remove(&record.key).unwrap_or_default() {
+            if self
+                .by_hash
+                .get(&hash)
+                .is_some_and(|location| location.key == record.key)
+            {
+                self.by_hash.remove(&hash);
+            }
+        }
+
+        let mut hashes = Vec::with_capacity(record.messages.len());
+        for message in record.messages {
+            self.by_hash.insert(
+                message.sha256.clone(),
+                Location {
+                    key: record.key.clone(),
+                    offset: message.offset,
+                    length: message.length,
+                },
+            );
+            hashes.push(message.sha256);
+        }
+        self.by_key.insert(record.key.clone(), hashes);
+        self.references.insert(record.key, record.references);
+    }
+}
+
+/// Content-addressable catalog of uploaded messages (`--dedup-catalog`).
+///
+/// Stored as a JSON Lines file with one line per completed object, so
+/// appending is cheap and a later line for the same key supersedes earlier ones.
+pub struct Catalog {
+    path: PathBuf,
+    index: Mutex<Index>,
+    file: Mutex<File>,
+}
+
+impl Catalog {
+    /// Load the catalog at `path`, creating it if missing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut index = Index::default();
+        if path.exists() {
+            let reader = BufReader::new(
+                File::open(path)
+                    .with_context(|| format!("Failed to open catalog {}", path.display()))?,
+            );
+            for (n, line) in reader.lines().enumerate() {
+                let line =
+                    line.with_context(|| format!("Failed to read catalog {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line).with_context(|| {
+                    format!("Invalid catalog entry at {}:{}", path.display(), n + 1)
+                })?;
+                index.apply(record);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open catalog {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            index: Mutex::new(index),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Number of distinct messages known.
+    pub fn len(&self) -> usize {
+        self.index.lock().unwrap().by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Location of an identical message stored in another object than `key`.
+    pub fn find(&self, hash: &str, key: &str) -> Option<Location> {
+        self.index
+            .lock()
+            .unwrap()
+            .by_hash
+            .get(hash)
+            .filter(|location| location.key != key)
+            .cloned()
+    }
+
+    /// Objects storing messages in `key` that `messages`, its content once
+    /// rewritten as (hash, offset, length), would no longer hold in place.
+    pub fn broken_references(&self, key: &str, messages: &[(String, u64, u64)]) -> Vec<String> {
+        let index = self.index.lock().unwrap();
+        let mut broken: Vec<String> = index
+            .references
+            .iter()
+            .filter(|(referrer, _)| referrer.as_str() != key)
+            .filter(|(_, references)| {
+                references.iter().any(|reference| {
+                    reference.stored_in == key
+                        && !messages.iter().any(|(sha256, offset, length)| {
+                            (sha256, *offset, *length)
+                                == (&reference.sha256, reference.offset, reference.length)
+                        })
+                })
+            })
+            .map(|(referrer, _)| referrer.clone())
+            .collect();
+        broken.sort();
+        broken
+    }
+
+    /// Record the messages stored in a completed object, as (hash, offset, length),
+    /// and those it stores in other objects, as (hash, location).
+    pub fn record_object(
+        &self,
+        key: &str,
+        messages: Vec<(String, u64, u64)>,
+        references: Vec<(String, Location)>,
+    ) -> Result<()> {
+        let record = ObjectRecord {
+            key: key.to_string(),
+            messages: messages
+                .into_iter()
+                .map(|(sha256, offset, length)| MessageRecord {
+                    sha256,
+                    offset,
+                    length,
+                })
+                .collect(),
+            references: references
+                .into_iter()
+                .map(|(sha256, location)| ReferenceRecord {
+                    sha256,
+                    stored_in: location.key,
+                    offset: location.offset,
+                    length: location.length,
+                })
+                .collect(),
+        };
+
+        let mut line = serde_json::to_string(&record).expect("catalog record serializes");
+        line.push('\n');
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write catalog {}", self.path.display()))?;
+
+        self.index.lock().unwrap().apply(record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_round_trip_and_rewrite() {
+        let path = std::env::temp_dir().join(format!("gfs-catalog-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (a, b) = (message_hash(b"GRIB a"), message_hash(b"GRIB b"));
+
+        let catalog = Catalog::open(&path).unwrap();
+        catalog
+            .record_object(
+                "wind_00.grb2",
+                vec![(a.clone(), 0, 6), (b.clone(), 6, 6)],
+                vec![],
+            )
+            .unwrap();
+        assert_eq!(catalog.find(&a, "wind_06.grb2").unwrap().offset, 0);
+        // Never deduplicate an object against itself
+        assert_eq!(catalog.find(&a, "wind_00.grb2"), None);
+
+        // Rewriting the object forgets messages it no longer holds
+        catalog
+            .record_object("wind_00.grb2", vec![(b.clone(), 0, 6)], vec![])
+            .unwrap();
+        drop(catalog);
+
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.find(&a, "wind_06.grb2"), None);
+        assert_eq!(catalog.find(&b, "wind_06.grb2").unwrap().offset, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_keeps_referenced_messages() {
+        let path = std::env::temp_dir().join(format!("gfs-catalog-refs-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (a, b) = (message_hash(b"GRIB a"), message_hash(b"GRIB b"));
+
+        let catalog = Catalog::open(&path).unwrap();
+        catalog
+            .record_object(
+                "wind_00.grb2",
+                vec![(a.clone(), 0, 6), (b.clone(), 6, 6)],
+                vec![],
+            )
+            .unwrap();
+        let location = catalog.find(&b, "wind_06.grb2").unwrap();
+        catalog
+            .record_object("wind_06.grb2", vec![], vec![(b.clone(), location)])
+            .unwrap();
+        drop(catalog);
+
+        let catalog = Catalog::open(&path).unwrap();
+        // The same bytes again, or without the unreferenced message in front
+        let same = [(a.clone(), 0, 6), (b.clone(), 6, 6)];
+        assert!(catalog.broken_references("wind_00.grb2", &same).is_empty());
+        let moved = [(b.clone(), 0, 6)];
+        assert_eq!(
+            catalog.broken_references("wind_00.grb2", &moved),
+            ["wind_06.grb2"]
+        );
+        // Rewriting the referrer itself is fine, and drops its references
+        assert!(catalog.broken_references("wind_06.grb2", &[]).is_empty());
+        catalog
+            .record_object("wind_06.grb2", vec![], vec![])
+            .unwrap();
+        assert!(catalog.broken_references("wind_00.grb2", &moved).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
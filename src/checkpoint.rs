@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Name of the checkpoint object, under the run's prefix.
+pub const CHECKPOINT_NAME: &str = "run-checkpoint.json";
+
+/// State of a run kept in the destination bucket (`--checkpoint`), so the
+/// `resume` subcommand can finish it from another machine given only the
+/// bucket and prefix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Command-line arguments of the run, without the program name; they
+    /// define its plan.
+    pub args: Vec<String>,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Number of files planned.
+    pub planned: usize,
+    /// Files processed, as `Task` names (e.g. `2020-01-01 06 f000`).
+    pub completed: BTreeSet<String>,
+    /// Files that failed, with their last error; retried on resume.
+    pub failed: BTreeMap<String, String>,
+    /// Whether the last run went through every planned file, rather than stopping
+    /// early (budget, access error); files not published yet are left for a resume.
+    pub finished: bool,
+}
+
+impl Checkpoint {
+    pub fn new(args: Vec<String>, planned: usize) -> Self {
+        let now = Utc::now();
+        Self {
+            args,
+            started: now,
+            updated: now,
+            planned,
+            completed: BTreeSet::new(),
+            failed: BTreeMap::new(),
+            finished: false,
+        }
+    }
+
+    /// Key of the checkpoint of a run writing under `prefix`.
+    pub fn key(prefix: &str) -> String {
+        let prefix = prefix.trim_end_matches('/');
+        if prefix.is_empty() {
+            CHECKPOINT_NAME.to_string()
+        } else {
+            format!("{prefix}/{CHECKPOINT_NAME}")
+        }
+    }
+
+    /// Record the outcome of a file.
+    pub fn record(&mut self, task: String, outcome: Result<(), String>) {
+        self.updated = Utc::now();
+        match outcome {
+            Ok(()) => {
+                self.failed.remove(&task);
+                self.completed.insert(task);
+            }
+            Err(e) => {
+                self.failed.insert(task, e);
+            }
+        }
+    }
+
+    pub fn is_completed(&self, task: &str) -> bool {
+        self.completed.contains(task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        assert_eq!(Checkpoint::key(""), "run-checkpoint.json");
+        assert_eq!(Checkpoint::key("wind/"), "wind/run-checkpoint.json");
+
+        let args: Vec<String> = ["--bucket", "b", "--checkpoint"].map(String::from).to_vec();
+        let mut checkpoint = Checkpoint::new(args, 3);
+        checkpoint.record("2020-01-01 00 f000".into(), Ok(()));
+        checkpoint.record("2020-01-01 06 f000".into(), Err("HTTP 404".into()));
+        assert!(checkpoint.is_completed("2020-01-01 00 f000"));
+        assert!(!checkpoint.is_completed("2020-01-01 06 f000"));
+
+        // A failure retried successfully on resume is no longer reported
+        checkpoint.record("2020-01-01 06 f000".into(), Ok(()));
+        assert!(checkpoint.failed.is_empty());
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&json).unwrap(),
+            checkpoint
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::pin::Pin;
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+
+/// Default size of the chunks handed to the parser (1 MiB).
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Regroup a byte stream into chunks of at least `size` bytes.
+///
+/// reqwest yields whatever the socket read returned, often 8-16 KB, and
+/// the parser pays a fixed cost per chunk. The last chunk may be shorter;
+/// on error the bytes buffered so far are yielded before the error, so no
+/// received data is lost.
+pub fn rechunk<S, E>(stream: S, size: usize) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    struct State<S, E> {
+        stream: Pin<Box<S>>,
+        buffer: BytesMut,
+        error: Option<E>,
+        done: bool,
+    }
+
+    let state = State {
+        stream: Box::pin(stream),
+        buffer: BytesMut::new(),
+        error: None,
+        done: false,
+    };
+
+    futures::stream::unfold(state, move |mut state| async move {
+        if let Some(e) = state.error.take() {
+            state.done = true;
+            return Some((Err(e), state));
+        }
+        if state.done {
+            return None;
+        }
+        loop {
+            match state.stream.next().await {
+                // Large enough already: pass through without copying
+                Some(Ok(chunk)) if state.buffer.is_empty() && chunk.len() >= size => {
+                    return Some((Ok(chunk), state));
+                }
+                Some(Ok(chunk)) => {
+                    state.buffer.extend_from_slice(&chunk);
+                    if state.buffer.len() >= size {
+                        let chunk = state.buffer.split().freeze();
+                        return Some((Ok(chunk), state));
+                    }
+                }
+                Some(Err(e)) => {
+                    if state.buffer.is_empty() {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                    state.error = Some(e);
+                    let chunk = state.buffer.split().freeze();
+                    return Some((Ok(chunk), state));
+                }
+                None => {
+                    state.done = true;
+                    if state.buffer.is_empty() {
+                        return None;
+                    }
+                    let chunk = state.buffer.split().freeze();
+                    return Some((Ok(chunk), state));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(
+        input: Vec<Result<&'static [u8], &'static str>>,
+        size: usize,
+    ) -> Vec<Result<Vec<u8>, &'static str>> {
+        let stream = futures::stream::iter(input.into_iter().map(|r| r.map(Bytes::from_static)));
+        futures::executor::block_on(
+            rechunk(stream, size)
+                .map(|r| r.map(|b| b.to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_rechunk_groups_small_chunks() {
+        let out = collect(vec![Ok(b"ab"), Ok(b"cd"), Ok(b"efgh"), Ok(b"i")], 4);
+        assert_eq!(
+            out,
+            vec![
+                Ok(b"abcd".to_vec()),
+                Ok(b"efgh".to_vec()),
+                Ok(b"i".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rechunk_yields_buffered_data_before_error() {
+        let out = collect(vec![Ok(b"ab"), Err("reset"), Ok(b"cd")], 4);
+        assert_eq!(out, vec![Ok(b"ab".to_vec()), Err("reset")]);
+    }
+}
@@ -0,0 +1,5080 @@
+//! Command line of the `gfs_wind_downloader` binary: the run, which drives the
+//! download pipeline, and the subcommands. `main()` is the binary's entry point,
+//! `embedded()` the one of the Python bindings.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+
+use crate::augment::{append, missing_params, only_route_params, wanted_ranges};
+use crate::budget::{Budget, BudgetExhausted};
+use crate::cache::{Cache, CacheWriter};
+use crate::catalog::{Catalog, Location};
+use crate::checkpoint::Checkpoint;
+use crate::chunk::{rechunk, DEFAULT_CHUNK_SIZE};
+use crate::compact::{compact_group, plan_groups, referenced, CompactIndex, INDEX_SUFFIX};
+use crate::compression::{decode, Compression};
+use crate::cycle::{
+    hourly_coverage_hours, parse_cycle_pattern, plan_best_available, plan_cycles, plan_targets,
+    plan_tasks, resolve_date, Cycle, CycleRules, ForecastHours, Sample, Shard, Target, Task,
+};
+use crate::error::{Outage, EXIT_RESUMABLE};
+use crate::estimate::Estimate;
+use crate::filter::{Filter, KeptMessage, OnParseError, Origin};
+use crate::gaps::KnownGaps;
+use crate::grib::Grib2StreamParser;
+use crate::idx::{
+    cached_idx_path, early_stop, idx_url, message_range, parse_idx, IdxEntry, IDX_SUFFIX,
+};
+use crate::latest::{LatestObject, LatestPointer, LATEST_NAME};
+use crate::limiter::{AdaptiveLimiter, BandwidthLimiter, RequestPacer};
+use crate::lock::LockFile;
+use crate::manifest::{Manifest, MANIFEST_SUFFIX};
+use crate::memory::{heap_usage, MemoryUse};
+use crate::merge::{merge_group, plan_merges};
+use crate::mirror::{mirror_key, RawMirror};
+use crate::model::Model;
+use crate::naming::{self, Naming, PARTIAL_SUFFIX};
+use crate::param::Param;
+use crate::politeness::Politeness;
+use crate::profile::{self, take_option, Config, CONFIG_FLAG, PROFILE_FLAG};
+#[cfg(feature = "profiling")]
+use crate::profiling::{profile_name, FileProfile};
+use crate::progress::{Progress, ProgressReport};
+use crate::provenance::{ProvenanceRecord, PROVENANCE_SUFFIX};
+use crate::quantize::Quantize;
+use crate::reader::{ArchiveReader, Selector};
+use crate::repair::{reframe, rewrite_object, Reframed};
+use crate::replica::ReplicaTarget;
+use crate::report::FilterReport;
+use crate::resume::{parse_content_range, ResumableDownload};
+use crate::retries::{DeadLetter, RetryLog, RetryRecord};
+use crate::route::{validate_routes, Route, DEFAULT_ROUTE};
+use crate::s3::{
+    self, copy_object, delete_object, get_object, head_metadata, head_size, list_keys, put_object,
+    validate_bucket_name, validate_prefix, ClientSettings, RunTags, S3Compat, S3MultipartUploader,
+    StorageClass, StoragePolicy,
+};
+use crate::selftest;
+use crate::source::Source;
+use crate::spot::{self, SpotWatcher};
+use crate::subset::BoundingBox;
+use crate::template::{
+    continuation_key, object_key, parse_daily_key_template, parse_key_template, parse_url_template,
+    validate_key, Template, DEFAULT_KEY_TEMPLATE, DEFAULT_LEAD_KEY_TEMPLATE,
+};
+use crate::term::{ColorChoice, Status, Styler};
+use crate::units::{self, format_duration, format_size, SizeUnits};
+use crate::update::{self, Updater};
+use crate::verify::{self, verify_object, verify_upload};
+use crate::Error;
+
+/// Maximum number of attempts for a cycle the source keeps throttling.
+const MAX_THROTTLE_ATTEMPTS: u32 = 5;
+
+/// Default maximum number of attempts for other transient failures
+/// (truncated downloads, network and server errors).
+const MAX_TRANSIENT_ATTEMPTS: u32 = 3;
+
+/// Delay before retrying a transient failure, multiplied by the attempt number.
+const TRANSIENT_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Fraction of a file that must have been downloaded for `--keep-partial` to apply.
+const KEEP_PARTIAL_THRESHOLD: f64 = 0.9;
+
+/// Object metadata recording the `Route::filter_hash()` an object was produced with.
+const FILTER_HASH_METADATA: &str = "filter-hash";
+
+/// Exit code when a file failed with an error outside the `Error` categories.
+const EXIT_OTHER_FAILURE: u8 = 1;
+
+/// Bytes between two progress lines of a file of unknown size, when several files
+/// run at once.
+const PROGRESS_LINE_BYTES: u64 = 100_000_000;
+
+/// How often a file held back by `--max-memory` checks the heap again.
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Download GFS wind data and stream to S3",
+    after_help = "Profiles: `--profile NAME [--config PATH]` is replaced by the options of a \
+                  profile of the config file (default: ~/.config/gfs_wind_downloader/config.toml); \
+                  options given after it override them.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    args_override_self = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: Option<Args>,
+
+    // Beside the run arguments rather than in them: clap cannot tell whether an
+    // optional argument group holding a flattened one was given
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+}
+
+/// Threads of the async runtime, for every command.
+#[derive(clap::Args, Debug, Clone, Copy)]
+struct RuntimeArgs {
+    /// Worker threads of the async runtime, running the downloads and uploads
+    /// (default: one per CPU core)
+    #[arg(long, global = true)]
+    worker_threads: Option<NonZeroUsize>,
+
+    /// Threads parsing, filtering and re-encoding the chunks of the files, off the
+    /// worker threads (default: 512, started as needed)
+    #[arg(long, global = true)]
+    blocking_threads: Option<NonZeroUsize>,
+}
+
+impl RuntimeArgs {
+    fn build(self) -> std::io::Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(threads) = self.worker_threads {
+            builder.worker_threads(threads.get());
+        }
+        if let Some(threads) = self.blocking_threads {
+            builder.max_blocking_threads(threads.get());
+        }
+        builder.build()
+    }
+}
+
+/// S3 service of the bucket, for the run and the subcommands.
+#[derive(clap::Args, Debug, Clone)]
+struct DestinationArgs {
+    /// Custom S3 endpoint URL (for MinIO or other S3-compatible storage)
+    #[arg(long)]
+    endpoint_url: Option<String>,
+
+    /// S3-compatible service behind --endpoint-url, whose quirks are worked around
+    /// (detected from the endpoint host by default)
+    #[arg(long, value_enum)]
+    s3_compat: Option<S3Compat>,
+
+    #[command(flatten)]
+    settings: ClientSettings,
+}
+
+impl DestinationArgs {
+    /// Check the bucket name and that it can be reached through the chosen
+    /// endpoint, before any network call.
+    fn check(&self, bucket: &str) -> Result<()> {
+        validate_bucket_name(bucket).map_err(|e| anyhow::anyhow!("Invalid --bucket: {e}"))?;
+        self.settings
+            .check(bucket)
+            .map_err(|e| anyhow::anyhow!("Invalid --s3-accelerate: {e}"))
+    }
+
+    /// Service behind the endpoint: `--s3-compat`, else detected from its host.
+    fn compat(&self) -> S3Compat {
+        self.s3_compat
+            .unwrap_or_else(|| S3Compat::detect(self.endpoint_url.as_deref()))
+    }
+
+    /// S3 client of the service.
+    async fn client(&self) -> aws_sdk_s3::Client {
+        s3::client(self.endpoint_url.as_deref(), self.compat(), &self.settings).await
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge the archived objects under a prefix into one object per month
+    /// (with a JSON index), for clients reading long time ranges
+    #[command(args_override_self = true)]
+    Compact(CompactArgs),
+    /// Read messages of an archived object with range requests, using its manifest
+    #[command(args_override_self = true)]
+    Fetch(FetchArgs),
+    /// Interleave the objects archived under the same name in several prefixes
+    /// (e.g. wind/ and gust/) into one object per name with a single manifest,
+    /// for consumers wanting one file per cycle
+    #[command(args_override_self = true)]
+    Merge(MergeArgs),
+    /// Re-frame the archived objects under a prefix whose messages announce wrong
+    /// lengths or lack their "7777" marker (written by earlier versions), rewriting
+    /// them and their manifests
+    #[command(args_override_self = true)]
+    Repair(RepairArgs),
+    /// Finish a run started with --checkpoint (e.g. on a replacement instance),
+    /// reading its arguments and progress from the bucket
+    #[command(args_override_self = true)]
+    Resume(ResumeArgs),
+    /// Check the archived objects under a prefix against their manifests, reading
+    /// only the message boundaries (no full downloads)
+    #[command(args_override_self = true)]
+    Verify(VerifyArgs),
+    /// Push a small bundled GRIB2 file through the pipeline into a temporary
+    /// object, verify it and delete it, to check credentials, permissions and
+    /// connectivity before a big run
+    #[command(args_override_self = true)]
+    Selftest(SelftestArgs),
+    /// Print a shell completion script, e.g. to
+    /// /usr/share/bash-completion/completions/gfs_wind_downloader
+    Completions(CompletionsArgs),
+    /// Print the man page (roff), e.g. to /usr/share/man/man1/gfs_wind_downloader.1
+    Manpage,
+    /// Replace this binary with the latest GitHub release for the platform, after
+    /// checking its minisign signature against the release key
+    SelfUpdate(SelfUpdateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CompletionsArgs {
+    /// Shell to complete in
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+#[derive(clap::Args, Debug)]
+struct SelfUpdateArgs {
+    /// Only report whether a newer release exists
+    #[arg(long)]
+    check: bool,
+
+    /// GitHub repository (owner/name) to install releases from
+    #[arg(long, default_value = update::DEFAULT_REPOSITORY)]
+    repository: String,
+
+    /// GitHub API URL (e.g. of a GitHub Enterprise server or a mirror)
+    #[arg(long, default_value = update::DEFAULT_API_URL)]
+    api_url: String,
+
+    /// Minisign public key signing the releases of --repository (default: the key
+    /// this binary was built with)
+    #[arg(long)]
+    public_key: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompactArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix of the objects to compact
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    /// Key prefix of the monthly objects, which keep the directory of the objects
+    /// they merge (e.g. wind/2020-01.grb2 under the prefix -> compacted/wind/2020-01.grb2)
+    #[arg(long, default_value = "compacted/")]
+    into: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    naming: Naming,
+
+    /// S3 storage class for the monthly objects
+    #[arg(long, value_enum, default_value_t = StorageClass::Standard)]
+    storage_class: StorageClass,
+
+    /// Delete the merged objects and their manifests once a month is compacted
+    #[arg(long)]
+    delete_sources: bool,
+
+    /// List the monthly objects that would be written, without copying anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// Prefix of an archive to merge, repeated for each (at least two, e.g. --from
+    /// wind/ --from gust/); objects with the same key relative to their prefix are
+    /// merged, their messages in the order of the prefixes
+    #[arg(long = "from", required = true)]
+    from: Vec<String>,
+
+    /// Key prefix of the merged objects, which keep the key of the objects they
+    /// merge relative to their prefix (e.g. wind/wind_20200101_00.grb2 ->
+    /// merged/wind_20200101_00.grb2)
+    #[arg(long, default_value = "merged/")]
+    into: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    naming: Naming,
+
+    /// S3 storage class for the merged objects
+    #[arg(long, value_enum, default_value_t = StorageClass::Standard)]
+    storage_class: StorageClass,
+
+    /// List the merged objects that would be written, without copying anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ResumeArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix of the run
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix of the objects to verify
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    naming: Naming,
+
+    /// Objects verified in parallel
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct SelftestArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix of the temporary object
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    /// Download this (small, uncompressed) GRIB2 file instead of using the bundled
+    /// one, to check the connectivity to a source too
+    #[arg(long)]
+    source_url: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RepairArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix of the objects to repair
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    naming: Naming,
+
+    /// S3 storage class for the rewritten objects
+    #[arg(long, value_enum, default_value_t = StorageClass::Standard)]
+    storage_class: StorageClass,
+
+    /// Objects checked in parallel
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+
+    /// List the fixes that would be made, without rewriting anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct FetchArgs {
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// Key of the archived object (e.g. wind/2020/wind_20200101_00.grb2)
+    #[arg(short, long)]
+    key: String,
+
+    /// Parameter: NCEP short name or discipline.category.number
+    #[arg(long)]
+    param: Option<Param>,
+
+    /// Level as written in the manifest (e.g. "10 m above ground", "850 mb")
+    #[arg(long)]
+    level: Option<String>,
+
+    /// Valid time (YYYY-MM-DDTHH:MM)
+    #[arg(long, value_parser = parse_valid_time)]
+    valid_time: Option<chrono::NaiveDateTime>,
+
+    #[command(flatten)]
+    destination: DestinationArgs,
+
+    #[command(flatten)]
+    naming: Naming,
+
+    /// Write the matching GRIB2 messages to this file
+    #[arg(short, long)]
+    output: Option<std::path::PathBuf>,
+
+    /// Decode the matching messages and write their values to this file as CSV,
+    /// one grid row per line (NaN for missing points)
+    #[arg(long)]
+    csv: Option<std::path::PathBuf>,
+}
+
+fn parse_valid_time(s: &str) -> Result<chrono::NaiveDateTime, String> {
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(&format!("{s}:00"), "%Y-%m-%dT%H:%M"))
+        .map_err(|_| format!("invalid valid time '{s}' (use YYYY-MM-DDTHH:MM)"))
+}
+
+#[derive(clap::Args, Debug)]
+struct Args {
+    /// Start date: YYYY-MM-DD, today, yesterday, or relative to today or a date in
+    /// days or weeks (e.g. -7d, yesterday-1w), resolved against the current UTC date
+    #[arg(short, long, visible_alias = "start", allow_hyphen_values = true)]
+    start_date: String,
+
+    /// End date, as the start date (e.g. yesterday)
+    #[arg(short, long, visible_alias = "end", allow_hyphen_values = true)]
+    end_date: String,
+
+    /// Time zone in which the start/end dates and --valid-hours are interpreted (e.g. Europe/Paris)
+    #[arg(long, default_value = "UTC")]
+    timezone: Tz,
+
+    /// Only keep cycles at these local hours of day (e.g. 0,12)
+    #[arg(long, value_delimiter = ',')]
+    valid_hours: Option<Vec<u32>>,
+
+    /// Leave out the cycles whose local date or "date hour" matches one of these
+    /// patterns, with * and ? wildcards (e.g. 2021-02-1*, "* 18")
+    #[arg(long, value_delimiter = ',', value_parser = parse_cycle_pattern)]
+    exclude: Vec<String>,
+
+    /// Only keep cycles on these local days of the week (e.g. mon,thu)
+    #[arg(long, value_delimiter = ',')]
+    include_weekdays: Vec<chrono::Weekday>,
+
+    /// S3 bucket name
+    #[arg(short, long)]
+    bucket: String,
+
+    /// S3 key prefix (e.g., "wind/2020/")
+    #[arg(short, long, default_value = "")]
+    prefix: String,
+
+    /// S3 key template, relative to the prefix.
+    /// Placeholders: {yyyy} {mm} {dd} {yyyymmdd} {hh} {fff} {valid_yyyymmdd} {valid_hh}
+    #[arg(long, default_value = DEFAULT_KEY_TEMPLATE, value_parser = parse_key_template)]
+    key_template: Template,
+
+    /// Also append the files of each day to one object per route, keyed by this
+    /// template (relative to --prefix and the route's prefix, without hourly
+    /// placeholders, e.g. daily/wind_{valid_yyyymmdd}.grb2). A day's objects are
+    /// written once all its files are processed, and not at all if one failed
+    #[arg(long, value_parser = parse_daily_key_template)]
+    daily_key_template: Option<Template>,
+
+    /// Send parameters to their own objects: PARAM[,PARAM...]=PREFIX, repeatable
+    /// (e.g. --route UGRD,VGRD=wind/ --route GUST=gust/). Prefixes are relative to
+    /// --prefix; parameters are NCEP short names or discipline.category.number
+    #[arg(long = "route", default_value = DEFAULT_ROUTE)]
+    routes: Vec<Route>,
+
+    /// Parameters to keep instead of UGRD and VGRD, comma-separated: NCEP short
+    /// names or discipline.category.number (e.g. --params GUST,PRMSL,0.0.0); short
+    /// for --route PARAM[,PARAM...]= (objects right under --prefix)
+    #[arg(long, value_delimiter = ',', conflicts_with = "routes")]
+    params: Vec<Param>,
+
+    /// Forecast models, comma-separated or repeated: each sets its cycle frequency
+    /// and default --url-template. With several models, keys are namespaced by
+    /// model ({model}/...) unless --key-template already uses {model}
+    #[arg(
+        long = "model",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "gfs"
+    )]
+    models: Vec<Model>,
+
+    /// Source URL template (same placeholders as --key-template, plus {ff}); repeat
+    /// for models publishing a cycle as several files. `.bz2` files are decompressed.
+    /// Defaults to NCAR THREDDS for GFS, the AWS open data buckets for NAM and RAP,
+    /// DWD open data for ICON, the MSC Datamart for GDPS/HRDPS
+    #[arg(long = "url-template", value_parser = parse_url_template)]
+    url_templates: Vec<Template>,
+
+    /// Server the files are downloaded from instead of the models' default one,
+    /// e.g. nomads for the most recent GFS cycles
+    #[arg(long, value_enum, conflicts_with = "url_templates")]
+    source: Option<Source>,
+
+    /// URL template of the pgrb2b companion file of each cycle (levels missing from the
+    /// main file); its routed messages are appended to the same objects. The default
+    /// RDA source has no b-files: use e.g. NOMADS or the AWS open data bucket
+    #[arg(long, value_parser = parse_url_template)]
+    b_url_template: Option<Template>,
+
+    /// AWS region (defaults to AWS_REGION env var or us-east-1)
+    #[arg(long)]
+    region: Option<String>,
+
+    /// Cap on the upload rate to S3, per second (e.g. 10MB), shared by all the
+    /// uploads of the run
+    #[arg(long, value_parser = units::parse_size)]
+    max_upload_bandwidth: Option<u64>,
+
+    /// Maximum number of cycles processed concurrently (default: from the source's
+    /// politeness profile, else 1). Automatically reduced while the source answers
+    /// with 429/503.
+    #[arg(long, visible_alias = "jobs")]
+    concurrency: Option<usize>,
+
+    /// Minimum time between two requests to the source, e.g. 500ms (default: from
+    /// the source's politeness profile, else none)
+    #[arg(long, value_parser = units::parse_duration)]
+    request_interval: Option<Duration>,
+
+    /// Attempts of a file failing with transient errors (truncated downloads,
+    /// network and server errors; default: from the source's politeness profile,
+    /// else 3)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: Option<u32>,
+
+    /// Seconds without throttling before concurrency ramps back up by one
+    #[arg(long, default_value_t = 60)]
+    throttle_cooldown: u64,
+
+    /// Stop starting new cycles once this much has been downloaded (e.g. 500GB, 2TiB)
+    #[arg(long, value_parser = units::parse_size)]
+    max_bytes: Option<u64>,
+
+    /// Hold back new files while the heap is over this size (e.g. 512MiB), for small
+    /// containers; files already running finish, and one file always runs
+    #[arg(long, value_parser = units::parse_size)]
+    max_memory: Option<u64>,
+
+    /// Minimum size of the chunks handed to the GRIB2 parser (e.g. 4MiB);
+    /// small network reads are regrouped up to this size
+    #[arg(long, default_value_t = DEFAULT_CHUNK_SIZE as u64, value_parser = units::parse_size)]
+    chunk_size: u64,
+
+    /// Process at most this many cycles
+    #[arg(long)]
+    max_cycles: Option<usize>,
+
+    /// Only process the INDEX-th of every COUNT planned cycles (e.g. 3/8), to split
+    /// a backfill across COUNT machines given the same range and options
+    #[arg(long, conflicts_with_all = ["checkpoint", "daily_key_template"])]
+    shard: Option<Shard>,
+
+    /// Keep a small but representative archive for previews: one of every N messages
+    /// matched by a route (1/N, e.g. 1/8) or one cycle per day (1/day); recorded as
+    /// x-amz-meta-sample
+    #[arg(long)]
+    sample: Option<Sample>,
+
+    /// S3 storage class for uploaded objects
+    #[arg(long, value_enum, default_value_t = StorageClass::Standard)]
+    storage_class: StorageClass,
+
+    /// Write the cycles older than this many days (at the start of the run) with
+    /// --archive-storage-class instead of --storage-class, e.g. during backfills,
+    /// rather than moving them later with a lifecycle transition
+    #[arg(long)]
+    archive_after: Option<u32>,
+
+    /// S3 storage class of the cycles older than --archive-after
+    #[arg(long, value_enum, default_value_t = StorageClass::StandardIa, requires = "archive_after")]
+    archive_storage_class: StorageClass,
+
+    /// Run ID the objects are tagged with (run-id tag), for cost allocation; the
+    /// start time of the run by default (e.g. 20200101T000000Z), kept on resume
+    #[arg(long, value_parser = s3::validate_tag_value)]
+    run_id: Option<String>,
+
+    /// Cost center the objects are tagged with (cost-center tag), for teams
+    /// sharing one archive bucket
+    #[arg(long, value_parser = s3::validate_tag_value)]
+    cost_center: Option<String>,
+
+    /// Don't check the write permissions on the prefix (a few bytes written to
+    /// <prefix>/.preflight and deleted) before the first download
+    #[arg(long)]
+    no_preflight: bool,
+
+    /// Don't tag the objects, e.g. with credentials lacking s3:PutObjectTagging
+    #[arg(long, conflicts_with_all = ["run_id", "cost_center"])]
+    no_tags: bool,
+
+    /// Make runs over the same sources write byte-identical objects, for regression
+    /// tests: files are processed one at a time in planned order, and the run-id tag
+    /// and the update time of latest pointers are set to 1970-01-01T00:00:00Z
+    #[arg(long, conflicts_with = "concurrency")]
+    deterministic: bool,
+
+    /// Also fetch f001-f005 of each cycle for continuous hourly coverage.
+    /// Objects are keyed by valid time (requires a source publishing hourly steps).
+    #[arg(long)]
+    hourly_coverage: bool,
+
+    /// Forecast hours to archive from each cycle, e.g. 0,3,6..120/3 for full forecast
+    /// runs; the default key template then ends with the lead time (anl, f003, ...)
+    #[arg(long, conflicts_with = "hourly_coverage")]
+    forecast_hours: Option<ForecastHours>,
+
+    /// Only archive the analysis (f000) of each cycle, the default plan spelled
+    /// out; conflicts with the options adding forecast steps
+    #[arg(long, conflicts_with_all = ["hourly_coverage", "forecast_hours", "best_available"])]
+    analysis_only: bool,
+
+    /// For each valid time, archive only the shortest available lead time,
+    /// falling back to earlier cycles when a file is missing
+    #[arg(long)]
+    best_available: bool,
+
+    /// Longest lead time (hours) considered by --best-available
+    #[arg(long, default_value_t = 24)]
+    max_lead_hours: u32,
+
+    /// Lock file preventing concurrent runs against the same destination
+    /// (defaults to a per-bucket/prefix file in the temp directory)
+    #[arg(long)]
+    lock_file: Option<std::path::PathBuf>,
+
+    /// Take over the lock file even if another instance seems to hold it
+    #[arg(long)]
+    force: bool,
+
+    /// When a file fails for good after 90% of it was downloaded, keep the
+    /// messages extracted so far as `<key>.partial` instead of discarding them
+    #[arg(long)]
+    keep_partial: bool,
+
+    /// Skip uploading wind messages byte-identical to one already stored,
+    /// tracking message SHA-256 hashes in this catalog file (JSON Lines)
+    #[arg(long)]
+    dedup_catalog: Option<std::path::PathBuf>,
+
+    /// Append a JSON line to this file for every retried file (task, attempt, cause,
+    /// HTTP status, backoff, concurrency), to tell source throttling from slow links
+    #[arg(long)]
+    retry_log: Option<std::path::PathBuf>,
+
+    /// Once every file was tried, try the failed ones again, up to this many more
+    /// rounds (files missing from the source, past --publication-delay, excepted)
+    #[arg(long, default_value_t = 0)]
+    requeue_rounds: u32,
+
+    /// Append a JSON line to this file for every file failing for good (task, source
+    /// URLs, rounds, cause, HTTP status and the full error), to retry them by hand
+    #[arg(long)]
+    dead_letter: Option<std::path::PathBuf>,
+
+    /// List of cycles known to be missing from the source, skipped instead of
+    /// failing, replacing the embedded one (lines: <model> <YYYY-MM-DD>T<HH> [f<FFF>])
+    #[arg(long)]
+    known_gaps: Option<std::path::PathBuf>,
+
+    /// Hours after a cycle during which its missing files are considered not
+    /// published yet: they are skipped without failing the run, while older
+    /// missing files are reported as gaps in the source
+    #[arg(long, default_value_t = 24)]
+    publication_delay: u32,
+
+    /// Only process the files whose objects are missing or were produced with
+    /// other parameters than their route's or another --bbox or --quantize (recorded
+    /// as x-amz-meta-filter-hash), e.g. to reprocess the archive after changing --route
+    #[arg(long, conflicts_with = "daily_key_template")]
+    stale_only: bool,
+
+    /// Skip the files whose objects all exist already with the size their manifest
+    /// gives, whatever parameters they were produced with, e.g. to re-run over a
+    /// range without downloading it again
+    #[arg(
+        long,
+        conflicts_with_all = ["stale_only", "augment", "daily_key_template", "metadata_only", "mirror_only"]
+    )]
+    skip_existing: bool,
+
+    /// Append the messages of the parameters added to a route since its objects were
+    /// archived (e.g. GUST), fetched by range as located by the source's .idx
+    /// inventory, instead of processing the whole files again; files without objects
+    /// yet are processed as usual
+    #[arg(
+        long,
+        conflicts_with_all = ["stale_only", "daily_key_template", "best_available", "metadata_only", "mirror_only", "max_object_size"]
+    )]
+    augment: bool,
+
+    /// Start a numbered continuation object (e.g. wind_20200101.2.grb2) when an
+    /// object would exceed this size (e.g. 1GB), mainly for daily objects
+    #[arg(long, value_parser = units::parse_size)]
+    max_object_size: Option<u64>,
+
+    /// Re-pack the wind fields to this precision, a power of ten in m/s (e.g. 0.1m/s):
+    /// values are rounded to it, for smaller objects (recorded as x-amz-meta-quantize)
+    #[arg(long)]
+    quantize: Option<Quantize>,
+
+    /// Crop the fields to this box, WEST,SOUTH,EAST,NORTH in degrees (e.g. -10,35,30,60;
+    /// recorded as x-amz-meta-bbox); regular lat/lon grids only
+    #[arg(long, allow_hyphen_values = true)]
+    bbox: Option<BoundingBox>,
+
+    /// Upload a <key>.provenance.jsonl log next to each object, tracing every message
+    /// back to its source file (message index, byte offset and length)
+    #[arg(long, conflicts_with = "cache_dir")]
+    provenance: bool,
+
+    /// Fetch the .idx inventory of each source file; when every wanted message
+    /// ends within the first 20% of the file, stop its download once they are read
+    #[arg(long)]
+    idx_early_stop: bool,
+
+    /// Fetch the .idx inventory of each uncompressed source file and download only
+    /// the byte ranges of the messages the routes want, with range requests, rather
+    /// than the whole file; files without inventory are downloaded whole
+    #[arg(long, conflicts_with_all = ["idx_early_stop", "mirror_raw", "filter_report"])]
+    idx_ranges: bool,
+
+    /// When a source file has no .idx inventory, generate one while parsing it and
+    /// keep it in this directory (by host and path), standing in for the missing
+    /// inventory in later runs (--idx-early-stop, re-fetching corrupt messages)
+    #[arg(long)]
+    idx_cache: Option<std::path::PathBuf>,
+
+    /// Also upload each source file unmodified under this prefix, by the path of its
+    /// URL (e.g. raw/gfs.20200101/00/atmos/gfs.t00z.pgrb2.0p25.f000), from the same
+    /// download as the filtered objects; an inventory generated with --idx-cache is
+    /// uploaded next to it (<key>.idx)
+    #[arg(long)]
+    mirror_raw: Option<String>,
+
+    /// Only mirror the source files (--mirror-raw): upload no filtered objects
+    #[arg(
+        long,
+        requires = "mirror_raw",
+        conflicts_with_all = ["stale_only", "daily_key_template", "latest_pointer", "metadata_only", "cache_dir"]
+    )]
+    mirror_only: bool,
+
+    /// After each cycle whose files all succeed, point <prefix>/latest.json to its
+    /// objects (unless it designates a newer cycle), so consumers find the newest
+    /// data with one GET
+    #[arg(long, conflicts_with = "metadata_only")]
+    latest_pointer: bool,
+
+    /// Copy each completed object, with its manifest and provenance log, to this
+    /// bucket (server-side CopyObject, no second download), e.g. for a disaster
+    /// recovery copy in another account or region; repeatable. The prefix replaces
+    /// --prefix in the keys; region= and profile= select the client writing the copies
+    #[arg(
+        long = "replicate-to",
+        value_name = "BUCKET[/PREFIX][,region=R][,profile=P]",
+        conflicts_with_all = ["metadata_only", "mirror_only"]
+    )]
+    replicas: Vec<ReplicaTarget>,
+
+    /// Suffix of the manifest of an object
+    #[arg(long, default_value = MANIFEST_SUFFIX, value_parser = naming::parse_suffix)]
+    manifest_suffix: String,
+
+    /// Suffix of the object keeping the messages uploaded before a late failure
+    /// (--keep-partial)
+    #[arg(long, default_value = PARTIAL_SUFFIX, value_parser = naming::parse_suffix)]
+    partial_suffix: String,
+
+    /// Suffix of the provenance log of an object (--provenance)
+    #[arg(long, default_value = PROVENANCE_SUFFIX, value_parser = naming::parse_suffix)]
+    provenance_suffix: String,
+
+    /// Name of the latest pointer objects (--latest-pointer)
+    #[arg(long, default_value = LATEST_NAME, value_parser = naming::parse_suffix)]
+    latest_name: String,
+
+    /// Read back each object after completing it: check its size against the bytes
+    /// written and its first and last bytes ("GRIB" and "7777"), failing the file
+    /// on a mismatch
+    #[arg(long, conflicts_with = "metadata_only")]
+    verify_upload: bool,
+
+    /// What to do with a message whose sections don't parse: leave it out (skip),
+    /// leave it out with a warning (warn), or fail the file (fail), for archives
+    /// that must be complete
+    #[arg(long, value_enum, default_value_t = OnParseError::Skip)]
+    on_parse_error: OnParseError,
+
+    /// Parse every message but upload no GRIB data: only a <key>.inventory.json
+    /// manifest per object, listing the messages it would hold, to survey sources
+    /// before committing to storage
+    #[arg(
+        long,
+        conflicts_with_all = ["provenance", "stale_only", "keep_partial", "dedup_catalog"]
+    )]
+    metadata_only: bool,
+
+    /// Keep the messages kept from each file in this directory; later runs with the
+    /// same sources and routed parameters (e.g. to another bucket) upload them from
+    /// there instead of downloading the files again
+    #[arg(long)]
+    cache_dir: Option<std::path::PathBuf>,
+
+    /// Write a CPU flamegraph of each file to this directory (e.g.
+    /// gfs_2020010100_f000.svg); files are then processed one at a time
+    #[cfg(feature = "profiling")]
+    #[arg(long)]
+    profile_dir: Option<std::path::PathBuf>,
+
+    /// Keep the run's arguments and progress in <prefix>/run-checkpoint.json in the
+    /// bucket, updated as files complete, so `resume` can finish the run from
+    /// another machine
+    #[arg(long, conflicts_with = "daily_key_template")]
+    checkpoint: bool,
+
+    /// On EC2 spot instances: watch the interruption notice and, once posted, stop
+    /// starting files, give up those in progress after their current chunk, save the
+    /// checkpoint and exit with code 9, so the run can be finished with `resume`
+    #[arg(long, requires = "checkpoint")]
+    spot: bool,
+
+    /// POST the run's progress (cycles and files done, throughput, ETA) as JSON
+    /// to this URL every --progress-interval, and once more at the end
+    #[arg(long)]
+    progress_webhook: Option<reqwest::Url>,
+
+    /// Interval between progress webhook posts (e.g. 30s, 10m, 1h)
+    #[arg(long, default_value = "10m", value_parser = units::parse_duration, requires = "progress_webhook")]
+    progress_interval: Duration,
+
+    /// At the end of the run, list every parameter/level combination seen in
+    /// the source with message counts and sizes, and whether it was kept
+    #[arg(long)]
+    filter_report: bool,
+
+    /// Colorize the status of each file: auto (when printing to a terminal and
+    /// NO_COLOR is not set), always or never
+    #[arg(long, value_enum, default_value_t)]
+    color: ColorChoice,
+
+    /// Units of the sizes printed: si (MB, GB) or binary (MiB, GiB)
+    #[arg(long, value_enum, default_value_t)]
+    size_units: SizeUnits,
+
+    /// List planned cycles and estimate download volume and S3 cost, without downloading
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Args {
+    /// Templates of the files making up a task of `model`: the source files
+    /// (defaulting to those of `--source`, then the model's), then the pgrb2b
+    /// companion if any.
+    fn source_templates(&self, model: Model) -> Vec<Template> {
+        let mut templates = if !self.url_templates.is_empty() {
+            self.url_templates.clone()
+        } else if let Some(source) = self.source {
+            source
+                .url_templates(model)
+                .expect("--source publishes the models")
+        } else {
+            model.url_templates()
+        };
+        templates.extend(self.b_url_template.clone());
+        templates
+    }
+
+    /// Step between the forecast hours the source of `model` publishes: that of
+    /// `--source`, or of the source the model's default templates point to. Files
+    /// of `--url-template` are taken to be published every hour.
+    fn forecast_hour_step(&self, model: Model) -> u32 {
+        if !self.url_templates.is_empty() {
+            return 1;
+        }
+        self.source
+            .or_else(|| {
+                Source::value_variants()
+                    .iter()
+                    .copied()
+                    .find(|source| source.url_templates(model) == Some(model.url_templates()))
+            })
+            .map_or(1, |source| source.forecast_hour_step(model))
+    }
+
+    /// Storage class of the objects of each cycle, for a run started at `now`.
+    fn storage_policy(&self, now: NaiveDateTime) -> StoragePolicy {
+        match self.archive_after {
+            Some(days) => {
+                StoragePolicy::by_age(self.storage_class, days, self.archive_storage_class, now)
+            }
+            None => StoragePolicy::fixed(self.storage_class),
+        }
+    }
+
+    /// Names of the manifests, partial uploads, provenance logs and pointers, listed
+    /// as fields of their own like the S3 client settings.
+    fn naming(&self) -> Naming {
+        Naming {
+            manifest_suffix: self.manifest_suffix.clone(),
+            partial_suffix: self.partial_suffix.clone(),
+            provenance_suffix: self.provenance_suffix.clone(),
+            latest_name: self.latest_name.clone(),
+        }
+    }
+}
+
+/// Clients and settings shared by every cycle of a run.
+struct RunContext {
+    http: reqwest::Client,
+    s3: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+    key_template: Template,
+    /// Files making up each task of a model, streamed in order into the same objects.
+    source_templates: HashMap<Model, Vec<Template>>,
+    routes: Arc<[Route]>,
+    /// Storage class of the objects of each cycle (`--storage-class`, `--archive-after`).
+    storage: StoragePolicy,
+    /// Names of the objects written next to the archived ones (`--manifest-suffix`, ...).
+    naming: Naming,
+    s3_compat: S3Compat,
+    keep_partial: bool,
+    chunk_size: usize,
+    catalog: Option<Catalog>,
+    /// Sizes of the objects the catalog points to, `None` for those gone.
+    catalog_targets: Mutex<HashMap<String, Option<u64>>>,
+    /// Log of the retried attempts (`--retry-log`).
+    retry_log: Option<RetryLog>,
+    /// End-of-run rounds of the failed files (`--requeue-rounds`).
+    requeue_rounds: u32,
+    /// Spaces the requests to the source (`--request-interval`).
+    pacer: Option<Arc<RequestPacer>>,
+    /// Attempts of a file failing with transient errors (`--max-attempts`).
+    max_attempts: u32,
+    /// Tally of the files completed so far, with `--filter-report`.
+    filter_report: Option<Mutex<FilterReport>>,
+    budget: Budget,
+    /// Largest buffers and files held back by the heap limit (`--max-memory`).
+    memory: MemoryUse,
+    limiter: AdaptiveLimiter,
+    /// Shared cap on the upload rate (`--max-upload-bandwidth`).
+    upload_bandwidth: Option<Arc<BandwidthLimiter>>,
+    /// Tags of the uploaded objects and bytes uploaded (`--run-id`, `--cost-center`).
+    tags: Arc<RunTags>,
+    /// Time after a cycle from which a missing file is a gap rather than not published yet.
+    publication_delay: chrono::TimeDelta,
+    /// Skip the targets whose objects are up to date (`--stale-only`).
+    stale_only: bool,
+    /// Skip the targets whose objects exist (`--skip-existing`).
+    skip_existing: bool,
+    /// Targets skipped by `--stale-only` or `--skip-existing`, or with nothing to add
+    /// by `--augment`.
+    up_to_date: AtomicUsize,
+    /// Append the parameters added to the routes to the archived objects (`--augment`).
+    augment: bool,
+    /// Volatile metadata set to the Unix epoch (`--deterministic`).
+    deterministic: bool,
+    /// Kept messages of each task, with `--cache-dir`.
+    cache: Option<Cache>,
+    /// Directory of the per-file CPU profiles (`--profile-dir`).
+    #[cfg(feature = "profiling")]
+    profile_dir: Option<std::path::PathBuf>,
+    /// Upload provenance logs (`--provenance`).
+    provenance: bool,
+    /// Size from which objects continue in numbered ones (`--max-object-size`).
+    max_object_size: Option<u64>,
+    /// Precision the wind fields are re-packed to (`--quantize`).
+    quantize: Option<Quantize>,
+    /// Box the fields are cropped to (`--bbox`).
+    bbox: Option<BoundingBox>,
+    /// Thinning of the run for previews (`--sample`).
+    sample: Option<Sample>,
+    /// Messages matched so far, counted across the files of the run (`--sample 1/N`).
+    sampled: Arc<AtomicU64>,
+    /// Upload inventories instead of objects and manifests (`--metadata-only`).
+    metadata_only: bool,
+    /// Read back each completed object (`--verify-upload`).
+    verify_upload: bool,
+    /// Handling of messages whose sections don't parse (`--on-parse-error`).
+    on_parse_error: OnParseError,
+    /// Stop downloads once the wanted messages are read (`--idx-early-stop`).
+    idx_early_stop: bool,
+    /// Download only the ranges of the wanted messages (`--idx-ranges`).
+    idx_ranges: bool,
+    /// Inventories generated for sources without one (`--idx-cache`).
+    idx_cache: Option<std::path::PathBuf>,
+    /// Prefix of the unmodified copies of the source files (`--mirror-raw`).
+    mirror_raw: Option<String>,
+    /// Per-day objects, with `--daily-key-template`.
+    daily: Option<DailyObjects>,
+    /// Run state saved to the bucket, with `--checkpoint`.
+    checkpoint: Option<tokio::sync::Mutex<Checkpoint>>,
+    /// Pointers to the newest complete cycle, with `--latest-pointer`.
+    latest: Option<LatestPointers>,
+    /// Buckets receiving a copy of each completed object (`--replicate-to`).
+    replicas: Vec<Replica>,
+    /// Copies to the replicas that failed.
+    replica_failures: AtomicUsize,
+    /// Set once a spot interruption notice is posted (`--spot`).
+    interrupted: AtomicBool,
+    /// Status lines of standard output and error (`--color`).
+    stdout: Styler,
+    stderr: Styler,
+    /// Units of the sizes printed (`--size-units`).
+    size_units: SizeUnits,
+}
+
+/// Bucket receiving copies of the objects, with the client writing them.
+struct Replica {
+    target: ReplicaTarget,
+    s3: aws_sdk_s3::Client,
+}
+
+/// Client writing the copies to `target`: the run's one, with the region and
+/// credentials of the replica when set.
+async fn replica_client(
+    destination: &DestinationArgs,
+    compat: S3Compat,
+    s3: &aws_sdk_s3::Client,
+    target: &ReplicaTarget,
+) -> aws_sdk_s3::Client {
+    let client = match &target.profile {
+        Some(profile) => {
+            let settings = ClientSettings {
+                aws_profile: Some(profile.clone()),
+                ..destination.settings.clone()
+            };
+            s3::client(destination.endpoint_url.as_deref(), compat, &settings).await
+        }
+        None => s3.clone(),
+    };
+    match &target.region {
+        Some(region) => aws_sdk_s3::Client::from_conf(
+            client
+                .config()
+                .to_builder()
+                .region(aws_sdk_s3::config::Region::new(region.clone()))
+                .build(),
+        ),
+        None => client,
+    }
+}
+
+/// Copy the objects at `keys` (with their storage class) to every replica,
+/// warning about the copies that fail: the primary objects are complete, the
+/// file is not processed again for them.
+async fn replicate(ctx: &RunContext, keys: &[(String, StorageClass)]) {
+    for replica in &ctx.replicas {
+        for (key, storage_class) in keys {
+            let dest_key = replica.target.key(&ctx.prefix, key);
+            let copied = copy_object(
+                &replica.s3,
+                &ctx.bucket,
+                key,
+                &replica.target.bucket,
+                &dest_key,
+                *storage_class,
+                ctx.s3_compat,
+            )
+            .await;
+            if let Err(e) = copied {
+                eprintln!("  Warning: copy of {key} to {} failed: {e}", replica.target);
+                ctx.replica_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Object being written for one route.
+struct Output {
+    uploader: S3MultipartUploader,
+    manifest: Manifest,
+    /// Bytes written to the object so far.
+    written: u64,
+    /// Messages routed to the object.
+    kept: u64,
+    /// Messages not stored again thanks to the dedup catalog.
+    deduplicated: u64,
+    /// Whether messages known to the dedup catalog are skipped.
+    deduplicate: bool,
+    /// Copy of the messages routed to the object, for the daily objects.
+    tee: Option<Vec<KeptMessage>>,
+    /// Origin of the messages, with `--provenance`.
+    provenance: Option<Vec<ProvenanceRecord>>,
+    /// Key of the first object, numbered for the continuations.
+    first_key: String,
+    /// Continuations started so far (`--max-object-size`).
+    continuations: u32,
+    /// Earlier objects, full at `--max-object-size`: completed with this one, once
+    /// the whole file was read, and aborted with it.
+    rolled: Vec<Output>,
+}
+
+impl Output {
+    fn new(ctx: &RunContext, task: &Task, route: &Route, url: &str, key: &str) -> Self {
+        let model = task.cycle.model.to_string();
+        let mut uploader = S3MultipartUploader::new(
+            ctx.s3.clone(),
+            &ctx.bucket,
+            key,
+            ctx.storage.class_for(task.cycle.time),
+            ctx.s3_compat,
+        )
+        .with_bandwidth_limit(ctx.upload_bandwidth.clone())
+        .with_tags(Some(ctx.tags.clone()));
+        uploader.set_metadata("model", model.clone());
+        uploader.set_metadata(FILTER_HASH_METADATA, route_hash(ctx, route));
+        if let Some(quantize) = ctx.quantize {
+            uploader.set_metadata("quantize", quantize.to_string());
+        }
+        if let Some(bbox) = ctx.bbox {
+            uploader.set_metadata("bbox", bbox.to_string());
+        }
+        if let Some(sample) = ctx.sample {
+            uploader.set_metadata("sample", sample.to_string());
+        }
+        let mut manifest = Manifest::new(url, key);
+        manifest.model = Some(model);
+        manifest.filter_hash = Some(route_hash(ctx, route));
+
+        Self {
+            uploader,
+            manifest,
+            written: 0,
+            kept: 0,
+            deduplicated: 0,
+            deduplicate: true,
+            tee: None,
+            provenance: None,
+            first_key: key.to_string(),
+            continuations: 0,
+            rolled: Vec::new(),
+        }
+    }
+
+    /// Add a message to the object, unless the dedup catalog knows it.
+    /// Returns where its bytes are stored.
+    async fn write(&mut self, ctx: &RunContext, msg: &KeptMessage) -> crate::Result<Location> {
+        self.kept += 1;
+        if let Some(tee) = &mut self.tee {
+            tee.push(msg.clone());
+        }
+
+        let sha256 = msg.sha256.as_deref();
+        let mut stored = ctx
+            .catalog
+            .as_ref()
+            .filter(|_| self.deduplicate)
+            .zip(sha256)
+            .and_then(|(catalog, hash)| catalog.find(hash, &self.manifest.key));
+        if let Some(location) = &stored {
+            if !catalog_target_holds(ctx, location).await? {
+                stored = None;
+            }
+        }
+        if let (Some(location), Some(hash)) = (stored, sha256) {
+            for product in &msg.products {
+                self.manifest.push_duplicate(&location, product, hash);
+            }
+            self.deduplicated += 1;
+            return Ok(location);
+        }
+
+        let length = msg.bytes.len() as u64;
+        if ctx
+            .max_object_size
+            .is_some_and(|max| self.written > 0 && self.written + length > max)
+        {
+            self.roll_over(ctx);
+        }
+
+        let location = Location {
+            key: self.manifest.key.clone(),
+            offset: self.written,
+            length,
+        };
+        for product in &msg.products {
+            self.manifest
+                .push(location.offset, location.length, product, sha256);
+        }
+        self.written += location.length;
+        if !ctx.metadata_only {
+            self.uploader.write(&msg.bytes).await?;
+        }
+        Ok(location)
+    }
+
+    /// Continue in the next numbered object, the full one being completed with it.
+    fn roll_over(&mut self, ctx: &RunContext) {
+        let key = continuation_key(&self.first_key, self.continuations + 2);
+        let next = Output {
+            uploader: self.uploader.continuation(&key),
+            manifest: self.manifest.continuation(&key),
+            written: 0,
+            kept: self.kept,
+            deduplicated: self.deduplicated,
+            deduplicate: self.deduplicate,
+            tee: self.tee.take(),
+            provenance: self.provenance.as_ref().map(|_| Vec::new()),
+            first_key: self.first_key.clone(),
+            continuations: self.continuations + 1,
+            rolled: Vec::new(),
+        };
+        let mut full = std::mem::replace(self, next);
+        println!(
+            "  {} reached --max-object-size ({}), continuing in {key}",
+            full.manifest.key,
+            format_size(full.written, ctx.size_units)
+        );
+        self.rolled = std::mem::take(&mut full.rolled);
+        self.rolled.push(full);
+    }
+
+    /// Abort the upload of the object and of the earlier ones it continues.
+    async fn abort(self) {
+        for output in self.rolled.into_iter().chain([Output {
+            rolled: Vec::new(),
+            ..self
+        }]) {
+            let _ = output.uploader.abort().await;
+        }
+    }
+
+    /// Complete the object, and read it back with `--verify-upload`. With
+    /// `--metadata-only`, nothing was uploaded.
+    async fn complete(self, ctx: &RunContext) -> crate::Result<Completed> {
+        let Output {
+            mut uploader,
+            manifest,
+            written,
+            deduplicate,
+            provenance,
+            ..
+        } = self;
+        set_time_metadata(&mut uploader, &manifest);
+        let storage_class = uploader.storage_class();
+        ctx.memory.record_upload(uploader.peak_buffered());
+        // Objects deduplicated into this one point into its previous bytes
+        let broken = ctx.catalog.as_ref().map_or_else(Vec::new, |catalog| {
+            catalog.broken_references(&manifest.key, &manifest.stored_hashes())
+        });
+        if !broken.is_empty() && !ctx.metadata_only {
+            let _ = uploader.abort().await;
+            return Err(Error::SinkError {
+                key: manifest.key,
+                reason: format!(
+                    "not rewritten: {} store messages in it that the new bytes don't hold \
+                     at the same place (--dedup-catalog)",
+                    broken.join(", ")
+                ),
+            });
+        }
+        if !ctx.metadata_only {
+            uploader.complete().await?;
+        }
+        let completed = Completed {
+            manifest,
+            storage_class,
+            provenance,
+            deduplicate,
+        };
+        if ctx.verify_upload && !ctx.metadata_only {
+            let key = &completed.manifest.key;
+            let problems = verify_upload(&ctx.s3, &ctx.bucket, key, written).await?;
+            if !problems.is_empty() {
+                completed.delete(ctx).await;
+                return Err(Error::SinkError {
+                    key: key.clone(),
+                    reason: format!("read-back check failed: {}", problems.join("; ")),
+                });
+            }
+        }
+        Ok(completed)
+    }
+}
+
+/// Object completed by `Output::complete()`, not published yet.
+struct Completed {
+    manifest: Manifest,
+    storage_class: StorageClass,
+    provenance: Option<Vec<ProvenanceRecord>>,
+    deduplicate: bool,
+}
+
+impl Completed {
+    /// Upload the manifest (and provenance log) of the object, which readers and
+    /// `--skip-existing` take for its publication. With `--metadata-only`, only
+    /// upload the manifest, as an inventory.
+    async fn publish(&self, ctx: &RunContext) -> crate::Result<()> {
+        let key = &self.manifest.key;
+        let manifest =
+            serde_json::to_vec_pretty(&self.manifest).expect("manifest serializes to JSON");
+        if ctx.metadata_only {
+            let inventory_key = Manifest::inventory_key_for(key);
+            return put_object(
+                &ctx.s3,
+                &ctx.bucket,
+                &inventory_key,
+                manifest,
+                "application/json",
+            )
+            .await;
+        }
+        let manifest_key = ctx.naming.manifest_key(key);
+        put_object(
+            &ctx.s3,
+            &ctx.bucket,
+            &manifest_key,
+            manifest,
+            "application/json",
+        )
+        .await?;
+        if let Some(records) = &self.provenance {
+            put_object(
+                &ctx.s3,
+                &ctx.bucket,
+                &ctx.naming.provenance_key(key),
+                ProvenanceRecord::to_jsonl(records),
+                "application/x-ndjson",
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Copy the published object to the replicas and record it in the catalog.
+    async fn distribute(self, ctx: &RunContext) {
+        if ctx.metadata_only {
+            return;
+        }
+        let key = &self.manifest.key;
+        let mut copies = vec![
+            (key.clone(), self.storage_class),
+            (ctx.naming.manifest_key(key), StorageClass::Standard),
+        ];
+        if self.provenance.is_some() {
+            copies.push((ctx.naming.provenance_key(key), StorageClass::Standard));
+        }
+        replicate(ctx, &copies).await;
+
+        if let Some(catalog) = ctx.catalog.as_ref().filter(|_| self.deduplicate) {
+            let stored = self.manifest.stored_hashes();
+            if let Err(e) = catalog.record_object(key, stored, self.manifest.references()) {
+                eprintln!("  Warning: {e:#}");
+            }
+        }
+    }
+
+    /// Delete the object and what was published of it, after another object of
+    /// its task failed; a failed deletion only warns.
+    async fn delete(&self, ctx: &RunContext) {
+        let key = &self.manifest.key;
+        let keys = if ctx.metadata_only {
+            vec![Manifest::inventory_key_for(key)]
+        } else {
+            vec![
+                ctx.naming.provenance_key(key),
+                ctx.naming.manifest_key(key),
+                key.clone(),
+            ]
+        };
+        for key in keys {
+            if let Err(e) = delete_object(&ctx.s3, &ctx.bucket, &key).await {
+                eprintln!("  Warning: failed to delete s3://{}/{key}: {e}", ctx.bucket);
+            }
+        }
+    }
+}
+
+/// Complete the objects of a task (those of each output, split ones included),
+/// all or nothing: once every object is complete, upload their manifests, then
+/// copy them to the replicas and record them in the catalog. When an object
+/// fails, the others are aborted or deleted with their manifests, leaving the
+/// task to be processed again.
+async fn finish_all(ctx: &RunContext, outputs: Vec<Output>) -> crate::Result<()> {
+    let mut pending = outputs
+        .into_iter()
+        .flat_map(|mut output| {
+            let rolled = std::mem::take(&mut output.rolled);
+            rolled.into_iter().chain([output])
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let mut completed = Vec::new();
+    let failure = loop {
+        let Some(output) = pending.next() else {
+            break None;
+        };
+        match output.complete(ctx).await {
+            Ok(object) => completed.push(object),
+            Err(e) => break Some(e),
+        }
+    };
+    let failure = match failure {
+        Some(e) => Some(e),
+        None => {
+            let mut failure = None;
+            for object in &completed {
+                if let Err(e) = object.publish(ctx).await {
+                    failure = Some(e);
+                    break;
+                }
+            }
+            failure
+        }
+    };
+    if let Some(e) = failure {
+        for output in pending {
+            output.abort().await;
+        }
+        for object in &completed {
+            object.delete(ctx).await;
+        }
+        return Err(e);
+    }
+    for object in completed {
+        object.distribute(ctx).await;
+    }
+    Ok(())
+}
+
+/// URLs of the files making up a task.
+fn task_sources(ctx: &RunContext, task: &Task) -> Vec<String> {
+    ctx.source_templates[&task.cycle.model]
+        .iter()
+        .map(|template| template.render(task))
+        .collect()
+}
+
+/// Process a single GFS file: download, route messages, upload one object per route to S3.
+/// `attempt` is the 1-based attempt number, used to tell whether a failure is final.
+/// Returns the messages of each route for the daily objects (none without them).
+async fn process_file(
+    ctx: &RunContext,
+    task: Task,
+    attempt: u32,
+    progress: ProgressDisplay,
+) -> crate::Result<Vec<Vec<KeptMessage>>> {
+    let sources = task_sources(ctx, &task);
+    let url = &sources[0];
+    let mut outputs: Vec<Output> = ctx
+        .routes
+        .iter()
+        .map(|route| {
+            let key = object_key(&[&ctx.prefix, &route.prefix], &ctx.key_template, &task);
+            let mut output = Output::new(ctx, &task, route, url, &key);
+            output.manifest.companions = sources[1..].to_vec();
+            output.tee = ctx.daily.as_ref().map(|_| Vec::new());
+            output.provenance = ctx.provenance.then(Vec::new);
+            output.uploader.set_metadata("lead", task.lead());
+            output.manifest.lead = Some(task.lead());
+            output
+        })
+        .collect();
+
+    let destinations: Vec<_> = outputs
+        .iter()
+        .map(|output| format!("s3://{}/{}", ctx.bucket, output.manifest.key))
+        .collect();
+    let tag = progress.tag();
+    if destinations.is_empty() {
+        println!("{tag}Processing: {task}");
+    } else {
+        println!("{tag}Processing: {task} -> {}", destinations.join(", "));
+    }
+
+    // Messages kept by an earlier run replace the download; otherwise they are cached
+    let cache_path = ctx.cache.as_ref().map(|cache| cache.path(&task, &sources));
+    let cached = cache_path.as_ref().filter(|path| path.exists());
+    let mut cache = match (&ctx.cache, &cache_path) {
+        (Some(cache), Some(path)) if cached.is_none() => cache
+            .create(path)
+            .inspect_err(|e| eprintln!("  Warning: {e:#}"))
+            .ok(),
+        _ => None,
+    };
+    if let Some(path) = cached {
+        println!("  {task}: reading kept messages from {}", path.display());
+    }
+
+    // Stream the files of the task one after the other into the same objects
+    let mut downloaded = 0;
+    let mut expected = Some(0);
+    let mut total = 0;
+    let mut report = ctx.filter_report.as_ref().map(|_| FilterReport::default());
+    let inputs = if cached.is_some() {
+        &sources[..1]
+    } else {
+        &sources[..]
+    };
+    for source in inputs {
+        let result = match cached {
+            Some(path) => {
+                read_cache(
+                    ctx,
+                    &task,
+                    path,
+                    source,
+                    &mut outputs,
+                    progress,
+                    report.as_mut(),
+                )
+                .await
+            }
+            None => {
+                download(
+                    ctx,
+                    &task,
+                    source,
+                    ctx.storage.class_for(task.cycle.time),
+                    &mut outputs,
+                    progress,
+                    &mut downloaded,
+                    &mut expected,
+                    report.as_mut(),
+                    &mut cache,
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(messages) => total += messages,
+            Err(e) => {
+                let late = expected.is_some_and(|expected| {
+                    downloaded as f64 >= KEEP_PARTIAL_THRESHOLD * expected as f64
+                });
+                let salvage = ctx.keep_partial
+                    && downloaded > 0
+                    && late
+                    && !will_retry(ctx, &e, attempt)
+                    && !e.is_interrupted();
+                for output in outputs {
+                    if salvage {
+                        save_partial(ctx, output, &e, downloaded, expected).await;
+                    } else {
+                        // Abort upload on error
+                        output.abort().await;
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    if let Some(Err(e)) = cache.map(CacheWriter::commit) {
+        eprintln!("  Warning: {e:#}");
+    }
+
+    let teed = outputs
+        .iter_mut()
+        .map(|output| output.tee.take().unwrap_or_default())
+        .collect();
+
+    // Complete uploads
+    let single = outputs.len() == 1;
+    let summaries: Vec<_> = outputs
+        .iter()
+        .map(|output| {
+            let label = if single {
+                task.to_string()
+            } else {
+                format!("{task} -> {}", output.manifest.key)
+            };
+            let (kept, deduplicated) = (output.kept, output.deduplicated);
+            if deduplicated > 0 {
+                format!("{label}: {kept} messages extracted from {total} total, {deduplicated} already stored elsewhere")
+            } else {
+                format!("{label}: {kept} messages extracted from {total} total")
+            }
+        })
+        .collect();
+    finish_all(ctx, outputs).await?;
+    for summary in summaries {
+        println!("{}", ctx.stdout.status(Status::Completed, &summary));
+    }
+
+    // Only count files once, whatever the attempts it took
+    if let (Some(run), Some(report)) = (&ctx.filter_report, report) {
+        run.lock().unwrap().merge(report);
+    }
+
+    Ok(teed)
+}
+
+/// Request a source file and stream it into the outputs, adding its size to
+/// `expected` (`None` once a size is unknown). Returns the number of messages read.
+#[allow(clippy::too_many_arguments)]
+async fn download(
+    ctx: &RunContext,
+    task: &Task,
+    url: &str,
+    storage_class: StorageClass,
+    outputs: &mut [Output],
+    progress: ProgressDisplay,
+    downloaded: &mut u64,
+    expected: &mut Option<u64>,
+    report: Option<&mut FilterReport>,
+    cache: &mut Option<CacheWriter>,
+) -> crate::Result<u64> {
+    // The inventory gives offsets in the uncompressed file
+    if ctx.idx_ranges && Compression::from_url(url) == Compression::None {
+        if let Some(messages) =
+            download_ranges(ctx, task, url, outputs, downloaded, expected, cache).await?
+        {
+            return Ok(messages);
+        }
+    }
+
+    pace(ctx).await;
+    let response = ctx
+        .http
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| Error::SourceUnavailable {
+            url: url.to_string(),
+            status: None,
+            reason: e.to_string(),
+        })?;
+
+    if !response.status().is_success() {
+        return Err(Error::from_status(url, response.status()));
+    }
+
+    let total_size = response.content_length();
+    *expected = expected.zip(total_size).map(|(a, b)| a + b);
+
+    let compression = Compression::from_url(url);
+    // The inventory gives offsets in the uncompressed file
+    let mirror = ctx.mirror_raw.as_deref().map(|prefix| {
+        let key = mirror_key(prefix, url);
+        let mut uploader = S3MultipartUploader::new(
+            ctx.s3.clone(),
+            &ctx.bucket,
+            &key,
+            storage_class,
+            ctx.s3_compat,
+        )
+        .with_bandwidth_limit(ctx.upload_bandwidth.clone())
+        .with_tags(Some(ctx.tags.clone()));
+        uploader.set_metadata("source", url.to_string());
+        RawMirror::new(uploader, &key)
+    });
+    // A mirror needs the whole file
+    let stop_at = match total_size {
+        Some(size)
+            if ctx.idx_early_stop && mirror.is_none() && compression == Compression::None =>
+        {
+            idx_early_stop(ctx, url, size).await
+        }
+        _ => None,
+    };
+    let index = match &ctx.idx_cache {
+        Some(dir) if stop_at.is_none() && compression == Compression::None => {
+            let path = cached_idx_path(dir, url);
+            (!path.exists() && lacks_idx(ctx, url).await).then_some(path)
+        }
+        _ => None,
+    };
+
+    let received = Arc::new(AtomicU64::new(0));
+    // Reconnect where a broken connection stopped rather than failing the file
+    let warned_url = url.to_string();
+    let stream = ResumableDownload::new(ctx.http.clone(), url)
+        .with_pacer(ctx.pacer.clone())
+        .on_resume(move |offset, reason| {
+            eprintln!("  Warning: {warned_url}: {reason}, resuming at byte {offset}");
+        })
+        .stream(response);
+    let stream = match &mirror {
+        Some(mirror) => stream.inspect_ok(mirror.tap()).boxed(),
+        None => stream.boxed(),
+    };
+    let body = SourceBody {
+        stream: decode(stream, compression, received.clone()),
+        progress,
+        total_size,
+        received,
+        cached: false,
+        stop_at,
+        index,
+        mirror,
+    };
+    stream_messages(ctx, task, body, url, outputs, downloaded, report, cache).await
+}
+
+/// Fetch only the messages of a plain source file that the routes want, with a
+/// range request per run of adjacent messages located by the file's `.idx`
+/// inventory (`--idx-ranges`). Returns the number of messages of the file, or
+/// `None` when it has no usable inventory, for the file to be downloaded whole.
+async fn download_ranges(
+    ctx: &RunContext,
+    task: &Task,
+    url: &str,
+    outputs: &mut [Output],
+    downloaded: &mut u64,
+    expected: &mut Option<u64>,
+    cache: &mut Option<CacheWriter>,
+) -> crate::Result<Option<u64>> {
+    let entries = match fetch_idx(ctx, url).await {
+        Ok(entries) if !entries.is_empty() => entries,
+        Ok(_) => return Ok(None),
+        Err(e) => {
+            eprintln!("  Warning: no usable inventory for {url} ({e}), downloading it whole");
+            return Ok(None);
+        }
+    };
+    let wanted = |param| ctx.routes.iter().any(|route| route.params.contains(&param));
+    let ranges = wanted_ranges(&entries, wanted);
+    // The size of a file read in parts is only known as they arrive
+    *expected = None;
+
+    let filter = file_filter(ctx, task, false);
+    let mut fetched = 0;
+    let mut size = None;
+    for &(start, end) in &ranges {
+        if ctx.interrupted.load(Ordering::Relaxed) {
+            return Err(Error::Interrupted {
+                reason: format!("spot interruption notice while reading {url}"),
+            });
+        }
+        let (bytes, total) = fetch_source_range(ctx, url, start, end).await?;
+        size = size.or(total);
+        fetched += bytes.len() as u64;
+        *downloaded += bytes.len() as u64;
+        ctx.budget.record(bytes.len() as u64);
+
+        ctx.memory.record_parser(bytes.len());
+        let chunk_filter = filter.clone();
+        let filtered = tokio::task::spawn_blocking(move || {
+            chunk_filter.filter_chunk(Grib2StreamParser::new(), &bytes)
+        })
+        .await
+        .expect("GRIB2 filtering panicked");
+        // Messages were numbered and located from the start of the range
+        let first = entries
+            .iter()
+            .find(|entry| entry.offset == start)
+            .map_or(1, |entry| entry.message);
+        let locate = |origin: Origin| Origin {
+            index: origin.index + first - 1,
+            offset: origin.offset + start,
+            ..origin
+        };
+        let undecodable: Vec<_> = filtered
+            .undecodable
+            .into_iter()
+            .map(|(origin, reason)| (locate(origin), reason))
+            .collect();
+        warn_undecodable(url, &undecodable);
+        for mut msg in filtered.kept {
+            msg.origin = locate(msg.origin);
+            write_kept(ctx, url, &msg, outputs, cache).await?;
+        }
+        if let Some(e) = filtered.error {
+            return Err(e);
+        }
+        if filtered.parser.pending() > 0 {
+            return Err(Error::ParseError {
+                offset: start + filtered.parser.offset(),
+                reason: format!("{url}: the range from byte {start} ends within a message"),
+            });
+        }
+    }
+
+    println!(
+        "  {url}: {} of {} fetched in {} ranges (.idx)",
+        format_size(fetched, ctx.size_units),
+        size.map_or_else(|| "?".to_string(), |size| format_size(size, ctx.size_units)),
+        ranges.len()
+    );
+    let mut messages: Vec<u64> = entries.iter().map(|entry| entry.message).collect();
+    messages.sort_unstable();
+    messages.dedup();
+    Ok(Some(messages.len() as u64))
+}
+
+/// Bytes `[start, end)` of a source file (to its end if `end` is `None`), with
+/// the size of the whole file when the source tells it.
+async fn fetch_source_range(
+    ctx: &RunContext,
+    url: &str,
+    start: u64,
+    end: Option<u64>,
+) -> crate::Result<(Bytes, Option<u64>)> {
+    let unavailable = |e: reqwest::Error| Error::SourceUnavailable {
+        url: url.to_string(),
+        status: None,
+        reason: e.to_string(),
+    };
+    let range = match end {
+        Some(end) => format!("bytes={start}-{}", end - 1),
+        None => format!("bytes={start}-"),
+    };
+    pace(ctx).await;
+    let response = ctx
+        .http
+        .get(url)
+        .header(reqwest::header::RANGE, range)
+        .send()
+        .await
+        .map_err(unavailable)?;
+    if !response.status().is_success() {
+        return Err(Error::from_status(url, response.status()));
+    }
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(Error::SourceUnavailable {
+            url: url.to_string(),
+            status: Some(response.status().as_u16()),
+            reason: "range request answered with the whole file".to_string(),
+        });
+    }
+    let total = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range)
+        .and_then(|(_, total)| total);
+    let bytes = response.bytes().await.map_err(unavailable)?;
+    let wanted = end
+        .map(|end| end - start)
+        .or(total.map(|total| total - start));
+    if let Some(wanted) = wanted.filter(|&wanted| (bytes.len() as u64) < wanted) {
+        return Err(Error::Truncated {
+            url: url.to_string(),
+            received: bytes.len() as u64,
+            expected: Some(wanted),
+        });
+    }
+    Ok((bytes, total))
+}
+
+/// Stream the messages cached for a task into the outputs, as if they came from `url`.
+/// Returns the number of messages read.
+async fn read_cache(
+    ctx: &RunContext,
+    task: &Task,
+    path: &std::path::Path,
+    url: &str,
+    outputs: &mut [Output],
+    progress: ProgressDisplay,
+    report: Option<&mut FilterReport>,
+) -> crate::Result<u64> {
+    let unreadable = |e: std::io::Error| Error::SourceUnavailable {
+        url: path.display().to_string(),
+        status: None,
+        reason: e.to_string(),
+    };
+    let file = tokio::fs::File::open(path).await.map_err(unreadable)?;
+    let total_size = file.metadata().await.map_err(unreadable)?.len();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let body = SourceBody {
+        stream: decode(
+            tokio_util::io::ReaderStream::new(file),
+            Compression::None,
+            received.clone(),
+        ),
+        progress,
+        total_size: Some(total_size),
+        received,
+        cached: true,
+        stop_at: None,
+        index: None,
+        mirror: None,
+    };
+    stream_messages(ctx, task, body, url, outputs, &mut 0, report, &mut None).await
+}
+
+/// How the download of a file is reported while it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProgressDisplay {
+    /// One line rewritten in place, when files run one at a time.
+    Inline,
+    /// Whole lines tagged with the job running the file, when several files run at
+    /// once (`--jobs`): one per quarter of the file, or per `PROGRESS_LINE_BYTES`
+    /// when its size is unknown.
+    Lines { job: usize, jobs: usize },
+}
+
+impl ProgressDisplay {
+    /// Display of a file run by `job` (from 1), out of `jobs` running at once.
+    fn new(job: usize, jobs: usize) -> Self {
+        if jobs == 1 {
+            Self::Inline
+        } else {
+            Self::Lines { job, jobs }
+        }
+    }
+
+    /// Tag starting the lines of the file, e.g. `[job 2/4] `.
+    fn tag(self) -> String {
+        match self {
+            Self::Inline => String::new(),
+            Self::Lines { job, jobs } => format!("[job {job}/{jobs}] "),
+        }
+    }
+}
+
+/// Content of a source file, decompressed if needed.
+struct SourceBody {
+    stream: BoxStream<'static, std::io::Result<Bytes>>,
+    progress: ProgressDisplay,
+    /// Length of the file as sent (compressed).
+    total_size: Option<u64>,
+    /// Bytes received so far, before decompression.
+    received: Arc<AtomicU64>,
+    /// Read from `--cache-dir` rather than downloaded.
+    cached: bool,
+    /// Offset after which no wanted message remains (`--idx-early-stop`).
+    stop_at: Option<u64>,
+    /// Where to keep the inventory generated while parsing (`--idx-cache`).
+    index: Option<std::path::PathBuf>,
+    /// Copy of the file as received (`--mirror-raw`).
+    mirror: Option<RawMirror>,
+}
+
+/// Wait for the slot of the next request to the source (`--request-interval`).
+async fn pace(ctx: &RunContext) {
+    if let Some(pacer) = &ctx.pacer {
+        pacer.wait().await;
+    }
+}
+
+/// The `.idx` inventory of a source file, the one generated by an earlier run
+/// if the source has none (`--idx-cache`).
+async fn fetch_idx(ctx: &RunContext, url: &str) -> Result<Vec<IdxEntry>, String> {
+    if let Some(dir) = &ctx.idx_cache {
+        let path = cached_idx_path(dir, url);
+        if let Ok(text) = tokio::fs::read_to_string(&path).await {
+            return parse_idx(&text).map_err(|e| format!("{}: {e}", path.display()));
+        }
+    }
+    pace(ctx).await;
+    let response = ctx
+        .http
+        .get(idx_url(url))
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| e.to_string())?;
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    parse_idx(&text)
+}
+
+/// Whether the source answers that `url` has no `.idx` inventory; an unreachable
+/// one is not taken for missing.
+async fn lacks_idx(ctx: &RunContext, url: &str) -> bool {
+    pace(ctx).await;
+    ctx.http
+        .head(idx_url(url))
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .is_ok_and(|response| response.status().is_client_error())
+}
+
+/// Keep the inventory generated while parsing `url` at `path`, written aside and
+/// renamed so an interrupted write leaves none.
+async fn save_generated_idx(url: &str, path: &std::path::Path, lines: &[String]) {
+    let staged = path.with_extension("idx.tmp");
+    let text: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    let saved = async {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&staged, text).await?;
+        tokio::fs::rename(&staged, path).await
+    }
+    .await;
+    match saved {
+        Ok(()) => println!(
+            "  {url}: no .idx inventory, generated {} ({} lines)",
+            path.display(),
+            lines.len()
+        ),
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&staged).await;
+            eprintln!("  Warning: could not save the inventory of {url} ({e})");
+        }
+    }
+}
+
+/// Offset from which the download of `url` (`size` bytes) can stop according to
+/// its `.idx` inventory, if the wanted messages are all near its start. A missing
+/// or unreadable inventory only means the whole file is read.
+async fn idx_early_stop(ctx: &RunContext, url: &str, size: u64) -> Option<u64> {
+    let entries = match fetch_idx(ctx, url).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("  Warning: no usable inventory for {url} ({e}), reading the whole file");
+            return None;
+        }
+    };
+    let wanted = |param| ctx.routes.iter().any(|route| route.params.contains(&param));
+    early_stop(&entries, wanted, size)
+}
+
+/// Fetch message `index` of `url` (`size` bytes) again with a range request located
+/// by the `.idx` inventory (fetched into `inventory` the first time), after it failed
+/// to parse or validate. `end` is where the parser resumes, which must be the end of
+/// the message for the stream to carry on after it.
+async fn refetch_message(
+    ctx: &RunContext,
+    url: &str,
+    size: u64,
+    inventory: &mut Option<Vec<IdxEntry>>,
+    index: u64,
+    end: u64,
+) -> Result<(Vec<u8>, Origin), String> {
+    let entries = match inventory {
+        Some(entries) => entries,
+        None => inventory.insert(
+            fetch_idx(ctx, url)
+                .await
+                .map_err(|e| format!("no inventory: {e}"))?,
+        ),
+    };
+    let (start, range_end) = message_range(entries, index, size)
+        .ok_or_else(|| format!("message {index} is not in the inventory"))?;
+    if range_end != end {
+        return Err(format!(
+            "the inventory ends message {index} at byte {range_end}, the stream at {end}"
+        ));
+    }
+
+    pace(ctx).await;
+    let response = ctx
+        .http
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes={start}-{}", end - 1))
+        .timeout(Duration::from_secs(60))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(format!("range request answered with {}", response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    ctx.budget.record(bytes.len() as u64);
+
+    let mut parser = Grib2StreamParser::new();
+    let messages = parser.feed(&bytes).map_err(|e| e.to_string())?;
+    match <[Vec<u8>; 1]>::try_from(messages) {
+        Ok([message]) if message.len() == bytes.len() => Ok((
+            message,
+            Origin {
+                index,
+                offset: start,
+                length: end - start,
+            },
+        )),
+        _ => Err(format!("bytes {start}-{end} do not hold a single message")),
+    }
+}
+
+/// Write a kept message to the objects of its routes (and to the cache file).
+async fn write_kept(
+    ctx: &RunContext,
+    url: &str,
+    msg: &KeptMessage,
+    outputs: &mut [Output],
+    cache: &mut Option<CacheWriter>,
+) -> crate::Result<()> {
+    if let Some(Err(e)) = cache.as_mut().map(|cache| cache.write(&msg.bytes)) {
+        eprintln!("  Warning: {e:#}, not caching {url}");
+        *cache = None;
+    }
+    for &route in &msg.routes {
+        let output = &mut outputs[route];
+        let stored = output.write(ctx, msg).await?;
+        if let Some(provenance) = &mut output.provenance {
+            provenance.extend(
+                msg.products
+                    .iter()
+                    .map(|product| ProvenanceRecord::new(url, &msg.origin, product, &stored)),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Report the messages left out by `--on-parse-error warn`.
+fn warn_undecodable(url: &str, undecodable: &[(Origin, String)]) {
+    for (origin, reason) in undecodable {
+        eprintln!(
+            "  Warning: {url}: message {} at byte {} does not parse ({reason}), skipped",
+            origin.index, origin.offset
+        );
+    }
+}
+
+/// Filter of the messages of a source file of `task`: the routes, restricted to
+/// the task's valid time for files holding several forecast hours, with the run's
+/// sampling and transforms, unless the messages are read back from the cache,
+/// which holds them sampled and transformed already.
+fn file_filter(ctx: &RunContext, task: &Task, cached: bool) -> Filter {
+    let mut filter = Filter::new(ctx.routes.clone())
+        .on_parse_error(ctx.on_parse_error)
+        .hashing(ctx.catalog.is_some());
+    if task.cycle.model.packs_forecast_hours() {
+        filter = filter.valid_at(task.valid_time());
+    }
+    if cached {
+        return filter;
+    }
+    if let Some(every) = ctx.sample.and_then(|sample| sample.messages()) {
+        filter = filter.sampling(every, ctx.sampled.clone());
+    }
+    // Crop before re-packing what is left
+    if let Some(bbox) = ctx.bbox {
+        filter = filter.with_transform(Arc::new(bbox));
+    }
+    if let Some(quantize) = ctx.quantize {
+        filter = filter.with_transform(Arc::new(quantize));
+    }
+    filter
+}
+
+/// Stream the source file through the parser, writing routed messages to their objects
+/// (and to the `--cache-dir` file if any). Returns the number of messages read; every
+/// message is tallied in `report` if given.
+#[allow(clippy::too_many_arguments)]
+async fn stream_messages(
+    ctx: &RunContext,
+    task: &Task,
+    mut body: SourceBody,
+    url: &str,
+    outputs: &mut [Output],
+    downloaded: &mut u64,
+    mut report: Option<&mut FilterReport>,
+    cache: &mut Option<CacheWriter>,
+) -> crate::Result<u64> {
+    let total_size = body.total_size;
+    let mut stream = std::pin::pin!(rechunk(body.stream, ctx.chunk_size));
+    let mut parser = Grib2StreamParser::new();
+    let filter = file_filter(ctx, task, body.cached)
+        .refetching(true)
+        .tallying(report.is_some())
+        .indexing(body.index.is_some());
+
+    // Bytes of this file as sent; `downloaded` also counts earlier files of the task
+    let mut received: u64 = 0;
+    // Bytes handed to the parser, after decompression
+    let mut parsed: u64 = 0;
+    let mut kept_messages: u64 = 0;
+    let mut total_messages: u64 = 0;
+    let truncated = |received| Error::Truncated {
+        url: url.to_string(),
+        received,
+        expected: total_size,
+    };
+
+    // Quarters of the file, or steps of its unknown size, reported
+    // (`ProgressDisplay::Lines`)
+    let mut steps: u64 = 0;
+
+    // Process stream
+    let mut stopped_early = false;
+    // `.idx` inventory, fetched to re-fetch a corrupt message
+    let mut inventory = None;
+    // `.idx` lines generated for a source without inventory
+    let mut generated = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let now = body.received.load(Ordering::Relaxed);
+        if !body.cached {
+            *downloaded += now - received;
+            ctx.budget.record(now - received);
+        }
+        received = now;
+        if let Some(mirror) = &mut body.mirror {
+            mirror.flush().await?;
+        }
+
+        let Ok(chunk) = chunk else {
+            report_waiting(url, &parser);
+            return Err(truncated(received));
+        };
+        parsed += chunk.len() as u64;
+
+        // Parse and route GRIB2 messages from chunk, off the async runtime threads
+        ctx.memory.record_parser(parser.pending() + chunk.len());
+        let chunk_filter = filter.clone();
+        let mut filtered =
+            tokio::task::spawn_blocking(move || chunk_filter.filter_chunk(parser, &chunk))
+                .await
+                .expect("GRIB2 filtering panicked");
+        loop {
+            parser = filtered.parser;
+            total_messages += filtered.messages;
+            if let (Some(report), Some(chunk_report)) = (report.as_deref_mut(), filtered.report) {
+                report.merge(chunk_report);
+            }
+            generated.extend(filtered.inventory);
+            warn_undecodable(url, &filtered.undecodable);
+            for msg in &filtered.kept {
+                kept_messages += 1;
+                write_kept(ctx, url, msg, outputs, cache).await?;
+            }
+            let Some(e) = filtered.error else {
+                break;
+            };
+
+            // Fetch a corrupt message again rather than the whole file
+            let index = parser.messages();
+            let refetched = match total_size {
+                Some(size) if !body.cached && Compression::from_url(url) == Compression::None => {
+                    refetch_message(ctx, url, size, &mut inventory, index, parser.offset()).await
+                }
+                _ => Err("not a plain downloaded file".to_string()),
+            };
+            total_messages += 1;
+            match (refetched, filtered.unframed) {
+                (Ok((message, origin)), _) => {
+                    println!(
+                        "  {url}: message {index} failed ({e}), re-fetched bytes {}-{}",
+                        origin.offset,
+                        origin.offset + origin.length
+                    );
+                    let mut undecodable = Vec::new();
+                    let kept = filter.filter_message(
+                        message,
+                        origin,
+                        report.as_deref_mut(),
+                        &mut undecodable,
+                    )?;
+                    warn_undecodable(url, &undecodable);
+                    if let Some(msg) = kept {
+                        kept_messages += 1;
+                        write_kept(ctx, url, &msg, outputs, cache).await?;
+                    }
+                }
+                // Missing its end marker: left out alone, as a message that doesn't parse
+                (Err(_), Some(origin)) if ctx.on_parse_error != OnParseError::Fail => {
+                    if ctx.on_parse_error == OnParseError::Warn {
+                        warn_undecodable(url, &[(origin, e.to_string())]);
+                    }
+                }
+                (Err(reason), _) => {
+                    eprintln!("  Could not re-fetch message {index} of {url} alone: {reason}");
+                    return Err(e);
+                }
+            }
+
+            // Carry on with the messages buffered after it
+            let chunk_filter = filter.clone();
+            filtered = tokio::task::spawn_blocking(move || chunk_filter.filter_chunk(parser, &[]))
+                .await
+                .expect("GRIB2 filtering panicked");
+        }
+        // The objects are aborted, leaving the file to `resume`
+        if ctx.interrupted.load(Ordering::Relaxed) {
+            return Err(Error::Interrupted {
+                reason: format!("spot interruption notice while reading {url}"),
+            });
+        }
+        if body.stop_at.is_some_and(|end| parser.offset() >= end) {
+            stopped_early = true;
+            break;
+        }
+
+        // Progress indicator
+        if body.progress != ProgressDisplay::Inline {
+            // Whole lines, so that files running together don't mix theirs
+            let name = url.rsplit('/').next().unwrap_or(url);
+            let tag = body.progress.tag();
+            match total_size {
+                Some(total) => {
+                    let quarter = received * 4 / total.max(1);
+                    if quarter > steps && quarter < 4 {
+                        steps = quarter;
+                        println!(
+                            "  {tag}{name}: {}% of {} | Messages: {total_messages} total, {kept_messages} kept",
+                            quarter * 25,
+                            format_size(total, ctx.size_units)
+                        );
+                    }
+                }
+                None => {
+                    let step = received / PROGRESS_LINE_BYTES;
+                    if step > steps {
+                        steps = step;
+                        println!(
+                            "  {tag}{name}: {} | Messages: {total_messages} total, {kept_messages} kept",
+                            format_size(received, ctx.size_units)
+                        );
+                    }
+                }
+            }
+            continue;
+        }
+        if let Some(total) = total_size {
+            let pct = (received as f64 / total as f64) * 100.0;
+            print!(
+                "\r  Downloaded: {pct:.1}% | Messages: {total_messages} total, {kept_messages} kept"
+            );
+        } else {
+            print!(
+                "\r  Downloaded: {} | Messages: {total_messages} total, {kept_messages} kept",
+                format_size(received, ctx.size_units)
+            );
+        }
+    }
+
+    if body.progress == ProgressDisplay::Inline {
+        println!();
+    }
+    if stopped_early {
+        println!(
+            "  {url}: wanted messages all read after {} of {} (.idx), stopping",
+            format_size(received, ctx.size_units),
+            format_size(total_size.unwrap_or(received), ctx.size_units)
+        );
+        return Ok(total_messages);
+    }
+
+    // A connection closed early can end the stream without an error
+    let pending = parser.pending() as u64;
+    let cut_short = total_size.is_some_and(|total| received < total);
+    if cut_short || pending > 0 {
+        report_waiting(url, &parser);
+    }
+    match total_size {
+        Some(_) if cut_short => return Err(truncated(received)),
+        // The whole file arrived, so the source file itself is cut short
+        Some(_) if pending > 0 => {
+            return Err(Error::ParseError {
+                offset: parsed - pending,
+                reason: format!("incomplete message ({pending} bytes) at end of file"),
+            })
+        }
+        None if pending > 0 => return Err(truncated(received)),
+        _ => {}
+    }
+
+    let mirrored = match body.mirror.take() {
+        Some(mirror) => {
+            let key = mirror.key().to_string();
+            let size = mirror.complete().await?;
+            println!(
+                "  {url}: mirrored to s3://{}/{key} ({})",
+                ctx.bucket,
+                format_size(size, ctx.size_units)
+            );
+            Some(key)
+        }
+        None => None,
+    };
+    if let Some(path) = &body.index {
+        save_generated_idx(url, path, &generated).await;
+        if let Some(key) = mirrored {
+            let text: String = generated.iter().map(|line| format!("{line}\n")).collect();
+            put_object(
+                &ctx.s3,
+                &ctx.bucket,
+                &format!("{key}{IDX_SUFFIX}"),
+                text.into_bytes(),
+                "text/plain",
+            )
+            .await?;
+        }
+    }
+    Ok(total_messages)
+}
+
+/// Tell which message a stream ended in, e.g. "waiting for 12.3 MB of message 47".
+fn report_waiting(url: &str, parser: &Grib2StreamParser) {
+    if let Some(waiting) = parser.waiting() {
+        eprintln!("  {url} ended while {waiting}");
+    }
+}
+
+/// Keep the messages uploaded before a late failure as `<key>.partial`,
+/// with metadata recording why and how much of the source was read.
+async fn save_partial(
+    ctx: &RunContext,
+    mut output: Output,
+    error: &Error,
+    downloaded: u64,
+    total_size: Option<u64>,
+) {
+    // The objects full at --max-object-size are kept as partial ones as well
+    for part in std::mem::take(&mut output.rolled) {
+        Box::pin(save_partial(ctx, part, error, downloaded, total_size)).await;
+    }
+    let Output {
+        mut uploader,
+        mut manifest,
+        ..
+    } = output;
+    set_time_metadata(&mut uploader, &manifest);
+    let partial_key = ctx.naming.partial_key(&manifest.key);
+    // Metadata travels as HTTP headers: printable ASCII only
+    let reason: String = error
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { ' ' })
+        .collect();
+    let metadata = HashMap::from([
+        ("partial".to_string(), "true".to_string()),
+        ("partial-reason".to_string(), reason),
+        ("received-bytes".to_string(), downloaded.to_string()),
+        (
+            "expected-bytes".to_string(),
+            total_size.map_or("unknown".to_string(), |t| t.to_string()),
+        ),
+    ]);
+    manifest.key = partial_key.clone();
+
+    let result = async {
+        uploader.complete_as_partial(&partial_key, metadata).await?;
+        put_object(
+            &ctx.s3,
+            &ctx.bucket,
+            &ctx.naming.manifest_key(&partial_key),
+            serde_json::to_vec_pretty(&manifest).expect("manifest serializes to JSON"),
+            "application/json",
+        )
+        .await
+    }
+    .await;
+
+    match result {
+        Ok(()) => println!(
+            "  Kept {} messages as s3://{}/{partial_key} (--keep-partial)",
+            manifest.messages.len(),
+            ctx.bucket
+        ),
+        Err(e) => eprintln!("  Failed to keep partial output as {partial_key}: {e}"),
+    }
+}
+
+/// Whether the object a catalog location points to still holds the message,
+/// checked once per object and run: compacting with `--delete-sources` deletes
+/// the objects no manifest points into, which the catalog may still list.
+async fn catalog_target_holds(ctx: &RunContext, location: &Location) -> crate::Result<bool> {
+    let known = ctx
+        .catalog_targets
+        .lock()
+        .unwrap()
+        .get(&location.key)
+        .copied();
+    let size = match known {
+        Some(size) => size,
+        None => {
+            let size = head_size(&ctx.s3, &ctx.bucket, &location.key).await?;
+            ctx.catalog_targets
+                .lock()
+                .unwrap()
+                .insert(location.key.clone(), size);
+            size
+        }
+    };
+    Ok(size.is_some_and(|size| location.offset + location.length <= size))
+}
+
+/// Attach the earliest reference time and the span of valid times of the
+/// messages of the object to it.
+fn set_time_metadata(uploader: &mut S3MultipartUploader, manifest: &Manifest) {
+    let iso = |t: chrono::NaiveDateTime| t.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let messages = &manifest.messages;
+    if let Some(reference) = messages.iter().filter_map(|m| m.reference_time).min() {
+        uploader.set_metadata("reference-time", iso(reference));
+    }
+    let valid = || messages.iter().filter_map(|m| m.valid_time);
+    if let (Some(min), Some(max)) = (valid().min(), valid().max()) {
+        uploader.set_metadata("valid-time-min", iso(min));
+        uploader.set_metadata("valid-time-max", iso(max));
+    }
+}
+
+/// Process one task, retrying while the source throttles us.
+async fn process_with_retries(ctx: &RunContext, task: Task) -> Result<Vec<Vec<KeptMessage>>> {
+    let mut attempt = 1;
+    loop {
+        let permit = ctx.limiter.acquire().await;
+        if ctx.budget.exhausted() {
+            return Err(BudgetExhausted.into());
+        }
+        if !ctx.memory.try_start(heap_usage().live) {
+            ctx.memory.record_held_back();
+            while !ctx.memory.try_start(heap_usage().live) {
+                tokio::time::sleep(MEMORY_POLL_INTERVAL).await;
+            }
+        }
+        let progress = ProgressDisplay::new(permit.job(), ctx.limiter.jobs());
+        let result = profiled_process_file(ctx, task, attempt, progress).await;
+        ctx.memory.finished();
+        drop(permit);
+
+        match result {
+            Err(e @ Error::Throttled { .. }) if will_retry(ctx, &e, attempt) => {
+                ctx.limiter.on_throttled();
+                eprintln!(
+                    "  Throttled on {task} ({e}), concurrency now {}, retrying",
+                    ctx.limiter.limit()
+                );
+                log_retry(ctx, task, attempt, &e, ctx.limiter.cooldown());
+                tokio::time::sleep(ctx.limiter.cooldown()).await;
+                attempt += 1;
+            }
+            Err(e) if will_retry(ctx, &e, attempt) => {
+                let delay = TRANSIENT_RETRY_DELAY * attempt;
+                eprintln!(
+                    "  {task} failed ({e}), retrying in {}",
+                    format_duration(delay)
+                );
+                log_retry(ctx, task, attempt, &e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result.map_err(Into::into),
+        }
+    }
+}
+
+/// Process a file, writing its CPU profile to `--profile-dir` if set.
+async fn profiled_process_file(
+    ctx: &RunContext,
+    task: Task,
+    attempt: u32,
+    progress: ProgressDisplay,
+) -> crate::Result<Vec<Vec<KeptMessage>>> {
+    #[cfg(feature = "profiling")]
+    if let Some(dir) = &ctx.profile_dir {
+        let profile = FileProfile::start();
+        let result = process_file(ctx, task, attempt, progress).await;
+        let path = dir.join(profile_name(&task));
+        match profile.and_then(|profile| profile.write_flamegraph(&path)) {
+            Ok(()) => println!("  {task}: CPU profile written to {}", path.display()),
+            Err(e) => eprintln!("  Warning: no CPU profile for {task}: {e}"),
+        }
+        return result;
+    }
+    process_file(ctx, task, attempt, progress).await
+}
+
+/// Record a retry in the `--retry-log`, if any; failing to write it only warns.
+fn log_retry(ctx: &RunContext, task: Task, attempt: u32, e: &Error, backoff: Duration) {
+    let Some(log) = &ctx.retry_log else {
+        return;
+    };
+    let record = RetryRecord::new(task.to_string(), attempt, e, backoff, ctx.limiter.limit());
+    if let Err(e) = log.record(&record) {
+        eprintln!("  Warning: {e:#}");
+    }
+}
+
+/// Whether `process_with_retries` tries a file again after failing with `e`.
+fn will_retry(ctx: &RunContext, e: &Error, attempt: u32) -> bool {
+    match e {
+        Error::Throttled { .. } => attempt < MAX_THROTTLE_ATTEMPTS,
+        e => attempt < ctx.max_attempts && e.is_transient(),
+    }
+}
+
+/// Produce one target, then add it to its day's objects.
+async fn process_target(ctx: &RunContext, target: &Target) -> Result<()> {
+    if ctx.stale_only && up_to_date(ctx, target.primary()).await? {
+        let summary = format!("{}: up to date", target.primary());
+        println!("{}", ctx.stdout.status(Status::Skipped, &summary));
+        ctx.up_to_date.fetch_add(1, Ordering::Relaxed);
+        return Ok(());
+    }
+    if ctx.skip_existing && uploaded(ctx, target.primary()).await? {
+        let summary = format!("{}: already uploaded", target.primary());
+        println!("{}", ctx.stdout.status(Status::Skipped, &summary));
+        ctx.up_to_date.fetch_add(1, Ordering::Relaxed);
+        return Ok(());
+    }
+    if ctx.augment && augment_task(ctx, target.primary()).await? {
+        return Ok(());
+    }
+    let result = produce_target(ctx, target).await;
+    let Some(daily) = &ctx.daily else {
+        return result.map(drop);
+    };
+    match result {
+        Ok(Some((task, teed))) => daily.add(ctx, target, &task, teed).await?,
+        // A file missing from the source leaves a gap, as in the per-cycle objects
+        Ok(None) => daily.add(ctx, target, target.primary(), Vec::new()).await?,
+        // Unless it may still be published: the day is written once it is there
+        Err(e) if is_not_found(&e) => {
+            if target_outage(ctx, target, &e) == Some(Outage::NotYetPublished) {
+                daily.fail(ctx, target).await;
+            } else {
+                daily.add(ctx, target, target.primary(), Vec::new()).await?;
+            }
+            return Err(e);
+        }
+        Err(e) => {
+            daily.fail(ctx, target).await;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// `filter-hash` of the objects of a route: its parameters, the crop and the
+/// packing of the messages (`--bbox`, `--quantize`) and their sampling (`--sample 1/N`).
+fn route_hash(ctx: &RunContext, route: &Route) -> String {
+    let mut transforms = Vec::new();
+    if let Some(bbox) = ctx.bbox {
+        transforms.push(format!("bbox={bbox}"));
+    }
+    if let Some(quantize) = ctx.quantize {
+        transforms.push(format!("quantize={quantize}"));
+    }
+    if let Some(every) = ctx.sample.and_then(|sample| sample.messages()) {
+        transforms.push(format!("sample=1/{every}"));
+    }
+    route.transformed_hash(&transforms)
+}
+
+/// Whether every object of a task exists and was produced with the current
+/// parameters of its route, crop and packing (`--stale-only`).
+async fn up_to_date(ctx: &RunContext, task: &Task) -> crate::Result<bool> {
+    for route in ctx.routes.iter() {
+        let key = object_key(&[&ctx.prefix, &route.prefix], &ctx.key_template, task);
+        let Some(metadata) = head_metadata(&ctx.s3, &ctx.bucket, &key).await? else {
+            return Ok(false);
+        };
+        let hash = route_hash(ctx, route);
+        if metadata.get(FILTER_HASH_METADATA) != Some(&hash) {
+            let previous = metadata
+                .get(FILTER_HASH_METADATA)
+                .map_or("none", String::as_str);
+            println!("  {key} was produced with another filter ({previous}, now {hash})");
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether every object of a task was uploaded whole (`--skip-existing`): it has
+/// a manifest, written once the object completed, and its messages end where the
+/// object does. Any other object is reported and processed again.
+async fn uploaded(ctx: &RunContext, task: &Task) -> crate::Result<bool> {
+    for route in ctx.routes.iter() {
+        let key = object_key(&[&ctx.prefix, &route.prefix], &ctx.key_template, task);
+        let Some(size) = head_size(&ctx.s3, &ctx.bucket, &key).await? else {
+            return Ok(false);
+        };
+        let manifest_key = ctx.naming.manifest_key(&key);
+        if head_size(&ctx.s3, &ctx.bucket, &manifest_key)
+            .await?
+            .is_none()
+        {
+            println!("  {key} exists but has no manifest, processing it again");
+            return Ok(false);
+        }
+        let body = get_object(&ctx.s3, &ctx.bucket, &manifest_key).await?;
+        let manifest: Manifest = match serde_json::from_slice(&body) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                println!("  {key} has an invalid manifest ({e}), processing it again");
+                return Ok(false);
+            }
+        };
+        let problems = verify::check_layout(&verify::stored_messages(&manifest), size);
+        if let Some(problem) = problems.first() {
+            println!("  {key} does not match its manifest ({problem}), processing it again");
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Append the messages of the parameters added to the routes since the objects of
+/// a task were archived (`--augment`), fetched by range as located by the `.idx`
+/// inventories of its files. Returns `false` when an object is missing, for the
+/// task to be processed as usual.
+async fn augment_task(ctx: &RunContext, task: &Task) -> Result<bool> {
+    let mut archived = Vec::new();
+    for route in ctx.routes.iter() {
+        let key = object_key(&[&ctx.prefix, &route.prefix], &ctx.key_template, task);
+        let Some(metadata) = head_metadata(&ctx.s3, &ctx.bucket, &key).await? else {
+            return Ok(false);
+        };
+        let manifest_key = ctx.naming.manifest_key(&key);
+        if head_size(&ctx.s3, &ctx.bucket, &manifest_key)
+            .await?
+            .is_none()
+        {
+            anyhow::bail!("{key} has no manifest to augment, reprocess it with --stale-only");
+        }
+        let manifest: Manifest =
+            serde_json::from_slice(&get_object(&ctx.s3, &ctx.bucket, &manifest_key).await?)
+                .with_context(|| format!("invalid manifest {manifest_key}"))?;
+        let missing = missing_params(route, &manifest);
+        archived.push((key, metadata, manifest, missing));
+    }
+    let wanted: Vec<Param> = archived
+        .iter()
+        .flat_map(|(.., missing)| missing.iter().copied())
+        .collect();
+    if wanted.is_empty() {
+        let summary = format!("{task}: up to date");
+        println!("{}", ctx.stdout.status(Status::Skipped, &summary));
+        ctx.up_to_date.fetch_add(1, Ordering::Relaxed);
+        return Ok(true);
+    }
+
+    let mut filter = Filter::new(ctx.routes.clone()).hashing(ctx.catalog.is_some());
+    if let Some(bbox) = ctx.bbox {
+        filter = filter.with_transform(Arc::new(bbox));
+    }
+    if let Some(quantize) = ctx.quantize {
+        filter = filter.with_transform(Arc::new(quantize));
+    }
+    let mut kept = Vec::new();
+    for url in task_sources(ctx, task) {
+        let entries = fetch_idx(ctx, &url)
+            .await
+            .map_err(|e| anyhow::anyhow!("no usable inventory for {url}: {e}"))?;
+        for (start, end) in wanted_ranges(&entries, |param| wanted.contains(&param)) {
+            let bytes = fetch_range(ctx, &url, start, end).await?;
+            let filtered = filter.filter_chunk(Grib2StreamParser::new(), &bytes);
+            if let Some(e) = filtered.error {
+                return Err(e.into());
+            }
+            if filtered.parser.pending() > 0 {
+                anyhow::bail!(
+                    "{url}: bytes {start}-{} end within a message",
+                    start + bytes.len() as u64
+                );
+            }
+            kept.extend(filtered.kept);
+        }
+    }
+
+    for (index, (key, metadata, mut manifest, missing)) in archived.into_iter().enumerate() {
+        let route = &ctx.routes[index];
+        let added: Vec<&KeptMessage> = kept
+            .iter()
+            .filter(|msg| msg.routes.contains(&index))
+            .filter(|msg| msg.products.iter().any(|p| missing.contains(&Param::of(p))))
+            .collect();
+        let names: Vec<_> = missing.iter().map(Param::to_string).collect();
+        if added.is_empty() {
+            if !missing.is_empty() {
+                println!("  {key}: no {} message in the source", names.join(", "));
+            }
+            continue;
+        }
+
+        let mut object = get_object(&ctx.s3, &ctx.bucket, &key).await?;
+        let size = object.len() as u64;
+        append(&mut object, &mut manifest, &added);
+        let mut uploader = S3MultipartUploader::new(
+            ctx.s3.clone(),
+            &ctx.bucket,
+            &key,
+            ctx.storage.class_for(task.cycle.time),
+            ctx.s3_compat,
+        )
+        .with_bandwidth_limit(ctx.upload_bandwidth.clone())
+        .with_tags(Some(ctx.tags.clone()));
+        for (name, value) in metadata {
+            uploader.set_metadata(&name, value);
+        }
+        // Objects still holding parameters dropped from the route stay stale
+        if only_route_params(route, &manifest) {
+            uploader.set_metadata(FILTER_HASH_METADATA, route_hash(ctx, route));
+            manifest.filter_hash = Some(route_hash(ctx, route));
+        }
+        if let Err(e) = uploader.write(&object).await {
+            let _ = uploader.abort().await;
+            return Err(e.into());
+        }
+        uploader.complete().await?;
+        put_object(
+            &ctx.s3,
+            &ctx.bucket,
+            &ctx.naming.manifest_key(&key),
+            serde_json::to_vec_pretty(&manifest).expect("manifest serializes to JSON"),
+            "application/json",
+        )
+        .await?;
+        let summary = format!(
+            "{key}: {} {} messages added ({} -> {})",
+            added.len(),
+            names.join(", "),
+            format_size(size, ctx.size_units),
+            format_size(object.len() as u64, ctx.size_units)
+        );
+        println!("{}", ctx.stdout.status(Status::Completed, &summary));
+    }
+    Ok(true)
+}
+
+/// Bytes `[start, end)` of a source file (to its end if `end` is `None`), with a
+/// range request.
+async fn fetch_range(ctx: &RunContext, url: &str, start: u64, end: Option<u64>) -> Result<Vec<u8>> {
+    let range = match end {
+        Some(end) => format!("bytes={start}-{}", end - 1),
+        None => format!("bytes={start}-"),
+    };
+    pace(ctx).await;
+    let response = ctx
+        .http
+        .get(url)
+        .header(reqwest::header::RANGE, range)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("range request to {url}"))?;
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        anyhow::bail!("{url}: range request answered with {}", response.status());
+    }
+    let bytes = response.bytes().await?;
+    ctx.budget.record(bytes.len() as u64);
+    Ok(bytes.to_vec())
+}
+
+/// Produce one target, falling back to the next candidate when a file is missing.
+/// Returns the task produced and its messages for the daily objects.
+async fn produce_target(
+    ctx: &RunContext,
+    target: &Target,
+) -> Result<Option<(Task, Vec<Vec<KeptMessage>>)>> {
+    let mut candidates = target.candidates.iter().peekable();
+    while let Some(&task) = candidates.next() {
+        match process_with_retries(ctx, task).await {
+            Err(e) if is_not_found(&e) => {
+                if let Some(next) = candidates.peek() {
+                    println!("  {task} unavailable, falling back to {next}");
+                } else {
+                    return Err(e);
+                }
+            }
+            result => return result.map(|teed| Some((task, teed))),
+        }
+    }
+    Ok(None)
+}
+
+/// `latest.json` pointers (`--latest-pointer`), moved forward as cycles complete.
+struct LatestPointers {
+    /// Whether each model gets its own pointer.
+    per_model: bool,
+    /// Targets of each cycle not produced yet, and the primary tasks of all of them.
+    cycles: Mutex<HashMap<Cycle, (usize, Vec<Task>)>>,
+    /// Cycle each pointer designates, read from the bucket on first use; the
+    /// lock also keeps the updates in order.
+    published: tokio::sync::Mutex<HashMap<String, Option<NaiveDateTime>>>,
+}
+
+impl LatestPointers {
+    fn new(targets: &[Target], per_model: bool) -> Self {
+        let mut cycles: HashMap<Cycle, (usize, Vec<Task>)> = HashMap::new();
+        for target in targets {
+            let (remaining, tasks) = cycles.entry(target.primary().cycle).or_default();
+            *remaining += 1;
+            tasks.push(*target.primary());
+        }
+        Self {
+            per_model,
+            cycles: Mutex::new(cycles),
+            published: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a produced target; once every target of its cycle is, point to the
+    /// cycle unless the pointer already designates a newer one. Failures only warn.
+    async fn produced(&self, ctx: &RunContext, target: &Target) {
+        let cycle = target.primary().cycle;
+        let mut tasks = {
+            let mut cycles = self.cycles.lock().unwrap();
+            let Some((remaining, tasks)) = cycles.get_mut(&cycle) else {
+                return;
+            };
+            *remaining -= 1;
+            if *remaining > 0 {
+                return;
+            }
+            std::mem::take(tasks)
+        };
+        tasks.sort_by_key(Task::valid_time);
+
+        let model = cycle.model.to_string();
+        let key = ctx
+            .naming
+            .latest_key(&ctx.prefix, self.per_model.then_some(model.as_str()));
+        let mut published = self.published.lock().await;
+        if !published.contains_key(&key) {
+            match read_latest(ctx, &key).await {
+                Ok(current) => {
+                    published.insert(key.clone(), current.map(|pointer| pointer.cycle));
+                }
+                Err(e) => {
+                    eprintln!("  Warning: failed to read s3://{}/{key}: {e:#}", ctx.bucket);
+                    return;
+                }
+            }
+        }
+        if published[&key].is_some_and(|newest| newest > cycle.time) {
+            return;
+        }
+
+        let pointer = LatestPointer {
+            model,
+            cycle: cycle.time,
+            objects: tasks
+                .iter()
+                .flat_map(|task| {
+                    ctx.routes.iter().map(|route| LatestObject {
+                        key: object_key(&[&ctx.prefix, &route.prefix], &ctx.key_template, task),
+                        valid_time: task.valid_time(),
+                    })
+                })
+                .collect(),
+            updated: if ctx.deterministic {
+                DateTime::UNIX_EPOCH
+            } else {
+                Utc::now()
+            },
+        };
+        let body = serde_json::to_vec_pretty(&pointer).expect("latest pointer serializes");
+        match put_object(&ctx.s3, &ctx.bucket, &key, body, "application/json").await {
+            Ok(()) => {
+                println!("  s3://{}/{key} now points to {cycle}", ctx.bucket);
+                published.insert(key, Some(cycle.time));
+            }
+            Err(e) => eprintln!("  Warning: failed to update s3://{}/{key}: {e}", ctx.bucket),
+        }
+    }
+}
+
+/// The pointer at `key`, if there is one.
+async fn read_latest(ctx: &RunContext, key: &str) -> Result<Option<LatestPointer>> {
+    if head_size(&ctx.s3, &ctx.bucket, key).await?.is_none() {
+        return Ok(None);
+    }
+    let body = get_object(&ctx.s3, &ctx.bucket, key).await?;
+    let pointer = serde_json::from_slice(&body).context("invalid latest pointer")?;
+    Ok(Some(pointer))
+}
+
+/// Objects of `--daily-key-template`: the messages of every file of a day,
+/// appended to one object per route as the files complete.
+struct DailyObjects {
+    template: Template,
+    days: HashMap<String, tokio::sync::Mutex<Day>>,
+}
+
+/// Daily objects of one day being written.
+#[derive(Default)]
+struct Day {
+    /// Targets of the day not processed yet.
+    remaining: usize,
+    /// Whether a file of the day failed, so its objects are not written.
+    failed: bool,
+    /// One object per route, started by the first completed file.
+    outputs: Vec<Output>,
+    /// Files appended so far.
+    files: usize,
+}
+
+impl DailyObjects {
+    fn new(template: Template, targets: &[Target]) -> Self {
+        let mut days: HashMap<String, tokio::sync::Mutex<Day>> = HashMap::new();
+        for target in targets {
+            let day = template.render(target.primary());
+            days.entry(day).or_default().get_mut().remaining += 1;
+        }
+        Self { template, days }
+    }
+
+    /// Append the messages of a produced target (`task`) to its day's objects,
+    /// and complete them if it was the day's last target.
+    async fn add(
+        &self,
+        ctx: &RunContext,
+        target: &Target,
+        task: &Task,
+        teed: Vec<Vec<KeptMessage>>,
+    ) -> crate::Result<()> {
+        let name = self.template.render(target.primary());
+        let mut day = self.days[&name].lock().await;
+        day.remaining -= 1;
+
+        if !day.failed && !teed.is_empty() {
+            let sources = task_sources(ctx, task);
+            if day.outputs.is_empty() {
+                day.outputs = ctx
+                    .routes
+                    .iter()
+                    .map(|route| {
+                        let prefixes = [ctx.prefix.as_str(), route.prefix.as_str()];
+                        let key = object_key(&prefixes, &self.template, target.primary());
+                        let mut output = Output::new(ctx, task, route, &sources[0], &key);
+                        output.manifest.companions = sources[1..].to_vec();
+                        output.deduplicate = false;
+                        output
+                    })
+                    .collect();
+            } else {
+                for output in &mut day.outputs {
+                    output.manifest.companions.extend(sources.iter().cloned());
+                }
+            }
+            day.files += 1;
+
+            for (output, messages) in day.outputs.iter_mut().zip(&teed) {
+                for msg in messages {
+                    if let Err(e) = output.write(ctx, msg).await {
+                        day.failed = true;
+                        abort_day(&mut day).await;
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        if day.remaining == 0 && !day.failed {
+            let files = day.files;
+            let outputs = std::mem::take(&mut day.outputs);
+            let summaries: Vec<_> = outputs
+                .iter()
+                .map(|output| {
+                    let (key, kept) = (&output.manifest.key, output.kept);
+                    format!("daily object {key}: {kept} messages from {files} files")
+                })
+                .collect();
+            finish_all(ctx, outputs).await?;
+            for summary in summaries {
+                println!("{}", ctx.stdout.status(Status::Completed, &summary));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a target that failed: its day's objects are not written.
+    async fn fail(&self, ctx: &RunContext, target: &Target) {
+        let name = self.template.render(target.primary());
+        let mut day = self.days[&name].lock().await;
+        day.remaining -= 1;
+        if !day.failed {
+            day.failed = true;
+            abort_day(&mut day).await;
+            let key = object_key(&[&ctx.prefix], &self.template, target.primary());
+            eprintln!(
+                "  Daily objects of {key} not written: {} failed",
+                target.primary()
+            );
+        }
+    }
+
+    /// Abort the objects of the days the run did not finish; returns their number.
+    async fn abandon(self) -> usize {
+        let mut unfinished = 0;
+        for day in self.days.into_values() {
+            let mut day = day.into_inner();
+            if day.remaining > 0 && !day.failed {
+                unfinished += 1;
+                abort_day(&mut day).await;
+            }
+        }
+        unfinished
+    }
+}
+
+/// Abort the uploads of a day's objects.
+async fn abort_day(day: &mut Day) {
+    for output in std::mem::take(&mut day.outputs) {
+        output.abort().await;
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Error>().is_some_and(Error::is_not_found)
+}
+
+/// Source outage behind the failure of a target, if any.
+fn target_outage(ctx: &RunContext, target: &Target, e: &anyhow::Error) -> Option<Outage> {
+    let age = chrono::Utc::now().naive_utc() - target.primary().cycle.time;
+    e.downcast_ref::<Error>()?
+        .outage(age, ctx.publication_delay)
+}
+
+/// Poll the spot interruption notice until one is posted, then flag the run as
+/// interrupted: no file starts anymore and those in progress stop after their current chunk.
+async fn watch_spot(ctx: &RunContext) {
+    let watcher = SpotWatcher::new(ctx.http.clone());
+    let mut interval = tokio::time::interval(spot::POLL_INTERVAL);
+    let mut warned = false;
+    loop {
+        interval.tick().await;
+        match watcher.notice().await {
+            Ok(None) => {}
+            Ok(Some(notice)) => {
+                eprintln!("Spot interruption notice ({notice}): stopping");
+                ctx.interrupted.store(true, Ordering::Relaxed);
+                return;
+            }
+            Err(e) if !warned => {
+                eprintln!("  Warning: cannot read the spot interruption notice: {e}");
+                warned = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Update the run checkpoint and write it back to the bucket; a failed write only warns.
+async fn save_checkpoint(ctx: &RunContext, update: impl FnOnce(&mut Checkpoint)) {
+    let Some(checkpoint) = &ctx.checkpoint else {
+        return;
+    };
+    let mut checkpoint = checkpoint.lock().await;
+    update(&mut checkpoint);
+    let key = Checkpoint::key(&ctx.prefix);
+    let body = serde_json::to_vec_pretty(&*checkpoint).expect("checkpoint serializes");
+    if let Err(e) = s3::put_object(&ctx.s3, &ctx.bucket, &key, body, "application/json").await {
+        eprintln!("  Warning: failed to save the run checkpoint: {e}");
+    }
+}
+
+/// Post a progress report to `--progress-webhook`; a failed post only warns.
+async fn post_progress(ctx: &RunContext, url: &reqwest::Url, report: &ProgressReport) {
+    let result = ctx
+        .http
+        .post(url.clone())
+        .timeout(Duration::from_secs(30))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(report).expect("progress report serializes"))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        eprintln!("  Warning: progress webhook failed: {e}");
+    }
+}
+
+/// Failed targets by cause, for the run summary.
+#[derive(Default)]
+struct OutageCounts {
+    /// Skipped rather than failed.
+    not_yet_published: AtomicUsize,
+    missing: AtomicUsize,
+    source_down: AtomicUsize,
+    other: AtomicUsize,
+}
+
+/// Print the planned targets and a cost estimate for `--dry-run`.
+fn print_dry_run(targets: &[Target], args: &Args, storage: &StoragePolicy) {
+    for target in targets {
+        let task = target.primary();
+        let keys: Vec<_> = args
+            .routes
+            .iter()
+            .map(|route| {
+                let key = object_key(&[&args.prefix, &route.prefix], &args.key_template, task);
+                format!("s3://{}/{key}", args.bucket)
+            })
+            .collect();
+        let sources: Vec<_> = args
+            .source_templates(task.cycle.model)
+            .iter()
+            .map(|template| template.render(task))
+            .collect();
+        println!("  {task}: {} -> {}", sources.join(" + "), keys.join(", "));
+        for fallback in &target.candidates[1..] {
+            println!("    fallback: {fallback}");
+        }
+    }
+
+    // One estimate per storage class, recent cycles first
+    let mut by_class: Vec<(StorageClass, Vec<&Target>)> = Vec::new();
+    for target in targets {
+        let class = storage.class_for(target.primary().cycle.time);
+        match by_class.iter_mut().find(|(c, _)| *c == class) {
+            Some((_, group)) => group.push(target),
+            None => by_class.push((class, vec![target])),
+        }
+    }
+    by_class.sort_by_key(|(class, _)| *class != storage.recent);
+    let estimates: Vec<(StorageClass, Estimate)> = by_class
+        .iter()
+        .map(|(class, group)| {
+            let download: u64 = group
+                .iter()
+                .map(|target| target.primary().cycle.model.typical_file_size())
+                .sum();
+            let mut estimate = Estimate::for_files(
+                group.len(),
+                download / group.len() as u64,
+                args.routes.len(),
+                *class,
+            );
+            if args.b_url_template.is_some() {
+                estimate = estimate.with_b_files();
+            }
+            (*class, estimate)
+        })
+        .collect();
+    let estimate = estimates
+        .iter()
+        .map(|(_, estimate)| estimate.clone())
+        .reduce(Estimate::merge)
+        .unwrap_or_else(|| Estimate::for_files(0, 0, args.routes.len(), storage.recent));
+    println!();
+    if estimate.objects == estimate.files {
+        println!("Dry run: {} files planned", estimate.files);
+    } else {
+        println!(
+            "Dry run: {} files planned, {} objects",
+            estimate.files, estimate.objects
+        );
+    }
+    println!(
+        "  Download volume:  ~{} (typical file sizes)",
+        format_size(estimate.download_bytes, args.size_units)
+    );
+    println!(
+        "  Stored volume:    ~{}",
+        format_size(estimate.stored_bytes, args.size_units)
+    );
+    println!(
+        "  S3 PUT requests:  ~{} (~${:.2})",
+        estimate.put_requests, estimate.request_cost_usd
+    );
+    let classes = match estimates.as_slice() {
+        [] => storage.recent.as_str().to_string(),
+        [(class, _)] => class.as_str().to_string(),
+        _ => estimates
+            .iter()
+            .map(|(class, estimate)| format!("{} files in {}", estimate.files, class.as_str()))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    println!(
+        "  Storage cost:     ~${:.2}/month ({classes}, us-east-1 list price)",
+        estimate.storage_cost_usd_per_month,
+    );
+}
+
+/// Run a command line (program name first), returning the exit code; invalid
+/// arguments, `--help` and `--version` print their message and exit the process.
+pub fn main(args: Vec<String>) -> Result<u8> {
+    let argv = expand_profile(args)?;
+    let cli = Cli::parse_from(&argv);
+    execute(cli, argv)
+}
+
+/// Run a command line within another program, like `main()` but returning the
+/// `clap::Error` of invalid arguments, `--help` and `--version` instead of exiting.
+/// `self-update`, which would replace the host's executable, and `--max-memory`,
+/// which needs the heap counted by the binary's allocator, are refused.
+pub fn embedded(args: Vec<String>) -> Result<u8> {
+    let argv = expand_profile(args)?;
+    let cli = Cli::try_parse_from(&argv)?;
+    if matches!(cli.command, Some(Command::SelfUpdate(_))) {
+        anyhow::bail!("self-update replaces the running executable: run it from the binary");
+    }
+    if cli
+        .run
+        .as_ref()
+        .is_some_and(|args| args.max_memory.is_some())
+    {
+        anyhow::bail!("--max-memory needs the heap accounting of the binary");
+    }
+    execute(cli, argv)
+}
+
+fn execute(cli: Cli, argv: Vec<String>) -> Result<u8> {
+    let runtime = cli
+        .runtime
+        .build()
+        .context("Cannot start the async runtime")?;
+    runtime.block_on(dispatch(cli, argv))
+}
+
+async fn dispatch(cli: Cli, argv: Vec<String>) -> Result<u8> {
+    match cli.command {
+        Some(Command::Compact(args)) => compact(args).await,
+        Some(Command::Fetch(args)) => fetch(args).await,
+        Some(Command::Merge(args)) => merge(args).await,
+        Some(Command::Repair(args)) => repair(args).await,
+        Some(Command::Resume(args)) => resume(args).await,
+        Some(Command::Verify(args)) => verify(args).await,
+        Some(Command::Selftest(args)) => selftest(args).await,
+        Some(Command::Completions(args)) => completions(args),
+        Some(Command::Manpage) => manpage(),
+        Some(Command::SelfUpdate(args)) => self_update(args).await,
+        None => {
+            run(
+                cli.run
+                    .expect("clap requires the run arguments without a subcommand"),
+                cli.destination,
+                argv[1..].to_vec(),
+                None,
+            )
+            .await
+        }
+    }
+}
+
+/// Replace `--profile NAME` by the options of the profile in the config file
+/// (`--config`, or the default one), in place so that the options given after it
+/// override the profile's. Options the command does not take are left out.
+fn expand_profile(mut argv: Vec<String>) -> Result<Vec<String>> {
+    let config = take_option(&mut argv, CONFIG_FLAG).map_err(anyhow::Error::msg)?;
+    let Some((index, name)) = take_option(&mut argv, PROFILE_FLAG).map_err(anyhow::Error::msg)?
+    else {
+        return Ok(argv);
+    };
+    let path = match config {
+        Some((_, path)) => std::path::PathBuf::from(path),
+        None => profile::default_path().context("No config file: HOME is not set")?,
+    };
+    let config = Config::load(&path)?;
+
+    let cli = Cli::command();
+    let command = argv
+        .get(1)
+        .and_then(|name| cli.find_subcommand(name))
+        .unwrap_or(&cli);
+    let has = |command: &clap::Command, option: &str| {
+        command
+            .get_arguments()
+            .any(|arg| arg.get_long() == Some(option))
+    };
+    let args = config
+        .args(
+            &name,
+            |option| has(command, option),
+            |option| has(&cli, option) || cli.get_subcommands().any(|sub| has(sub, option)),
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid --profile: {e}"))?;
+    argv.splice(index..index, args);
+    Ok(argv)
+}
+
+/// Merge archived objects into monthly objects (`compact` subcommand).
+async fn compact(args: CompactArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    args.naming
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid object names: {e}"))?;
+    validate_prefix(&args.into).map_err(|e| anyhow::anyhow!("Invalid --into: {e}"))?;
+    if args.into.is_empty() {
+        anyhow::bail!("Invalid --into: must not be empty");
+    }
+    let compat = args.destination.compat();
+    compat
+        .check_storage_class(args.storage_class)
+        .map_err(|e| anyhow::anyhow!("Invalid --storage-class: {e}"))?;
+
+    let s3 = args.destination.client().await;
+
+    // Objects archived with a manifest, outside the compacted prefix
+    let keys = list_keys(&s3, &args.bucket, &args.prefix).await?;
+    let listed: std::collections::HashSet<&str> = keys.iter().map(String::as_str).collect();
+    let candidates: Vec<&String> = keys
+        .iter()
+        .filter(|key| {
+            !key.starts_with(&args.into)
+                && !args.naming.is_auxiliary(key)
+                && !key.ends_with(INDEX_SUFFIX)
+                && listed.contains(args.naming.manifest_key(key).as_str())
+        })
+        .collect();
+    println!(
+        "Found {} archived objects under s3://{}/{}",
+        candidates.len(),
+        args.bucket,
+        args.prefix
+    );
+
+    let objects: Vec<(String, Manifest)> = futures::stream::iter(candidates)
+        .map(|key| {
+            let s3 = &s3;
+            let (bucket, naming) = (&args.bucket, &args.naming);
+            async move {
+                let bytes = get_object(s3, bucket, &naming.manifest_key(key)).await?;
+                let manifest: Manifest = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid manifest for {key}"))?;
+                anyhow::Ok((key.clone(), manifest))
+            }
+        })
+        .buffered(16)
+        .try_collect()
+        .await?;
+
+    // Leave the current month alone: it is still being archived
+    let now = chrono::Utc::now().date_naive();
+    let month_start = now.with_day(1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let groups = plan_groups(objects, &args.prefix, &args.into, month_start);
+
+    let existing = list_keys(&s3, &args.bucket, &args.into).await?;
+    let stored = if args.delete_sources && !args.dry_run {
+        stored_references(&s3, &args.bucket, &args.naming).await?
+    } else {
+        std::collections::BTreeSet::new()
+    };
+    let (mut compacted, mut merged) = (0, 0);
+    for group in groups {
+        if existing.contains(&group.key) {
+            println!("  {} already exists, skipping", group.key);
+            continue;
+        }
+        println!("  {} <- {} objects", group.key, group.objects.len());
+        if args.dry_run {
+            continue;
+        }
+
+        let index = compact_group(&s3, &args.bucket, &group, args.storage_class, compat).await?;
+        let size: u64 = index.objects.iter().map(|o| o.length).sum();
+        println!(
+            "    {}, index at {}{INDEX_SUFFIX}",
+            format_size(size, SizeUnits::default()),
+            group.key
+        );
+        compacted += 1;
+        merged += group.objects.len();
+
+        if args.delete_sources {
+            for (key, _) in &group.objects {
+                if stored.contains(key) {
+                    println!("    {key} kept: other objects store messages in it");
+                    continue;
+                }
+                delete_object(&s3, &args.bucket, key).await?;
+                delete_object(&s3, &args.bucket, &args.naming.manifest_key(key)).await?;
+            }
+        }
+    }
+
+    println!();
+    if args.dry_run {
+        println!("Dry run: nothing written");
+    } else {
+        println!("Compacted {merged} objects into {compacted} monthly objects");
+    }
+    Ok(0)
+}
+
+/// Keys of the objects that other objects store messages in (`stored_in`), from
+/// every manifest and compacted index of the bucket, whatever their prefix.
+async fn stored_references(
+    s3: &aws_sdk_s3::Client,
+    bucket: &str,
+    naming: &Naming,
+) -> Result<std::collections::BTreeSet<String>> {
+    let keys = list_keys(s3, bucket, "").await?;
+    let listed: std::collections::HashSet<&str> = keys.iter().map(String::as_str).collect();
+    let documents: Vec<String> = keys
+        .iter()
+        .filter_map(|key| {
+            if key.ends_with(INDEX_SUFFIX) {
+                return Some(key.clone());
+            }
+            let manifest_key = naming.manifest_key(key);
+            (!naming.is_auxiliary(key) && listed.contains(manifest_key.as_str()))
+                .then_some(manifest_key)
+        })
+        .collect();
+
+    let references: Vec<Vec<String>> = futures::stream::iter(documents)
+        .map(|key| async move {
+            let bytes = get_object(s3, bucket, &key).await?;
+            let messages = if key.ends_with(INDEX_SUFFIX) {
+                let index: CompactIndex = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid index {key}"))?;
+                index.objects.into_iter().flat_map(|o| o.messages).collect()
+            } else {
+                let manifest: Manifest = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid manifest {key}"))?;
+                manifest.messages
+            };
+            let keys = referenced(&messages)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            anyhow::Ok(keys)
+        })
+        .buffered(16)
+        .try_collect()
+        .await?;
+    Ok(references.into_iter().flatten().collect())
+}
+
+/// Merge the objects of several archive prefixes (`merge` subcommand).
+async fn merge(args: MergeArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    if args.from.len() < 2 {
+        anyhow::bail!("Invalid --from: give the prefixes of at least two archives");
+    }
+    for prefix in &args.from {
+        validate_prefix(prefix).map_err(|e| anyhow::anyhow!("Invalid --from: {e}"))?;
+    }
+    args.naming
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid object names: {e}"))?;
+    validate_prefix(&args.into).map_err(|e| anyhow::anyhow!("Invalid --into: {e}"))?;
+    if args.into.is_empty()
+        || args
+            .from
+            .iter()
+            .any(|from| from.starts_with(&args.into) || args.into.starts_with(from.as_str()))
+    {
+        anyhow::bail!("Invalid --into: must not be empty, nor overlap a --from prefix");
+    }
+    let compat = args.destination.compat();
+    compat
+        .check_storage_class(args.storage_class)
+        .map_err(|e| anyhow::anyhow!("Invalid --storage-class: {e}"))?;
+
+    let s3 = args.destination.client().await;
+
+    // Objects archived with a manifest under each prefix
+    let mut archives = Vec::with_capacity(args.from.len());
+    for prefix in &args.from {
+        let keys = list_keys(&s3, &args.bucket, prefix).await?;
+        let listed: std::collections::HashSet<&str> = keys.iter().map(String::as_str).collect();
+        let candidates: Vec<&String> = keys
+            .iter()
+            .filter(|key| {
+                !args.naming.is_auxiliary(key)
+                    && !key.ends_with(INDEX_SUFFIX)
+                    && listed.contains(args.naming.manifest_key(key).as_str())
+            })
+            .collect();
+        println!(
+            "Found {} archived objects under s3://{}/{prefix}",
+            candidates.len(),
+            args.bucket
+        );
+        let objects: Vec<(String, Manifest)> = futures::stream::iter(candidates)
+            .map(|key| {
+                let s3 = &s3;
+                let (bucket, naming) = (&args.bucket, &args.naming);
+                async move {
+                    let bytes = get_object(s3, bucket, &naming.manifest_key(key)).await?;
+                    let manifest: Manifest = serde_json::from_slice(&bytes)
+                        .with_context(|| format!("Invalid manifest for {key}"))?;
+                    anyhow::Ok((key.clone(), manifest))
+                }
+            })
+            .buffered(16)
+            .try_collect()
+            .await?;
+        archives.push(objects);
+    }
+
+    let (groups, incomplete) = plan_merges(&args.from, archives, &args.into);
+    for name in &incomplete {
+        println!("  {name} is missing from some prefixes, skipping");
+    }
+
+    let existing = list_keys(&s3, &args.bucket, &args.into).await?;
+    let mut merged = 0;
+    for group in groups {
+        if existing.contains(&group.key) {
+            println!("  {} already exists, skipping", group.key);
+            continue;
+        }
+        println!("  {} <- {} objects", group.key, group.objects.len());
+        if args.dry_run {
+            continue;
+        }
+
+        let manifest = merge_group(
+            &s3,
+            &args.bucket,
+            &group,
+            &args.naming,
+            args.storage_class,
+            compat,
+        )
+        .await?;
+        let size = manifest
+            .messages
+            .last()
+            .map_or(0, |message| message.offset + message.length);
+        println!(
+            "    {} messages, {}",
+            manifest.messages.len(),
+            format_size(size, SizeUnits::default())
+        );
+        merged += 1;
+    }
+
+    println!();
+    if args.dry_run {
+        println!("Dry run: nothing written");
+    } else {
+        println!(
+            "Merged {merged} objects ({} skipped as incomplete)",
+            incomplete.len()
+        );
+    }
+    Ok(0)
+}
+
+/// Read selected messages of an archived object (`fetch` subcommand).
+async fn fetch(args: FetchArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+
+    let reader =
+        ArchiveReader::new(args.destination.client().await, &args.bucket).with_naming(&args.naming);
+    let selector = Selector {
+        param: args.param,
+        level: args.level.clone(),
+        valid_time: args.valid_time,
+    };
+    let messages = reader.fetch(&args.key, &selector).await?;
+    if messages.is_empty() {
+        anyhow::bail!("No message of {} matches the selection", args.key);
+    }
+
+    let mut grib = Vec::new();
+    let mut csv = String::new();
+    for message in &messages {
+        let entry = &message.entry;
+        let param = Param::new(entry.discipline, entry.category, entry.number);
+        let level = entry.level.as_deref().unwrap_or("unknown level");
+        let valid = entry
+            .valid_time
+            .map_or("?".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+        println!(
+            "{param}, {level}, valid {valid}: {}",
+            format_size(message.bytes.len() as u64, SizeUnits::default())
+        );
+        grib.extend_from_slice(&message.bytes);
+
+        if args.csv.is_some() {
+            let field = message.decode()?;
+            let (min, max) = field
+                .values
+                .iter()
+                .filter(|v| !v.is_nan())
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+                    (lo.min(v), hi.max(v))
+                });
+            println!("  {}x{} grid, min {min}, max {max}", field.ni, field.nj);
+
+            csv.push_str(&format!("# {param}, {level}, valid {valid}\n"));
+            for row in field.values.chunks(field.ni) {
+                let row: Vec<_> = row.iter().map(f32::to_string).collect();
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+        }
+    }
+
+    if let Some(path) = &args.output {
+        std::fs::write(path, &grib).with_context(|| format!("Cannot write {}", path.display()))?;
+        println!("Wrote {} messages to {}", messages.len(), path.display());
+    }
+    if let Some(path) = &args.csv {
+        std::fs::write(path, csv).with_context(|| format!("Cannot write {}", path.display()))?;
+        println!("Wrote decoded values to {}", path.display());
+    }
+    Ok(0)
+}
+
+/// Check archived objects against their manifests (`verify` subcommand).
+async fn verify(args: VerifyArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    args.naming
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid object names: {e}"))?;
+    let client = args.destination.client().await;
+    let reader = ArchiveReader::new(client.clone(), &args.bucket).with_naming(&args.naming);
+
+    let keys: Vec<String> = s3::list_keys(&client, &args.bucket, &args.prefix)
+        .await?
+        .into_iter()
+        .filter_map(|key| {
+            key.strip_suffix(args.naming.manifest_suffix.as_str())
+                .map(str::to_string)
+        })
+        .collect();
+    println!(
+        "Verifying {} objects under s3://{}/{}",
+        keys.len(),
+        args.bucket,
+        args.prefix
+    );
+
+    let (client, reader, bucket) = (&client, &reader, &args.bucket);
+    let results: Vec<_> = futures::stream::iter(&keys)
+        .map(|key| async move {
+            let manifest = reader.manifest(key).await?;
+            let verification = verify_object(client, bucket, key, &manifest).await?;
+            Ok::<_, Error>((key, verification))
+        })
+        .buffer_unordered(args.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    let (mut messages, mut requests, mut damaged) = (0, 0, 0);
+    for (key, verification) in &results {
+        messages += verification.messages;
+        requests += verification.requests;
+        if !verification.problems.is_empty() {
+            damaged += 1;
+            for problem in &verification.problems {
+                println!("  {key}: {problem}");
+            }
+        }
+    }
+    println!(
+        "Verified {} objects ({messages} messages, {requests} range requests): {damaged} with problems",
+        results.len()
+    );
+    // Same code as invalid GRIB2 data during a run
+    Ok(if damaged > 0 { 5 } else { 0 })
+}
+
+/// Push a source file through the pipeline into a temporary object, verify it and
+/// delete it (`selftest` subcommand). Exits with the code of the first failing
+/// step, as a run would.
+async fn selftest(args: SelftestArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    let compat = args.destination.compat();
+    let styler = Styler::stdout(ColorChoice::Auto);
+    let passed = |step: &str| println!("{}", styler.status(Status::Completed, step));
+    let failed = |step: &str, e: &Error| {
+        println!("{}", styler.status(Status::Failed, &format!("{step}: {e}")));
+        e.exit_code()
+    };
+
+    // Source file
+    let (source, bytes) = match &args.source_url {
+        None => ("bundled fixture".to_string(), selftest::fixture()),
+        Some(url) => {
+            let http = reqwest::Client::builder()
+                .user_agent(concat!("gfs_wind_downloader/", env!("CARGO_PKG_VERSION")))
+                .timeout(Duration::from_secs(60))
+                .build()?;
+            let unavailable = |e: reqwest::Error| Error::SourceUnavailable {
+                url: url.clone(),
+                status: None,
+                reason: e.to_string(),
+            };
+            let download = async {
+                let response = http.get(url).send().await.map_err(unavailable)?;
+                if !response.status().is_success() {
+                    return Err(Error::from_status(url, response.status()));
+                }
+                Ok(response.bytes().await.map_err(unavailable)?.to_vec())
+            };
+            match download.await {
+                Ok(bytes) => (url.clone(), bytes),
+                Err(e) => return Ok(failed("Download", &e)),
+            }
+        }
+    };
+    passed(&format!(
+        "Download: {source} ({})",
+        format_size(bytes.len() as u64, SizeUnits::Si)
+    ));
+
+    // Filter stage, with the default route
+    let routes: Arc<[Route]> = vec![DEFAULT_ROUTE.parse::<Route>().expect("default route")].into();
+    let filter = Filter::new(routes).hashing(true);
+    let prefix = args.prefix.trim_end_matches('/');
+    let name = format!(
+        "selftest-{}.grb2",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    );
+    let key = if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    };
+    let (object, manifest) = match selftest::filter_file(&filter, &source, &key, &bytes) {
+        Ok((_, manifest)) if manifest.messages.is_empty() => {
+            let e = Error::ParseError {
+                offset: 0,
+                reason: format!("no message matches the default route ({DEFAULT_ROUTE})"),
+            };
+            return Ok(failed("Filter", &e));
+        }
+        Ok(filtered) => filtered,
+        Err(e) => return Ok(failed("Filter", &e)),
+    };
+    passed(&format!(
+        "Filter: {} messages kept ({})",
+        manifest.messages.len(),
+        format_size(object.len() as u64, SizeUnits::Si)
+    ));
+
+    // Upload, then check the object and delete it whatever the outcome
+    let client = args.destination.client().await;
+    let manifest_key = Manifest::key_for(&key);
+    let upload = async {
+        let mut uploader = S3MultipartUploader::new(
+            client.clone(),
+            &args.bucket,
+            &key,
+            StorageClass::Standard,
+            compat,
+        );
+        uploader.set_metadata("selftest", "true".to_string());
+        if let Err(e) = uploader.write(&object).await {
+            uploader.abort().await?;
+            return Err(e);
+        }
+        uploader.complete().await
+    };
+    let upload = upload.await;
+    let completed = upload.is_ok();
+    let upload = match upload {
+        Ok(()) => {
+            let body = serde_json::to_vec_pretty(&manifest).expect("manifest serializes to JSON");
+            put_object(
+                &client,
+                &args.bucket,
+                &manifest_key,
+                body,
+                "application/json",
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    let mut exit_code = match upload {
+        Ok(()) => {
+            passed(&format!(
+                "Upload: s3://{}/{key} and its manifest",
+                args.bucket
+            ));
+            None
+        }
+        Err(e) => Some(failed("Upload", &e)),
+    };
+
+    if exit_code.is_none() {
+        let check = async {
+            let verification = verify_object(&client, &args.bucket, &key, &manifest).await?;
+            let mut problems = verification.problems;
+            if problems.is_empty() && get_object(&client, &args.bucket, &key).await? != object {
+                problems.push("object read back differs from the bytes uploaded".to_string());
+            }
+            Ok::<_, Error>((verification.requests, problems))
+        };
+        exit_code = match check.await {
+            Ok((requests, problems)) if problems.is_empty() => {
+                passed(&format!(
+                    "Verify: {requests} range requests and a full read"
+                ));
+                None
+            }
+            Ok((_, problems)) => {
+                let e = Error::ParseError {
+                    offset: 0,
+                    reason: problems.join("; "),
+                };
+                Some(failed("Verify", &e))
+            }
+            Err(e) => Some(failed("Verify", &e)),
+        };
+    }
+
+    // Nothing to delete when the upload was aborted
+    if completed {
+        let cleanup = async {
+            delete_object(&client, &args.bucket, &key).await?;
+            delete_object(&client, &args.bucket, &manifest_key).await
+        };
+        match cleanup.await {
+            Ok(()) => passed("Delete: temporary object and manifest removed"),
+            Err(e) => {
+                let code = failed("Delete", &e);
+                exit_code.get_or_insert(code);
+            }
+        }
+    }
+
+    println!();
+    Ok(match exit_code {
+        Some(code) => {
+            println!("Self-test failed");
+            code
+        }
+        None => {
+            println!("Self-test passed");
+            0
+        }
+    })
+}
+
+/// Re-frame archived objects with inconsistent message lengths or missing end
+/// markers (`repair` subcommand).
+async fn repair(args: RepairArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    args.naming
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid object names: {e}"))?;
+    let compat = args.destination.compat();
+    compat
+        .check_storage_class(args.storage_class)
+        .map_err(|e| anyhow::anyhow!("Invalid --storage-class: {e}"))?;
+    let client = args.destination.client().await;
+    let reader = ArchiveReader::new(client.clone(), &args.bucket).with_naming(&args.naming);
+
+    let keys: Vec<String> = s3::list_keys(&client, &args.bucket, &args.prefix)
+        .await?
+        .into_iter()
+        .filter_map(|key| {
+            key.strip_suffix(args.naming.manifest_suffix.as_str())
+                .map(str::to_string)
+        })
+        .collect();
+    println!(
+        "Checking {} objects under s3://{}/{}",
+        keys.len(),
+        args.bucket,
+        args.prefix
+    );
+
+    // Objects failing verification are downloaded and re-framed; only framing
+    // problems are fixed, anything else is left for a new download
+    let (client, reader, bucket) = (&client, &reader, &args.bucket);
+    let results: Vec<_> = futures::stream::iter(&keys)
+        .map(|key| async move {
+            let mut manifest = reader.manifest(key).await?;
+            let verification = verify_object(client, bucket, key, &manifest).await?;
+            if verification.problems.is_empty() {
+                return Ok::<_, Error>((key, manifest, None));
+            }
+            let object = s3::get_object(client, bucket, key).await?;
+            let outcome = reframe(&object)
+                .and_then(|reframed| {
+                    if !reframed.is_changed() {
+                        return Err(verification.problems.join("; "));
+                    }
+                    reframed.remap(&mut manifest, None)?;
+                    Ok(reframed)
+                })
+                .map_err(|reason| format!("not repairable: {reason}"));
+            Ok((key, manifest, Some(outcome)))
+        })
+        .buffer_unordered(args.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    // Manifests to keep, with the index of their object among the repaired ones
+    let mut manifests: Vec<(&String, Manifest, Option<usize>)> = Vec::new();
+    let mut repaired: Vec<(&String, Reframed)> = Vec::new();
+    let mut unrepairable = 0;
+    for (key, manifest, outcome) in results {
+        match outcome {
+            Some(Ok(reframed)) => {
+                for fix in reframed.fixes() {
+                    println!("  {key}: {fix}");
+                }
+                manifests.push((key, manifest, Some(repaired.len())));
+                repaired.push((key, reframed));
+            }
+            Some(Err(problem)) => {
+                println!("  {key}: {problem}");
+                unrepairable += 1;
+            }
+            None => manifests.push((key, manifest, None)),
+        }
+    }
+
+    // Messages deduplicated into a repaired object (--dedup-catalog) moved with it
+    let mut updated = 0;
+    for (key, manifest, object) in &mut manifests {
+        let mut moved = 0;
+        for (stored_in, reframed) in &repaired {
+            match reframed.remap(manifest, Some(stored_in.as_str())) {
+                Ok(changed) => moved += changed,
+                Err(e) => {
+                    println!("  {key}: not repairable: {e} in {stored_in}");
+                    unrepairable += 1;
+                }
+            }
+        }
+        if moved > 0 {
+            println!("  {key}: {moved} entries stored in repaired objects updated");
+            updated += usize::from(object.is_none());
+        }
+        if args.dry_run {
+            continue;
+        }
+        if let Some(index) = *object {
+            rewrite_object(
+                client,
+                bucket,
+                key,
+                &repaired[index].1,
+                manifest,
+                &args.naming,
+                args.storage_class,
+                compat,
+            )
+            .await?;
+        } else if moved > 0 {
+            let body = serde_json::to_vec_pretty(&*manifest).expect("manifest serializes");
+            s3::put_object(
+                client,
+                bucket,
+                &args.naming.manifest_key(key),
+                body,
+                "application/json",
+            )
+            .await?;
+        }
+    }
+
+    println!();
+    if args.dry_run {
+        println!("Dry run: nothing written");
+    }
+    println!(
+        "Repaired {} objects ({updated} further manifests updated): {unrepairable} not repairable",
+        repaired.len()
+    );
+    // Same code as invalid GRIB2 data during a run
+    Ok(if unrepairable > 0 { 5 } else { 0 })
+}
+
+/// Finish a checkpointed run (`resume` subcommand).
+async fn resume(args: ResumeArgs) -> Result<u8> {
+    args.destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    let client = args.destination.client().await;
+
+    let key = Checkpoint::key(&args.prefix);
+    if s3::head_metadata(&client, &args.bucket, &key)
+        .await?
+        .is_none()
+    {
+        anyhow::bail!(
+            "No run checkpoint at s3://{}/{key} (was the run started with --checkpoint?)",
+            args.bucket
+        );
+    }
+    let body = s3::get_object(&client, &args.bucket, &key).await?;
+    let checkpoint: Checkpoint = serde_json::from_slice(&body)
+        .with_context(|| format!("Invalid run checkpoint s3://{}/{key}", args.bucket))?;
+
+    let argv = std::iter::once("gfs_wind_downloader".to_string()).chain(checkpoint.args.clone());
+    let run_cli = Cli::try_parse_from(argv).with_context(|| {
+        format!(
+            "Invalid arguments in run checkpoint s3://{}/{key}",
+            args.bucket
+        )
+    })?;
+    let run_args = run_cli
+        .run
+        .context("The run checkpoint does not hold the arguments of a download run")?;
+    if run_args.bucket != args.bucket || run_args.prefix != args.prefix {
+        anyhow::bail!(
+            "The run checkpoint s3://{}/{key} belongs to a run writing to s3://{}/{}",
+            args.bucket,
+            run_args.bucket,
+            run_args.prefix
+        );
+    }
+    run(
+        run_args,
+        run_cli.destination,
+        checkpoint.args.clone(),
+        Some(checkpoint),
+    )
+    .await
+}
+
+/// Print a shell completion script (`completions` subcommand).
+fn completions(args: CompletionsArgs) -> Result<u8> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    // Generated in memory: clap_complete panics on write errors (e.g. a closed pipe)
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, &mut command, name, &mut script);
+    std::io::stdout()
+        .write_all(&script)
+        .context("Failed to write the completion script")?;
+    Ok(0)
+}
+
+/// Print the man page (`manpage` subcommand).
+fn manpage() -> Result<u8> {
+    clap_mangen::Man::new(Cli::command())
+        .render(&mut std::io::stdout())
+        .context("Failed to write the man page")?;
+    Ok(0)
+}
+
+/// Install the latest release over this binary (`self-update` subcommand).
+async fn self_update(args: SelfUpdateArgs) -> Result<u8> {
+    let key: update::PublicKey = args
+        .public_key
+        .as_deref()
+        .or(update::PUBLIC_KEY)
+        .context(
+            "This binary was built without the release key (GFS_WIND_DOWNLOADER_UPDATE_KEY), \
+             pass the key signing the releases with --public-key",
+        )?
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid --public-key: {e}"))?;
+    let http = reqwest::Client::builder()
+        .user_agent(concat!("gfs_wind_downloader/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(600))
+        .build()?;
+    let updater = Updater::new(http, &args.api_url, &args.repository);
+    let release = updater.latest().await?;
+    let (current, tag) = (env!("CARGO_PKG_VERSION"), &release.tag_name);
+    if !update::is_newer(tag, current) {
+        println!("Up to date: {current} (latest release {tag})");
+        return Ok(0);
+    }
+    println!("New release {tag} (running {current})");
+    if args.check {
+        return Ok(0);
+    }
+
+    let name = update::asset_name();
+    let asset = release
+        .asset(&name)
+        .with_context(|| format!("Release {tag} has no binary {name} for this platform"))?;
+    let signature_name = format!("{name}{}", update::SIGNATURE_SUFFIX);
+    let signature = release.asset(&signature_name).with_context(|| {
+        format!("Release {tag} publishes no {signature_name}, refusing to install {name}")
+    })?;
+    let signature = String::from_utf8_lossy(&updater.download(signature).await?).into_owned();
+    let binary = updater.download(asset).await?;
+    update::verify_signature(&binary, &signature, &key)
+        .map_err(|e| anyhow::anyhow!("Refusing to install {name}: {e}"))?;
+
+    let exe = std::env::current_exe().context("Failed to locate the running binary")?;
+    update::replace_binary(&exe, &binary)?;
+    println!("Updated {} to {tag}", exe.display());
+    Ok(0)
+}
+
+/// Counters and queues shared by the targets of a run.
+struct RoundState<'a> {
+    skipped: &'a AtomicUsize,
+    failed: &'a AtomicUsize,
+    outages: &'a OutageCounts,
+    exit_code: &'a AtomicU8,
+    halted: &'a AtomicBool,
+    /// Targets left for `resume` after a spot interruption notice.
+    interrupted: &'a AtomicUsize,
+    progress: &'a Progress,
+    /// Failed targets to try again in the next round.
+    requeued: &'a Mutex<Vec<Target>>,
+    /// Targets failing for good, with `--dead-letter`.
+    dead_letter: Option<&'a RetryLog>,
+}
+
+/// Process `targets`, up to `--concurrency` at a time. `round` is 0 for the first
+/// pass, then counts the end-of-run rounds of the failed targets.
+async fn process_round(
+    ctx: &RunContext,
+    targets: Vec<Target>,
+    concurrency: usize,
+    round: u32,
+    state: &RoundState<'_>,
+) {
+    futures::stream::iter(targets)
+        .for_each_concurrent(concurrency, |target| async move {
+            if process_round_target(ctx, &target, round, state).await {
+                state.progress.done(&target);
+            }
+        })
+        .await;
+}
+
+/// Process one target of a round and account for its outcome. Returns false when
+/// it is requeued for the next round.
+async fn process_round_target(
+    ctx: &RunContext,
+    target: &Target,
+    round: u32,
+    state: &RoundState<'_>,
+) -> bool {
+    if state.halted.load(Ordering::Relaxed) {
+        state.skipped.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    if ctx.interrupted.load(Ordering::Relaxed) {
+        state.interrupted.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    let e = match process_target(ctx, target).await {
+        Ok(()) => {
+            let task = target.primary().to_string();
+            save_checkpoint(ctx, |c| c.record(task, Ok(()))).await;
+            if let Some(latest) = &ctx.latest {
+                latest.produced(ctx, target).await;
+            }
+            return true;
+        }
+        Err(e) if e.is::<BudgetExhausted>() => {
+            state.skipped.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        Err(e) if e.downcast_ref::<Error>().is_some_and(Error::is_interrupted) => {
+            let summary = format!("{}: interrupted, left for resume", target.primary());
+            println!("{}", ctx.stdout.status(Status::Skipped, &summary));
+            state.interrupted.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        Err(e) => e,
+    };
+
+    let outage = target_outage(ctx, target, &e);
+    if outage == Some(Outage::NotYetPublished) {
+        let summary = format!("{}: not published yet", target.primary());
+        println!("{}", ctx.stdout.status(Status::Skipped, &summary));
+        state
+            .outages
+            .not_yet_published
+            .fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    let error = e.downcast_ref::<Error>();
+    // A file missing past the publication delay is a gap, another try won't find it
+    let requeue = round < ctx.requeue_rounds
+        && outage != Some(Outage::Missing)
+        && !error.is_some_and(Error::is_fatal);
+    if requeue {
+        eprintln!(
+            "  {} failed ({e}), requeued for the end of the run",
+            target.primary()
+        );
+        state.requeued.lock().unwrap().push(target.clone());
+        return false;
+    }
+
+    let summary = format!("{}: {e}", target.primary());
+    eprintln!("{}", ctx.stderr.status(Status::Failed, &summary));
+    let task = target.primary().to_string();
+    if let Some(log) = state.dead_letter {
+        let letter = DeadLetter::new(
+            task.clone(),
+            task_sources(ctx, target.primary()),
+            round + 1,
+            &e,
+        );
+        if let Err(e) = log.record(&letter) {
+            eprintln!("  Warning: {e:#}");
+        }
+    }
+    save_checkpoint(ctx, |c| c.record(task, Err(e.to_string()))).await;
+    state.failed.fetch_add(1, Ordering::Relaxed);
+    match outage {
+        Some(Outage::Missing) => &state.outages.missing,
+        Some(Outage::SourceDown) => &state.outages.source_down,
+        _ => &state.outages.other,
+    }
+    .fetch_add(1, Ordering::Relaxed);
+    let code = error.map_or(EXIT_OTHER_FAILURE, Error::exit_code);
+    let _ = state
+        .exit_code
+        .compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+    if error.is_some_and(Error::is_fatal) && !state.halted.swap(true, Ordering::Relaxed) {
+        eprintln!("  Stopping: the remaining files would fail the same way");
+    }
+    true
+}
+
+/// Download run, or the rest of a checkpointed one when `resumed`.
+async fn run(
+    mut args: Args,
+    destination: DestinationArgs,
+    command_line: Vec<String>,
+    resumed: Option<Checkpoint>,
+) -> Result<u8> {
+    // Relative dates (and --archive-after) of a resumed run keep resolving to the
+    // time it started
+    let started = resumed
+        .as_ref()
+        .map_or_else(chrono::Utc::now, |checkpoint| checkpoint.started);
+    let today = started.date_naive();
+    let start_date = resolve_date(&args.start_date, today)
+        .map_err(|e| anyhow::anyhow!("Invalid --start-date: {e}"))?;
+    let end_date = resolve_date(&args.end_date, today)
+        .map_err(|e| anyhow::anyhow!("Invalid --end-date: {e}"))?;
+
+    if start_date > end_date {
+        anyhow::bail!("Start date must be before or equal to end date");
+    }
+
+    // Validate the destination before any network call
+    destination.check(&args.bucket)?;
+    validate_prefix(&args.prefix).map_err(|e| anyhow::anyhow!("Invalid --prefix: {e}"))?;
+    if !args.params.is_empty() {
+        args.routes = vec![Route {
+            params: std::mem::take(&mut args.params),
+            prefix: String::new(),
+        }];
+    }
+    validate_routes(&args.routes).map_err(|e| anyhow::anyhow!("Invalid --route: {e}"))?;
+    let naming = args.naming();
+    naming
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid object names: {e}"))?;
+    if let Some(prefix) = &args.mirror_raw {
+        validate_prefix(prefix).map_err(|e| anyhow::anyhow!("Invalid --mirror-raw: {e}"))?;
+    }
+    for replica in &args.replicas {
+        let same_prefix = match &replica.prefix {
+            Some(prefix) => prefix.trim_end_matches('/') == args.prefix.trim_end_matches('/'),
+            None => true,
+        };
+        if replica.bucket == args.bucket && same_prefix {
+            anyhow::bail!("Invalid --replicate-to: {replica} is where the objects are written");
+        }
+    }
+    let s3_compat = destination.compat();
+    s3_compat
+        .check_storage_class(args.storage_class)
+        .map_err(|e| anyhow::anyhow!("Invalid --storage-class: {e}"))?;
+    if args.archive_after.is_some() {
+        s3_compat
+            .check_storage_class(args.archive_storage_class)
+            .map_err(|e| anyhow::anyhow!("Invalid --archive-storage-class: {e}"))?;
+    }
+
+    args.models.sort();
+    args.models.dedup();
+    if args.models.len() > 1 {
+        if !args.url_templates.is_empty() || args.b_url_template.is_some() {
+            anyhow::bail!("--url-template and --b-url-template apply to a single --model");
+        }
+        // Keep the objects of each model apart
+        if !args.key_template.uses("model") {
+            args.key_template = parse_key_template(&format!("{{model}}/{}", args.key_template))
+                .map_err(|e| anyhow::anyhow!("Invalid --key-template: {e}"))?;
+        }
+        if let Some(daily) = args
+            .daily_key_template
+            .as_mut()
+            .filter(|t| !t.uses("model"))
+        {
+            *daily = parse_daily_key_template(&format!("{{model}}/{daily}"))
+                .map_err(|e| anyhow::anyhow!("Invalid --daily-key-template: {e}"))?;
+        }
+    }
+
+    if let Some(source) = args.source {
+        if let Some(model) = args
+            .models
+            .iter()
+            .find(|&&model| source.url_templates(model).is_none())
+        {
+            anyhow::bail!("--source {source} doesn't publish --model {model}");
+        }
+    }
+    if args.bbox.is_some() {
+        if let Some(model) = args.models.iter().find(|model| !model.lat_lon_grid()) {
+            anyhow::bail!("--bbox crops regular lat/lon grids only, not those of --model {model}");
+        }
+    }
+
+    // Several lead times per cycle: tell the analysis from the forecast steps
+    let forecast_steps = args
+        .forecast_hours
+        .as_ref()
+        .is_some_and(|forecast| forecast.hours != [0]);
+    if (args.hourly_coverage || forecast_steps)
+        && args.key_template.to_string() == DEFAULT_KEY_TEMPLATE
+    {
+        args.key_template =
+            parse_key_template(DEFAULT_LEAD_KEY_TEMPLATE).expect("default key template is valid");
+    }
+    if forecast_steps
+        && !["lead", "fff", "ff"]
+            .iter()
+            .any(|f| args.key_template.uses(f))
+    {
+        anyhow::bail!(
+            "--key-template must tell the --forecast-hours apart with {{lead}} or {{fff}}"
+        );
+    }
+
+    // Named after the models and routes of the run, e.g. "NAM/RAP Data Downloader"
+    let models: Vec<_> = args
+        .models
+        .iter()
+        .map(|model| model.to_string().to_uppercase())
+        .collect();
+    let wind = args.routes.len() == 1 && args.routes[0].to_string() == DEFAULT_ROUTE;
+    let banner = format!(
+        "{} {}Data Downloader -> S3",
+        models.join("/"),
+        if wind { "Wind " } else { "" }
+    );
+    println!("{banner}");
+    println!("{}", "=".repeat(banner.chars().count()));
+    if args.models != [Model::Gfs] {
+        println!("Key template: {}", args.key_template);
+    }
+    println!("Date range: {start_date} to {end_date} ({})", args.timezone);
+    if let Some(forecast) = &args.forecast_hours {
+        println!(
+            "Forecast hours: {forecast} ({} per cycle)",
+            forecast.hours.len()
+        );
+    }
+    if let Some(source) = args.source {
+        println!("Source: {source}");
+        if let Some(days) = source.retention_days() {
+            if start_date < today - chrono::Days::new(days.into()) {
+                eprintln!(
+                    "  Warning: {source} only keeps the last {days} days, earlier cycles will be missing"
+                );
+            }
+        }
+        for &model in &args.models {
+            if let Some(first) = source.first_cycle(model) {
+                if start_date.and_time(chrono::NaiveTime::MIN) < first {
+                    eprintln!(
+                        "  Warning: {source} has {model} cycles from {}, earlier cycles will be missing",
+                        first.format("%Y-%m-%d %HZ")
+                    );
+                }
+            }
+        }
+    }
+    if args.hourly_coverage && !args.analysis_only {
+        for &model in &args.models {
+            let step = args.forecast_hour_step(model);
+            if step > 1 {
+                let hours: Vec<_> = hourly_coverage_hours(model.cycle_interval_hours(), step)
+                    .iter()
+                    .map(|hour| format!("f{hour:03}"))
+                    .collect();
+                eprintln!(
+                    "  Warning: the source of {model} publishes every {step} forecast hours, \
+                     --hourly-coverage fetches {} of each cycle (--source aws or nomads has every hour)",
+                    hours.join(", ")
+                );
+            }
+        }
+    }
+    println!("S3 bucket: {}", args.bucket);
+    println!("S3 prefix: {}", args.prefix);
+    if !args.replicas.is_empty() {
+        let replicas: Vec<_> = args.replicas.iter().map(ReplicaTarget::to_string).collect();
+        println!("Replicas: {}", replicas.join(", "));
+    }
+    if args.routes.len() > 1 || !args.routes[0].prefix.is_empty() {
+        let routes: Vec<_> = args.routes.iter().map(Route::to_string).collect();
+        println!("Routes: {}", routes.join(" "));
+    } else if args.routes[0].to_string() != DEFAULT_ROUTE {
+        let params: Vec<_> = args.routes[0].params.iter().map(Param::to_string).collect();
+        println!("Parameters: {}", params.join(", "));
+    }
+    println!();
+
+    // Plan every cycle in the range
+    let mut cycles = Vec::new();
+    for &model in &args.models {
+        cycles.extend(plan_cycles(
+            start_date,
+            end_date,
+            args.timezone,
+            args.valid_hours.as_deref(),
+            model,
+        )?);
+    }
+    cycles.sort_by_key(|cycle| (cycle.time, cycle.model));
+
+    let rules = CycleRules {
+        exclude: args.exclude.clone(),
+        weekdays: args.include_weekdays.clone(),
+    };
+    if !rules.is_empty() {
+        let planned = cycles.len();
+        rules.select(&mut cycles, args.timezone);
+        println!(
+            "Cycle rules: keeping {} of {planned} cycles in the range",
+            cycles.len()
+        );
+    }
+
+    if let Some(shard) = args.shard {
+        let planned = cycles.len();
+        shard.select(&mut cycles);
+        println!(
+            "Shard {shard}: processing {} of {planned} planned cycles",
+            cycles.len()
+        );
+    }
+
+    if let Some(sample @ Sample::Daily) = args.sample {
+        let planned = cycles.len();
+        sample.select(&mut cycles, args.timezone);
+        println!(
+            "Sample {sample}: processing {} of {planned} planned cycles",
+            cycles.len()
+        );
+    }
+
+    if let Some(max_cycles) = args.max_cycles {
+        if cycles.len() > max_cycles {
+            println!(
+                "Limiting run to {max_cycles} of {} planned cycles (--max-cycles)",
+                cycles.len()
+            );
+            cycles.truncate(max_cycles);
+        }
+    }
+
+    let tasks: Vec<Task> = cycles
+        .iter()
+        .flat_map(|cycle| {
+            let forecast_hours = if let Some(forecast) = &args.forecast_hours {
+                forecast.hours.clone()
+            } else if args.hourly_coverage && !args.analysis_only {
+                hourly_coverage_hours(
+                    cycle.model.cycle_interval_hours(),
+                    args.forecast_hour_step(cycle.model),
+                )
+            } else {
+                vec![0]
+            };
+            plan_tasks(&[*cycle], &forecast_hours)
+        })
+        .collect();
+    // Prefixes and templates are checked apart, not the length of the keys they make
+    if let Some(task) = tasks.first() {
+        for route in args.routes.iter() {
+            validate_key(&object_key(
+                &[&args.prefix, &route.prefix],
+                &args.key_template,
+                task,
+            ))
+            .map_err(|e| anyhow::anyhow!("Invalid --prefix, --route or --key-template: {e}"))?;
+        }
+    }
+    let mut targets = if args.best_available {
+        plan_best_available(&tasks, args.max_lead_hours)
+    } else {
+        plan_targets(&tasks)
+    };
+
+    let gaps = match &args.known_gaps {
+        Some(path) => KnownGaps::load(path)?,
+        None => KnownGaps::embedded(),
+    };
+    let gap_targets = gaps.remove_from(&mut targets);
+    if gap_targets > 0 {
+        println!("Skipping {gap_targets} files known to be missing from the source (known gaps)");
+    }
+
+    let checkpoint = match resumed {
+        Some(checkpoint) => {
+            let planned = targets.len();
+            targets.retain(|target| !checkpoint.is_completed(&target.primary().to_string()));
+            println!(
+                "Resuming the run started {}: {} of {planned} files already processed, {} failed before",
+                checkpoint.started.format("%Y-%m-%d %H:%M UTC"),
+                planned - targets.len(),
+                checkpoint.failed.len()
+            );
+            Some(checkpoint)
+        }
+        None => args
+            .checkpoint
+            .then(|| Checkpoint::new(command_line, targets.len())),
+    };
+
+    let storage = args.storage_policy(started.naive_utc());
+    let run_id = args.run_id.clone().unwrap_or_else(|| {
+        let started = if args.deterministic {
+            DateTime::UNIX_EPOCH
+        } else {
+            started
+        };
+        started.format("%Y%m%dT%H%M%SZ").to_string()
+    });
+    let tags = if args.no_tags {
+        RunTags::untagged()
+    } else if !s3_compat.supports_tagging() {
+        if args.run_id.is_some() || args.cost_center.is_some() {
+            println!("Note: {s3_compat:?} does not support object tagging, objects are not tagged");
+        }
+        RunTags::untagged()
+    } else {
+        RunTags::new(&run_id, args.cost_center.as_deref())
+    };
+    if args.dry_run {
+        print_dry_run(&targets, &args, &storage);
+        return Ok(0);
+    }
+
+    let lock_path = args
+        .lock_file
+        .clone()
+        .unwrap_or_else(|| LockFile::default_path(&args.bucket, &args.prefix));
+    let _lock = LockFile::acquire(&lock_path, args.force)?;
+    if args.metadata_only {
+        println!("Metadata only: uploading inventories, no GRIB data");
+    }
+
+    let catalog = args
+        .dedup_catalog
+        .as_deref()
+        .map(Catalog::open)
+        .transpose()?;
+    let retry_log = args.retry_log.as_deref().map(RetryLog::open).transpose()?;
+    let dead_letter = args
+        .dead_letter
+        .as_deref()
+        .map(RetryLog::open)
+        .transpose()?;
+    if let (Some(catalog), Some(path)) = (&catalog, &args.dedup_catalog) {
+        println!(
+            "Dedup catalog: {} ({} messages)",
+            path.display(),
+            catalog.len()
+        );
+    }
+
+    let cache = args
+        .cache_dir
+        .as_deref()
+        .map(|dir| {
+            let mut cache = Cache::open(dir, &args.routes)?;
+            if let Some(every) = args.sample.and_then(|sample| sample.messages()) {
+                cache = cache.with_transform("sample", every);
+            }
+            if let Some(bbox) = args.bbox {
+                cache = cache.with_transform("bbox", bbox);
+            }
+            if let Some(quantize) = args.quantize {
+                cache = cache.with_transform("quantize", quantize);
+            }
+            anyhow::Ok(cache)
+        })
+        .transpose()?;
+
+    // Initialize AWS SDK
+    let s3_client = destination.client().await;
+
+    // Fail now rather than on the first upload, possibly an hour into the run
+    if !args.no_preflight && !args.metadata_only {
+        let prefixes = std::iter::once(&args.prefix).chain(&args.mirror_raw);
+        for prefix in prefixes {
+            let checked = s3::preflight(
+                &s3_client,
+                &args.bucket,
+                prefix,
+                storage.recent,
+                s3_compat,
+                Some(&tags),
+            )
+            .await;
+            if let Err(e) = checked {
+                eprintln!(
+                    "Permission check of s3://{}/{prefix} failed: {e}",
+                    args.bucket
+                );
+                eprintln!(
+                    "  Runs need s3:PutObject, s3:CreateMultipartUpload, s3:UploadPart, \
+                     s3:CompleteMultipartUpload and s3:AbortMultipartUpload there \
+                     (--no-preflight skips this check)"
+                );
+                return Ok(e.exit_code());
+            }
+        }
+        println!(
+            "Write permissions checked on s3://{}/{}",
+            args.bucket, args.prefix
+        );
+    }
+
+    let mut replicas = Vec::new();
+    for target in &args.replicas {
+        replicas.push(Replica {
+            s3: replica_client(&destination, s3_compat, &s3_client, target).await,
+            target: target.clone(),
+        });
+    }
+
+    // Initialize HTTP client
+    let http_client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(600))
+        .build()?;
+
+    // Limits of the source's provider, unless overridden
+    let templates: Vec<String> = args
+        .models
+        .iter()
+        .flat_map(|&model| args.source_templates(model))
+        .map(|template| template.to_string())
+        .collect();
+    let politeness = Politeness::for_sources(templates.iter().map(String::as_str));
+    let concurrency = args
+        .concurrency
+        .or(politeness.as_ref().map(|p| p.concurrency))
+        .unwrap_or(1)
+        .max(1);
+    let concurrency = if args.deterministic {
+        println!("Deterministic output: one file at a time, volatile metadata set to the epoch");
+        1
+    } else {
+        concurrency
+    };
+    // The profiler samples the whole process: one file at a time per profile
+    #[cfg(feature = "profiling")]
+    let concurrency = match &args.profile_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create --profile-dir {}", dir.display()))?;
+            println!("CPU profiles: {} (one file at a time)", dir.display());
+            1
+        }
+        None => concurrency,
+    };
+    let request_interval = args
+        .request_interval
+        .or(politeness.as_ref().map(|p| p.request_interval))
+        .filter(|interval| !interval.is_zero());
+    let max_attempts = args
+        .max_attempts
+        .or(politeness.as_ref().map(|p| p.max_attempts))
+        .unwrap_or(MAX_TRANSIENT_ATTEMPTS);
+    if let Some(politeness) = &politeness {
+        println!(
+            "Source limits ({}): {concurrency} cycles at once, {} between requests, {max_attempts} attempts",
+            politeness.provider,
+            request_interval.map_or("no delay".to_string(), |interval| format!("{interval:?}")),
+        );
+    }
+
+    let ctx = RunContext {
+        http: http_client,
+        s3: s3_client,
+        bucket: args.bucket.clone(),
+        prefix: args.prefix.clone(),
+        key_template: args.key_template.clone(),
+        source_templates: args
+            .models
+            .iter()
+            .map(|&model| (model, args.source_templates(model)))
+            .collect(),
+        routes: if args.mirror_only {
+            Arc::new([])
+        } else {
+            args.routes.clone().into()
+        },
+        storage,
+        naming,
+        s3_compat,
+        keep_partial: args.keep_partial,
+        chunk_size: args.chunk_size as usize,
+        catalog,
+        catalog_targets: Mutex::default(),
+        retry_log,
+        requeue_rounds: args.requeue_rounds,
+        pacer: request_interval.map(|interval| Arc::new(RequestPacer::new(interval))),
+        max_attempts,
+        filter_report: args
+            .filter_report
+            .then(|| Mutex::new(FilterReport::default())),
+        budget: Budget::new(args.max_bytes),
+        memory: MemoryUse::new(args.max_memory),
+        upload_bandwidth: args
+            .max_upload_bandwidth
+            .map(|rate| Arc::new(BandwidthLimiter::new(rate))),
+        tags: Arc::new(tags),
+        limiter: AdaptiveLimiter::new(concurrency, Duration::from_secs(args.throttle_cooldown)),
+        publication_delay: chrono::TimeDelta::hours(args.publication_delay.into()),
+        stale_only: args.stale_only,
+        skip_existing: args.skip_existing,
+        up_to_date: AtomicUsize::new(0),
+        augment: args.augment,
+        deterministic: args.deterministic,
+        cache,
+        #[cfg(feature = "profiling")]
+        profile_dir: args.profile_dir.clone(),
+        provenance: args.provenance,
+        max_object_size: args.max_object_size,
+        quantize: args.quantize,
+        bbox: args.bbox,
+        sample: args.sample,
+        sampled: Arc::new(AtomicU64::new(0)),
+        metadata_only: args.metadata_only,
+        verify_upload: args.verify_upload,
+        on_parse_error: args.on_parse_error,
+        idx_early_stop: args.idx_early_stop,
+        idx_ranges: args.idx_ranges,
+        idx_cache: args.idx_cache.clone(),
+        mirror_raw: args.mirror_raw.clone(),
+        daily: args
+            .daily_key_template
+            .clone()
+            .map(|template| DailyObjects::new(template, &targets)),
+        checkpoint: checkpoint.map(tokio::sync::Mutex::new),
+        latest: args
+            .latest_pointer
+            .then(|| LatestPointers::new(&targets, args.models.len() > 1)),
+        replicas,
+        replica_failures: AtomicUsize::new(0),
+        interrupted: AtomicBool::new(false),
+        stdout: Styler::stdout(args.color),
+        stderr: Styler::stderr(args.color),
+        size_units: args.size_units,
+    };
+    // Saved before any work, so an interrupted run can always be resumed
+    save_checkpoint(&ctx, |_| {}).await;
+
+    let skipped = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let outages = OutageCounts::default();
+    let exit_code = AtomicU8::new(0);
+    let halted = AtomicBool::new(false);
+    // Targets left for `resume` after a spot interruption notice
+    let interrupted = AtomicUsize::new(0);
+    let started = Instant::now();
+    let progress = Progress::new(&targets, started);
+
+    // Failed files queued for another round at the end of the run (`--requeue-rounds`)
+    let requeued = Mutex::new(Vec::new());
+    let work = async {
+        let mut pending = targets;
+        for round in 0.. {
+            process_round(
+                &ctx,
+                pending,
+                concurrency,
+                round,
+                &RoundState {
+                    skipped: &skipped,
+                    failed: &failed,
+                    outages: &outages,
+                    exit_code: &exit_code,
+                    halted: &halted,
+                    interrupted: &interrupted,
+                    progress: &progress,
+                    requeued: &requeued,
+                    dead_letter: dead_letter.as_ref(),
+                },
+            )
+            .await;
+            pending = std::mem::take(&mut *requeued.lock().unwrap());
+            if pending.is_empty() {
+                break;
+            }
+            println!();
+            println!(
+                "Retrying {} failed files (round {} of {})",
+                pending.len(),
+                round + 1,
+                ctx.requeue_rounds
+            );
+        }
+    };
+
+    let reports = async {
+        let Some(url) = &args.progress_webhook else {
+            return std::future::pending().await;
+        };
+        let mut interval = tokio::time::interval(args.progress_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let failed = failed.load(Ordering::Relaxed);
+            let report = progress.report(Instant::now(), ctx.budget.downloaded(), failed, false);
+            post_progress(&ctx, url, &report).await;
+        }
+    };
+    let spot_notice = async {
+        if args.spot {
+            watch_spot(&ctx).await;
+        }
+        // Keep going while the files in progress stop
+        std::future::pending::<()>().await
+    };
+    tokio::select! {
+        () = work => {}
+        () = reports => {}
+        () = spot_notice => {}
+    }
+    if let Some(url) = &args.progress_webhook {
+        let failed = failed.load(Ordering::Relaxed);
+        let report = progress.report(Instant::now(), ctx.budget.downloaded(), failed, true);
+        post_progress(&ctx, url, &report).await;
+    }
+
+    let interrupted = interrupted.into_inner();
+    let finished = skipped.load(Ordering::Relaxed) == 0 && interrupted == 0;
+    save_checkpoint(&ctx, |c| c.finished = finished).await;
+    if interrupted > 0 {
+        println!();
+        println!(
+            "Interrupted by a spot interruption notice: {interrupted} files left, finish the run with `resume`"
+        );
+        exit_code.store(EXIT_RESUMABLE, Ordering::Relaxed);
+    }
+
+    let up_to_date = ctx.up_to_date.load(Ordering::Relaxed);
+    if up_to_date > 0 {
+        println!();
+        let option = if ctx.augment {
+            "--augment"
+        } else if ctx.skip_existing {
+            "--skip-existing"
+        } else {
+            "--stale-only"
+        };
+        println!("{up_to_date} files already up to date ({option})");
+    }
+
+    let skipped = skipped.into_inner();
+    if halted.into_inner() {
+        println!();
+        println!("Run stopped after an access error: {skipped} files not processed");
+    } else if skipped > 0 {
+        println!();
+        println!(
+            "Download budget reached after {} (--max-bytes): {skipped} files not processed",
+            format_size(ctx.budget.downloaded(), ctx.size_units)
+        );
+    }
+
+    if let Some(daily) = ctx.daily {
+        let unfinished = daily.abandon().await;
+        if unfinished > 0 {
+            println!();
+            println!(
+                "{unfinished} days not written to daily objects: the run stopped before their end"
+            );
+        }
+    }
+
+    if let Some(report) = ctx.filter_report {
+        let report = report.into_inner().unwrap();
+        println!();
+        if report.is_empty() {
+            println!("Filter report: no file completed");
+        } else {
+            println!("Filter report (completed files):");
+            print!("{}", report.render(ctx.size_units));
+        }
+    }
+
+    let not_yet_published = outages.not_yet_published.into_inner();
+    if not_yet_published > 0 {
+        println!();
+        println!(
+            "{not_yet_published} files not published yet (cycles less than {}h old), skipped",
+            args.publication_delay
+        );
+    }
+
+    let failed = failed.into_inner();
+    if failed > 0 {
+        println!();
+        println!("{failed} files failed:");
+        let causes = [
+            (outages.missing.into_inner(), "missing from the source"),
+            (
+                outages.source_down.into_inner(),
+                "source down (network, HTTP 5xx, throttling)",
+            ),
+            (outages.other.into_inner(), "other errors"),
+        ];
+        for (count, cause) in causes.into_iter().filter(|(count, _)| *count > 0) {
+            println!("  {count} {cause}");
+        }
+    }
+
+    let replica_failures = ctx.replica_failures.load(Ordering::Relaxed);
+    if replica_failures > 0 {
+        println!();
+        println!("{replica_failures} copies to the replicas failed (--replicate-to)");
+        if exit_code.load(Ordering::Relaxed) == 0 {
+            exit_code.store(EXIT_OTHER_FAILURE, Ordering::Relaxed);
+        }
+    }
+
+    let heap = heap_usage();
+    let buffers = format!(
+        "buffers up to {} parsing, {} uploading",
+        format_size(ctx.memory.parser_peak() as u64, ctx.size_units),
+        format_size(ctx.memory.upload_peak() as u64, ctx.size_units)
+    );
+    println!();
+    // The heap is only counted by the binary's allocator
+    if heap.allocated > 0 {
+        println!(
+            "Memory: {} heap at most, {} allocated ({} per file); {buffers}",
+            format_size(heap.peak as u64, ctx.size_units),
+            format_size(heap.allocated, ctx.size_units),
+            format_size(heap.allocated / ctx.memory.started().max(1), ctx.size_units),
+        );
+    } else {
+        println!("Memory: {buffers}");
+    }
+    if let Some(max) = args.max_memory {
+        let held_back = ctx.memory.held_back();
+        if held_back > 0 || heap.peak as u64 > max {
+            println!(
+                "  --max-memory {}: {held_back} files held back while the heap was over it",
+                format_size(max, ctx.size_units)
+            );
+        }
+    }
+
+    println!();
+    let mut attribution = vec![format!("run {run_id}")];
+    if let Some(cost_center) = &args.cost_center {
+        attribution.push(format!("cost center {cost_center}"));
+    }
+    println!(
+        "Uploaded {} ({})",
+        format_size(ctx.tags.uploaded(), ctx.size_units),
+        attribution.join(", ")
+    );
+    println!("Done in {}", format_duration(started.elapsed()));
+
+    Ok(exit_code.into_inner())
+}
@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use aws_sdk_s3::Client;
+use chrono::{Datelike, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::manifest::{Manifest, MessageEntry};
+use crate::s3::{get_object, put_object, S3Compat, S3MultipartUploader, StorageClass};
+
+/// Suffix appended to a compacted object key to name its index.
+pub const INDEX_SUFFIX: &str = ".index.json";
+
+/// Index uploaded next to a compacted object: where each original object's
+/// bytes and messages now live.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactIndex {
+    /// Key of the compacted object.
+    pub key: String,
+    pub objects: Vec<IndexEntry>,
+}
+
+/// An original object within a compacted one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Key the object was stored under.
+    pub key: String,
+    /// Byte offset of the object in the compacted object.
+    pub offset: u64,
+    pub length: u64,
+    /// Source URL recorded in the object's manifest.
+    pub source: String,
+    /// Messages of the object, with offsets in the compacted object
+    /// (entries with `stored_in` keep pointing to the object holding them).
+    pub messages: Vec<MessageEntry>,
+}
+
+impl CompactIndex {
+    pub fn key_for(compacted_key: &str) -> String {
+        format!("{compacted_key}{INDEX_SUFFIX}")
+    }
+}
+
+/// Objects of one directory and month, merged into a single object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    /// Key of the compacted object, e.g. `compacted/gfs/2020-01.grb2`.
+    pub key: String,
+    /// Original objects with their manifests, by valid time.
+    pub objects: Vec<(String, Manifest)>,
+}
+
+/// First valid time of an object, from its manifest.
+fn valid_time(manifest: &Manifest) -> Option<NaiveDateTime> {
+    manifest.messages.iter().find_map(|m| m.valid_time)
+}
+
+/// Group objects by directory (relative to `prefix`) and month of valid time.
+///
+/// Objects of `{into}/<directory>/<yyyy>-<mm>.grb2` never mix directories,
+/// so routes and models written to their own prefixes stay apart. Objects
+/// without a valid time, or valid at or after `before` (the month still
+/// being archived), are left out.
+pub fn plan_groups(
+    objects: Vec<(String, Manifest)>,
+    prefix: &str,
+    into: &str,
+    before: NaiveDateTime,
+) -> Vec<Group> {
+    let mut groups: BTreeMap<String, Vec<(NaiveDateTime, String, Manifest)>> = BTreeMap::new();
+
+    for (key, manifest) in objects {
+        let Some(time) = valid_time(&manifest) else {
+            continue;
+        };
+        if time >= before {
+            continue;
+        }
+        let relative = key.strip_prefix(prefix).unwrap_or(&key);
+        let directory = relative
+            .trim_start_matches('/')
+            .rsplit_once('/')
+            .map_or("", |(directory, _)| directory);
+
+        let mut parts: Vec<&str> = Vec::new();
+        for part in [into.trim_end_matches('/'), directory] {
+            if !part.is_empty() {
+                parts.push(part);
+            }
+        }
+        let month = format!("{:04}-{:02}.grb2", time.year(), time.month());
+        parts.push(&month);
+
+        groups
+            .entry(parts.join("/"))
+            .or_default()
+            .push((time, key, manifest));
+    }
+
+    groups
+        .into_iter()
+        .map(|(key, mut objects)| {
+            objects.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+            Group {
+                key,
+                objects: objects
+                    .into_iter()
+                    .map(|(_, key, manifest)| (key, manifest))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Keys of the objects holding messages of other objects (their `stored_in`,
+/// set by deduplication): deleting one would lose those messages.
+pub fn referenced<'a>(messages: impl IntoIterator<Item = &'a MessageEntry>) -> BTreeSet<&'a str> {
+    messages
+        .into_iter()
+        .filter_map(|message| message.stored_in.as_deref())
+        .collect()
+}
+
+/// Messages of a manifest, moved to `offset` in the compacted object.
+fn rebase(manifest: &Manifest, offset: u64) -> Vec<MessageEntry> {
+    manifest
+        .messages
+        .iter()
+        .cloned()
+        .map(|mut message| {
+            if message.stored_in.is_none() {
+                message.offset += offset;
+            }
+            message
+        })
+        .collect()
+}
+
+/// Concatenate the objects of a group into one object, then upload its index.
+pub async fn compact_group(
+    client: &Client,
+    bucket: &str,
+    group: &Group,
+    storage_class: StorageClass,
+    compat: S3Compat,
+) -> Result<CompactIndex> {
+    let mut uploader =
+        S3MultipartUploader::new(client.clone(), bucket, &group.key, storage_class, compat);
+    uploader.set_metadata("compacted-objects", group.objects.len().to_string());
+
+    let mut index = CompactIndex {
+        key: group.key.clone(),
+        objects: Vec::with_capacity(group.objects.len()),
+    };
+    let mut offset = 0;
+    for (key, manifest) in &group.objects {
+        let bytes = match get_object(client, bucket, key).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = uploader.abort().await;
+                return Err(e);
+            }
+        };
+        if let Err(e) = uploader.write(&bytes).await {
+            let _ = uploader.abort().await;
+            return Err(e);
+        }
+
+        index.objects.push(IndexEntry {
+            key: key.clone(),
+            offset,
+            length: bytes.len() as u64,
+            source: manifest.source.clone(),
+            messages: rebase(manifest, offset),
+        });
+        offset += bytes.len() as u64;
+    }
+
+    uploader.complete().await?;
+    put_object(
+        client,
+        bucket,
+        &CompactIndex::key_for(&group.key),
+        serde_json::to_vec_pretty(&index).expect("index serializes to JSON"),
+        "application/json",
+    )
+    .await?;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::timed_entry;
+    use chrono::NaiveDate;
+
+    fn manifest(key: &str, month: u32, offsets: &[u64]) -> (String, Manifest) {
+        let time = NaiveDate::from_ymd_opt(2020, month, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut manifest = Manifest::new("http://example.com/gfs", key);
+        for &offset in offsets {
+            manifest.messages.push(timed_entry(2, time, offset, 10));
+        }
+        (key.to_string(), manifest)
+    }
+
+    #[test]
+    fn test_groups_by_directory_and_month() {
+        let groups = plan_groups(
+            vec![
+                manifest("archive/wind/wind_20200131_00.grb2", 1, &[0]),
+                manifest("archive/wind/wind_20200101_00.grb2", 1, &[0, 10]),
+                manifest("archive/gust/wind_20200101_00.grb2", 1, &[0]),
+                manifest("archive/wind/empty.grb2", 1, &[]),
+                manifest("archive/wind/wind_20200201_00.grb2", 2, &[0]),
+            ],
+            "archive/",
+            "compacted/",
+            NaiveDate::from_ymd_opt(2020, 2, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        );
+
+        let keys: Vec<_> = groups.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            ["compacted/gust/2020-01.grb2", "compacted/wind/2020-01.grb2"]
+        );
+        // Ordered by valid time; objects without messages or of the
+        // current month are left out
+        let wind: Vec<_> = groups[1].objects.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            wind,
+            [
+                "archive/wind/wind_20200101_00.grb2",
+                "archive/wind/wind_20200131_00.grb2"
+            ]
+        );
+
+        let (_, first) = &groups[1].objects[0];
+        let offsets: Vec<_> = rebase(first, 100).iter().map(|m| m.offset).collect();
+        assert_eq!(offsets, [100, 110]);
+    }
+
+    #[test]
+    fn test_referenced_objects() {
+        let (_, mut manifest) = manifest("archive/wind/wind_20200102_00.grb2", 1, &[0, 10, 20]);
+        manifest.messages[1].stored_in = Some("archive/wind/wind_20200101_00.grb2".to_string());
+        manifest.messages[2].stored_in = Some("archive/wind/wind_20200101_00.grb2".to_string());
+        assert_eq!(
+            referenced(&manifest.messages),
+            BTreeSet::from(["archive/wind/wind_20200101_00.grb2"])
+        );
+    }
+}
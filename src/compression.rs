@@ -0,0 +1,103 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_compression::tokio::bufread::BzDecoder;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Compression of a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// bzip2, used by DWD for its per-variable ICON files.
+    Bzip2,
+}
+
+impl Compression {
+    /// Guess the compression of a file from its URL extension.
+    pub fn from_url(url: &str) -> Self {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if path.ends_with(".bz2") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Decompress a source byte stream, adding the bytes received (before
+/// decompression) to `received` as they arrive.
+///
+/// A compressed stream cut short fails with an `UnexpectedEof` error once its
+/// buffered content has been yielded.
+pub fn decode<S>(
+    stream: S,
+    compression: Compression,
+    received: Arc<AtomicU64>,
+) -> BoxStream<'static, io::Result<Bytes>>
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let stream = stream.inspect_ok(move |chunk| {
+        received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    });
+    match compression {
+        Compression::None => stream.boxed(),
+        Compression::Bzip2 => ReaderStream::new(BzDecoder::new(StreamReader::new(stream))).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::BzEncoder;
+    use tokio::io::AsyncReadExt;
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        futures::executor::block_on(BzEncoder::new(data).read_to_end(&mut compressed)).unwrap();
+        compressed
+    }
+
+    fn run(chunks: Vec<io::Result<Bytes>>) -> (Vec<io::Result<Vec<u8>>>, u64) {
+        let received = Arc::new(AtomicU64::new(0));
+        let stream = decode(
+            futures::stream::iter(chunks),
+            Compression::Bzip2,
+            received.clone(),
+        );
+        let out = futures::executor::block_on(stream.map_ok(|b| b.to_vec()).collect());
+        (out, received.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn test_bzip2_decoding_counts_compressed_bytes() {
+        assert_eq!(
+            Compression::from_url("https://example.com/U_10M.grib2.bz2"),
+            Compression::Bzip2
+        );
+        assert_eq!(
+            Compression::from_url("https://example.com/gfs.grib2?x=.bz2"),
+            Compression::None
+        );
+
+        let data = b"GRIB message bytes ".repeat(100);
+        let compressed = bzip2(&data);
+        let (head, tail) = compressed.split_at(compressed.len() / 2);
+
+        let (out, received) = run(vec![
+            Ok(Bytes::copy_from_slice(head)),
+            Ok(Bytes::copy_from_slice(tail)),
+        ]);
+        let decoded: Vec<u8> = out.into_iter().flat_map(Result::unwrap).collect();
+        assert_eq!(decoded, data);
+        assert_eq!(received, compressed.len() as u64);
+
+        // Truncated archive
+        let (out, _) = run(vec![Ok(Bytes::copy_from_slice(head))]);
+        assert!(out.last().unwrap().is_err());
+    }
+}
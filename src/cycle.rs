@@ -0,0 +1,747 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike, Weekday};
+use chrono_tz::Tz;
+
+use crate::model::Model;
+
+/// A model cycle, identified by its model and UTC initialization time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cycle {
+    pub model: Model,
+    pub time: NaiveDateTime,
+}
+
+impl Cycle {
+    pub fn date(&self) -> NaiveDate {
+        self.time.date()
+    }
+
+    /// Two-digit cycle hour ("00", "06", ...).
+    pub fn hour(&self) -> String {
+        format!("{:02}", self.time.hour())
+    }
+}
+
+impl fmt::Display for Cycle {
+    /// `2020-01-01 06`, prefixed with the model name for models other than GFS.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.model != Model::Gfs {
+            write!(f, "{} ", self.model)?;
+        }
+        write!(f, "{} {}", self.date(), self.hour())
+    }
+}
+
+/// One source file to process: a cycle and one of its forecast steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Task {
+    pub cycle: Cycle,
+    pub forecast_hour: u32,
+}
+
+impl Task {
+    /// Time the forecast step is valid for.
+    pub fn valid_time(&self) -> NaiveDateTime {
+        self.cycle.time + Duration::hours(self.forecast_hour as i64)
+    }
+
+    /// `anl` for the analysis (forecast hour 0), `fNNN` for a forecast step.
+    pub fn lead(&self) -> String {
+        match self.forecast_hour {
+            0 => "anl".to_string(),
+            hour => format!("f{hour:03}"),
+        }
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} f{:03}", self.cycle, self.forecast_hour)
+    }
+}
+
+/// Expand cycles into one task per requested forecast hour.
+pub fn plan_tasks(cycles: &[Cycle], forecast_hours: &[u32]) -> Vec<Task> {
+    cycles
+        .iter()
+        .flat_map(|&cycle| {
+            forecast_hours.iter().map(move |&forecast_hour| Task {
+                cycle,
+                forecast_hour,
+            })
+        })
+        .collect()
+}
+
+/// Forecast hours giving continuous hourly coverage: each cycle's analysis
+/// plus its short forecasts up to the next cycle (f000..f005 for 6-hourly cycles),
+/// every `step` hours for a source that publishes no hour in between.
+pub fn hourly_coverage_hours(interval_hours: u32, step: u32) -> Vec<u32> {
+    (0..interval_hours).step_by(step.max(1) as usize).collect()
+}
+
+/// Forecast hours of each cycle (`--forecast-hours`), e.g. `0,3,6..120/3`: hours
+/// and inclusive ranges with an optional step, in increasing order without repeats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForecastHours {
+    pub hours: Vec<u32>,
+    source: String,
+}
+
+impl FromStr for ForecastHours {
+    type Err = String;
+
+    /// Parse `HOUR` or `FIRST..LAST[/STEP]` items separated by commas.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid =
+            |item: &str| format!("invalid forecast hours '{item}', expected e.g. 0,3,6..120/3");
+        let mut hours = Vec::new();
+        for item in s.split(',').map(str::trim) {
+            let parse = |n: &str| n.trim().parse::<u32>().map_err(|_| invalid(item));
+            let Some((first, rest)) = item.split_once("..") else {
+                hours.push(parse(item)?);
+                continue;
+            };
+            let (last, step) = match rest.split_once('/') {
+                Some((last, step)) => (parse(last)?, parse(step)?),
+                None => (parse(rest)?, 1),
+            };
+            let first = parse(first)?;
+            if step == 0 || first > last {
+                return Err(invalid(item));
+            }
+            hours.extend((first..=last).step_by(step as usize));
+        }
+        hours.sort();
+        hours.dedup();
+        Ok(Self {
+            hours,
+            source: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ForecastHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// One output object, with the tasks able to provide it in order of preference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub candidates: Vec<Task>,
+}
+
+impl Target {
+    /// Preferred task; its valid time names the output.
+    pub fn primary(&self) -> &Task {
+        &self.candidates[0]
+    }
+}
+
+/// Turn planned tasks into targets, one per task.
+pub fn plan_targets(tasks: &[Task]) -> Vec<Target> {
+    tasks
+        .iter()
+        .map(|&task| Target {
+            candidates: vec![task],
+        })
+        .collect()
+}
+
+/// Best-available composite: one target per distinct valid time, preferring
+/// the shortest lead time.
+///
+/// Besides the planned tasks, earlier cycles of the same model covering the
+/// same valid time with a lead of at most `max_lead_hours` are added as
+/// fallbacks, so a missing cycle is replaced by the freshest forecast still available.
+pub fn plan_best_available(tasks: &[Task], max_lead_hours: u32) -> Vec<Target> {
+    let mut by_valid_time: BTreeMap<(Model, NaiveDateTime), BTreeSet<Task>> = BTreeMap::new();
+
+    for task in tasks {
+        let model = task.cycle.model;
+        let interval_hours = model.cycle_interval_hours();
+        let candidates = by_valid_time.entry((model, task.valid_time())).or_default();
+        candidates.insert(*task);
+
+        let mut fallback = *task;
+        while fallback.forecast_hour + interval_hours <= max_lead_hours {
+            fallback = Task {
+                cycle: Cycle {
+                    model,
+                    time: fallback.cycle.time - Duration::hours(interval_hours as i64),
+                },
+                forecast_hour: fallback.forecast_hour + interval_hours,
+            };
+            candidates.insert(fallback);
+        }
+    }
+
+    by_valid_time
+        .into_values()
+        .map(|candidates| {
+            let mut candidates: Vec<Task> = candidates.into_iter().collect();
+            candidates.sort_by_key(|task| task.forecast_hour);
+            Target { candidates }
+        })
+        .collect()
+}
+
+/// Resolve a date expression against `today`: a date (`2020-01-01`), `today`,
+/// `yesterday` or `tomorrow`, followed by an optional offset in days or weeks
+/// (`yesterday-1w`); a bare offset counts from today (`-7d`).
+pub fn resolve_date(expr: &str, today: NaiveDate) -> std::result::Result<NaiveDate, String> {
+    let invalid = || {
+        format!("invalid date '{expr}' (use YYYY-MM-DD, today, yesterday or an offset such as -7d)")
+    };
+    let expr = expr.trim();
+    let (base, offset) = if expr.starts_with(|c: char| c.is_ascii_digit()) {
+        let date = expr.get(..10).ok_or_else(invalid)?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?;
+        (date, &expr[10..])
+    } else if let Some(offset) = expr.strip_prefix("today") {
+        (today, offset)
+    } else if let Some(offset) = expr.strip_prefix("yesterday") {
+        (today - Duration::days(1), offset)
+    } else if let Some(offset) = expr.strip_prefix("tomorrow") {
+        (today + Duration::days(1), offset)
+    } else {
+        (today, expr)
+    };
+    if offset.is_empty() {
+        return Ok(base);
+    }
+
+    let sign = match offset.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return Err(invalid()),
+    };
+    let (count, days) = if let Some(count) = offset[1..].strip_suffix('d') {
+        (count, 1)
+    } else if let Some(count) = offset[1..].strip_suffix('w') {
+        (count, 7)
+    } else {
+        return Err(invalid());
+    };
+    let count: u32 = count.parse().map_err(|_| invalid())?;
+    base.checked_add_signed(Duration::days(sign * count as i64 * days))
+        .ok_or_else(invalid)
+}
+
+/// Plan every cycle of `model` (`Model::cycle_interval_hours()` apart from 00Z)
+/// whose initialization time falls within the date range.
+///
+/// `start` and `end` are calendar days in `tz`: the range covers local
+/// midnight on `start` up to (excluding) local midnight after `end`.
+/// When `valid_hours` is given, only cycles whose local hour is listed are kept.
+pub fn plan_cycles(
+    start: NaiveDate,
+    end: NaiveDate,
+    tz: Tz,
+    valid_hours: Option<&[u32]>,
+    model: Model,
+) -> Result<Vec<Cycle>> {
+    let interval = model.cycle_interval_hours().max(1) as i64;
+    let range_start = local_midnight_utc(start, tz)?;
+    let range_end = local_midnight_utc(end + Duration::days(1), tz)?;
+
+    // Round down to the first cycle at or before the range start
+    let day_start = range_start.date().and_hms_opt(0, 0, 0).unwrap();
+    let offset = (range_start - day_start).num_hours() / interval;
+    let mut time = day_start + Duration::hours(offset * interval);
+
+    let mut cycles = Vec::new();
+    while time < range_end {
+        let local_hour = tz.from_utc_datetime(&time).hour();
+        let wanted = valid_hours.is_none_or(|hours| hours.contains(&local_hour));
+        if time >= range_start && wanted {
+            cycles.push(Cycle { model, time });
+        }
+        time += Duration::hours(interval);
+    }
+    Ok(cycles)
+}
+
+/// Part of a range processed by one of several workers (`--shard 3/8`): the
+/// `index`-th (1-based) of every `count` planned cycles, so machines given the
+/// same range and the same count split it without coordinating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Keep the cycles of this shard, `cycles` being in planning order.
+    pub fn select(&self, cycles: &mut Vec<Cycle>) {
+        let mut position = 0;
+        cycles.retain(|_| {
+            position += 1;
+            (position - 1) % self.count == self.index - 1
+        });
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    /// Parse `INDEX/COUNT`, e.g. `3/8`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("invalid shard '{s}', expected INDEX/COUNT (e.g. 3/8)"))?;
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid shard '{s}', expected INDEX/COUNT (e.g. 3/8)"))
+        };
+        let (index, count) = (parse(index)?, parse(count)?);
+        if index == 0 || index > count {
+            return Err(format!(
+                "invalid shard '{s}': the index must be between 1 and {count}"
+            ));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Rules layered on the date range (`--exclude`, `--include-weekdays`), applied
+/// to the local time of the cycles like the range itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CycleRules {
+    /// Patterns of cycles left out, matching their local `YYYY-MM-DD` date or
+    /// `YYYY-MM-DD HH` label, with `*` and `?` wildcards (e.g. `2021-02-1*`).
+    pub exclude: Vec<String>,
+    /// Days of the week kept; every day if empty.
+    pub weekdays: Vec<Weekday>,
+}
+
+impl CycleRules {
+    pub fn is_empty(&self) -> bool {
+        self.exclude.is_empty() && self.weekdays.is_empty()
+    }
+
+    /// Whether a cycle passes the rules, in `tz`.
+    pub fn allows(&self, cycle: &Cycle, tz: Tz) -> bool {
+        let local = tz.from_utc_datetime(&cycle.time).naive_local();
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&local.weekday()) {
+            return false;
+        }
+        let date = local.format("%Y-%m-%d").to_string();
+        let label = local.format("%Y-%m-%d %H").to_string();
+        !self.exclude.iter().any(|pattern| {
+            wildcard_match(pattern.as_bytes(), date.as_bytes())
+                || wildcard_match(pattern.as_bytes(), label.as_bytes())
+        })
+    }
+
+    /// Keep the cycles passing the rules.
+    pub fn select(&self, cycles: &mut Vec<Cycle>, tz: Tz) {
+        cycles.retain(|cycle| self.allows(cycle, tz));
+    }
+}
+
+/// Check an `--exclude` pattern: digits, `-`, a space before the hour and wildcards.
+pub fn parse_cycle_pattern(s: &str) -> std::result::Result<String, String> {
+    let pattern = s.trim();
+    if pattern.is_empty()
+        || !pattern
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | ' ' | '*' | '?'))
+    {
+        return Err(format!(
+            "invalid cycle pattern '{s}' (expected e.g. 2021-02-1* or \"2021-02-01 06\")"
+        ));
+    }
+    Ok(pattern.to_string())
+}
+
+/// Match `text` against `pattern`, where `*` stands for any run of characters
+/// and `?` for one.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| wildcard_match(rest, &text[skip..])),
+        Some((&c, rest)) => text
+            .split_first()
+            .is_some_and(|(&t, tail)| (c == b'?' || c == t) && wildcard_match(rest, tail)),
+    }
+}
+
+/// Thinning of a run for quick previews (`--sample`): one of every N messages
+/// matched by a route (`1/8`), or one cycle per day (`1/day`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sample {
+    Messages(u64),
+    Daily,
+}
+
+impl Sample {
+    /// With `Daily`, keep the first cycle of each local day in `tz` and model,
+    /// `cycles` being in planning order.
+    pub fn select(&self, cycles: &mut Vec<Cycle>, tz: Tz) {
+        if *self != Sample::Daily {
+            return;
+        }
+        let mut days = BTreeSet::new();
+        cycles.retain(|cycle| {
+            let day = tz.from_utc_datetime(&cycle.time).date_naive();
+            days.insert((cycle.model, day))
+        });
+    }
+
+    /// N when keeping one of every N matched messages.
+    pub fn messages(&self) -> Option<u64> {
+        match self {
+            Sample::Messages(every) => Some(*every),
+            Sample::Daily => None,
+        }
+    }
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    /// Parse `1/N` (e.g. `1/8`) or `1/day`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("invalid sample '{s}', expected 1/N (e.g. 1/8) or 1/day");
+        let every = s.trim().strip_prefix("1/").ok_or_else(invalid)?.trim();
+        if every == "day" {
+            return Ok(Sample::Daily);
+        }
+        match every.parse::<u64>() {
+            Ok(every) if every > 0 => Ok(Sample::Messages(every)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sample::Messages(every) => write!(f, "1/{every}"),
+            Sample::Daily => write!(f, "1/day"),
+        }
+    }
+}
+
+/// UTC instant of local midnight starting `date` in `tz`.
+fn local_midnight_utc(date: NaiveDate, tz: Tz) -> Result<NaiveDateTime> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    let local = tz
+        .from_local_datetime(&midnight)
+        .earliest()
+        // Midnight skipped by a DST transition: the day starts an hour later
+        .or_else(|| {
+            tz.from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+        })
+        .with_context(|| format!("{date} has no local midnight in {tz}"))?;
+    Ok(local.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_plan_utc_day() {
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let hours: Vec<_> = cycles.iter().map(Cycle::hour).collect();
+        assert_eq!(hours, ["00", "06", "12", "18"]);
+        assert!(cycles.iter().all(|c| c.date() == date("2020-01-01")));
+    }
+
+    #[test]
+    fn test_plan_local_day_shifts_cycles() {
+        // Paris is UTC+1 in January: local 2020-01-01 is 2019-12-31T23:00Z..2020-01-01T23:00Z
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            chrono_tz::Europe::Paris,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let labels: Vec<_> = cycles.iter().map(Cycle::to_string).collect();
+        assert_eq!(
+            labels,
+            [
+                "2020-01-01 00",
+                "2020-01-01 06",
+                "2020-01-01 12",
+                "2020-01-01 18"
+            ]
+        );
+
+        // New York is UTC-5: local day runs 05:00Z..05:00Z next day
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            chrono_tz::America::New_York,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let labels: Vec<_> = cycles.iter().map(Cycle::to_string).collect();
+        assert_eq!(
+            labels,
+            [
+                "2020-01-01 06",
+                "2020-01-01 12",
+                "2020-01-01 18",
+                "2020-01-02 00"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hourly_coverage_is_continuous() {
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        assert_eq!(hourly_coverage_hours(6, 3), [0, 3]);
+        let tasks = plan_tasks(&cycles, &hourly_coverage_hours(6, 1));
+        assert_eq!(tasks.len(), 24);
+
+        let valid: Vec<_> = tasks.iter().map(|t| t.valid_time().hour()).collect();
+        assert_eq!(valid, (0..24).collect::<Vec<_>>());
+        assert_eq!(tasks[7].to_string(), "2020-01-01 06 f001");
+    }
+
+    #[test]
+    fn test_forecast_hours() {
+        let hours: ForecastHours = "0,3,6..120/3".parse().unwrap();
+        assert_eq!(hours.hours.len(), 41);
+        assert_eq!(hours.hours[..4], [0, 3, 6, 9]);
+        assert_eq!(hours.hours.last(), Some(&120));
+        let hours: ForecastHours = "12, 0..2, 1".parse().unwrap();
+        assert_eq!(hours.hours, [0, 1, 2, 12]);
+        assert_eq!(hours.to_string(), "12, 0..2, 1");
+        for invalid in ["", "6..0", "0..12/0", "f003", "0..", "-3"] {
+            assert!(invalid.parse::<ForecastHours>().is_err(), "{invalid}");
+        }
+
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let tasks = plan_tasks(
+            &cycles[..1],
+            &"0..6/3".parse::<ForecastHours>().unwrap().hours,
+        );
+        let leads: Vec<_> = tasks.iter().map(Task::lead).collect();
+        assert_eq!(leads, ["anl", "f003", "f006"]);
+    }
+
+    #[test]
+    fn test_best_available_dedups_valid_times() {
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        // 00Z f006 and 06Z f000 are both valid at 06Z
+        let tasks = plan_tasks(&cycles[..2], &[0, 6]);
+        let targets = plan_best_available(&tasks, 6);
+
+        let valid: Vec<_> = targets
+            .iter()
+            .map(|t| t.primary().valid_time().hour())
+            .collect();
+        assert_eq!(valid, [0, 6, 12]);
+
+        // 06Z: analysis first, then the 00Z forecast
+        let labels: Vec<_> = targets[1].candidates.iter().map(Task::to_string).collect();
+        assert_eq!(labels, ["2020-01-01 06 f000", "2020-01-01 00 f006"]);
+
+        // 00Z falls back to the previous day's 18Z cycle
+        assert_eq!(targets[0].candidates[1].to_string(), "2019-12-31 18 f006");
+    }
+
+    #[test]
+    fn test_valid_hours_use_local_time() {
+        // 12Z is 13:00 in Paris during winter
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-02"),
+            chrono_tz::Europe::Paris,
+            Some(&[13]),
+            Model::Gfs,
+        )
+        .unwrap();
+        let labels: Vec<_> = cycles.iter().map(Cycle::to_string).collect();
+        assert_eq!(labels, ["2020-01-01 12", "2020-01-02 12"]);
+    }
+
+    #[test]
+    fn test_plan_hourly_cycles() {
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-01"),
+            Tz::UTC,
+            None,
+            Model::Rap,
+        )
+        .unwrap();
+        assert_eq!(cycles.len(), 24);
+        assert_eq!(cycles[13].to_string(), "rap 2020-01-01 13");
+
+        // Fallbacks step back one cycle at a time
+        let tasks = plan_tasks(&cycles[..1], &[0]);
+        let targets = plan_best_available(&tasks, 2);
+        let labels: Vec<_> = targets[0].candidates.iter().map(Task::to_string).collect();
+        assert_eq!(
+            labels,
+            [
+                "rap 2020-01-01 00 f000",
+                "rap 2019-12-31 23 f001",
+                "rap 2019-12-31 22 f002"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shards_partition_cycles() {
+        let cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-02"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let shard = |s: &str| {
+            let mut cycles = cycles.clone();
+            s.parse::<Shard>().unwrap().select(&mut cycles);
+            cycles
+        };
+        let labels =
+            |cycles: Vec<Cycle>| -> Vec<String> { cycles.iter().map(Cycle::to_string).collect() };
+        assert_eq!(
+            labels(shard("1/3")),
+            ["2020-01-01 00", "2020-01-01 18", "2020-01-02 12"]
+        );
+        assert_eq!(labels(shard("3/3")), ["2020-01-01 12", "2020-01-02 06"]);
+        assert_eq!(shard("1/1"), cycles);
+
+        // Every cycle goes to exactly one shard
+        let mut all: Vec<_> = (1..=3).flat_map(|i| shard(&format!("{i}/3"))).collect();
+        all.sort();
+        assert_eq!(all, cycles);
+
+        assert!("0/8".parse::<Shard>().is_err());
+        assert!("9/8".parse::<Shard>().is_err());
+        assert!("3".parse::<Shard>().is_err());
+        assert_eq!("3/8".parse::<Shard>().unwrap().to_string(), "3/8");
+    }
+
+    #[test]
+    fn test_sample_one_cycle_per_local_day() {
+        let mut cycles = plan_cycles(
+            date("2020-01-01"),
+            date("2020-01-02"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let sample: Sample = "1/day".parse().unwrap();
+        assert_eq!(sample.messages(), None);
+        // Cycles planned in UTC, sampled by day in Paris: 00 UTC is 01:00 there
+        sample.select(&mut cycles, "Europe/Paris".parse().unwrap());
+        let hours: Vec<_> = cycles.iter().map(ToString::to_string).collect();
+        assert_eq!(hours, ["2020-01-01 00", "2020-01-02 00"]);
+
+        let every: Sample = " 1/8".parse().unwrap();
+        assert_eq!(every.messages(), Some(8));
+        assert_eq!(every.to_string(), "1/8");
+        every.select(&mut cycles, Tz::UTC);
+        assert_eq!(cycles.len(), 2);
+        for invalid in ["1/0", "2/8", "1/week", "8"] {
+            assert!(invalid.parse::<Sample>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_relative_dates() {
+        let today = date("2024-03-01");
+        let cases = [
+            ("2020-01-01", "2020-01-01"),
+            ("today", "2024-03-01"),
+            ("yesterday", "2024-02-29"),
+            ("-7d", "2024-02-23"),
+            ("+1w", "2024-03-08"),
+            ("yesterday-1w", "2024-02-22"),
+            ("2020-01-31+1d", "2020-02-01"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(resolve_date(expr, today), Ok(date(expected)), "{expr}");
+        }
+        for invalid in ["2020-13-01", "last week", "-7", "7d", "today-d", "-7y"] {
+            assert!(resolve_date(invalid, today).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_cycle_rules() {
+        let mut cycles = plan_cycles(
+            date("2021-02-08"),
+            date("2021-02-21"),
+            Tz::UTC,
+            None,
+            Model::Gfs,
+        )
+        .unwrap();
+        let rules = CycleRules {
+            exclude: vec![
+                parse_cycle_pattern("2021-02-1*").unwrap(),
+                parse_cycle_pattern("* 18").unwrap(),
+            ],
+            weekdays: vec![Weekday::Mon, "thu".parse().unwrap()],
+        };
+        rules.select(&mut cycles, Tz::UTC);
+        let kept: Vec<_> = cycles.iter().map(ToString::to_string).collect();
+        // Mondays and Thursdays outside 2021-02-10..19, without the 18Z cycles
+        assert_eq!(kept, ["2021-02-08 00", "2021-02-08 06", "2021-02-08 12"]);
+        assert!(CycleRules::default().is_empty());
+        assert!(parse_cycle_pattern("2021-02-1[0-5]").is_err());
+        assert!(wildcard_match(b"2021-??-01", b"2021-02-01"));
+        assert!(!wildcard_match(b"2021-02-0?", b"2021-02-10"));
+    }
+}
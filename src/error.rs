@@ -0,0 +1,278 @@
+#[cfg(feature = "pipeline")]
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata};
+use chrono::TimeDelta;
+
+/// Result type of the pipeline operations.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Failure categories of the pipeline.
+///
+/// Each category maps to a retry policy (`is_transient()`) and a process
+/// exit code (`exit_code()`), so scripts can tell a missing file from bad
+/// credentials without parsing messages.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The source could not be reached or does not serve the file.
+    #[error("source unavailable: {url}: {reason}")]
+    SourceUnavailable {
+        url: String,
+        /// HTTP status, if the server answered.
+        status: Option<u16>,
+        reason: String,
+    },
+
+    /// The download ended before the whole file was received.
+    #[error(
+        "truncated download of {url}: received {received} of {} bytes",
+        .expected.map_or("?".to_string(), |e| e.to_string())
+    )]
+    Truncated {
+        url: String,
+        received: u64,
+        /// Announced length, if any.
+        expected: Option<u64>,
+    },
+
+    /// The source data is not valid GRIB2.
+    #[error("GRIB2 parse error at byte {offset}: {reason}")]
+    ParseError { offset: u64, reason: String },
+
+    /// Writing to S3 failed.
+    #[error("S3 error on {key}: {reason}")]
+    SinkError { key: String, reason: String },
+
+    /// The source asks us to slow down (HTTP 429/503).
+    #[error("throttled by source: HTTP {status} for {url}")]
+    Throttled { url: String, status: u16 },
+
+    /// Credentials were missing, invalid or denied by the source or S3.
+    #[error("access denied: {target}: {reason}")]
+    Auth { target: String, reason: String },
+
+    /// The run was stopped before the file was done (spot interruption notice).
+    #[error("interrupted: {reason}")]
+    Interrupted { reason: String },
+}
+
+/// Exit code of a run interrupted before its end, to be finished with `resume`.
+pub const EXIT_RESUMABLE: u8 = 9;
+
+/// Why a file could not be read from the source, reported separately in the
+/// run summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outage {
+    /// Missing, but its cycle is recent enough that it may just not be published yet.
+    NotYetPublished,
+    /// Missing well after its cycle: a gap in the source.
+    Missing,
+    /// The source failed to serve it (network error, HTTP 5xx, throttling).
+    SourceDown,
+}
+
+/// S3 error codes caused by credentials or permissions.
+#[cfg(feature = "pipeline")]
+const AUTH_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "InvalidAccessKeyId",
+    "SignatureDoesNotMatch",
+    "ExpiredToken",
+    "InvalidToken",
+    "AllAccessDisabled",
+];
+
+impl Error {
+    /// Classify a non-success HTTP status returned by the source.
+    #[cfg(feature = "pipeline")]
+    pub fn from_status(url: &str, status: reqwest::StatusCode) -> Self {
+        let url = url.to_string();
+        match status.as_u16() {
+            429 | 503 => Error::Throttled {
+                url,
+                status: status.as_u16(),
+            },
+            401 | 403 => Error::Auth {
+                target: url,
+                reason: format!("HTTP {status}"),
+            },
+            code => Error::SourceUnavailable {
+                url,
+                status: Some(code),
+                reason: format!("HTTP {status}"),
+            },
+        }
+    }
+
+    /// Classify an S3 SDK error for an object key.
+    #[cfg(feature = "pipeline")]
+    pub fn from_sdk<E, R>(key: &str, action: &str, err: aws_sdk_s3::error::SdkError<E, R>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+        R: std::fmt::Debug,
+    {
+        let reason = format!("{action}: {}", DisplayErrorContext(&err));
+        match err.code() {
+            Some(code) if AUTH_ERROR_CODES.contains(&code) => Error::Auth {
+                target: format!("S3 {key}"),
+                reason,
+            },
+            _ => Error::SinkError {
+                key: key.to_string(),
+                reason,
+            },
+        }
+    }
+
+    /// Whether the file does not exist on the source.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::SourceUnavailable {
+                status: Some(404),
+                ..
+            }
+        )
+    }
+
+    /// Whether retrying the same file may succeed: throttling, truncated
+    /// downloads, and network or server errors (but not a missing file).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Throttled { .. } | Error::Truncated { .. } => true,
+            Error::SourceUnavailable { status, .. } => status.is_none_or(|s| s >= 500),
+            Error::ParseError { .. }
+            | Error::SinkError { .. }
+            | Error::Auth { .. }
+            | Error::Interrupted { .. } => false,
+        }
+    }
+
+    /// Classify a failure to get a file from the source, given the time since its
+    /// cycle: a missing file is only a gap once `publication_delay` has passed.
+    pub fn outage(&self, cycle_age: TimeDelta, publication_delay: TimeDelta) -> Option<Outage> {
+        match self {
+            Error::SourceUnavailable {
+                status: Some(404), ..
+            } if cycle_age < publication_delay => Some(Outage::NotYetPublished),
+            Error::SourceUnavailable {
+                status: Some(404), ..
+            } => Some(Outage::Missing),
+            Error::SourceUnavailable { status, .. } if status.is_none_or(|s| s >= 500) => {
+                Some(Outage::SourceDown)
+            }
+            Error::Throttled { .. } => Some(Outage::SourceDown),
+            _ => None,
+        }
+    }
+
+    /// Whether the file was given up because the run is being interrupted.
+    pub fn is_interrupted(&self) -> bool {
+        matches!(self, Error::Interrupted { .. })
+    }
+
+    /// Whether the error will affect every file, so the run should stop.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Auth { .. })
+    }
+
+    /// Name of the category, for machine-readable logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::SourceUnavailable { .. } => "source_unavailable",
+            Error::Truncated { .. } => "truncated",
+            Error::ParseError { .. } => "parse_error",
+            Error::SinkError { .. } => "sink_error",
+            Error::Throttled { .. } => "throttled",
+            Error::Auth { .. } => "auth",
+            Error::Interrupted { .. } => "interrupted",
+        }
+    }
+
+    /// HTTP status the source answered with, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::SourceUnavailable { status, .. } => *status,
+            Error::Throttled { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Process exit code reported when a file fails with this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::SourceUnavailable { .. } => 3,
+            Error::Truncated { .. } => 4,
+            Error::ParseError { .. } => 5,
+            Error::SinkError { .. } => 6,
+            Error::Throttled { .. } => 7,
+            Error::Auth { .. } => 8,
+            Error::Interrupted { .. } => EXIT_RESUMABLE,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "pipeline"))]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_status_categories() {
+        let url = "https://example.com/f000";
+
+        let missing = Error::from_status(url, StatusCode::NOT_FOUND);
+        assert!(missing.is_not_found());
+        assert!(!missing.is_transient());
+        assert_eq!(missing.exit_code(), 3);
+
+        let throttled = Error::from_status(url, StatusCode::TOO_MANY_REQUESTS);
+        assert!(matches!(throttled, Error::Throttled { status: 429, .. }));
+        assert!(throttled.is_transient());
+        assert_eq!(
+            (throttled.kind(), throttled.status()),
+            ("throttled", Some(429))
+        );
+
+        let denied = Error::from_status(url, StatusCode::FORBIDDEN);
+        assert!(denied.is_fatal());
+        assert_eq!(denied.exit_code(), 8);
+
+        assert!(Error::from_status(url, StatusCode::BAD_GATEWAY).is_transient());
+    }
+
+    #[test]
+    fn test_outages() {
+        let url = "https://example.com/f000";
+        let delay = TimeDelta::hours(24);
+        let missing = Error::from_status(url, StatusCode::NOT_FOUND);
+        assert_eq!(
+            missing.outage(TimeDelta::hours(5), delay),
+            Some(Outage::NotYetPublished)
+        );
+        assert_eq!(
+            missing.outage(TimeDelta::days(30), delay),
+            Some(Outage::Missing)
+        );
+
+        let down = Error::from_status(url, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            down.outage(TimeDelta::hours(5), delay),
+            Some(Outage::SourceDown)
+        );
+        let unreachable = Error::SourceUnavailable {
+            url: url.to_string(),
+            status: None,
+            reason: "connection refused".to_string(),
+        };
+        assert_eq!(
+            unreachable.outage(TimeDelta::days(30), delay),
+            Some(Outage::SourceDown)
+        );
+
+        let gone = Error::from_status(url, StatusCode::GONE);
+        assert_eq!(gone.outage(TimeDelta::days(30), delay), None);
+        assert_eq!(
+            Error::from_status(url, StatusCode::FORBIDDEN).outage(TimeDelta::zero(), delay),
+            None
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::s3::{StorageClass, MIN_PART_SIZE};
+
+/// Typical size of the companion pgrb2b f000 file.
+pub const TYPICAL_B_FILE_SIZE: u64 = 250 * 1000 * 1000;
+
+/// Fraction of a source file kept by the wind filter (~212 of ~8,400 messages),
+/// also used as the size of other routes.
+pub const TYPICAL_WIND_FRACTION: f64 = 0.05;
+
+/// Rough cost estimate for a planned run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub files: usize,
+    /// Objects written: one per file and route.
+    pub objects: usize,
+    pub download_bytes: u64,
+    pub stored_bytes: u64,
+    pub put_requests: u64,
+    pub request_cost_usd: f64,
+    pub storage_cost_usd_per_month: f64,
+}
+
+impl Estimate {
+    /// Estimate volumes and S3 costs for `files` source files of `file_size`
+    /// bytes, each split into `routes` objects written with `storage_class`.
+    pub fn for_files(
+        files: usize,
+        file_size: u64,
+        routes: usize,
+        storage_class: StorageClass,
+    ) -> Self {
+        let objects = files * routes;
+        let object_size = (file_size as f64 * TYPICAL_WIND_FRACTION) as u64;
+
+        // CreateMultipartUpload + one UploadPart per 5 MB + CompleteMultipartUpload + manifest
+        let parts = object_size.div_ceil(MIN_PART_SIZE as u64).max(1);
+        let put_requests = objects as u64 * (parts + 3);
+
+        let stored_bytes = objects as u64 * object_size;
+        let stored_gb = stored_bytes as f64 / 1e9;
+        let (gb_month, per_thousand_puts) = storage_class.list_prices_usd();
+
+        Self {
+            files,
+            objects,
+            download_bytes: files as u64 * file_size,
+            stored_bytes,
+            put_requests,
+            request_cost_usd: put_requests as f64 / 1000.0 * per_thousand_puts,
+            storage_cost_usd_per_month: stored_gb * gb_month,
+        }
+    }
+
+    /// Add the estimate of other files of the run, e.g. written with another
+    /// storage class.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.files += other.files;
+        self.objects += other.objects;
+        self.download_bytes += other.download_bytes;
+        self.stored_bytes += other.stored_bytes;
+        self.put_requests += other.put_requests;
+        self.request_cost_usd += other.request_cost_usd;
+        self.storage_cost_usd_per_month += other.storage_cost_usd_per_month;
+        self
+    }
+
+    /// Account for also downloading the pgrb2b companion of each file.
+    pub fn with_b_files(mut self) -> Self {
+        self.download_bytes += self.files as u64 * TYPICAL_B_FILE_SIZE;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GFS_FILE_SIZE: u64 = 500 * 1000 * 1000;
+
+    #[test]
+    fn test_estimate_scales_with_files() {
+        let one = Estimate::for_files(1, GFS_FILE_SIZE, 1, StorageClass::Standard);
+        let many = Estimate::for_files(1460, GFS_FILE_SIZE, 1, StorageClass::Standard);
+
+        assert_eq!(one.download_bytes, GFS_FILE_SIZE);
+        // 25 MB output -> 5 parts + create + complete + manifest
+        assert_eq!(one.put_requests, 8);
+        assert_eq!(many.put_requests, 1460 * 8);
+        assert_eq!(many.stored_bytes, 1460 * one.stored_bytes);
+
+        let deep = Estimate::for_files(1460, GFS_FILE_SIZE, 1, StorageClass::DeepArchive);
+        assert!(deep.storage_cost_usd_per_month < many.storage_cost_usd_per_month);
+
+        // Routes multiply objects, not downloads
+        let routed = Estimate::for_files(1460, GFS_FILE_SIZE, 2, StorageClass::Standard);
+        assert_eq!(routed.download_bytes, many.download_bytes);
+        assert_eq!(routed.put_requests, 2 * many.put_requests);
+
+        let with_b =
+            Estimate::for_files(1460, GFS_FILE_SIZE, 1, StorageClass::Standard).with_b_files();
+        assert_eq!(
+            with_b.download_bytes - many.download_bytes,
+            1460 * TYPICAL_B_FILE_SIZE
+        );
+        assert_eq!(with_b.put_requests, many.put_requests);
+
+        // Older files written to another class
+        let split = Estimate::for_files(1000, GFS_FILE_SIZE, 1, StorageClass::Standard).merge(
+            Estimate::for_files(460, GFS_FILE_SIZE, 1, StorageClass::StandardIa),
+        );
+        assert_eq!(split.files, 1460);
+        assert_eq!(split.stored_bytes, many.stored_bytes);
+        assert!(split.storage_cost_usd_per_month < many.storage_cost_usd_per_month);
+        assert!(split.request_cost_usd > many.request_cost_usd);
+    }
+}
@@ -0,0 +1,214 @@
+//! C ABI (`--features ffi`) for the streaming parser and the parameter filter,
+//! for C/C++ ingestion systems. The header `include/gfs_wind_downloader.h` is
+//! generated with cbindgen.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::grib::{products, Grib2StreamParser};
+use crate::param::Param;
+
+/// `gwd_parser_next()` result: a message was returned.
+pub const GWD_MESSAGE: c_int = 1;
+/// `gwd_parser_next()` result: more bytes must be pushed.
+pub const GWD_NEED_MORE: c_int = 0;
+/// `gwd_parser_next()` result: invalid data, see `gwd_parser_error()`.
+pub const GWD_ERROR: c_int = -1;
+
+/// Streaming GRIB2 message splitter.
+pub struct GwdParser {
+    parser: Grib2StreamParser,
+    error: Option<CString>,
+}
+
+/// Parameters kept by a filter.
+pub struct GwdFilter {
+    params: Vec<Param>,
+}
+
+/// A complete GRIB2 message, owned by the caller until `gwd_message_free()`.
+#[repr(C)]
+pub struct GwdMessage {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Create a parser; free it with `gwd_parser_free()`.
+#[no_mangle]
+pub extern "C" fn gwd_parser_new() -> *mut GwdParser {
+    Box::into_raw(Box::new(GwdParser {
+        parser: Grib2StreamParser::new(),
+        error: None,
+    }))
+}
+
+/// Free a parser.
+///
+/// # Safety
+/// `parser` must come from `gwd_parser_new()` (or be NULL) and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_parser_free(parser: *mut GwdParser) {
+    if !parser.is_null() {
+        drop(Box::from_raw(parser));
+    }
+}
+
+/// Append `len` bytes of the stream.
+///
+/// # Safety
+/// `parser` must be a live parser and `data` point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_parser_push(parser: *mut GwdParser, data: *const u8, len: usize) {
+    if len > 0 {
+        (*parser).parser.push(std::slice::from_raw_parts(data, len));
+    }
+}
+
+/// Extract the next complete message into `message`: returns `GWD_MESSAGE`,
+/// `GWD_NEED_MORE`, or `GWD_ERROR` for a message without its end marker
+/// (the parser can go on with the next one).
+///
+/// # Safety
+/// `parser` must be a live parser and `message` point to a writable `GwdMessage`.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_parser_next(
+    parser: *mut GwdParser,
+    message: *mut GwdMessage,
+) -> c_int {
+    let parser = &mut *parser;
+    match parser.parser.next_message() {
+        Ok(Some(msg)) => {
+            let msg = Box::into_raw(msg.into_boxed_slice());
+            *message = GwdMessage {
+                data: msg as *mut u8,
+                len: msg.len(),
+            };
+            GWD_MESSAGE
+        }
+        Ok(None) => GWD_NEED_MORE,
+        Err(e) => {
+            parser.error = CString::new(e.to_string()).ok();
+            GWD_ERROR
+        }
+    }
+}
+
+/// Description of the last `GWD_ERROR`, valid until the next error or
+/// `gwd_parser_free()`; NULL if none.
+///
+/// # Safety
+/// `parser` must be a live parser.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_parser_error(parser: *const GwdParser) -> *const c_char {
+    (*parser).error.as_ref().map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Bytes of an incomplete message still buffered; non-zero at the end of the
+/// stream means it was cut short.
+///
+/// # Safety
+/// `parser` must be a live parser.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_parser_pending(parser: *const GwdParser) -> usize {
+    (*parser).parser.pending()
+}
+
+/// Free the data of a message returned by `gwd_parser_next()`.
+///
+/// # Safety
+/// `message` must hold data from `gwd_parser_next()` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_message_free(message: *mut GwdMessage) {
+    let message = &mut *message;
+    if !message.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            message.data,
+            message.len,
+        )));
+        message.data = ptr::null_mut();
+        message.len = 0;
+    }
+}
+
+/// Create a filter keeping the messages with any of the comma-separated
+/// parameters (NCEP short names or discipline.category.number, e.g.
+/// "UGRD,VGRD"); NULL if a parameter is unknown. Free it with `gwd_filter_free()`.
+///
+/// # Safety
+/// `params` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_filter_new(params: *const c_char) -> *mut GwdFilter {
+    let Ok(params) = CStr::from_ptr(params).to_str() else {
+        return ptr::null_mut();
+    };
+    match params.split(',').map(str::parse).collect() {
+        Ok(params) => Box::into_raw(Box::new(GwdFilter { params })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Free a filter.
+///
+/// # Safety
+/// `filter` must come from `gwd_filter_new()` (or be NULL) and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_filter_free(filter: *mut GwdFilter) {
+    if !filter.is_null() {
+        drop(Box::from_raw(filter));
+    }
+}
+
+/// Whether a message has a product with one of the filter's parameters.
+///
+/// # Safety
+/// `filter` must be a live filter and `data` point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gwd_filter_matches(
+    filter: *const GwdFilter,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    let msg = std::slice::from_raw_parts(data, len);
+    products(msg)
+        .iter()
+        .any(|product| (*filter).params.contains(&Param::of(product)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message;
+
+    #[test]
+    fn test_c_parser_and_filter() {
+        let mut stream = message(2);
+        stream.extend(message(22));
+        let (head, tail) = stream.split_at(30);
+
+        unsafe {
+            let parser = gwd_parser_new();
+            let filter = gwd_filter_new(c"UGRD,VGRD".as_ptr());
+            let mut msg = GwdMessage {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+
+            gwd_parser_push(parser, head.as_ptr(), head.len());
+            assert_eq!(gwd_parser_next(parser, &mut msg), GWD_NEED_MORE);
+            gwd_parser_push(parser, tail.as_ptr(), tail.len());
+
+            let mut kept = Vec::new();
+            while gwd_parser_next(parser, &mut msg) == GWD_MESSAGE {
+                kept.push(gwd_filter_matches(filter, msg.data, msg.len));
+                gwd_message_free(&mut msg);
+            }
+            assert_eq!(kept, [true, false]);
+            assert_eq!(gwd_parser_pending(parser), 0);
+            assert!(gwd_parser_error(parser).is_null());
+            assert!(gwd_filter_new(c"WINDY".as_ptr()).is_null());
+
+            gwd_filter_free(filter);
+            gwd_parser_free(parser);
+        }
+    }
+}
@@ -0,0 +1,584 @@
+use crate::error::{Error, Result};
+use crate::grib::sections;
+
+/// Decoded values of a GRIB2 message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    /// Points along a parallel (x), or the number of points for grids
+    /// without rows (e.g. ICON's icosahedral grid).
+    pub ni: usize,
+    /// Points along a meridian (y); 1 for grids without rows.
+    pub nj: usize,
+    /// Values in the scanning order of the grid (`ni` per row); NaN where
+    /// the bitmap marks a point as missing.
+    pub values: Vec<f32>,
+}
+
+impl Field {
+    /// Value at column `i`, row `j`.
+    pub fn get(&self, i: usize, j: usize) -> Option<f32> {
+        if i >= self.ni {
+            return None;
+        }
+        self.values.get(j * self.ni + i).copied()
+    }
+}
+
+/// Most points a message may hold: bounds what a malformed header makes the
+/// decoder allocate (400 MB of values).
+const MAX_POINTS: usize = 100_000_000;
+
+/// Most bits of a packed value, group width or spatial differencing
+/// descriptor: more does not fit the integer arithmetic of the unpacking.
+const MAX_BITS: usize = 32;
+
+fn parse_error(reason: impl Into<String>) -> Error {
+    Error::ParseError {
+        offset: 0,
+        reason: reason.into(),
+    }
+}
+
+/// Decode the (first) field of a GRIB2 message.
+///
+/// Supports simple packing (template 5.0) and complex packing with or
+/// without spatial differencing (5.2, 5.3), as used by GFS and the other
+/// NCEP models; JPEG 2000 and PNG packing are reported as parse errors.
+pub fn decode(msg: &[u8]) -> Result<Field> {
+    Decoder::default().decode(msg)
+}
+
+/// Decodes messages one after the other, reusing the buffers the values are
+/// unpacked in: only the values of each `Field` are allocated.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Values present in the data section, before the bitmap is applied.
+    packed: Vec<f32>,
+    /// Scaled integers of complex packing, `None` where missing.
+    scaled: Vec<Option<i64>>,
+}
+
+impl Decoder {
+    /// Decode the (first) field of a GRIB2 message, like `decode()`.
+    pub fn decode(&mut self, msg: &[u8]) -> Result<Field> {
+        if msg.len() < 16 || !msg.starts_with(b"GRIB") || msg[7] != 2 {
+            return Err(parse_error("not a GRIB2 message"));
+        }
+
+        let (mut grid, mut representation, mut bitmap, mut data) = (None, None, None, None);
+        for (number, section) in sections(msg) {
+            match number {
+                3 if grid.is_none() => grid = Some(section),
+                5 if representation.is_none() => representation = Some(section),
+                6 if bitmap.is_none() => bitmap = Some(section),
+                7 => {
+                    data = Some(section);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let (Some(grid), Some(representation), Some(data)) = (grid, representation, data) else {
+            return Err(parse_error(
+                "message without grid, data representation or data section",
+            ));
+        };
+
+        let (ni, nj) = grid_shape(grid)?;
+        unpack(
+            representation,
+            &data[5..],
+            ni * nj,
+            &mut self.packed,
+            &mut self.scaled,
+        )?;
+
+        let values: Vec<f32> = match bitmap {
+            Some(section) if section.get(5) == Some(&0) => {
+                let bits = &section[6..];
+                let mut packed = self.packed.iter().copied();
+                (0..ni * nj)
+                    .map(|i| {
+                        let present = bits.get(i / 8).is_some_and(|b| b & (0x80 >> (i % 8)) != 0);
+                        if present {
+                            packed.next().unwrap_or(f32::NAN)
+                        } else {
+                            f32::NAN
+                        }
+                    })
+                    .collect()
+            }
+            Some(section) if section.get(5).is_some_and(|&b| b != 255) => {
+                return Err(parse_error(format!(
+                    "unsupported bitmap indicator {}",
+                    section[5]
+                )));
+            }
+            _ => self.packed.clone(),
+        };
+        if values.len() != ni * nj {
+            return Err(parse_error(format!(
+                "{} values for a {ni}x{nj} grid",
+                values.len()
+            )));
+        }
+        Ok(Field { ni, nj, values })
+    }
+}
+
+/// Grid dimensions from section 3.
+fn grid_shape(section: &[u8]) -> Result<(usize, usize)> {
+    let points = read_u32(section, 6).ok_or_else(|| parse_error("short grid section"))? as usize;
+    let template = read_u16(section, 12).ok_or_else(|| parse_error("short grid section"))?;
+    if points > MAX_POINTS {
+        return Err(parse_error(format!("grid of {points} points")));
+    }
+    // Lat/lon, rotated lat/lon, polar stereographic, Lambert and Gaussian
+    // grids all give Ni and Nj at octets 31-38
+    if matches!(template, 0 | 1 | 20 | 30 | 40) {
+        if let (Some(ni), Some(nj)) = (read_u32(section, 30), read_u32(section, 34)) {
+            if ni as usize * nj as usize == points {
+                return Ok((ni as usize, nj as usize));
+            }
+        }
+    }
+    Ok((points, 1))
+}
+
+/// Unpack the values encoded in section 7 (without the bitmap) into `packed`,
+/// using `scaled` for complex packing; there are at most `points` of them.
+fn unpack(
+    section: &[u8],
+    data: &[u8],
+    points: usize,
+    packed: &mut Vec<f32>,
+    scaled: &mut Vec<Option<i64>>,
+) -> Result<()> {
+    let short = || parse_error("short data representation section");
+    let count = read_u32(section, 5).ok_or_else(short)? as usize;
+    let template = read_u16(section, 9).ok_or_else(short)?;
+    let reference = f32::from_be_bytes(section.get(11..15).ok_or_else(short)?.try_into().unwrap());
+    let binary_scale = read_i16(section, 15).ok_or_else(short)?;
+    let decimal_scale = read_i16(section, 17).ok_or_else(short)?;
+    let bits = *section.get(19).ok_or_else(short)? as usize;
+    if count > points {
+        return Err(parse_error(format!("{count} values for {points} points")));
+    }
+    if bits > MAX_BITS {
+        return Err(parse_error(format!("{bits} bits per value")));
+    }
+
+    let scale = Scale {
+        reference: reference as f64,
+        binary: 2f64.powi(binary_scale as i32),
+        decimal: 10f64.powi(-(decimal_scale as i32)),
+    };
+
+    packed.clear();
+    match template {
+        0 => {
+            let mut reader = BitReader::new(data);
+            for _ in 0..count {
+                let x = if bits == 0 {
+                    Some(0)
+                } else {
+                    reader.read(bits)
+                };
+                let x = x.ok_or_else(|| parse_error("short data section"))?;
+                packed.push(scale.apply(x as f64));
+            }
+            Ok(())
+        }
+        2 | 3 => complex(section, template, data, count, bits, &scale, packed, scaled),
+        _ => Err(parse_error(format!(
+            "unsupported data representation template 5.{template}"
+        ))),
+    }
+}
+
+/// Reference value and binary/decimal scale factors of section 5.
+struct Scale {
+    reference: f64,
+    binary: f64,
+    decimal: f64,
+}
+
+impl Scale {
+    fn apply(&self, x: f64) -> f32 {
+        ((self.reference + x * self.binary) * self.decimal) as f32
+    }
+}
+
+/// Complex packing (5.2) and complex packing with spatial differencing (5.3).
+#[allow(clippy::too_many_arguments)]
+fn complex(
+    section: &[u8],
+    template: u16,
+    data: &[u8],
+    count: usize,
+    bits: usize,
+    scale: &Scale,
+    packed: &mut Vec<f32>,
+    scaled: &mut Vec<Option<i64>>,
+) -> Result<()> {
+    let short_section = || parse_error("short data representation section");
+    let short_data = || parse_error("short data section");
+    let octet = |index: usize| section.get(index).copied().ok_or_else(short_section);
+    let missing_management = octet(22)?;
+    let groups = read_u32(section, 31).ok_or_else(short_section)? as usize;
+    let width_reference = octet(35)? as u64;
+    let width_bits = octet(36)? as usize;
+    let length_reference = read_u32(section, 37).ok_or_else(short_section)? as u64;
+    let length_increment = octet(41)? as u64;
+    let last_length = read_u32(section, 42).ok_or_else(short_section)? as u64;
+    let length_bits = octet(46)? as usize;
+    let (order, extra_octets) = if template == 3 {
+        (octet(47)?, octet(48)? as usize)
+    } else {
+        (0, 0)
+    };
+    if groups > count {
+        return Err(parse_error(format!("{groups} groups for {count} values")));
+    }
+    if order > 2 {
+        return Err(parse_error(format!(
+            "spatial differencing of order {order}"
+        )));
+    }
+    if [width_bits, length_bits, extra_octets * 8]
+        .iter()
+        .any(|&n| n > MAX_BITS)
+    {
+        return Err(parse_error("group descriptors wider than 32 bits"));
+    }
+
+    let mut reader = BitReader::new(data);
+    let mut first = Vec::new();
+    for _ in 0..order {
+        first.push(read_signed(&mut reader, extra_octets * 8).ok_or_else(short_data)?);
+    }
+    let minimum = if order > 0 {
+        read_signed(&mut reader, extra_octets * 8).ok_or_else(short_data)?
+    } else {
+        0
+    };
+
+    let references = reader.read_all(groups, bits).ok_or_else(short_data)?;
+    let widths = reader.read_all(groups, width_bits).ok_or_else(short_data)?;
+    let lengths = reader
+        .read_all(groups, length_bits)
+        .ok_or_else(short_data)?;
+
+    // Scaled values, None where missing
+    scaled.clear();
+    for g in 0..groups {
+        let width = (width_reference + widths[g]) as usize;
+        if width > MAX_BITS {
+            return Err(parse_error(format!("group of {width}-bit values")));
+        }
+        let length = if g + 1 == groups {
+            last_length
+        } else {
+            length_reference + lengths[g] * length_increment
+        };
+        // Values past the count are dropped: don't unpack them
+        let length = length.min((count - scaled.len().min(count)) as u64);
+        let all_ones = |n: usize| if n == 0 { 0 } else { (1u64 << n) - 1 };
+        for _ in 0..length {
+            let (value, missing) = if width == 0 {
+                let missing = missing_management > 0
+                    && bits > 0
+                    && (references[g] == all_ones(bits)
+                        || (missing_management == 2 && references[g] == all_ones(bits) - 1));
+                (references[g], missing)
+            } else {
+                let x = reader.read(width).ok_or_else(short_data)?;
+                let missing = missing_management > 0
+                    && (x == all_ones(width)
+                        || (missing_management == 2 && x == all_ones(width) - 1));
+                (references[g] + x, missing)
+            };
+            scaled.push((!missing).then_some(value as i64));
+        }
+    }
+
+    if order > 0 {
+        // Undo the differencing over the non-missing values
+        let mut previous: Vec<i64> = Vec::with_capacity(2);
+        for value in scaled.iter_mut().flatten() {
+            let n = previous.len();
+            // Wrapping: a malformed message decodes to garbage, not a panic
+            let x = if n < order as usize {
+                first[n]
+            } else if order == 1 {
+                value.wrapping_add(minimum).wrapping_add(previous[n - 1])
+            } else {
+                value
+                    .wrapping_add(minimum)
+                    .wrapping_add(previous[n - 1].wrapping_mul(2))
+                    .wrapping_sub(previous[n - 2])
+            };
+            *value = x;
+            previous.push(x);
+            if previous.len() > 2 {
+                previous.remove(0);
+            }
+        }
+    }
+
+    scaled.truncate(count);
+    packed.extend(
+        scaled
+            .iter()
+            .map(|x| x.map_or(f32::NAN, |x| scale.apply(x as f64))),
+    );
+    Ok(())
+}
+
+/// Read a sign-and-magnitude integer of `bits` bits.
+fn read_signed(reader: &mut BitReader, bits: usize) -> Option<i64> {
+    if bits == 0 {
+        return Some(0);
+    }
+    let raw = reader.read(bits)?;
+    let magnitude = (raw & ((1 << (bits - 1)) - 1)) as i64;
+    Some(if raw >> (bits - 1) == 1 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+pub(crate) fn read_u16(section: &[u8], index: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        section.get(index..index + 2)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn read_u32(section: &[u8], index: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        section.get(index..index + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read a 2-octet sign-and-magnitude integer.
+pub(crate) fn read_i16(section: &[u8], index: usize) -> Option<i16> {
+    let raw = read_u16(section, index)?;
+    let magnitude = (raw & 0x7fff) as i16;
+    Some(if raw & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+/// Big-endian bit reader over section 7.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bit: 0 }
+    }
+
+    fn read(&mut self, bits: usize) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..bits {
+            let byte = *self.data.get(self.bit / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.bit % 8)) & 1) as u64;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+
+    /// Read `count` values of `bits` bits, then skip to the next octet.
+    fn read_all(&mut self, count: usize, bits: usize) -> Option<Vec<u64>> {
+        let values = (0..count).map(|_| self.read(bits)).collect();
+        self.bit = self.bit.div_ceil(8) * 8;
+        values
+    }
+}
+
+/// Big-endian bit writer of section 7, the counterpart of `BitReader`.
+#[cfg(any(feature = "pipeline", test))]
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    pub(crate) data: Vec<u8>,
+    bit: usize,
+}
+
+#[cfg(any(feature = "pipeline", test))]
+impl BitWriter {
+    pub(crate) fn write(&mut self, value: u64, bits: usize) {
+        for i in (0..bits).rev() {
+            if self.bit.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.data.last_mut().unwrap() |= 0x80 >> (self.bit % 8);
+            }
+            self.bit += 1;
+        }
+    }
+
+    /// Skip to the next octet.
+    pub(crate) fn align(&mut self) {
+        self.bit = self.bit.div_ceil(8) * 8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assemble, body_section, grid_section};
+
+    /// A 3x2 lat/lon message with the given section 5 body, bitmap and data.
+    fn message(representation: &[u8], bitmap: Option<u8>, data: &[u8]) -> Vec<u8> {
+        let bitmap = match bitmap {
+            Some(bits) => body_section(6, &[0, bits]),
+            None => body_section(6, &[255]),
+        };
+        assemble(
+            0,
+            &[
+                &grid_section(3, 2),
+                &body_section(5, representation),
+                &bitmap,
+                &body_section(7, data),
+            ],
+        )
+    }
+
+    /// Section 5 body (after the 5-octet header) up to the type of original field.
+    fn representation(
+        count: u32,
+        template: u16,
+        reference: f32,
+        decimal: i16,
+        bits: u8,
+    ) -> Vec<u8> {
+        let mut r = count.to_be_bytes().to_vec();
+        r.extend_from_slice(&template.to_be_bytes());
+        r.extend_from_slice(&reference.to_be_bytes());
+        r.extend_from_slice(&0u16.to_be_bytes());
+        let sign = if decimal < 0 { 0x8000 } else { 0 };
+        r.extend_from_slice(&(decimal.unsigned_abs() | sign).to_be_bytes());
+        r.push(bits);
+        r.push(0);
+        r
+    }
+
+    #[test]
+    fn test_decode_simple_packing_with_bitmap() {
+        let mut data = BitWriter::default();
+        for x in [0, 5, 10, 15, 20] {
+            data.write(x, 8);
+        }
+        // Tenths above 270.0; the fourth point is masked
+        let msg = message(
+            &representation(5, 0, 2700.0, 1, 8),
+            Some(0b1110_1100),
+            &data.data,
+        );
+
+        let field = decode(&msg).unwrap();
+        assert_eq!((field.ni, field.nj), (3, 2));
+        assert_eq!(field.values[..3], [270.0, 270.5, 271.0]);
+        assert!(field.values[3].is_nan());
+        assert_eq!(field.get(1, 1), Some(271.5));
+        assert_eq!(field.get(2, 1), Some(272.0));
+    }
+
+    #[test]
+    fn test_decode_complex_packing_with_spatial_differencing() {
+        // Original scaled values 10 12 15 15 14 20: second-order differences
+        // from the third value are 1 -3 -1 7, minimum -3
+        let values = [10i64, 12, 15, 15, 14, 20];
+        let differences: Vec<i64> = (2..6)
+            .map(|i| values[i] - 2 * values[i - 1] + values[i - 2])
+            .collect();
+        assert_eq!(differences, [1, -3, -1, 7]);
+
+        let mut r = representation(6, 3, 0.0, 0, 4);
+        r.extend_from_slice(&[1, 0]); // group splitting, no missing values
+        r.extend_from_slice(&[0; 8]); // missing substitutes
+        r.extend_from_slice(&2u32.to_be_bytes()); // groups
+        r.push(0); // width reference
+        r.push(4); // bits per width
+        r.extend_from_slice(&4u32.to_be_bytes()); // length reference
+        r.push(1); // length increment
+        r.extend_from_slice(&2u32.to_be_bytes()); // last group length
+        r.push(4); // bits per length
+        r.extend_from_slice(&[2, 2]); // second order, 2 octets per descriptor
+
+        let mut data = BitWriter::default();
+        // h1, h2, minimum (sign and magnitude)
+        data.write(10, 16);
+        data.write(12, 16);
+        data.write(0x8003, 16);
+        // Group references (x - minimum: placeholders 0 0, then 4 0 2 10)
+        data.write(0, 4);
+        data.write(2, 4);
+        data.align();
+        // Widths: 3 bits, then 4
+        data.write(3, 4);
+        data.write(4, 4);
+        data.align();
+        // Scaled lengths: 4 + 0 (the last group's is given in section 5)
+        data.write(0, 4);
+        data.write(0, 4);
+        data.align();
+        // Group 1: 0 0 4 0, group 2: 2 10 (less the group reference 2)
+        for x in [0, 0, 4, 0] {
+            data.write(x, 3);
+        }
+        for x in [0, 8] {
+            data.write(x, 4);
+        }
+
+        let msg = message(&r, None, &data.data);
+        let field = decode(&msg).unwrap();
+        let expected: Vec<f32> = values.iter().map(|&v| v as f32).collect();
+        assert_eq!(field.values, expected);
+
+        // Same values from a decoder reusing its buffers
+        let mut decoder = Decoder::default();
+        assert_eq!(decoder.decode(&msg).unwrap(), field);
+        assert_eq!(decoder.decode(&msg).unwrap(), field);
+    }
+
+    #[test]
+    fn test_decode_reports_unsupported_packing() {
+        let msg = message(&representation(6, 40, 0.0, 0, 8), None, &[0; 6]);
+        let err = decode(&msg).unwrap_err().to_string();
+        assert!(err.contains("template 5.40"), "{err}");
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_headers() {
+        // Values wider than 64 bits, more values than points
+        for r in [
+            representation(6, 0, 0.0, 0, 200),
+            representation(u32::MAX, 0, 0.0, 0, 0),
+        ] {
+            let msg = message(&r, None, &[0; 6]);
+            assert!(decode(&msg).is_err());
+        }
+
+        // Complex packing with 2^32 - 1 groups of zero-bit references, and with
+        // 255-octet spatial differencing descriptors
+        let mut r = representation(6, 3, 0.0, 0, 0);
+        r.extend_from_slice(&[1, 0]);
+        r.extend_from_slice(&[0; 8]);
+        r.extend_from_slice(&u32::MAX.to_be_bytes());
+        r.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 6, 0, 2, 2]);
+        let err = decode(&message(&r, None, &[0; 6])).unwrap_err().to_string();
+        assert!(err.contains("groups for 6 values"), "{err}");
+        r[26..30].copy_from_slice(&1u32.to_be_bytes());
+        r[43] = 255;
+        let err = decode(&message(&r, None, &[0; 6])).unwrap_err().to_string();
+        assert!(err.contains("wider than 32 bits"), "{err}");
+    }
+}
@@ -0,0 +1,525 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+
+use crate::catalog::message_hash;
+use crate::error::{Error, Result};
+use crate::grib::{check_sections, products, Grib2StreamParser, ProductInfo};
+use crate::idx::idx_lines;
+use crate::report::FilterReport;
+use crate::route::Route;
+
+/// Hook applied to each kept message between the filter and the sink, e.g. to
+/// re-encode, redact or annotate messages without forking the pipeline.
+pub trait MessageTransform: Send + Sync {
+    /// Bytes to store for a message matched by a route, or `None` to drop it.
+    /// An error fails the file like a parse error.
+    fn transform(&self, message: Vec<u8>, products: &[ProductInfo]) -> Result<Option<Vec<u8>>>;
+}
+
+/// What the filter does with a message whose sections don't parse
+/// (`--on-parse-error`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OnParseError {
+    /// Leave it out silently, as any message no route matches. A message missing
+    /// its end marker is left out the same way.
+    #[default]
+    Skip,
+    /// Leave it out, reporting it in `FilteredChunk::undecodable`.
+    Warn,
+    /// Fail the file with a parse error.
+    Fail,
+}
+
+/// Keeps the messages of a stream matching routes.
+#[derive(Clone)]
+pub struct Filter {
+    routes: Arc<[Route]>,
+    transforms: Vec<Arc<dyn MessageTransform>>,
+    on_parse_error: OnParseError,
+    /// Keep one of every N matched messages, counted by the clones of the filter.
+    sample: Option<(u64, Arc<AtomicU64>)>,
+    /// Keep only the messages valid at this time.
+    valid_at: Option<NaiveDateTime>,
+    /// Stop at a message missing its end marker, for the caller to fetch it again.
+    refetch: bool,
+    hash: bool,
+    tally: bool,
+    index: bool,
+}
+
+/// Outcome of filtering one chunk.
+pub struct FilteredChunk {
+    /// The parser, handed back for the next chunk.
+    pub parser: Grib2StreamParser,
+    /// Number of messages completed by the chunk.
+    pub messages: u64,
+    pub kept: Vec<KeptMessage>,
+    /// Every message of the chunk, kept or not, when tallying.
+    pub report: Option<FilterReport>,
+    /// `.idx` lines of every message of the chunk, when indexing.
+    pub inventory: Vec<String>,
+    /// Messages left out because their sections don't parse, with the reason
+    /// (`OnParseError::Warn`).
+    pub undecodable: Vec<(Origin, String)>,
+    /// Message missing its end marker that `error` is about, when the filter
+    /// stops at such messages (`refetching()` or `OnParseError::Fail`).
+    pub unframed: Option<Origin>,
+    /// Parse (or transform) error hit after the messages above.
+    pub error: Option<Error>,
+}
+
+/// A message matched by at least one route.
+#[derive(Clone)]
+pub struct KeptMessage {
+    /// Message bytes, as returned by the transform if any.
+    pub bytes: Vec<u8>,
+    pub products: Vec<ProductInfo>,
+    /// Where the message was read from the stream.
+    pub origin: Origin,
+    /// Indices of the matching routes.
+    pub routes: Vec<usize>,
+    /// Content hash of the bytes, when hashing.
+    pub sha256: Option<String>,
+}
+
+/// Position of a message in the stream it was read from (after decompression).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin {
+    /// 1-based index of the message in the stream.
+    pub index: u64,
+    pub offset: u64,
+    /// Length in the stream, before any transform.
+    pub length: u64,
+}
+
+impl Filter {
+    pub fn new(routes: Arc<[Route]>) -> Self {
+        Self {
+            routes,
+            transforms: Vec::new(),
+            on_parse_error: OnParseError::Skip,
+            sample: None,
+            valid_at: None,
+            refetch: false,
+            hash: false,
+            tally: false,
+            index: false,
+        }
+    }
+
+    /// Apply `transform` to the kept messages, after those installed before.
+    pub fn with_transform(mut self, transform: Arc<dyn MessageTransform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// Check the sections of every message, leaving out or failing on those that
+    /// don't parse instead of only keeping what can be read of them.
+    pub fn on_parse_error(mut self, policy: OnParseError) -> Self {
+        self.on_parse_error = policy;
+        self
+    }
+
+    /// Keep only the 1st, N+1-th, 2N+1-th... of the messages matched by a route
+    /// (`--sample 1/N`), counted in `matched`: the count carries over the chunks
+    /// of the stream, each filtered by a clone, and over the filters sharing it.
+    pub fn sampling(mut self, every: u64, matched: Arc<AtomicU64>) -> Self {
+        self.sample = Some((every.max(1), matched));
+        self
+    }
+
+    /// Keep only the messages valid at `time`, from files holding several forecast
+    /// hours (`Model::packs_forecast_hours()`).
+    pub fn valid_at(mut self, time: NaiveDateTime) -> Self {
+        self.valid_at = Some(time);
+        self
+    }
+
+    /// Stop at a message missing its end marker with a parse error, instead of
+    /// leaving it out like the messages whose sections don't parse, so that the
+    /// caller can fetch it again.
+    pub fn refetching(mut self, refetch: bool) -> Self {
+        self.refetch = refetch;
+        self
+    }
+
+    /// Compute the SHA-256 of the kept messages (for the dedup catalog).
+    pub fn hashing(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Tally every message in a `FilterReport`.
+    pub fn tallying(mut self, tally: bool) -> Self {
+        self.tally = tally;
+        self
+    }
+
+    /// Describe every message in `.idx` lines, e.g. for a source without inventory.
+    pub fn indexing(mut self, index: bool) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Parse a chunk and keep the messages matching a route.
+    /// CPU-bound, so the pipeline runs it on the blocking thread pool.
+    pub fn filter_chunk(&self, mut parser: Grib2StreamParser, chunk: &[u8]) -> FilteredChunk {
+        parser.push(chunk);
+        let mut messages = 0;
+        let mut kept = Vec::new();
+        let mut report = self.tally.then(FilterReport::default);
+        let mut inventory = Vec::new();
+        let mut undecodable = Vec::new();
+        let mut unframed = None;
+
+        let error = loop {
+            match parser.next_message() {
+                Ok(Some(msg)) => {
+                    messages += 1;
+                    let len = msg.len() as u64;
+                    let origin = Origin {
+                        index: parser.messages(),
+                        offset: parser.offset() - len,
+                        length: len,
+                    };
+                    let products = products(&msg);
+                    if self.index {
+                        inventory.extend(idx_lines(origin.index, origin.offset, &products));
+                    }
+                    match self.filter_products(
+                        msg,
+                        products,
+                        origin,
+                        report.as_mut(),
+                        &mut undecodable,
+                    ) {
+                        Ok(message) => kept.extend(message),
+                        Err(e) => break Some(e),
+                    }
+                }
+                Ok(None) => break None,
+                // Missing its end marker: the parser is past it, ready for the next one
+                Err(Error::ParseError { offset, reason }) => {
+                    let origin = Origin {
+                        index: parser.messages(),
+                        offset,
+                        length: parser.offset() - offset,
+                    };
+                    if self.refetch || self.on_parse_error == OnParseError::Fail {
+                        unframed = Some(origin);
+                        break Some(Error::ParseError { offset, reason });
+                    }
+                    messages += 1;
+                    if self.on_parse_error == OnParseError::Warn {
+                        undecodable.push((origin, reason));
+                    }
+                }
+                Err(e) => break Some(e),
+            }
+        };
+
+        FilteredChunk {
+            parser,
+            messages,
+            kept,
+            report,
+            inventory,
+            undecodable,
+            unframed,
+            error,
+        }
+    }
+
+    /// Keep a complete message if it matches a route, tallying it in `report`
+    /// and adding it to `undecodable` if left out as such.
+    pub fn filter_message(
+        &self,
+        msg: Vec<u8>,
+        origin: Origin,
+        report: Option<&mut FilterReport>,
+        undecodable: &mut Vec<(Origin, String)>,
+    ) -> Result<Option<KeptMessage>> {
+        let products = products(&msg);
+        self.filter_products(msg, products, origin, report, undecodable)
+    }
+
+    /// Keep a message whose products were read already.
+    fn filter_products(
+        &self,
+        msg: Vec<u8>,
+        products: Vec<ProductInfo>,
+        origin: Origin,
+        report: Option<&mut FilterReport>,
+        undecodable: &mut Vec<(Origin, String)>,
+    ) -> Result<Option<KeptMessage>> {
+        if self.on_parse_error != OnParseError::Skip {
+            if let Err(reason) = check_sections(&msg) {
+                if self.on_parse_error == OnParseError::Fail {
+                    return Err(Error::ParseError {
+                        offset: origin.offset,
+                        reason: format!("message {} does not parse: {reason}", origin.index),
+                    });
+                }
+                if let Some(report) = report {
+                    report.record(&products, origin.length, false);
+                }
+                undecodable.push((origin, reason));
+                return Ok(None);
+            }
+        }
+
+        // Messages of the file's other forecast hours match no route
+        let valid = self.valid_at.is_none_or(|time| {
+            products
+                .iter()
+                .any(|p| p.time.is_some_and(|t| t.valid_time() == time))
+        });
+        let matching: Vec<usize> = self
+            .routes
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| valid && route.matches(&products))
+            .map(|(i, _)| i)
+            .collect();
+
+        let sampled_out = !matching.is_empty()
+            && self.sample.as_ref().is_some_and(|(every, matched)| {
+                matched.fetch_add(1, Ordering::Relaxed) % every != 0
+            });
+        let message = if matching.is_empty() || sampled_out {
+            None
+        } else {
+            self.apply(msg, products.clone())?
+        };
+        if let Some(report) = report {
+            report.record(&products, origin.length, message.is_some());
+        }
+        Ok(message.map(|(bytes, products)| KeptMessage {
+            sha256: self.hash.then(|| message_hash(&bytes)),
+            bytes,
+            products,
+            origin,
+            routes: matching,
+        }))
+    }
+
+    /// Run the transforms in turn on a matched message; the products are read
+    /// again from transformed bytes that are still GRIB2.
+    fn apply(
+        &self,
+        mut msg: Vec<u8>,
+        mut products: Vec<ProductInfo>,
+    ) -> Result<Option<(Vec<u8>, Vec<ProductInfo>)>> {
+        for transform in &self.transforms {
+            let Some(bytes) = transform.transform(msg, &products)? else {
+                return Ok(None);
+            };
+            let transformed = crate::grib::products(&bytes);
+            if !transformed.is_empty() {
+                products = transformed;
+            }
+            msg = bytes;
+        }
+        Ok(Some((msg, products)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::Param;
+    use crate::testing::{assemble, message, product_section, section};
+    use chrono::NaiveDate;
+
+    /// Drops VGRD, turns UGRD into GUST and fails on gusts from the source.
+    struct Rewrite;
+
+    impl MessageTransform for Rewrite {
+        fn transform(&self, mut msg: Vec<u8>, products: &[ProductInfo]) -> Result<Option<Vec<u8>>> {
+            match products[0].number {
+                2 => {
+                    msg[16 + 10] = 22;
+                    Ok(Some(msg))
+                }
+                3 => Ok(None),
+                _ => Err(Error::ParseError {
+                    offset: 0,
+                    reason: "unexpected gust".to_string(),
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_between_filter_and_sink() {
+        let routes: Arc<[Route]> = vec!["UGRD,VGRD,GUST=".parse().unwrap()].into();
+        let filter = Filter::new(routes)
+            .with_transform(Arc::new(Rewrite))
+            .hashing(true)
+            .tallying(true)
+            .indexing(true);
+
+        let mut stream = message(2);
+        stream.extend(message(3));
+        stream.extend(message(0));
+        let filtered = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert_eq!(filtered.messages, 3);
+        assert!(filtered.error.is_none());
+
+        // UGRD rewritten (and described as such), VGRD dropped, TMP not routed
+        assert_eq!(filtered.kept.len(), 1);
+        let kept = &filtered.kept[0];
+        assert_eq!(kept.products[0].number, 22);
+        assert_eq!(kept.sha256, Some(message_hash(&kept.bytes)));
+        // every source message is indexed, as read before the transform
+        let length = message(2).len() as u64;
+        assert_eq!(filtered.inventory.len(), 3);
+        assert!(filtered.inventory[1].starts_with(&format!("2:{length}:")));
+        assert_eq!(
+            crate::idx::parse_idx(&filtered.inventory.join("\n")).unwrap()[0].param,
+            Some(Param::new(0, 2, 2))
+        );
+        // the report tallies source parameters, kept only if the transform kept them
+        let report = filtered.report.unwrap();
+        let level = products(&message(2))[0].level;
+        assert_eq!(report.get(Param::new(0, 2, 2), level).unwrap().kept, 1);
+        assert_eq!(report.get(Param::new(0, 2, 3), level).unwrap().kept, 0);
+        assert_eq!(report.get(Param::new(0, 2, 0), level).unwrap().kept, 0);
+
+        let failed = filter.filter_chunk(Grib2StreamParser::new(), &message(22));
+        assert!(failed.kept.is_empty());
+        assert!(failed
+            .error
+            .unwrap()
+            .to_string()
+            .contains("unexpected gust"));
+
+        // A UGRD message whose product section overruns the message
+        let mut corrupt = message(2);
+        corrupt[19] = 40;
+        let stream = [corrupt, message(3)].concat();
+        let skipped = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert!(skipped.undecodable.is_empty());
+        assert!(skipped.error.is_none());
+        let filter = filter.on_parse_error(OnParseError::Warn);
+        let warned = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert!(warned.error.is_none());
+        assert_eq!(warned.undecodable.len(), 1);
+        assert_eq!(warned.undecodable[0].0.index, 1);
+        assert!(warned.undecodable[0].1.contains("announces 40"));
+        let filter = filter.on_parse_error(OnParseError::Fail);
+        let failed = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert_eq!(failed.messages, 1);
+        assert!(matches!(
+            failed.error,
+            Some(Error::ParseError { offset: 0, .. })
+        ));
+
+        // A message missing its end marker is left out alone, unless told to fail
+        let mut broken = message(2);
+        let end = broken.len() - 4;
+        broken[end..].copy_from_slice(b"8888");
+        let stream = [message(2), broken, message(2)].concat();
+        let length = message(2).len() as u64;
+        let origin = Origin {
+            index: 2,
+            offset: length,
+            length,
+        };
+        let filter = filter.on_parse_error(OnParseError::Skip);
+        let skipped = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert!(skipped.error.is_none());
+        assert_eq!((skipped.messages, skipped.kept.len()), (3, 2));
+        assert!(skipped.undecodable.is_empty());
+        let filter = filter.on_parse_error(OnParseError::Warn);
+        let warned = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert!(warned.error.is_none());
+        assert_eq!(warned.kept.len(), 2);
+        assert_eq!(warned.undecodable[0].0, origin);
+        assert!(warned.undecodable[0].1.contains("'7777'"));
+        let filter = filter.on_parse_error(OnParseError::Fail);
+        let failed = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert_eq!(failed.kept.len(), 1);
+        assert_eq!(failed.unframed, Some(origin));
+        assert!(matches!(failed.error, Some(Error::ParseError { .. })));
+        // ... or to stop at it to fetch it again, the rest left to the parser
+        let filter = filter.on_parse_error(OnParseError::Skip).refetching(true);
+        let stopped = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        assert_eq!(stopped.unframed, Some(origin));
+        assert!(stopped.error.is_some());
+        let rest = filter.filter_chunk(stopped.parser, &[]);
+        assert_eq!(rest.kept[0].origin.index, 3);
+
+        // One of every 2 UGRD messages, counted across chunks
+        let routes: Arc<[Route]> = vec!["UGRD=".parse().unwrap()].into();
+        let matched = Arc::new(AtomicU64::new(0));
+        let filter = Filter::new(routes.clone()).sampling(2, matched.clone());
+        let stream = [message(2), message(0), message(2), message(2)].concat();
+        let (first, rest) = stream.split_at(message(2).len() + 1);
+        let chunk = filter.clone().filter_chunk(Grib2StreamParser::new(), first);
+        let next = filter.clone().filter_chunk(chunk.parser, rest);
+        let kept: Vec<_> = [chunk.kept, next.kept].concat();
+        let indices: Vec<_> = kept.iter().map(|k| k.origin.index).collect();
+        assert_eq!(indices, [1, 4]);
+
+        // ... and across the filters sharing the count, e.g. of the next file
+        let next_file = Filter::new(routes).sampling(2, matched);
+        let chunk = next_file.filter_chunk(Grib2StreamParser::new(), &message(2));
+        assert!(chunk.kept.is_empty());
+    }
+
+    #[test]
+    fn test_kept_messages_locate_their_source() {
+        let routes: Arc<[Route]> = vec!["UGRD,VGRD,GUST=".parse().unwrap()].into();
+        let filter = Filter::new(routes).with_transform(Arc::new(Rewrite));
+
+        // The dropped VGRD and the TMP not routed still count in the positions
+        let stream = [message(3), message(0), message(2)].concat();
+        let length = message(2).len() as u64;
+        for chunk_size in [stream.len(), 7] {
+            let mut parser = Grib2StreamParser::new();
+            let mut kept = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                let filtered = filter.clone().filter_chunk(parser, chunk);
+                parser = filtered.parser;
+                kept.extend(filtered.kept);
+            }
+            assert_eq!(kept.len(), 1);
+            assert_eq!(
+                kept[0].origin,
+                Origin {
+                    index: 3,
+                    offset: 2 * length,
+                    length
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_keep_the_messages_valid_at_a_time() {
+        // UGRD of the 2020-01-01 00Z cycle at forecast hours 0, 1 and 2
+        let mut identification = section(1, 21);
+        identification[12..19].copy_from_slice(&[0x07, 0xe4, 1, 1, 0, 0, 0]);
+        let stream: Vec<u8> = (0..3)
+            .flat_map(|hour| {
+                let mut product = product_section(2, 2);
+                product[17] = 1;
+                product[21] = hour;
+                assemble(0, &[&identification, &product])
+            })
+            .collect();
+
+        let routes: Arc<[Route]> = vec!["UGRD=".parse().unwrap()].into();
+        let time = NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(1, 0, 0)
+            .unwrap();
+        let filter = Filter::new(routes).valid_at(time);
+        let chunk = filter.filter_chunk(Grib2StreamParser::new(), &stream);
+        let indices: Vec<_> = chunk.kept.iter().map(|k| k.origin.index).collect();
+        assert_eq!(indices, [2]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+
+use crate::cycle::{Cycle, Target, Task};
+use crate::model::Model;
+
+/// Gaps shipped with the binary, used unless `--known-gaps` gives another list.
+const EMBEDDED: &str = include_str!("known_gaps.txt");
+
+/// Cycles and forecast steps known to be missing from the sources, so backfills
+/// skip them rather than retrying and failing.
+#[derive(Debug, Default)]
+pub struct KnownGaps {
+    /// Whole cycles.
+    cycles: HashSet<(Model, NaiveDateTime)>,
+    /// Single forecast steps.
+    steps: HashSet<Task>,
+}
+
+impl KnownGaps {
+    /// The list shipped with the binary.
+    pub fn embedded() -> Self {
+        Self::parse(EMBEDDED).expect("embedded known gaps are valid")
+    }
+
+    /// Read a list in the same format as the embedded one.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read known gaps {}", path.display()))?;
+        Self::parse(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid known gaps {}: {e}", path.display()))
+    }
+
+    /// Parse `<model> <YYYY-MM-DD>T<HH> [f<FFF>]` lines; `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut gaps = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| format!("line {}: {reason}", n + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (model, time, step) = match fields[..] {
+                [model, time] => (model, time, None),
+                [model, time, step] => (model, time, Some(step)),
+                _ => {
+                    return Err(invalid(format!(
+                        "expected '<model> <YYYY-MM-DD>T<HH> [f<FFF>]', got '{line}'"
+                    )))
+                }
+            };
+            let model = Model::from_str(model, true).map_err(invalid)?;
+            let time = NaiveDateTime::parse_from_str(&format!("{time}:00"), "%Y-%m-%dT%H:%M")
+                .map_err(|_| invalid(format!("invalid cycle time '{time}'")))?;
+            match step {
+                None => {
+                    gaps.cycles.insert((model, time));
+                }
+                Some(step) => {
+                    let forecast_hour =
+                        step.strip_prefix('f')
+                            .and_then(|hour| hour.parse().ok())
+                            .ok_or_else(|| invalid(format!("invalid forecast step '{step}'")))?;
+                    gaps.steps.insert(Task {
+                        cycle: Cycle { model, time },
+                        forecast_hour,
+                    });
+                }
+            }
+        }
+        Ok(gaps)
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.cycles.len() + self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the file of a task is known to be missing.
+    pub fn contains(&self, task: &Task) -> bool {
+        self.cycles.contains(&(task.cycle.model, task.cycle.time)) || self.steps.contains(task)
+    }
+
+    /// Drop the candidates known to be missing, then the targets left without any.
+    /// Returns the number of targets dropped.
+    pub fn remove_from(&self, targets: &mut Vec<Target>) -> usize {
+        let planned = targets.len();
+        for target in targets.iter_mut() {
+            target.candidates.retain(|task| !self.contains(task));
+        }
+        targets.retain(|target| !target.candidates.is_empty());
+        planned - targets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::plan_targets;
+    use crate::testing::task;
+
+    #[test]
+    fn test_known_gaps() {
+        assert!(KnownGaps::parse(EMBEDDED).is_ok());
+        for line in EMBEDDED
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+        {
+            let source = line.split_once('#').map(|(_, source)| source.trim());
+            assert!(
+                line.trim().is_empty() || source.is_some_and(|source| !source.is_empty()),
+                "embedded gap without a source: '{line}'"
+            );
+        }
+
+        let gaps = KnownGaps::parse(
+            "# outages\ngfs 2020-01-01T06  # whole cycle\n\nGFS 2020-01-01T12 f003\n",
+        )
+        .unwrap();
+        assert_eq!(gaps.len(), 2);
+        assert!(gaps.contains(&task(6, 0)) && gaps.contains(&task(6, 3)));
+        assert!(gaps.contains(&task(12, 3)));
+        assert!(!gaps.contains(&task(12, 0)) && !gaps.contains(&task(0, 0)));
+
+        let mut targets = plan_targets(&[task(0, 0), task(6, 0), task(12, 0)]);
+        assert_eq!(gaps.remove_from(&mut targets), 1);
+        assert_eq!(targets.len(), 2);
+
+        let err = KnownGaps::parse("gfs 2020-01-01T06\ngfs 2020-01-01 06\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+        assert!(KnownGaps::parse("gfs 2020-01-01T06 3").is_err());
+        assert!(KnownGaps::parse("gefs 2020-01-01T06").is_err());
+    }
+}
@@ -1,71 +1,217 @@
+use std::fmt;
+
 use bytes::{Buf, BytesMut};
-use std::io::Cursor;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::error::{Error, Result};
+
+/// Length of the indicator section (Section 0), which holds the message length.
+pub const INDICATOR_LEN: usize = 16;
+
+/// End section (Section 8) closing every message.
+pub const END_MARKER: &[u8] = b"7777";
 
 /// Streaming parser for GRIB2 messages.
 /// Accumulates incoming bytes and extracts complete GRIB2 messages.
+///
+/// Works as a state machine: searching for the "GRIB" magic, reading the
+/// indicator section, then waiting for the body of a message of known
+/// length. `step()` reports how many more bytes the current state needs.
 pub struct Grib2StreamParser {
     buffer: BytesMut,
+    /// Stream offset of the first buffered byte.
+    offset: u64,
+    state: State,
+    /// Number of messages extracted so far.
+    messages: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Looking for the "GRIB" magic; scanned bytes without it are dropped.
+    Searching,
+    /// The buffer starts with "GRIB"; waiting for the rest of the indicator section.
+    Indicator,
+    /// The buffer starts with a message of this length.
+    Body(usize),
+}
+
+/// Outcome of a parser step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// A complete message.
+    Message(Vec<u8>),
+    /// At least this many more bytes are needed before the next message.
+    Need(usize),
+}
+
+/// What the parser is waiting for, for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Waiting {
+    /// 1-based index of the message being read.
+    pub message: u64,
+    /// Bytes still missing from the message.
+    pub needed: usize,
+    /// Total length of the message.
+    pub length: usize,
+}
+
+impl fmt::Display for Waiting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "waiting for {:.1} MB of message {} ({:.1} MB)",
+            self.needed as f64 / 1e6,
+            self.message,
+            self.length as f64 / 1e6
+        )
+    }
 }
 
 impl Grib2StreamParser {
     pub fn new() -> Self {
         Self {
             buffer: BytesMut::with_capacity(64 * 1024),
+            offset: 0,
+            state: State::Searching,
+            messages: 0,
         }
     }
 
     /// Feed incoming bytes and return any complete GRIB2 messages.
-    pub fn feed(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
-        self.buffer.extend_from_slice(data);
+    /// Fails on a message without its "7777" end marker.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.push(data);
         let mut messages = Vec::new();
 
-        while let Some(msg) = self.try_extract_message() {
+        while let Some(msg) = self.next_message()? {
             messages.push(msg);
         }
-        messages
+        Ok(messages)
     }
 
-    /// Try to extract a complete GRIB2 message from the buffer.
-    fn try_extract_message(&mut self) -> Option<Vec<u8>> {
-        // Find "GRIB" magic bytes
-        let pos = self.buffer.windows(4).position(|w| w == b"GRIB")?;
+    /// Append incoming bytes without extracting messages.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
 
-        // Skip any garbage before "GRIB"
-        if pos > 0 {
-            self.buffer.advance(pos);
+    /// Extract the next complete message, if any. Unlike `feed()`, messages
+    /// preceding an invalid one are returned before the error.
+    pub fn next_message(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.step()? {
+            Step::Message(msg) => Ok(Some(msg)),
+            Step::Need(_) => Ok(None),
         }
+    }
 
-        // Need at least 16 bytes for the indicator section
-        if self.buffer.len() < 16 {
-            return None;
-        }
+    /// Advance the state machine as far as the buffered bytes allow.
+    ///
+    /// Each buffered byte is scanned for the "GRIB" magic at most once:
+    /// garbage is dropped as it is scanned, and once a message header has
+    /// been read the parser only waits for the buffer to reach its length.
+    pub fn step(&mut self) -> Result<Step> {
+        loop {
+            match self.state {
+                State::Searching => {
+                    // Find "GRIB" magic bytes
+                    let Some(pos) = self.buffer.windows(4).position(|w| w == b"GRIB") else {
+                        // Drop the garbage, keeping a tail that may hold the start of "GRIB"
+                        self.advance(self.buffer.len().saturating_sub(3));
+                        return Ok(Step::Need(INDICATOR_LEN - self.buffer.len()));
+                    };
+                    // Skip any garbage before "GRIB"
+                    self.advance(pos);
+                    self.state = State::Indicator;
+                }
+                State::Indicator => {
+                    // Need at least 16 bytes for the indicator section
+                    if self.buffer.len() < INDICATOR_LEN {
+                        return Ok(Step::Need(INDICATOR_LEN - self.buffer.len()));
+                    }
 
-        // Read message length from octets 8-15 (8-byte big-endian)
-        let len_bytes: [u8; 8] = self.buffer[8..16].try_into().ok()?;
-        let msg_len = u64::from_be_bytes(len_bytes) as usize;
+                    // Read message length from octets 8-15 (8-byte big-endian)
+                    let len_bytes: [u8; 8] = self.buffer[8..16].try_into().expect("8 bytes");
+                    let msg_len = u64::from_be_bytes(len_bytes) as usize;
 
-        // Sanity check: messages shouldn't be larger than 1GB
-        if msg_len > 1_000_000_000 {
-            // Invalid message, skip these 4 bytes and try again
-            self.buffer.advance(4);
-            return None;
+                    // Sanity check: messages shouldn't be larger than 1GB, and
+                    // can't be shorter than their indicator section
+                    if !(INDICATOR_LEN..=1_000_000_000).contains(&msg_len) {
+                        // Invalid message, skip these 4 bytes and try again
+                        self.advance(4);
+                        self.state = State::Searching;
+                        continue;
+                    }
+
+                    self.state = State::Body(msg_len);
+                }
+                State::Body(msg_len) => {
+                    // Check if we have the complete message
+                    if self.buffer.len() < msg_len {
+                        return Ok(Step::Need(msg_len - self.buffer.len()));
+                    }
+                    return self.extract(msg_len).map(Step::Message);
+                }
+            }
         }
+    }
 
-        // Check if we have the complete message
-        if self.buffer.len() < msg_len {
-            return None;
+    /// The message being waited for, once its header has been read.
+    pub fn waiting(&self) -> Option<Waiting> {
+        match self.state {
+            State::Body(length) => Some(Waiting {
+                message: self.messages + 1,
+                needed: length - self.buffer.len().min(length),
+                length,
+            }),
+            State::Searching | State::Indicator => None,
         }
+    }
 
-        // Extract message
+    /// Number of messages extracted so far.
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
+    /// Stream offset of the first byte not consumed yet: right after a message
+    /// is returned, the offset of its end.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Bytes of an incomplete message still buffered.
+    /// Non-zero once the stream has ended means the source was cut short.
+    pub fn pending(&self) -> usize {
+        self.buffer
+            .windows(4)
+            .position(|w| w == b"GRIB")
+            .map_or(0, |pos| self.buffer.len() - pos)
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.buffer.advance(n);
+        self.offset += n as u64;
+    }
+
+    /// Take the complete message at the start of the buffer.
+    fn extract(&mut self, msg_len: usize) -> Result<Vec<u8>> {
+        let offset = self.offset;
         let msg = self.buffer.split_to(msg_len).to_vec();
+        self.offset += msg_len as u64;
+        self.state = State::Searching;
+        self.messages += 1;
 
         // Verify ends with "7777"
-        if msg.len() < 4 || &msg[msg.len() - 4..] != b"7777" {
-            // Invalid message ending, data might be corrupted
-            return None;
+        if !msg.ends_with(END_MARKER) {
+            return Err(Error::ParseError {
+                offset,
+                reason: format!(
+                    "message {} of {msg_len} bytes does not end with '7777'",
+                    self.messages
+                ),
+            });
         }
 
-        Some(msg)
+        Ok(msg)
     }
 }
 
@@ -75,51 +221,493 @@ impl Default for Grib2StreamParser {
     }
 }
 
-/// Check if a GRIB2 message contains a wind variable (UGRD or VGRD).
+/// Identification of a product (one section 4) within a GRIB2 message.
 ///
-/// Uses the grib crate to parse the message and check the parameter
-/// category and number in the product definition section.
-pub fn is_wind_message(msg: &[u8]) -> bool {
-    let Ok(grib2) = grib::from_reader(Cursor::new(msg)) else {
-        return false;
-    };
+/// Read directly from the section octets, which share the same layout for
+/// every product definition template (4.0 analysis/forecast, 4.1/4.11
+/// ensemble members, 4.8 statistically processed fields, ...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProductInfo {
+    /// Discipline from the indicator section (0 = meteorological).
+    pub discipline: u8,
+    /// Product definition template number.
+    pub template: u16,
+    /// Parameter category (Code Table 4.1).
+    pub category: u8,
+    /// Parameter number (Code Table 4.2).
+    pub number: u8,
+    /// Reference, forecast and statistical period times, when decodable.
+    pub time: Option<ProductTime>,
+    /// Vertical level, when decodable.
+    pub level: Option<Level>,
+}
 
-    for (_, submsg) in grib2.iter() {
-        // Get the product definition section
-        let prod_def = submsg.prod_def();
+/// Time information of a product.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProductTime {
+    /// Reference time (Section 1), usually the model cycle.
+    pub reference: NaiveDateTime,
+    /// Start of the period the product covers.
+    pub start: NaiveDateTime,
+    /// End of the period; equal to `start` for instantaneous fields.
+    pub end: NaiveDateTime,
+    /// Statistical process (Code Table 4.10: 0 average, 2 maximum, ...)
+    /// for statistically processed templates (4.8, 4.11, ...).
+    pub statistic: Option<u8>,
+}
 
-        // Parameter category 2 = Momentum
-        // Parameter number 2 = UGRD (U-component of wind)
-        // Parameter number 3 = VGRD (V-component of wind)
-        let Some(cat) = prod_def.parameter_category() else {
-            continue;
+impl ProductTime {
+    /// Valid time of the product: the forecast time for instantaneous
+    /// fields, the end of the interval for statistically processed ones.
+    pub fn valid_time(&self) -> NaiveDateTime {
+        self.end
+    }
+}
+
+/// A fixed surface (Code Table 4.5) and its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Surface {
+    /// Type of surface (1 ground, 100 isobaric, 103 height above ground, ...).
+    pub kind: u8,
+    /// Decimal scale factor of `scaled_value`.
+    pub scale: i8,
+    /// Scaled value; `None` for surfaces without a value.
+    pub scaled_value: Option<i32>,
+}
+
+impl Surface {
+    /// Value in the surface's SI unit (Pa, m, K, ...).
+    pub fn value(&self) -> Option<f64> {
+        self.scaled_value
+            .map(|v| v as f64 / 10f64.powi(self.scale as i32))
+    }
+
+    /// Name of a surface without a value, as printed by wgrib2.
+    fn name(&self) -> Option<&'static str> {
+        Some(match self.kind {
+            1 => "surface",
+            2 => "cloud base",
+            3 => "cloud top",
+            4 => "0C isotherm",
+            6 => "max wind",
+            7 => "tropopause",
+            8 => "top of atmosphere",
+            10 => "entire atmosphere",
+            101 => "mean sea level",
+            200 => "entire atmosphere (considered as a single layer)",
+            204 => "highest tropospheric freezing level",
+            220 => "planetary boundary layer",
+            _ => return None,
+        })
+    }
+
+    /// Value in the unit it is usually shown in (mb rather than Pa), with that unit.
+    fn display_value(&self) -> Option<(f64, &'static str)> {
+        let (divisor, unit) = match self.kind {
+            100 => (100.0, "mb"),
+            102 => (1.0, "m above mean sea level"),
+            103 => (1.0, "m above ground"),
+            104 => (1.0, "sigma level"),
+            105 => (1.0, "hybrid level"),
+            106 => (1.0, "m below ground"),
+            107 => (1.0, "K isentropic level"),
+            108 => (100.0, "mb above ground"),
+            109 => (1e-6, "PVU"),
+            _ => return None,
         };
-        let Some(num) = prod_def.parameter_number() else {
-            continue;
+        // Round off the noise of the decimal scaling
+        let value = (self.value()? / divisor * 1e6).round() / 1e6;
+        Some((value, unit))
+    }
+}
+
+impl fmt::Display for Surface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name() {
+            return f.write_str(name);
+        }
+        match (self.display_value(), self.value()) {
+            (Some((value, unit)), _) => write!(f, "{value} {unit}"),
+            (None, Some(value)) => write!(f, "surface type {} = {value}", self.kind),
+            (None, None) => write!(f, "surface type {}", self.kind),
+        }
+    }
+}
+
+/// Vertical level of a product: a surface, or a layer between two surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Level {
+    pub first: Surface,
+    pub second: Option<Surface>,
+}
+
+impl fmt::Display for Level {
+    /// wgrib2-style description, e.g. `10 m above ground`, `850 mb` or `0-0.1 m below ground`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(second) = self.second else {
+            return self.first.fmt(f);
         };
+        match (self.first.display_value(), second.display_value()) {
+            (Some((top, unit)), Some((bottom, other))) if unit == other => {
+                write!(f, "{top}-{bottom} {unit}")
+            }
+            _ => write!(f, "{} - {second}", self.first),
+        }
+    }
+}
+
+/// Iterate over the sections of a GRIB2 message as (number, bytes) pairs.
+///
+/// Stops at the "7777" end section or at the first malformed section.
+pub(crate) fn sections(msg: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut pos = 16;
+    std::iter::from_fn(move || {
+        let rest = msg.get(pos..)?;
+        if rest.len() < 5 || rest.starts_with(END_MARKER) {
+            return None;
+        }
+        let len = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        if len < 5 || len > rest.len() {
+            return None;
+        }
+        pos += len;
+        Some((rest[4], &rest[..len]))
+    })
+}
+
+/// Check that the sections of a message chain up to its "7777" end section and
+/// include a product definition, so that all its products can be read.
+pub fn check_sections(msg: &[u8]) -> std::result::Result<(), String> {
+    if msg.len() < 20 || !msg.ends_with(END_MARKER) {
+        return Err("no end section".to_string());
+    }
+    let end = msg.len() - END_MARKER.len();
+    let (mut pos, mut products) = (16, 0);
+    while pos < end {
+        let rest = &msg[pos..end];
+        if rest.len() < 5 {
+            return Err(format!("truncated section at byte {pos}"));
+        }
+        let len = u32::from_be_bytes(rest[..4].try_into().expect("4 bytes")) as usize;
+        let number = rest[4];
+        if !(1..=7).contains(&number) {
+            return Err(format!("unknown section {number} at byte {pos}"));
+        }
+        if len < 5 || len > rest.len() {
+            return Err(format!(
+                "section {number} at byte {pos} announces {len} bytes"
+            ));
+        }
+        products += usize::from(number == 4);
+        pos += len;
+    }
+    if products == 0 {
+        return Err("no product definition section".to_string());
+    }
+    Ok(())
+}
+
+/// Extract the product identification of every section 4 in a message.
+pub fn products(msg: &[u8]) -> Vec<ProductInfo> {
+    if msg.len() < 16 || !msg.starts_with(b"GRIB") || msg[7] != 2 {
+        return Vec::new();
+    }
+    let discipline = msg[6];
+    let mut reference = None;
+    let mut products = Vec::new();
+
+    for (number, section) in sections(msg) {
+        match number {
+            1 => reference = read_timestamp(section, 12),
+            4 if section.len() >= 11 => {
+                let template = u16::from_be_bytes([section[7], section[8]]);
+                products.push(ProductInfo {
+                    discipline,
+                    template,
+                    category: section[9],
+                    number: section[10],
+                    time: reference.and_then(|r| product_time(section, template, r)),
+                    level: product_level(section, template),
+                });
+            }
+            _ => {}
+        }
+    }
+    products
+}
+
+/// Read a 7-octet timestamp (year(2), month, day, hour, minute, second) at `index`.
+fn read_timestamp(section: &[u8], index: usize) -> Option<NaiveDateTime> {
+    let b = section.get(index..index + 7)?;
+    let year = u16::from_be_bytes([b[0], b[1]]) as i32;
+    NaiveDate::from_ymd_opt(year, b[2] as u32, b[3] as u32)?.and_hms_opt(
+        b[4] as u32,
+        b[5] as u32,
+        b[6] as u32,
+    )
+}
+
+/// Convert a time value in Code Table 4.4 units to a duration.
+fn time_range(unit: u8, value: i64) -> Option<Duration> {
+    match unit {
+        0 => Some(Duration::minutes(value)),
+        1 => Some(Duration::hours(value)),
+        2 => Some(Duration::days(value)),
+        10 => Some(Duration::hours(3 * value)),
+        11 => Some(Duration::hours(6 * value)),
+        12 => Some(Duration::hours(12 * value)),
+        13 => Some(Duration::seconds(value)),
+        _ => None,
+    }
+}
+
+/// Decode the period covered by a product from its section 4.
+///
+/// Templates 4.0-4.15 store the forecast time at octets 18-22. Statistically
+/// processed templates additionally store the end of the overall time
+/// interval, followed 12 octets later by the statistical process.
+fn product_time(section: &[u8], template: u16, reference: NaiveDateTime) -> Option<ProductTime> {
+    if template > 15 {
+        return None;
+    }
+    let unit = *section.get(17)?;
+    let value = u32::from_be_bytes(section.get(18..22)?.try_into().ok()?);
+    let start = reference + time_range(unit, value as i64)?;
+
+    // 1-based octet of "year of end of overall time interval"
+    let end_octet = match template {
+        8 => Some(35),
+        9 => Some(48),
+        10 => Some(36),
+        11 => Some(38),
+        12 => Some(37),
+        _ => None,
+    };
+
+    let (end, statistic) = match end_octet {
+        Some(octet) => (
+            read_timestamp(section, octet - 1)?,
+            section.get(octet - 1 + 12).copied(),
+        ),
+        None => (start, None),
+    };
 
-        if cat == 2 && (num == 2 || num == 3) {
-            return true;
+    Some(ProductTime {
+        reference,
+        start,
+        end,
+        statistic,
+    })
+}
+
+/// Read a fixed surface (type, scale factor, scaled value) at `index`.
+///
+/// Negative factors and values use a sign bit rather than two's complement;
+/// all bits set means missing.
+fn read_surface(section: &[u8], index: usize) -> Option<Surface> {
+    let b = section.get(index..index + 6)?;
+    if b[0] == 255 {
+        return None;
+    }
+    let scaled_value = u32::from_be_bytes(b[2..6].try_into().ok()?);
+    let scaled_value = (scaled_value != u32::MAX).then(|| {
+        let magnitude = (scaled_value & 0x7fff_ffff) as i32;
+        if scaled_value & 0x8000_0000 != 0 {
+            -magnitude
+        } else {
+            magnitude
         }
+    });
+    let scale = match b[1] {
+        255 => 0,
+        s if s & 0x80 != 0 => -((s & 0x7f) as i8),
+        s => s as i8,
+    };
+    Some(Surface {
+        kind: b[0],
+        scale,
+        scaled_value,
+    })
+}
+
+/// Decode the level of a product: templates 4.0-4.15 store the first and
+/// second fixed surfaces at octets 23-28 and 29-34.
+fn product_level(section: &[u8], template: u16) -> Option<Level> {
+    if template > 15 {
+        return None;
     }
+    Some(Level {
+        first: read_surface(section, 22)?,
+        second: read_surface(section, 28),
+    })
+}
 
-    false
+/// Check if a product is a wind variable (UGRD or VGRD).
+///
+/// Matches discipline 0 (meteorological), parameter category 2 (momentum),
+/// parameter number 2 (UGRD) or 3 (VGRD), whatever the product template.
+pub fn is_wind_product(p: &ProductInfo) -> bool {
+    p.discipline == 0 && p.category == 2 && (p.number == 2 || p.number == 3)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{assemble, section};
+
+    /// Build a minimal GRIB2 message: indicator, sections 1 and 4, end section.
+    /// Reference time is 2020-01-01 00:00, forecast time 6 hours.
+    fn message(discipline: u8, template: u16, category: u8, number: u8) -> Vec<u8> {
+        let mut sect1 = section(1, 21);
+        sect1[12..19].copy_from_slice(&[0x07, 0xe4, 1, 1, 0, 0, 0]);
+
+        let mut sect4 = section(4, 58);
+        sect4[7..9].copy_from_slice(&template.to_be_bytes());
+        sect4[9] = category;
+        sect4[10] = number;
+        sect4[17] = 1;
+        sect4[18..22].copy_from_slice(&6u32.to_be_bytes());
+        // 10 m above ground, no second surface
+        sect4[22] = 103;
+        sect4[24..28].copy_from_slice(&10u32.to_be_bytes());
+        sect4[28..34].fill(255);
+        if template == 8 {
+            // Maximum over 2020-01-01 06:00 .. 12:00
+            sect4[34..41].copy_from_slice(&[0x07, 0xe4, 1, 1, 12, 0, 0]);
+            sect4[46] = 2;
+        }
+
+        assemble(discipline, &[&sect1, &sect4])
+    }
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn is_wind_message(msg: &[u8]) -> bool {
+        products(msg).iter().any(is_wind_product)
+    }
+
+    #[test]
+    fn test_wind_filter_any_product_template() {
+        // Analysis (4.0), ensemble (4.1, 4.11) and statistical (4.8) templates
+        for template in [0, 1, 8, 11] {
+            assert!(is_wind_message(&message(0, template, 2, 2)));
+            assert!(is_wind_message(&message(0, template, 2, 3)));
+        }
+        // Temperature, and momentum parameters of another discipline
+        assert!(!is_wind_message(&message(0, 0, 0, 0)));
+        assert!(!is_wind_message(&message(10, 0, 2, 2)));
+    }
+
+    #[test]
+    fn test_check_sections() {
+        let msg = message(0, 0, 2, 2);
+        assert_eq!(check_sections(&msg), Ok(()));
+        // Section 1 claiming more bytes than the message holds
+        let mut long = msg.clone();
+        long[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(check_sections(&long)
+            .unwrap_err()
+            .contains("announces 1000"));
+        // Section 4 numbered 9
+        let mut unknown = msg.clone();
+        unknown[16 + 21 + 4] = 9;
+        assert_eq!(
+            check_sections(&unknown),
+            Err("unknown section 9 at byte 37".to_string())
+        );
+        assert!(check_sections(&msg[..msg.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_products_reads_section_4() {
+        let msg = message(0, 8, 2, 22);
+        assert_eq!(
+            products(&msg),
+            vec![ProductInfo {
+                discipline: 0,
+                template: 8,
+                category: 2,
+                number: 22,
+                time: Some(ProductTime {
+                    reference: at(0),
+                    start: at(6),
+                    end: at(12),
+                    statistic: Some(2),
+                }),
+                level: Some(Level {
+                    first: Surface {
+                        kind: 103,
+                        scale: 0,
+                        scaled_value: Some(10),
+                    },
+                    second: None,
+                }),
+            }]
+        );
+        assert!(products(b"GRIB").is_empty());
+    }
+
+    #[test]
+    fn test_instantaneous_product_time() {
+        let time = products(&message(0, 0, 2, 2))[0].time.unwrap();
+        assert_eq!(time.reference, at(0));
+        assert_eq!(time.start, at(6));
+        assert_eq!(time.end, at(6));
+        assert_eq!(time.statistic, None);
+        assert_eq!(time.valid_time(), at(6));
+    }
+
+    #[test]
+    fn test_level_display() {
+        let surface = |kind, scale, scaled_value| Surface {
+            kind,
+            scale,
+            scaled_value,
+        };
+        let level = |first, second| Level { first, second }.to_string();
+
+        assert_eq!(
+            products(&message(0, 0, 2, 2))[0].level.unwrap().to_string(),
+            "10 m above ground"
+        );
+        assert_eq!(level(surface(100, 0, Some(85000)), None), "850 mb");
+        assert_eq!(level(surface(1, 0, None), None), "surface");
+        assert_eq!(
+            level(surface(106, 1, Some(0)), Some(surface(106, 1, Some(1)))),
+            "0-0.1 m below ground"
+        );
+        assert_eq!(level(surface(109, 9, Some(2000)), None), "2 PVU");
+        assert_eq!(
+            level(surface(150, 0, Some(3)), None),
+            "surface type 150 = 3"
+        );
+
+        // Sign bit rather than two's complement
+        let mut section = vec![0u8; 34];
+        section[22..28].copy_from_slice(&[109, 0x89, 0x80, 0, 0x07, 0xd0]);
+        section[28] = 255;
+        let negative = product_level(&section, 0).unwrap();
+        assert_eq!(negative.first.scale, -9);
+        assert_eq!(negative.first.scaled_value, Some(-2000));
+        assert_eq!(negative.second, None);
+    }
 
     #[test]
     fn test_parser_incomplete_message() {
         let mut parser = Grib2StreamParser::new();
 
         // Feed partial GRIB header
-        let messages = parser.feed(b"GRIB");
+        let messages = parser.feed(b"GRIB").unwrap();
         assert!(messages.is_empty());
+        assert_eq!(parser.pending(), 4);
 
         // Feed more partial data
-        let messages = parser.feed(&[0u8; 12]);
+        let messages = parser.feed(&[0u8; 12]).unwrap();
         assert!(messages.is_empty());
     }
 
@@ -130,7 +718,8 @@ mod tests {
         // Feed garbage then GRIB magic
         let mut data = vec![0u8; 100];
         data[50..54].copy_from_slice(b"GRIB");
-        let messages = parser.feed(&data);
+        data[58..66].copy_from_slice(&1000u64.to_be_bytes());
+        let messages = parser.feed(&data).unwrap();
 
         // Should not return anything yet (incomplete message)
         assert!(messages.is_empty());
@@ -138,4 +727,68 @@ mod tests {
         // Buffer should have advanced past garbage
         assert!(parser.buffer.starts_with(b"GRIB"));
     }
+
+    #[test]
+    fn test_parser_rejects_missing_end_marker() {
+        let mut parser = Grib2StreamParser::new();
+        let mut msg = message(0, 0, 2, 2);
+        let len = msg.len();
+        msg[len - 4..].copy_from_slice(b"8888");
+
+        let mut data = vec![0u8; 10];
+        data.extend_from_slice(&msg);
+        match parser.feed(&data) {
+            Err(Error::ParseError { offset, .. }) => assert_eq!(offset, 10),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parser_skips_short_length() {
+        // "GRIB" announcing 8 bytes, shorter than its own indicator section
+        let mut data = b"GRIB\0\0\0\x02".to_vec();
+        data.extend_from_slice(&8u64.to_be_bytes());
+        data.extend_from_slice(&message(0, 0, 2, 2));
+
+        let mut parser = Grib2StreamParser::new();
+        let messages = parser.feed(&data).unwrap();
+        assert_eq!(messages, vec![message(0, 0, 2, 2)]);
+        assert_eq!(parser.pending(), 0);
+    }
+
+    #[test]
+    fn test_parser_small_chunks() {
+        let mut data = b"noise GRI".to_vec();
+        data.extend_from_slice(&message(0, 0, 2, 2));
+        data.extend_from_slice(&message(0, 0, 2, 3));
+
+        let mut parser = Grib2StreamParser::new();
+        let mut messages = Vec::new();
+        for piece in data.chunks(3) {
+            messages.extend(parser.feed(piece).unwrap());
+        }
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], message(0, 0, 2, 3));
+        assert_eq!(parser.pending(), 0);
+    }
+
+    #[test]
+    fn test_parser_reports_needed_bytes() {
+        let msg = message(0, 0, 2, 2);
+        let mut parser = Grib2StreamParser::new();
+
+        parser.push(&msg[..10]);
+        assert_eq!(parser.step().unwrap(), Step::Need(6));
+        assert_eq!(parser.waiting(), None);
+
+        parser.push(&msg[10..20]);
+        assert_eq!(parser.step().unwrap(), Step::Need(msg.len() - 20));
+        let waiting = parser.waiting().unwrap();
+        assert_eq!((waiting.message, waiting.length), (1, msg.len()));
+
+        parser.push(&msg[20..]);
+        assert_eq!(parser.step().unwrap(), Step::Message(msg));
+        assert_eq!(parser.messages(), 1);
+        assert_eq!(parser.step().unwrap(), Step::Need(16));
+    }
 }
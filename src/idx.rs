@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+
+use crate::grib::{ProductInfo, ProductTime};
+use crate::param::Param;
+
+/// Suffix of the wgrib2 inventory published next to GRIB2 files (NOMADS, AWS).
+pub const IDX_SUFFIX: &str = ".idx";
+
+/// Share of a file within which the wanted messages must end for the download
+/// to stop early (`--idx-early-stop`).
+pub const EARLY_STOP_SHARE: f64 = 0.2;
+
+/// One line of a `.idx` inventory, e.g.
+/// `12:4123562:d=2020010100:UGRD:10 m above ground:anl:`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxEntry {
+    /// 1-based message number; the products of one message share it (`12.1`, `12.2`).
+    pub message: u64,
+    /// Byte offset of the message in the file.
+    pub offset: u64,
+    /// Parameter, if its short name is known.
+    pub param: Option<Param>,
+}
+
+/// URL of the inventory of a source file, before any query string.
+pub fn idx_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, query)) => format!("{path}{IDX_SUFFIX}?{query}"),
+        None => format!("{url}{IDX_SUFFIX}"),
+    }
+}
+
+/// Parse a `.idx` inventory.
+pub fn parse_idx(text: &str) -> Result<Vec<IdxEntry>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<_> = line.split(':').collect();
+            let (Some(number), Some(offset), Some(name)) =
+                (fields.first(), fields.get(1), fields.get(3))
+            else {
+                return Err(format!("invalid .idx line '{line}'"));
+            };
+            let message = number.split('.').next().unwrap_or(number);
+            Ok(IdxEntry {
+                message: message
+                    .parse()
+                    .map_err(|_| format!("invalid message number in .idx line '{line}'"))?,
+                offset: offset
+                    .parse()
+                    .map_err(|_| format!("invalid offset in .idx line '{line}'"))?,
+                param: name.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+/// Lines of the inventory describing message `index` at `offset`, one per product
+/// and numbered `index.1`, `index.2`... when it holds several, as wgrib2 writes
+/// them (`--idx-cache`).
+pub fn idx_lines(index: u64, offset: u64, products: &[ProductInfo]) -> Vec<String> {
+    products
+        .iter()
+        .enumerate()
+        .map(|(i, product)| {
+            let number = if products.len() > 1 {
+                format!("{index}.{}", i + 1)
+            } else {
+                index.to_string()
+            };
+            let (date, forecast) = match &product.time {
+                Some(time) => (
+                    time.reference.format("%Y%m%d%H").to_string(),
+                    forecast_description(time),
+                ),
+                None => ("?".to_string(), "?".to_string()),
+            };
+            let level = product
+                .level
+                .as_ref()
+                .map_or_else(|| "?".to_string(), |level| level.to_string());
+            format!(
+                "{number}:{offset}:d={date}:{}:{level}:{forecast}:",
+                Param::of(product)
+            )
+        })
+        .collect()
+}
+
+/// wgrib2 description of a product's time, e.g. `anl`, `6 hour fcst` or
+/// `0-6 hour ave fcst`.
+fn forecast_description(time: &ProductTime) -> String {
+    let hours = |t: chrono::NaiveDateTime| (t - time.reference).num_hours();
+    match time.statistic {
+        Some(statistic) => {
+            let kind = match statistic {
+                0 => "ave",
+                1 => "acc",
+                2 => "max",
+                3 => "min",
+                _ => "stat",
+            };
+            format!("{}-{} hour {kind} fcst", hours(time.start), hours(time.end))
+        }
+        None if time.start == time.reference => "anl".to_string(),
+        None => format!("{} hour fcst", hours(time.start)),
+    }
+}
+
+/// Where the inventory generated for a source without one is kept under `dir`
+/// (`--idx-cache`): its host and path, without the query string, e.g.
+/// `<dir>/example.com/gfs.20200101/gfs.t00z.pgrb2.0p25.f000.idx`.
+pub fn cached_idx_path(dir: &Path, url: &str) -> PathBuf {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let path = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let mut cached = dir.to_path_buf();
+    for part in path.split('/') {
+        if !part.is_empty() && part != "." && part != ".." {
+            cached.push(part);
+        }
+    }
+    cached.set_file_name(format!(
+        "{}{IDX_SUFFIX}",
+        cached.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    cached
+}
+
+/// Offset where the last message holding a `wanted` parameter ends in a file of
+/// `file_size` bytes, or `None` if no message is wanted. Parameters with an unknown
+/// name count as wanted.
+pub fn wanted_end(
+    entries: &[IdxEntry],
+    wanted: impl Fn(Param) -> bool,
+    file_size: u64,
+) -> Option<u64> {
+    let last = entries
+        .iter()
+        .rposition(|entry| entry.param.is_none_or(&wanted))?;
+    let message = &entries[last];
+    Some(
+        entries[last..]
+            .iter()
+            .find(|entry| entry.message != message.message)
+            .map_or(file_size, |next| next.offset),
+    )
+}
+
+/// Byte range `[start, end)` of a message in a file of `file_size` bytes, to fetch
+/// it again with a range request.
+pub fn message_range(entries: &[IdxEntry], message: u64, file_size: u64) -> Option<(u64, u64)> {
+    let first = entries.iter().position(|entry| entry.message == message)?;
+    let end = entries[first..]
+        .iter()
+        .find(|entry| entry.message != message)
+        .map_or(file_size, |next| next.offset);
+    Some((entries[first].offset, end))
+}
+
+/// Offset from which the download of a file of `file_size` bytes can stop, when
+/// all the wanted messages end within its first `EARLY_STOP_SHARE`.
+pub fn early_stop(
+    entries: &[IdxEntry],
+    wanted: impl Fn(Param) -> bool,
+    file_size: u64,
+) -> Option<u64> {
+    wanted_end(entries, wanted, file_size)
+        .filter(|&end| end as f64 <= EARLY_STOP_SHARE * file_size as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idx_early_stop() {
+        let idx = "1:0:d=2020010100:PRMSL:mean sea level:anl:\n\
+                   2.1:1000:d=2020010100:UGRD:10 m above ground:anl:\n\
+                   2.2:1000:d=2020010100:VGRD:10 m above ground:anl:\n\
+                   3:1800:d=2020010100:TMP:2 m above ground:anl:\n\
+                   4:2500:d=2020010100:REFC:entire atmosphere:anl:\n";
+        let entries = parse_idx(idx).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[2].message, 2);
+        assert_eq!(entries[3].param, Some(Param::new(0, 0, 0)));
+        // REFC is not a known short name
+        assert_eq!(entries[4].param, None);
+
+        let wind = |param: Param| param.discipline == 0 && param.category == 2;
+        // Both products of message 2 end where message 3 starts
+        assert_eq!(wanted_end(&entries[..4], wind, 10_000), Some(1800));
+        assert_eq!(early_stop(&entries[..4], wind, 10_000), Some(1800));
+        assert_eq!(early_stop(&entries[..4], wind, 5_000), None);
+        // Unknown parameters are kept to the end of the file
+        assert_eq!(wanted_end(&entries, wind, 10_000), Some(10_000));
+        assert_eq!(wanted_end(&entries[..1], wind, 10_000), None);
+
+        assert_eq!(message_range(&entries, 2, 10_000), Some((1000, 1800)));
+        assert_eq!(message_range(&entries, 4, 10_000), Some((2500, 10_000)));
+        assert_eq!(message_range(&entries, 5, 10_000), None);
+
+        assert_eq!(
+            idx_url("https://example.com/gfs.f000?sig=1"),
+            "https://example.com/gfs.f000.idx?sig=1"
+        );
+        assert!(parse_idx("1:abc:d=2020010100:UGRD")
+            .unwrap_err()
+            .contains("offset"));
+
+        // Generated lines read back as the published ones
+        let (reference, valid) = (
+            chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(6, 0, 0)
+                .unwrap(),
+        );
+        let product = |number, start, statistic| ProductInfo {
+            discipline: 0,
+            template: 0,
+            category: 2,
+            number,
+            time: Some(ProductTime {
+                reference,
+                start,
+                end: valid,
+                statistic,
+            }),
+            level: None,
+        };
+        let lines = idx_lines(2, 1000, &[product(2, valid, None), product(3, valid, None)]);
+        assert_eq!(lines[0], "2.1:1000:d=2020010100:UGRD:?:6 hour fcst:");
+        assert_eq!(
+            parse_idx(&lines.join("\n")).unwrap(),
+            entries[1..3].to_vec()
+        );
+        assert_eq!(
+            idx_lines(7, 0, &[product(22, reference, Some(2))]),
+            ["7:0:d=2020010100:GUST:?:0-6 hour max fcst:"]
+        );
+
+        assert_eq!(
+            cached_idx_path(
+                Path::new("/cache"),
+                "https://example.com/../gfs.20200101/gfs.f000?sig=1"
+            ),
+            Path::new("/cache/example.com/gfs.20200101/gfs.f000.idx")
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Name of the pointer object, under the run's prefix.
+pub const LATEST_NAME: &str = "latest.json";
+
+/// Pointer to the newest complete cycle (`--latest-pointer`), so consumers find
+/// the newest data with one GET instead of listing the bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatestPointer {
+    pub model: String,
+    /// Initialization time of the cycle.
+    pub cycle: NaiveDateTime,
+    /// Objects of the cycle, by valid time.
+    pub objects: Vec<LatestObject>,
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatestObject {
+    pub key: String,
+    pub valid_time: NaiveDateTime,
+}
+
+impl LatestPointer {
+    /// Key of the pointer of a run writing under `prefix`, in a directory per model
+    /// when the run covers several.
+    pub fn key(prefix: &str, model: Option<&str>) -> String {
+        Self::key_named(prefix, model, LATEST_NAME)
+    }
+
+    /// Key of the pointer named `name` instead of `latest.json` (`--latest-name`).
+    pub fn key_named(prefix: &str, model: Option<&str>, name: &str) -> String {
+        let dirs: Vec<_> = [prefix.trim_end_matches('/')]
+            .into_iter()
+            .chain(model)
+            .filter(|dir| !dir.is_empty())
+            .collect();
+        if dirs.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{name}", dirs.join("/"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_latest_pointer() {
+        assert_eq!(LatestPointer::key("", None), "latest.json");
+        assert_eq!(LatestPointer::key("wind/", None), "wind/latest.json");
+        assert_eq!(
+            LatestPointer::key("wind", Some("icon-eu")),
+            "wind/icon-eu/latest.json"
+        );
+        assert_eq!(LatestPointer::key("", Some("gfs")), "gfs/latest.json");
+
+        let cycle = NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        let pointer = LatestPointer {
+            model: "gfs".to_string(),
+            cycle,
+            objects: vec![LatestObject {
+                key: "wind/wind_20200101_06.grb2".to_string(),
+                valid_time: cycle,
+            }],
+            updated: Utc::now(),
+        };
+        let json = serde_json::to_string(&pointer).unwrap();
+        assert!(json.contains(r#""cycle":"2020-01-01T06:00:00""#), "{json}");
+        assert_eq!(
+            serde_json::from_str::<LatestPointer>(&json).unwrap(),
+            pointer
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Stream GFS GRIB2 files from an HTTP source, keep the wind messages and
+//! upload them to S3 without touching disk.
+//!
+//! The command line (`cli`), run by the `gfs_wind_downloader` binary and the
+//! Python bindings, drives these modules; failures are reported as [`Error`],
+//! whose category decides retries and exit codes.
+
+#[cfg(feature = "arrays")]
+pub mod arrays;
+#[cfg(feature = "pipeline")]
+pub mod augment;
+pub mod budget;
+#[cfg(feature = "pipeline")]
+pub mod cache;
+pub mod catalog;
+pub mod checkpoint;
+pub mod chunk;
+#[cfg(feature = "pipeline")]
+pub mod cli;
+#[cfg(feature = "pipeline")]
+pub mod compact;
+#[cfg(feature = "pipeline")]
+pub mod compression;
+pub mod cycle;
+pub mod error;
+#[cfg(feature = "pipeline")]
+pub mod estimate;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod field;
+#[cfg(feature = "pipeline")]
+pub mod filter;
+pub mod gaps;
+pub mod grib;
+pub mod idx;
+pub mod latest;
+#[cfg(feature = "pipeline")]
+pub mod limiter;
+pub mod lock;
+pub mod manifest;
+pub mod memory;
+#[cfg(feature = "pipeline")]
+pub mod merge;
+#[cfg(feature = "pipeline")]
+pub mod mirror;
+pub mod model;
+#[cfg(feature = "pipeline")]
+pub mod naming;
+#[cfg(feature = "pipeline")]
+mod pack;
+pub mod param;
+pub mod politeness;
+#[cfg(feature = "pipeline")]
+pub mod profile;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod progress;
+#[cfg(feature = "pipeline")]
+pub mod provenance;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "pipeline")]
+pub mod quantize;
+#[cfg(feature = "pipeline")]
+pub mod reader;
+#[cfg(feature = "pipeline")]
+pub mod repair;
+#[cfg(feature = "pipeline")]
+pub mod replica;
+pub mod report;
+#[cfg(feature = "pipeline")]
+pub mod resume;
+pub mod retries;
+#[cfg(feature = "pipeline")]
+pub mod route;
+#[cfg(feature = "pipeline")]
+pub mod s3;
+#[cfg(feature = "pipeline")]
+pub mod selftest;
+#[cfg(feature = "pipeline")]
+pub mod source;
+#[cfg(feature = "pipeline")]
+pub mod spot;
+#[cfg(feature = "pipeline")]
+pub mod subset;
+pub mod template;
+pub mod term;
+#[cfg(test)]
+mod testing;
+pub mod units;
+#[cfg(feature = "pipeline")]
+pub mod update;
+#[cfg(feature = "pipeline")]
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::{Error, Result};
@@ -0,0 +1,260 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// Concurrency limiter that backs off when the source throttles us.
+///
+/// The effective limit starts at `max` and is halved each time the source
+/// answers with 429/503. Once `cooldown` has elapsed without further
+/// throttling, the limit grows back by one slot per cooldown period.
+pub struct AdaptiveLimiter {
+    max: usize,
+    cooldown: Duration,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+struct State {
+    limit: usize,
+    in_flight: usize,
+    last_change: Instant,
+    /// Jobs held by a permit, by job number minus one.
+    busy: Vec<bool>,
+}
+
+/// A slot held while processing one cycle. Released on drop.
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveLimiter,
+    job: usize,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(max: usize, cooldown: Duration) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            cooldown,
+            state: Mutex::new(State {
+                limit: max,
+                in_flight: 0,
+                last_change: Instant::now(),
+                busy: vec![false; max],
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Wait for a free slot under the current limit.
+    pub async fn acquire(&self) -> Permit<'_> {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                self.ramp_up(&mut state);
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    // The limit never exceeds `max`, so a job is free
+                    let job = state.busy.iter().position(|busy| !busy).unwrap_or(0);
+                    state.busy[job] = true;
+                    return Permit {
+                        limiter: self,
+                        job: job + 1,
+                    };
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Record a throttling response from the source and halve the limit.
+    pub fn on_throttled(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.limit = (state.limit / 2).max(1);
+        state.last_change = Instant::now();
+    }
+
+    /// Current effective concurrency limit.
+    pub fn limit(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        self.ramp_up(&mut state);
+        state.limit
+    }
+
+    /// Cooldown applied after throttling before ramping back up.
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+
+    /// Number of jobs, the limit before any throttling.
+    pub fn jobs(&self) -> usize {
+        self.max
+    }
+
+    fn ramp_up(&self, state: &mut State) {
+        if state.limit < self.max && state.last_change.elapsed() >= self.cooldown {
+            state.limit += 1;
+            state.last_change = Instant::now();
+        }
+    }
+
+    fn release(&self, job: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.busy[job - 1] = false;
+        self.ramp_up(&mut state);
+        drop(state);
+        self.notify.notify_waiters();
+    }
+}
+
+impl Permit<'_> {
+    /// Job running under this permit, from 1 to the limiter's `jobs()`: the lowest
+    /// one free when it was acquired.
+    pub fn job(&self) -> usize {
+        self.job
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(self.job);
+    }
+}
+
+/// Caps the average rate of the bytes sent through it (`--max-upload-bandwidth`).
+///
+/// Each send reserves the time its bytes take at the cap, after the previous
+/// reservations, and waits for the start of its slot: sends are never split, so
+/// the rate averages out over a few parts.
+pub struct BandwidthLimiter {
+    bytes_per_second: u64,
+    /// End of the last reservation.
+    next: Mutex<Instant>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `bytes` can be sent.
+    pub async fn acquire(&self, bytes: u64) {
+        let start = self.reserve(Instant::now(), bytes);
+        tokio::time::sleep_until(start.into()).await;
+    }
+
+    /// Reserve a slot for `bytes` at `now`; returns when it starts.
+    fn reserve(&self, now: Instant, bytes: u64) -> Instant {
+        let mut next = self.next.lock().unwrap();
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        start
+    }
+}
+
+/// Spaces the requests sent to the source by a minimum interval
+/// (`--request-interval`), whatever the number of cycles in flight.
+pub struct RequestPacer {
+    interval: Duration,
+    /// Earliest time of the next request.
+    next: Mutex<Instant>,
+}
+
+impl RequestPacer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the slot of the next request.
+    pub async fn wait(&self) {
+        let start = self.reserve(Instant::now());
+        tokio::time::sleep_until(start.into()).await;
+    }
+
+    /// Reserve the next slot at `now`; returns when it starts.
+    fn reserve(&self, now: Instant) -> Instant {
+        let mut next = self.next.lock().unwrap();
+        let start = (*next).max(now);
+        *next = start + self.interval;
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_halves_limit() {
+        let limiter = AdaptiveLimiter::new(8, Duration::from_secs(3600));
+        limiter.on_throttled();
+        assert_eq!(limiter.limit(), 4);
+        limiter.on_throttled();
+        assert_eq!(limiter.limit(), 2);
+        limiter.on_throttled();
+        limiter.on_throttled();
+        assert_eq!(limiter.limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_permits_take_the_lowest_free_job() {
+        let limiter = AdaptiveLimiter::new(3, Duration::from_secs(3600));
+        let first = limiter.acquire().await;
+        let second = limiter.acquire().await;
+        assert_eq!((first.job(), second.job()), (1, 2));
+        drop(first);
+        assert_eq!(limiter.acquire().await.job(), 1);
+        let third = limiter.acquire().await;
+        assert_eq!((third.job(), limiter.jobs()), (1, 3));
+    }
+
+    #[test]
+    fn test_bandwidth_reservations() {
+        let limiter = BandwidthLimiter::new(1000);
+        let now = Instant::now();
+        // The first send starts at once, the next ones after the bytes before them
+        assert_eq!(limiter.reserve(now, 2000), now);
+        assert_eq!(limiter.reserve(now, 500), now + Duration::from_secs(2));
+        assert_eq!(
+            limiter.reserve(now + Duration::from_secs(1), 1000),
+            now + Duration::from_millis(2500)
+        );
+        // An idle period is not saved up for later bursts
+        let later = now + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(later, 1000), later);
+    }
+
+    #[test]
+    fn test_request_pacing() {
+        let pacer = RequestPacer::new(Duration::from_millis(500));
+        let now = Instant::now();
+        assert_eq!(pacer.reserve(now), now);
+        assert_eq!(pacer.reserve(now), now + Duration::from_millis(500));
+        assert_eq!(pacer.reserve(now), now + Duration::from_secs(1));
+        let later = now + Duration::from_secs(60);
+        assert_eq!(pacer.reserve(later), later);
+    }
+
+    #[test]
+    fn test_ramps_up_after_cooldown() {
+        let limiter = AdaptiveLimiter::new(4, Duration::ZERO);
+        limiter.on_throttled();
+        limiter.on_throttled();
+        // Each check past the cooldown recovers a single slot, capped at max
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.limit(), 3);
+        assert_eq!(limiter.limit(), 4);
+        assert_eq!(limiter.limit(), 4);
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Exclusive lock held for the duration of a run, stored as a file containing the PID.
+///
+/// Prevents an overrunning cron job from starting a second instance that
+/// writes the same keys concurrently. The file is removed on drop.
+pub struct LockFile {
+    path: PathBuf,
+}
+
+impl LockFile {
+    /// Take the lock at `path`. With `force`, an existing lock is replaced.
+    pub fn acquire(path: &Path, force: bool) -> Result<Self> {
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())
+                        .with_context(|| format!("Failed to write lock file {}", path.display()))?;
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let holder = fs::read_to_string(path).unwrap_or_default();
+                    let holder = holder.trim();
+
+                    if !force && process_alive(holder) {
+                        anyhow::bail!(
+                            "Another instance (PID {holder}) holds {}; use --force if it is not running",
+                            path.display()
+                        );
+                    }
+
+                    eprintln!(
+                        "Replacing stale lock file {} (PID {holder})",
+                        path.display()
+                    );
+                    fs::remove_file(path).with_context(|| {
+                        format!("Failed to remove lock file {}", path.display())
+                    })?;
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create lock file {}", path.display()))
+                }
+            }
+        }
+    }
+
+    /// Default lock path for a destination, in the system temp directory.
+    pub fn default_path(bucket: &str, prefix: &str) -> PathBuf {
+        let name: String = format!("{bucket}-{}", prefix.trim_matches('/'))
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        std::env::temp_dir().join(format!("gfs_wind_downloader-{name}.lock"))
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether the process recorded in a lock file still runs.
+/// Without a way to tell (non-Linux, unreadable PID), assume it does.
+fn process_alive(pid: &str) -> bool {
+    if cfg!(target_os = "linux") {
+        match pid.parse::<u32>() {
+            Ok(pid) => Path::new("/proc").join(pid.to_string()).exists(),
+            Err(_) => true,
+        }
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("gfs-lock-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let lock = LockFile::acquire(&path, false).unwrap();
+        // Held by this (running) process
+        assert!(LockFile::acquire(&path, false).is_err());
+
+        drop(lock);
+        assert!(!path.exists());
+
+        let _lock = LockFile::acquire(&path, false).unwrap();
+        let _forced = LockFile::acquire(&path, true).unwrap();
+    }
+
+    #[test]
+    fn test_default_path_is_per_destination() {
+        let a = LockFile::default_path("bucket", "wind/2020/");
+        let b = LockFile::default_path("bucket", "wind/2021/");
+        assert_ne!(a, b);
+        assert!(a.ends_with("gfs_wind_downloader-bucket-wind_2020.lock"));
+    }
+}
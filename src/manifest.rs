@@ -0,0 +1,180 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::Location;
+use crate::grib::ProductInfo;
+
+/// Suffix appended to an object key to name its manifest.
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+/// Suffix of the manifest of an object not uploaded (`--metadata-only`).
+pub const INVENTORY_SUFFIX: &str = ".inventory.json";
+
+/// Sidecar JSON describing the messages of an uploaded object.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Source URL the messages were extracted from.
+    pub source: String,
+    /// Further source files of the task (per-variable files, pgrb2b companion),
+    /// whose messages follow those of `source`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<String>,
+    /// Model the messages come from (`gfs`, `icon-eu`, ...).
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Lead time of the source file: `anl` for the analysis, else `fNNN`.
+    /// Not set on daily objects, which gather several files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lead: Option<String>,
+    /// Key of the GRIB2 object this manifest describes.
+    pub key: String,
+    /// `Route::filter_hash()` of the parameters the object was produced with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_hash: Option<String>,
+    pub messages: Vec<MessageEntry>,
+}
+
+/// One GRIB2 message of the object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageEntry {
+    /// Byte offset of the message in the object (in `stored_in` if set).
+    pub offset: u64,
+    /// Message length in bytes.
+    pub length: u64,
+    pub discipline: u8,
+    pub category: u8,
+    pub number: u8,
+    /// Product definition template number.
+    pub template: u16,
+    /// Level of the product, e.g. `10 m above ground` or `850 mb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    pub reference_time: Option<NaiveDateTime>,
+    /// Reference time plus forecast offset (end of the period for statistical fields).
+    pub valid_time: Option<NaiveDateTime>,
+    /// Start of the period covered (forecast time for instantaneous fields).
+    pub period_start: Option<NaiveDateTime>,
+    /// End of the period covered; differs from the start for
+    /// statistically processed fields (maximum gust, averages, ...).
+    pub period_end: Option<NaiveDateTime>,
+    /// Statistical process (Code Table 4.10), if any.
+    pub statistic: Option<u8>,
+    /// SHA-256 of the message, with `--dedup-catalog`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Key of the object holding the message bytes, when an identical
+    /// message was already uploaded there and this one was not stored again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_in: Option<String>,
+}
+
+impl Manifest {
+    pub fn new(source: &str, key: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            model: None,
+            lead: None,
+            companions: Vec::new(),
+            key: key.to_string(),
+            filter_hash: None,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Empty manifest for a continuation of the object at `key`, from the same sources.
+    pub fn continuation(&self, key: &str) -> Self {
+        Self {
+            source: self.source.clone(),
+            companions: self.companions.clone(),
+            model: self.model.clone(),
+            lead: self.lead.clone(),
+            key: key.to_string(),
+            filter_hash: self.filter_hash.clone(),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Record a message written at `offset` in the object.
+    pub fn push(&mut self, offset: u64, length: u64, product: &ProductInfo, sha256: Option<&str>) {
+        self.push_entry(offset, length, product, sha256, None);
+    }
+
+    /// Record a message skipped because identical bytes are stored at `location`.
+    pub fn push_duplicate(&mut self, location: &Location, product: &ProductInfo, sha256: &str) {
+        self.push_entry(
+            location.offset,
+            location.length,
+            product,
+            Some(sha256),
+            Some(location.key.clone()),
+        );
+    }
+
+    /// Messages stored in this object, as (hash, offset, length) for the catalog.
+    pub fn stored_hashes(&self) -> Vec<(String, u64, u64)> {
+        let mut stored: Vec<_> = self
+            .messages
+            .iter()
+            .filter(|m| m.stored_in.is_none())
+            .filter_map(|m| Some((m.sha256.clone()?, m.offset, m.length)))
+            .collect();
+        // One entry per message, even with several products
+        stored.dedup();
+        stored
+    }
+
+    /// Messages stored in other objects, as (hash, location) for the catalog.
+    pub fn references(&self) -> Vec<(String, Location)> {
+        let mut references: Vec<_> = self
+            .messages
+            .iter()
+            .filter_map(|m| {
+                let location = Location {
+                    key: m.stored_in.clone()?,
+                    offset: m.offset,
+                    length: m.length,
+                };
+                Some((m.sha256.clone()?, location))
+            })
+            .collect();
+        references.dedup();
+        references
+    }
+
+    fn push_entry(
+        &mut self,
+        offset: u64,
+        length: u64,
+        product: &ProductInfo,
+        sha256: Option<&str>,
+        stored_in: Option<String>,
+    ) {
+        let time = product.time;
+        self.messages.push(MessageEntry {
+            offset,
+            length,
+            discipline: product.discipline,
+            category: product.category,
+            number: product.number,
+            template: product.template,
+            level: product.level.as_ref().map(ToString::to_string),
+            reference_time: time.map(|t| t.reference),
+            valid_time: time.map(|t| t.valid_time()),
+            period_start: time.map(|t| t.start),
+            period_end: time.map(|t| t.end),
+            statistic: time.and_then(|t| t.statistic),
+            sha256: sha256.map(str::to_string),
+            stored_in,
+        });
+    }
+
+    /// Key of the manifest object for a data object key.
+    pub fn key_for(object_key: &str) -> String {
+        format!("{object_key}{MANIFEST_SUFFIX}")
+    }
+
+    /// Key of the inventory standing for the manifest of an object key with
+    /// `--metadata-only`.
+    pub fn inventory_key_for(object_key: &str) -> String {
+        format!("{object_key}{INVENTORY_SUFFIX}")
+    }
+}
@@ -0,0 +1,188 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+/// Global allocator counting the heap in use, its peak and the bytes allocated
+/// ([`heap_usage()`]), installed by the binary for the memory summary and
+/// `--max-memory`. Allocations are served by the system allocator.
+pub struct CountingAllocator;
+
+fn count_allocation(size: usize) {
+    let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(live, Ordering::Relaxed);
+    ALLOCATED.fetch_add(size as u64, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            count_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            count_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+            count_allocation(new_size);
+        }
+        new
+    }
+}
+
+/// Heap figures of the process, all zero unless [`CountingAllocator`] is the
+/// global allocator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HeapUsage {
+    /// Bytes allocated and not freed yet.
+    pub live: usize,
+    /// Largest `live` seen.
+    pub peak: usize,
+    /// Bytes allocated in total (a reallocation counts its new size).
+    pub allocated: u64,
+}
+
+pub fn heap_usage() -> HeapUsage {
+    HeapUsage {
+        live: LIVE.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        allocated: ALLOCATED.load(Ordering::Relaxed),
+    }
+}
+
+/// Memory of a run: the largest parser and upload buffers, and the files
+/// running against the heap limit (`--max-memory`).
+///
+/// A file only starts while the heap is under the limit, unless no other file
+/// is running so the run keeps progressing: the heap can exceed the limit by
+/// what the files already running still allocate, not by new files.
+pub struct MemoryUse {
+    max_heap: Option<u64>,
+    parser: AtomicUsize,
+    upload: AtomicUsize,
+    running: AtomicUsize,
+    started: AtomicU64,
+    held_back: AtomicU64,
+}
+
+impl MemoryUse {
+    pub fn new(max_heap: Option<u64>) -> Self {
+        Self {
+            max_heap,
+            parser: AtomicUsize::new(0),
+            upload: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            started: AtomicU64::new(0),
+            held_back: AtomicU64::new(0),
+        }
+    }
+
+    /// Account for the bytes held by a GRIB2 parser.
+    pub fn record_parser(&self, bytes: usize) {
+        self.parser.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// Account for the bytes buffered by an uploader.
+    pub fn record_upload(&self, bytes: usize) {
+        self.upload.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// Largest parser buffer so far.
+    pub fn parser_peak(&self) -> usize {
+        self.parser.load(Ordering::Relaxed)
+    }
+
+    /// Largest upload buffer so far.
+    pub fn upload_peak(&self) -> usize {
+        self.upload.load(Ordering::Relaxed)
+    }
+
+    /// Start a file if the heap (`live` bytes) allows it, counting it as running
+    /// until `finished()`.
+    pub fn try_start(&self, live: usize) -> bool {
+        let over = self.max_heap.is_some_and(|max| live as u64 >= max);
+        if over && self.running.load(Ordering::Relaxed) > 0 {
+            return false;
+        }
+        self.running.fetch_add(1, Ordering::Relaxed);
+        self.started.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// A file started by `try_start()` is done.
+    pub fn finished(&self) {
+        self.running.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Files started so far, attempts included.
+    pub fn started(&self) -> u64 {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Account for a file waiting for the heap to shrink.
+    pub fn record_held_back(&self) {
+        self.held_back.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Files held back by the heap limit.
+    pub fn held_back(&self) -> u64 {
+        self.held_back.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_accounting() {
+        let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+        let before = heap_usage();
+        unsafe {
+            let ptr = CountingAllocator.alloc(layout);
+            assert!(heap_usage().live >= before.live + (1 << 20));
+            let ptr = CountingAllocator.realloc(ptr, layout, 2 << 20);
+            let grown = Layout::from_size_align(2 << 20, 8).unwrap();
+            CountingAllocator.dealloc(ptr, grown);
+        }
+        let after = heap_usage();
+        assert_eq!(after.live, before.live);
+        assert!(after.peak >= 2 << 20);
+        assert_eq!(after.allocated, before.allocated + (3 << 20));
+
+        let memory = MemoryUse::new(Some(100));
+        memory.record_parser(40);
+        memory.record_parser(10);
+        assert_eq!(memory.parser_peak(), 40);
+        // Over the limit, the first file still starts but no other one
+        assert!(memory.try_start(150));
+        assert!(!memory.try_start(150));
+        memory.record_held_back();
+        assert!(memory.try_start(50));
+        memory.finished();
+        memory.finished();
+        assert!(memory.try_start(150));
+        assert_eq!((memory.started(), memory.held_back()), (3, 1));
+
+        let unlimited = MemoryUse::new(None);
+        assert!(unlimited.try_start(usize::MAX) && unlimited.try_start(usize::MAX));
+    }
+}
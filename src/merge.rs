@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+
+use aws_sdk_s3::Client;
+
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, MessageEntry};
+use crate::naming::Naming;
+use crate::s3::{get_object, get_range, put_object, S3Compat, S3MultipartUploader, StorageClass};
+
+/// Objects archived under the same name in several prefixes (e.g. the `wind/`
+/// and `gust/` routes of a run), merged into one object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeGroup {
+    /// Key of the merged object, e.g. `combined/wind_20200101_00.grb2`.
+    pub key: String,
+    /// Objects to merge with their manifests, in the order of the prefixes.
+    pub objects: Vec<(String, Manifest)>,
+}
+
+/// A message of an archived object: its bytes and the manifest entries of its
+/// products.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredMessage {
+    pub entries: Vec<MessageEntry>,
+    pub bytes: Vec<u8>,
+}
+
+/// Group the objects listed under each prefix (`objects[i]` under `prefixes[i]`)
+/// by their key relative to it, into `into`. Only the names found under every
+/// prefix are merged; the others are returned apart, for the per-cycle objects
+/// to be complete.
+pub fn plan_merges(
+    prefixes: &[String],
+    objects: Vec<Vec<(String, Manifest)>>,
+    into: &str,
+) -> (Vec<MergeGroup>, Vec<String>) {
+    let mut by_name: BTreeMap<String, Vec<(String, Manifest)>> = BTreeMap::new();
+    for (prefix, objects) in prefixes.iter().zip(objects) {
+        for (key, manifest) in objects {
+            let name = key
+                .strip_prefix(prefix.as_str())
+                .unwrap_or(&key)
+                .trim_start_matches('/')
+                .to_string();
+            by_name.entry(name).or_default().push((key, manifest));
+        }
+    }
+
+    let into = into.trim_end_matches('/');
+    let mut groups = Vec::new();
+    let mut incomplete = Vec::new();
+    for (name, objects) in by_name {
+        if objects.len() < prefixes.len() {
+            incomplete.push(name);
+            continue;
+        }
+        let key = if into.is_empty() {
+            name
+        } else {
+            format!("{into}/{name}")
+        };
+        groups.push(MergeGroup { key, objects });
+    }
+    (groups, incomplete)
+}
+
+/// Interleave the messages of the objects of a group into one object: by valid
+/// time, then in the order of the objects. Returns its bytes and manifest, whose
+/// entries all point into it.
+pub fn interleave(key: &str, parts: &[(&Manifest, Vec<StoredMessage>)]) -> (Vec<u8>, Manifest) {
+    let first = parts.first().map(|(manifest, _)| *manifest);
+    let mut manifest = Manifest::new(first.map_or("", |m| m.source.as_str()), key);
+    manifest.model = first.and_then(|m| m.model.clone());
+    manifest.lead = first.and_then(|m| m.lead.clone());
+    for (part, _) in parts {
+        for source in std::iter::once(&part.source).chain(&part.companions) {
+            if *source != manifest.source && !manifest.companions.contains(source) {
+                manifest.companions.push(source.clone());
+            }
+        }
+    }
+
+    let mut messages: Vec<&StoredMessage> = parts.iter().flat_map(|(_, m)| m).collect();
+    messages.sort_by_key(|message| message.entries.first().and_then(|e| e.valid_time));
+    let mut object = Vec::new();
+    for message in messages {
+        let offset = object.len() as u64;
+        manifest
+            .messages
+            .extend(message.entries.iter().cloned().map(|entry| MessageEntry {
+                offset,
+                length: message.bytes.len() as u64,
+                stored_in: None,
+                ..entry
+            }));
+        object.extend_from_slice(&message.bytes);
+    }
+    (object, manifest)
+}
+
+/// Manifest entries of an object grouped by message (the products of one message
+/// share its location).
+fn message_entries(manifest: &Manifest) -> Vec<Vec<MessageEntry>> {
+    let mut messages: Vec<Vec<MessageEntry>> = Vec::new();
+    for entry in &manifest.messages {
+        match messages.last_mut() {
+            Some(message)
+                if (message[0].offset, &message[0].stored_in)
+                    == (entry.offset, &entry.stored_in) =>
+            {
+                message.push(entry.clone())
+            }
+            _ => messages.push(vec![entry.clone()]),
+        }
+    }
+    messages
+}
+
+/// Read the messages of an archived object, following the entries stored in
+/// another object by `--dedup-catalog`.
+async fn read_messages(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    manifest: &Manifest,
+) -> Result<Vec<StoredMessage>> {
+    let object = get_object(client, bucket, key).await?;
+    let mut messages = Vec::new();
+    for entries in message_entries(manifest) {
+        let (offset, length) = (entries[0].offset, entries[0].length);
+        let bytes = match &entries[0].stored_in {
+            Some(stored_in) => get_range(client, bucket, stored_in, offset, length).await?,
+            None => object
+                .get(offset as usize..(offset + length) as usize)
+                .ok_or_else(|| Error::ParseError {
+                    offset,
+                    reason: format!(
+                        "{key}: the manifest lists a message past the end of the object"
+                    ),
+                })?
+                .to_vec(),
+        };
+        messages.push(StoredMessage { entries, bytes });
+    }
+    Ok(messages)
+}
+
+/// Write the merged object of a group, then its manifest.
+pub async fn merge_group(
+    client: &Client,
+    bucket: &str,
+    group: &MergeGroup,
+    naming: &Naming,
+    storage_class: StorageClass,
+    compat: S3Compat,
+) -> Result<Manifest> {
+    let mut parts = Vec::with_capacity(group.objects.len());
+    for (key, manifest) in &group.objects {
+        parts.push((
+            manifest,
+            read_messages(client, bucket, key, manifest).await?,
+        ));
+    }
+    let (object, manifest) = interleave(&group.key, &parts);
+
+    let mut uploader =
+        S3MultipartUploader::new(client.clone(), bucket, &group.key, storage_class, compat);
+    uploader.set_metadata("merged-objects", group.objects.len().to_string());
+    if let Some(model) = &manifest.model {
+        uploader.set_metadata("model", model.clone());
+    }
+    if let Err(e) = uploader.write(&object).await {
+        let _ = uploader.abort().await;
+        return Err(e);
+    }
+    uploader.complete().await?;
+    put_object(
+        client,
+        bucket,
+        &naming.manifest_key(&group.key),
+        serde_json::to_vec_pretty(&manifest).expect("manifest serializes to JSON"),
+        "application/json",
+    )
+    .await?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{time, timed_entry};
+
+    /// Two-byte message of parameter 0.2.`number` from the `hour` analysis.
+    fn entry(number: u8, hour: u32, offset: u64) -> MessageEntry {
+        timed_entry(number, time(hour), offset, 2)
+    }
+
+    fn archived(key: &str, source: &str, entries: Vec<MessageEntry>) -> (String, Manifest) {
+        let mut manifest = Manifest::new(source, key);
+        manifest.messages = entries;
+        (key.to_string(), manifest)
+    }
+
+    #[test]
+    fn test_merge_prefixes() {
+        let prefixes = ["wind/".to_string(), "gust".to_string()];
+        let (groups, incomplete) = plan_merges(
+            &prefixes,
+            vec![
+                vec![
+                    archived("wind/a.grb2", "f000", vec![entry(2, 0, 0), entry(3, 0, 0)]),
+                    archived("wind/b.grb2", "f000", vec![]),
+                ],
+                vec![archived("gust/a.grb2", "f000-b", vec![entry(22, 0, 0)])],
+            ],
+            "combined/",
+        );
+        assert_eq!(incomplete, ["b.grb2"]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, "combined/a.grb2");
+
+        // Messages of two valid times from each object
+        let wind = archived(
+            "wind/x",
+            "f000",
+            vec![entry(2, 0, 0), entry(3, 0, 0), entry(2, 6, 2)],
+        )
+        .1;
+        let gust = archived("gust/x", "f000-b", vec![entry(22, 0, 0), entry(22, 6, 2)]).1;
+        let wind_messages: Vec<_> = message_entries(&wind)
+            .into_iter()
+            .zip([vec![1, 1], vec![2, 2]])
+            .map(|(entries, bytes)| StoredMessage { entries, bytes })
+            .collect();
+        assert_eq!(wind_messages[0].entries.len(), 2);
+        let gust_messages: Vec<_> = message_entries(&gust)
+            .into_iter()
+            .zip([vec![3, 3], vec![4, 4]])
+            .map(|(entries, bytes)| StoredMessage { entries, bytes })
+            .collect();
+        let (object, manifest) = interleave(
+            "combined/x",
+            &[(&wind, wind_messages), (&gust, gust_messages)],
+        );
+        assert_eq!(object, [1, 1, 3, 3, 2, 2, 4, 4]);
+        let layout: Vec<_> = manifest
+            .messages
+            .iter()
+            .map(|m| (m.number, m.offset))
+            .collect();
+        assert_eq!(layout, [(2, 0), (3, 0), (22, 2), (2, 4), (22, 6)]);
+        assert_eq!(manifest.source, "f000");
+        assert_eq!(manifest.companions, ["f000-b"]);
+    }
+}
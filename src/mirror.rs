@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+use crate::error::Result;
+use crate::s3::S3MultipartUploader;
+
+/// S3 key of the copy of a source file under `prefix` (`--mirror-raw`): the path
+/// of its URL, without host nor query string, e.g.
+/// `raw/gfs.20200101/00/atmos/gfs.t00z.pgrb2.0p25.f000`.
+pub fn mirror_key(prefix: &str, url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let path = url.split_once("://").map_or(url, |(_, rest)| {
+        rest.split_once('/').map_or("", |(_, path)| path)
+    });
+    let mut key = prefix.trim_end_matches('/').to_string();
+    for part in path.split('/') {
+        if !part.is_empty() && part != "." && part != ".." {
+            if !key.is_empty() {
+                key.push('/');
+            }
+            key.push_str(part);
+        }
+    }
+    key
+}
+
+/// Unmodified copy of a source file, uploaded while it streams through the
+/// parser (`--mirror-raw`). The bytes are tapped as received, before any
+/// decompression; an upload dropped before `complete()` is aborted.
+pub struct RawMirror {
+    uploader: Option<S3MultipartUploader>,
+    key: String,
+    /// Bytes received and not written to the upload yet.
+    pending: Arc<Mutex<Vec<Bytes>>>,
+    written: u64,
+}
+
+impl RawMirror {
+    pub fn new(uploader: S3MultipartUploader, key: &str) -> Self {
+        Self {
+            uploader: Some(uploader),
+            key: key.to_string(),
+            pending: Arc::default(),
+            written: 0,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Hook for the source stream, recording each chunk it yields.
+    pub fn tap(&self) -> impl Fn(&Bytes) + Send + Sync + 'static {
+        let pending = self.pending.clone();
+        move |chunk| pending.lock().unwrap().push(chunk.clone())
+    }
+
+    /// Write the bytes received so far to the upload.
+    pub async fn flush(&mut self) -> Result<()> {
+        let chunks = std::mem::take(&mut *self.pending.lock().unwrap());
+        let uploader = self.uploader.as_mut().expect("mirror not completed");
+        for chunk in chunks {
+            uploader.write(&chunk).await?;
+            self.written += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Complete the upload once the whole file was received. Returns its size.
+    pub async fn complete(mut self) -> Result<u64> {
+        self.flush().await?;
+        let uploader = self.uploader.take().expect("mirror not completed");
+        uploader.complete().await?;
+        Ok(self.written)
+    }
+}
+
+impl Drop for RawMirror {
+    fn drop(&mut self) {
+        // Gone already once completed
+        if let (Some(uploader), Ok(runtime)) =
+            (self.uploader.take(), tokio::runtime::Handle::try_current())
+        {
+            runtime.spawn(uploader.abort());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_key() {
+        assert_eq!(
+            mirror_key(
+                "raw/",
+                "https://noaa-gfs-bdp-pds.s3.amazonaws.com/gfs.20200101/00/atmos/gfs.t00z.pgrb2.0p25.f000"
+            ),
+            "raw/gfs.20200101/00/atmos/gfs.t00z.pgrb2.0p25.f000"
+        );
+        assert_eq!(
+            mirror_key(
+                "mirror/dwd",
+                "https://opendata.dwd.de/weather/../u_10m/icon.grib2.bz2?sig=1"
+            ),
+            "mirror/dwd/weather/u_10m/icon.grib2.bz2"
+        );
+        assert_eq!(mirror_key("", "http://127.0.0.1:9001/gfs.f000"), "gfs.f000");
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::template::{parse_url_template, Template, DEFAULT_URL_TEMPLATE};
+
+/// NAM 12 km CONUS (AWIPS grid 218) files on the AWS open data bucket.
+pub const NAM_URL_TEMPLATE: &str =
+    "https://noaa-nam-pds.s3.amazonaws.com/nam.{yyyymmdd}/nam.t{hh}z.awphys{ff}.tm00.grib2";
+
+/// RAP 13 km CONUS (grid 130) files on the AWS open data bucket.
+pub const RAP_URL_TEMPLATE: &str =
+    "https://noaa-rap-pds.s3.amazonaws.com/rap.{yyyymmdd}/rap.t{hh}z.awp130pgrbf{ff}.grib2";
+
+/// ICON global (icosahedral grid) 10 m wind, one bzip2 file per component.
+/// DWD keeps about 24 hours of cycles.
+pub const ICON_URL_TEMPLATES: &[&str] = &[
+    "https://opendata.dwd.de/weather/nwp/icon/grib/{hh}/u_10m/icon_global_icosahedral_single-level_{yyyymmdd}{hh}_{fff}_U_10M.grib2.bz2",
+    "https://opendata.dwd.de/weather/nwp/icon/grib/{hh}/v_10m/icon_global_icosahedral_single-level_{yyyymmdd}{hh}_{fff}_V_10M.grib2.bz2",
+];
+
+/// ICON-EU (regular 0.0625° grid over Europe) 10 m wind, one bzip2 file per component.
+pub const ICON_EU_URL_TEMPLATES: &[&str] = &[
+    "https://opendata.dwd.de/weather/nwp/icon-eu/grib/{hh}/u_10m/icon-eu_europe_regular-lat-lon_single-level_{yyyymmdd}{hh}_{fff}_U_10M.grib2.bz2",
+    "https://opendata.dwd.de/weather/nwp/icon-eu/grib/{hh}/v_10m/icon-eu_europe_regular-lat-lon_single-level_{yyyymmdd}{hh}_{fff}_V_10M.grib2.bz2",
+];
+
+/// GDPS (0.15° global) 10 m wind from the MSC Datamart, one file per component.
+pub const GDPS_URL_TEMPLATES: &[&str] = &[
+    "https://dd.weather.gc.ca/model_gem_global/15km/grib2/lat_lon/{hh}/{fff}/{yyyymmdd}T{hh}Z_MSC_GDPS_UGRD_AGL-10m_LatLon0.15_PT{fff}H.grib2",
+    "https://dd.weather.gc.ca/model_gem_global/15km/grib2/lat_lon/{hh}/{fff}/{yyyymmdd}T{hh}Z_MSC_GDPS_VGRD_AGL-10m_LatLon0.15_PT{fff}H.grib2",
+];
+
+/// HRDPS (2.5 km continental, rotated grid) 10 m wind from the MSC Datamart.
+pub const HRDPS_URL_TEMPLATES: &[&str] = &[
+    "https://dd.weather.gc.ca/model_hrdps/continental/2.5km/{hh}/{fff}/{yyyymmdd}T{hh}Z_MSC_HRDPS_UGRD_AGL-10m_RLatLon0.0225_PT{fff}H.grib2",
+    "https://dd.weather.gc.ca/model_hrdps/continental/2.5km/{hh}/{fff}/{yyyymmdd}T{hh}Z_MSC_HRDPS_VGRD_AGL-10m_RLatLon0.0225_PT{fff}H.grib2",
+];
+
+/// Arpège 0.1° Europe surface package (SP1, 10 m wind and gusts among others) from
+/// Météo-France open data, one file per 12 forecast hours.
+pub const ARPEGE_URL_TEMPLATE: &str = "https://object.data.gouv.fr/meteofrance-pnt/pnt/{yyyy}-{mm}-{dd}T{hh}:00:00Z/arpege/01/SP1/arpege__01__SP1__{arpege_steps}__{yyyy}-{mm}-{dd}T{hh}:00:00Z.grib2";
+
+/// Arome 0.01° France surface package (SP1) from Météo-France open data, one file
+/// per forecast hour.
+pub const AROME_URL_TEMPLATE: &str = "https://object.data.gouv.fr/meteofrance-pnt/pnt/{yyyy}-{mm}-{dd}T{hh}:00:00Z/arome/001/SP1/arome__001__SP1__{ff}H__{yyyy}-{mm}-{dd}T{hh}:00:00Z.grib2";
+
+/// Forecast model whose files are archived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, ValueEnum)]
+pub enum Model {
+    /// Global Forecast System, 0.25°, 4 cycles a day
+    #[default]
+    Gfs,
+    /// North American Mesoscale, 12 km CONUS, 4 cycles a day
+    Nam,
+    /// Rapid Refresh, 13 km CONUS, hourly cycles
+    Rap,
+    /// DWD ICON global, 4 cycles a day (latest 24 hours only)
+    Icon,
+    /// DWD ICON-EU nest, 8 cycles a day (latest 24 hours only)
+    IconEu,
+    /// Environment Canada global model, 2 cycles a day (latest 24 hours only)
+    Gdps,
+    /// Environment Canada 2.5 km continental model, 4 cycles a day (latest 24 hours only)
+    Hrdps,
+    /// Météo-France Arpège, 0.1° Europe, 4 cycles a day (latest days only)
+    Arpege,
+    /// Météo-France Arome, 0.01° France, 8 cycles a day (latest days only)
+    Arome,
+}
+
+impl Model {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Model::Gfs => "gfs",
+            Model::Nam => "nam",
+            Model::Rap => "rap",
+            Model::Icon => "icon",
+            Model::IconEu => "icon-eu",
+            Model::Gdps => "gdps",
+            Model::Hrdps => "hrdps",
+            Model::Arpege => "arpege",
+            Model::Arome => "arome",
+        }
+    }
+
+    /// Hours between two cycles.
+    pub fn cycle_interval_hours(&self) -> u32 {
+        match self {
+            Model::Gdps => 12,
+            Model::Gfs | Model::Nam | Model::Icon | Model::Hrdps | Model::Arpege => 6,
+            Model::IconEu | Model::Arome => 3,
+            Model::Rap => 1,
+        }
+    }
+
+    /// Whether a source file holds several forecast hours (Arpège): each task then
+    /// keeps only the messages valid at its own time.
+    pub fn packs_forecast_hours(&self) -> bool {
+        *self == Model::Arpege
+    }
+
+    /// Whether the default source gives the fields on a regular lat/lon grid, the
+    /// only one `--bbox` crops (NAM and RAP are Lambert conformal, ICON icosahedral
+    /// and HRDPS rotated).
+    pub fn lat_lon_grid(&self) -> bool {
+        !matches!(self, Model::Nam | Model::Rap | Model::Icon | Model::Hrdps)
+    }
+
+    /// Source URL templates used when `--url-template` is not given: one
+    /// per file making up a task (ICON publishes each variable separately).
+    pub fn url_templates(&self) -> Vec<Template> {
+        let templates = match self {
+            Model::Gfs => &[DEFAULT_URL_TEMPLATE],
+            Model::Nam => &[NAM_URL_TEMPLATE],
+            Model::Rap => &[RAP_URL_TEMPLATE],
+            Model::Icon => ICON_URL_TEMPLATES,
+            Model::IconEu => ICON_EU_URL_TEMPLATES,
+            Model::Gdps => GDPS_URL_TEMPLATES,
+            Model::Hrdps => HRDPS_URL_TEMPLATES,
+            Model::Arpege => &[ARPEGE_URL_TEMPLATE],
+            Model::Arome => &[AROME_URL_TEMPLATE],
+        };
+        templates
+            .iter()
+            .map(|t| parse_url_template(t).expect("built-in URL templates are valid"))
+            .collect()
+    }
+
+    /// Typical download for an analysis from the default source, for dry-run estimates.
+    pub fn typical_file_size(&self) -> u64 {
+        match self {
+            Model::Gfs => 500 * 1000 * 1000,
+            Model::Nam => 70 * 1000 * 1000,
+            Model::Rap => 20 * 1000 * 1000,
+            // Both compressed components
+            Model::Icon => 10 * 1000 * 1000,
+            Model::IconEu => 6 * 1000 * 1000,
+            Model::Gdps => 6 * 1000 * 1000,
+            Model::Hrdps => 12 * 1000 * 1000,
+            // Several parameters, over 13 forecast hours for Arpège
+            Model::Arpege => 100 * 1000 * 1000,
+            Model::Arome => 60 * 1000 * 1000,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::{Cycle, Task};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_model_url_layouts() {
+        let task = Task {
+            cycle: Cycle {
+                model: Model::Rap,
+                time: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(13, 0, 0)
+                    .unwrap(),
+            },
+            forecast_hour: 1,
+        };
+        assert_eq!(
+            Model::Rap.url_templates()[0].render(&task),
+            "https://noaa-rap-pds.s3.amazonaws.com/rap.20200101/rap.t13z.awp130pgrbf01.grib2"
+        );
+        assert_eq!(
+            Model::Nam.url_templates()[0].render(&task),
+            "https://noaa-nam-pds.s3.amazonaws.com/nam.20200101/nam.t13z.awphys01.tm00.grib2"
+        );
+        assert!(Model::Gfs.url_templates()[0]
+            .render(&task)
+            .ends_with(".f001.grib2"));
+
+        let icon: Vec<_> = Model::IconEu
+            .url_templates()
+            .iter()
+            .map(|t| t.render(&task))
+            .collect();
+        assert_eq!(icon.len(), 2);
+        let gdps = Model::Gdps.url_templates()[0].render(&task);
+        assert!(
+            gdps.ends_with("/13/001/20200101T13Z_MSC_GDPS_UGRD_AGL-10m_LatLon0.15_PT001H.grib2")
+        );
+
+        assert!(icon[1].ends_with(
+            "/13/v_10m/icon-eu_europe_regular-lat-lon_single-level_2020010113_001_V_10M.grib2.bz2"
+        ));
+
+        assert_eq!(
+            Model::Arome.url_templates()[0].render(&task),
+            "https://object.data.gouv.fr/meteofrance-pnt/pnt/2020-01-01T13:00:00Z/arome/001/SP1/arome__001__SP1__01H__2020-01-01T13:00:00Z.grib2"
+        );
+        assert!(Model::Arpege.url_templates()[0]
+            .render(&task)
+            .ends_with("/arpege/01/SP1/arpege__01__SP1__000H012H__2020-01-01T13:00:00Z.grib2"));
+    }
+
+    #[test]
+    fn test_lat_lon_grids() {
+        let cropped: Vec<_> = Model::value_variants()
+            .iter()
+            .filter(|model| model.lat_lon_grid())
+            .map(Model::as_str)
+            .collect();
+        assert_eq!(cropped, ["gfs", "icon-eu", "gdps", "arpege", "arome"]);
+    }
+}
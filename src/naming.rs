@@ -0,0 +1,149 @@
+use crate::latest::{LatestPointer, LATEST_NAME};
+use crate::manifest::MANIFEST_SUFFIX;
+use crate::provenance::PROVENANCE_SUFFIX;
+
+/// Suffix of the object holding the messages uploaded before a late failure
+/// (`--keep-partial`).
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Names of the objects written next to the archived ones: manifests, partial
+/// uploads, provenance logs and latest pointers. Changing them lets the archive
+/// follow the conventions of an existing bucket, or the ignore rules of the
+/// consumers listing it.
+#[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
+pub struct Naming {
+    /// Suffix of the manifest of an object
+    #[arg(long, default_value = MANIFEST_SUFFIX, value_parser = parse_suffix)]
+    pub manifest_suffix: String,
+
+    /// Suffix of the object keeping the messages uploaded before a late failure
+    /// (--keep-partial)
+    #[arg(long, default_value = PARTIAL_SUFFIX, value_parser = parse_suffix)]
+    pub partial_suffix: String,
+
+    /// Suffix of the provenance log of an object (--provenance)
+    #[arg(long, default_value = PROVENANCE_SUFFIX, value_parser = parse_suffix)]
+    pub provenance_suffix: String,
+
+    /// Name of the latest pointer objects (--latest-pointer)
+    #[arg(long, default_value = LATEST_NAME, value_parser = parse_suffix)]
+    pub latest_name: String,
+}
+
+impl Default for Naming {
+    /// The names used by earlier versions.
+    fn default() -> Self {
+        Self {
+            manifest_suffix: MANIFEST_SUFFIX.to_string(),
+            partial_suffix: PARTIAL_SUFFIX.to_string(),
+            provenance_suffix: PROVENANCE_SUFFIX.to_string(),
+            latest_name: LATEST_NAME.to_string(),
+        }
+    }
+}
+
+/// Check a name suffix: not empty, and within the object's "directory".
+pub fn parse_suffix(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if s.contains('/') || s.chars().any(char::is_control) {
+        return Err(format!(
+            "name '{s}' must not contain '/' or control characters"
+        ));
+    }
+    Ok(s.to_string())
+}
+
+impl Naming {
+    /// Check that the suffixes tell the objects apart.
+    pub fn check(&self) -> Result<(), String> {
+        let suffixes = [
+            ("--manifest-suffix", &self.manifest_suffix),
+            ("--partial-suffix", &self.partial_suffix),
+            ("--provenance-suffix", &self.provenance_suffix),
+        ];
+        for (i, (option, suffix)) in suffixes.iter().enumerate() {
+            for (other, other_suffix) in &suffixes[i + 1..] {
+                if suffix.ends_with(other_suffix.as_str())
+                    || other_suffix.ends_with(suffix.as_str())
+                {
+                    return Err(format!(
+                        "{option} '{suffix}' and {other} '{other_suffix}' can't be told apart"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Key of the manifest of `object_key`.
+    pub fn manifest_key(&self, object_key: &str) -> String {
+        format!("{object_key}{}", self.manifest_suffix)
+    }
+
+    /// Key of the partial upload of `object_key`.
+    pub fn partial_key(&self, object_key: &str) -> String {
+        format!("{object_key}{}", self.partial_suffix)
+    }
+
+    /// Key of the provenance log of `object_key`.
+    pub fn provenance_key(&self, object_key: &str) -> String {
+        format!("{object_key}{}", self.provenance_suffix)
+    }
+
+    /// Key of the latest pointer of a run writing under `prefix`, in a directory
+    /// per model when the run covers several.
+    pub fn latest_key(&self, prefix: &str, model: Option<&str>) -> String {
+        LatestPointer::key_named(prefix, model, &self.latest_name)
+    }
+
+    /// Whether `key` is a manifest, partial upload or provenance log rather than
+    /// an archived object.
+    pub fn is_auxiliary(&self, key: &str) -> bool {
+        [
+            &self.manifest_suffix,
+            &self.partial_suffix,
+            &self.provenance_suffix,
+        ]
+        .iter()
+        .any(|suffix| key.ends_with(suffix.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_naming() {
+        let default = Naming::default();
+        assert_eq!(
+            default.manifest_key("wind/wind_20200101_00.grb2"),
+            "wind/wind_20200101_00.grb2.manifest.json"
+        );
+        assert_eq!(default.latest_key("wind/", None), "wind/latest.json");
+        assert!(default.check().is_ok());
+
+        let naming = Naming {
+            manifest_suffix: ".idx.json".to_string(),
+            partial_suffix: "._incomplete".to_string(),
+            latest_name: "_LATEST".to_string(),
+            ..Naming::default()
+        };
+        assert_eq!(naming.partial_key("a.grb2"), "a.grb2._incomplete");
+        assert_eq!(naming.latest_key("wind", Some("gfs")), "wind/gfs/_LATEST");
+        assert!(naming.is_auxiliary("a.grb2.idx.json"));
+        assert!(naming.is_auxiliary("a.grb2.provenance.jsonl"));
+        assert!(!naming.is_auxiliary("a.grb2"));
+
+        // Suffixes that would hide each other
+        let clash = Naming {
+            partial_suffix: ".json".to_string(),
+            ..Naming::default()
+        };
+        assert!(clash.check().is_err());
+        assert!(parse_suffix("a/b").is_err());
+        assert!(parse_suffix("").is_err());
+    }
+}
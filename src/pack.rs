@@ -0,0 +1,154 @@
+use crate::field::{read_i16, BitWriter};
+
+/// Values per group of the complex packing written by `complex_packing()`.
+const GROUP_LENGTH: usize = 16;
+
+/// Section 6: a bitmap of the points with a value, if some have none.
+pub(crate) fn bitmap(values: &[f32]) -> Vec<u8> {
+    if !values.iter().any(|v| v.is_nan()) {
+        return vec![0, 0, 0, 6, 6, 255];
+    }
+    let mut bits = vec![0u8; values.len().div_ceil(8)];
+    for (i, value) in values.iter().enumerate() {
+        if !value.is_nan() {
+            bits[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    let mut section = ((6 + bits.len()) as u32).to_be_bytes().to_vec();
+    section.extend_from_slice(&[6, 0]);
+    section.extend(bits);
+    section
+}
+
+/// Sections 5 and 7 of the scaled integers `values` with complex packing and
+/// second-order spatial differencing, in fixed-length groups.
+pub(crate) fn complex_packing(values: &[i64], decimal_scale: i16) -> Option<(Vec<u8>, Vec<u8>)> {
+    if values.len() < 3 {
+        return None;
+    }
+    // The reference value takes the minimum, so the first values are positive
+    let base = *values.iter().min()?;
+    let x: Vec<i64> = values.iter().map(|v| v - base).collect();
+    let differences: Vec<i64> = (2..x.len())
+        .map(|k| x[k] - 2 * x[k - 1] + x[k - 2])
+        .collect();
+    let minimum = *differences.iter().min()?;
+    // The first two values are placeholders, given in the extra descriptors
+    let packed: Vec<u64> = [0, 0]
+        .into_iter()
+        .chain(differences.iter().map(|d| (d - minimum) as u64))
+        .collect();
+
+    let groups: Vec<&[u64]> = packed.chunks(GROUP_LENGTH).collect();
+    let references: Vec<u64> = groups.iter().map(|g| *g.iter().min().unwrap()).collect();
+    let widths: Vec<u64> = groups
+        .iter()
+        .zip(&references)
+        .map(|(g, reference)| bit_width(g.iter().max().unwrap() - reference) as u64)
+        .collect();
+    let reference_bits = bit_width(*references.iter().max()?);
+    let width_reference = *widths.iter().min()?;
+    let width_bits = bit_width(widths.iter().max()? - width_reference);
+    let descriptor = [x[0], x[1], minimum]
+        .iter()
+        .map(|v| v.unsigned_abs())
+        .max()?;
+    let extra_octets = (bit_width(descriptor) + 1).div_ceil(8);
+
+    let mut data = BitWriter::default();
+    for value in [x[0], x[1], minimum] {
+        data.write(sign_magnitude(value, extra_octets * 8), extra_octets * 8);
+    }
+    for &reference in &references {
+        data.write(reference, reference_bits);
+    }
+    data.align();
+    for &width in &widths {
+        data.write(width - width_reference, width_bits);
+    }
+    data.align();
+    // Group lengths take no bits: all are GROUP_LENGTH but the last one
+    for ((group, &reference), &width) in groups.iter().zip(&references).zip(&widths) {
+        for &value in group.iter() {
+            data.write(value - reference, width as usize);
+        }
+    }
+    data.align();
+
+    let mut section5 = 49u32.to_be_bytes().to_vec();
+    section5.push(5);
+    section5.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    section5.extend_from_slice(&3u16.to_be_bytes());
+    section5.extend_from_slice(&(base as f32).to_be_bytes());
+    section5.extend_from_slice(&0u16.to_be_bytes()); // binary scale factor
+    let decimal_scale = sign_magnitude(decimal_scale as i64, 16) as u16;
+    section5.extend_from_slice(&decimal_scale.to_be_bytes());
+    section5.push(reference_bits as u8);
+    section5.push(0); // floating point original values
+    section5.push(1); // general group splitting
+    section5.push(0); // no missing values, the bitmap marks them
+    section5.extend_from_slice(&[0; 8]); // missing value substitutes
+    section5.extend_from_slice(&(groups.len() as u32).to_be_bytes());
+    section5.push(width_reference as u8);
+    section5.push(width_bits as u8);
+    section5.extend_from_slice(&(GROUP_LENGTH as u32).to_be_bytes());
+    section5.push(1); // length increment
+    let last = groups.last()?.len() as u32;
+    section5.extend_from_slice(&last.to_be_bytes());
+    section5.push(0); // bits per scaled group length
+    section5.push(2); // second-order spatial differencing
+    section5.push(extra_octets as u8);
+
+    let mut section7 = ((5 + data.data.len()) as u32).to_be_bytes().to_vec();
+    section7.push(7);
+    section7.extend(data.data);
+    Some((section5, section7))
+}
+
+/// Sections 5 and 7 of `values` (the points with a value) with simple packing
+/// (template 5.0), without loss: the integers packed are those the reference
+/// value and scale factors of `representation`, the message's section 5, give
+/// the values from, re-based on their minimum.
+pub(crate) fn simple_packing(values: &[f32], representation: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let reference = f32::from_be_bytes(representation.get(11..15)?.try_into().ok()?) as f64;
+    let binary = 2f64.powi(read_i16(representation, 15)? as i32);
+    let decimal = 10f64.powi(read_i16(representation, 17)? as i32);
+    let x: Vec<i64> = values
+        .iter()
+        .map(|&v| ((v as f64 * decimal - reference) / binary).round() as i64)
+        .collect();
+    let base = x.iter().min().copied().unwrap_or(0);
+    let bits = bit_width(x.iter().map(|v| (v - base) as u64).max().unwrap_or(0));
+
+    let mut data = BitWriter::default();
+    for v in &x {
+        data.write((v - base) as u64, bits);
+    }
+    data.align();
+
+    let mut section5 = 21u32.to_be_bytes().to_vec();
+    section5.push(5);
+    section5.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    section5.extend_from_slice(&0u16.to_be_bytes());
+    section5.extend_from_slice(&((reference + base as f64 * binary) as f32).to_be_bytes());
+    // Same scale factors and type of original values
+    section5.extend_from_slice(representation.get(15..19)?);
+    section5.push(bits as u8);
+    section5.push(*representation.get(20)?);
+
+    let mut section7 = ((5 + data.data.len()) as u32).to_be_bytes().to_vec();
+    section7.push(7);
+    section7.extend(data.data);
+    Some((section5, section7))
+}
+
+/// Sign-and-magnitude form of `value` on `bits` bits.
+fn sign_magnitude(value: i64, bits: usize) -> u64 {
+    let sign = if value < 0 { 1 << (bits - 1) } else { 0 };
+    sign | value.unsigned_abs()
+}
+
+/// Bits needed to write `value`.
+fn bit_width(value: u64) -> usize {
+    (u64::BITS - value.leading_zeros()) as usize
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::grib::ProductInfo;
+
+/// A GRIB2 parameter: discipline, category (Code Table 4.1) and number (Code Table 4.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Param {
+    pub discipline: u8,
+    pub category: u8,
+    pub number: u8,
+}
+
+/// NCEP short names of the parameters found in GFS pgrb2 files.
+/// Numbers 192 and above are NCEP local entries.
+const PARAMS: &[(&str, u8, u8, u8)] = &[
+    ("TMP", 0, 0, 0),
+    ("POT", 0, 0, 2),
+    ("TMAX", 0, 0, 4),
+    ("TMIN", 0, 0, 5),
+    ("DPT", 0, 0, 6),
+    ("APTMP", 0, 0, 21),
+    ("SPFH", 0, 1, 0),
+    ("RH", 0, 1, 1),
+    ("PWAT", 0, 1, 3),
+    ("PRATE", 0, 1, 7),
+    ("APCP", 0, 1, 8),
+    ("SNOD", 0, 1, 11),
+    ("WEASD", 0, 1, 13),
+    ("CLWMR", 0, 1, 22),
+    ("CRAIN", 0, 1, 192),
+    ("WDIR", 0, 2, 0),
+    ("WIND", 0, 2, 1),
+    ("UGRD", 0, 2, 2),
+    ("VGRD", 0, 2, 3),
+    ("VVEL", 0, 2, 8),
+    ("DZDT", 0, 2, 9),
+    ("ABSV", 0, 2, 10),
+    ("UFLX", 0, 2, 17),
+    ("VFLX", 0, 2, 18),
+    ("GUST", 0, 2, 22),
+    ("VWSH", 0, 2, 192),
+    ("USTM", 0, 2, 194),
+    ("VSTM", 0, 2, 195),
+    ("PRES", 0, 3, 0),
+    ("PRMSL", 0, 3, 1),
+    ("HGT", 0, 3, 5),
+    ("MSLET", 0, 3, 192),
+    ("HPBL", 0, 3, 196),
+    ("DSWRF", 0, 4, 192),
+    ("DLWRF", 0, 5, 192),
+    ("TCDC", 0, 6, 1),
+    ("CAPE", 0, 7, 6),
+    ("CIN", 0, 7, 7),
+    ("HLCY", 0, 7, 8),
+    ("O3MR", 0, 14, 192),
+    ("VIS", 0, 19, 0),
+    ("LAND", 2, 0, 0),
+    ("SOILW", 2, 0, 192),
+    ("ICEC", 10, 2, 0),
+];
+
+impl Param {
+    pub const fn new(discipline: u8, category: u8, number: u8) -> Self {
+        Self {
+            discipline,
+            category,
+            number,
+        }
+    }
+
+    /// Parameter of a product.
+    pub fn of(product: &ProductInfo) -> Self {
+        Self::new(product.discipline, product.category, product.number)
+    }
+
+    /// NCEP short name (e.g. `UGRD`), if known.
+    pub fn name(&self) -> Option<&'static str> {
+        PARAMS
+            .iter()
+            .find(|(_, d, c, n)| (*d, *c, *n) == (self.discipline, self.category, self.number))
+            .map(|(name, ..)| *name)
+    }
+}
+
+impl fmt::Display for Param {
+    /// Short name if known, `discipline.category.number` otherwise.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}.{}.{}", self.discipline, self.category, self.number),
+        }
+    }
+}
+
+impl FromStr for Param {
+    type Err = String;
+
+    /// Parse a short name (`UGRD`, case-insensitive) or `discipline.category.number` (`0.2.22`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, d, c, n)) = PARAMS
+            .iter()
+            .find(|(name, ..)| name.eq_ignore_ascii_case(s.trim()))
+        {
+            return Ok(Self::new(*d, *c, *n));
+        }
+
+        let numbers: Vec<_> = s.trim().split('.').map(str::parse::<u8>).collect();
+        match numbers.as_slice() {
+            [Ok(d), Ok(c), Ok(n)] => Ok(Self::new(*d, *c, *n)),
+            _ => {
+                let known: Vec<_> = PARAMS.iter().map(|(name, ..)| *name).collect();
+                Err(format!(
+                    "unknown parameter '{s}'; use discipline.category.number (e.g. 0.2.22) or one of: {}",
+                    known.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_names_and_numbers() {
+        let ugrd: Param = "ugrd".parse().unwrap();
+        assert_eq!(ugrd, Param::new(0, 2, 2));
+        assert_eq!(ugrd.to_string(), "UGRD");
+
+        let gust: Param = "0.2.22".parse().unwrap();
+        assert_eq!(gust.name(), Some("GUST"));
+
+        assert_eq!(Param::new(0, 2, 250).to_string(), "0.2.250");
+        assert!("WINDY".parse::<Param>().unwrap_err().contains("UGRD"));
+    }
+}
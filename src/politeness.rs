@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+/// Usage limits of a data provider, applied to the runs reading from it unless
+/// overridden (`--concurrency`, `--request-interval`, `--max-attempts`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Politeness {
+    /// Provider(s) the limits come from, e.g. `NOMADS`.
+    pub provider: String,
+    /// Cycles processed at once.
+    pub concurrency: usize,
+    /// Minimum time between two requests to the source.
+    pub request_interval: Duration,
+    /// Attempts of a file failing with transient errors.
+    pub max_attempts: u32,
+}
+
+/// Providers with published (or documented) usage limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    /// NCAR RDA asks scripted downloads to run a couple of transfers at a time.
+    Rda,
+    /// NOMADS blocks addresses going over 120 requests per minute, for longer
+    /// when they keep retrying.
+    Nomads,
+    /// The AWS open data buckets (NODD) serve S3 request rates, with no limit
+    /// published beyond S3's.
+    AwsOpenData,
+}
+
+impl Provider {
+    fn of_host(host: &str) -> Option<Self> {
+        if host_in(host, "rda.ucar.edu") {
+            Some(Provider::Rda)
+        } else if host_in(host, "nomads.ncep.noaa.gov") {
+            Some(Provider::Nomads)
+        } else if host.starts_with("noaa-") && host.ends_with(".amazonaws.com") {
+            Some(Provider::AwsOpenData)
+        } else {
+            None
+        }
+    }
+
+    fn politeness(self) -> Politeness {
+        let (provider, concurrency, interval_ms, max_attempts) = match self {
+            Provider::Rda => ("NCAR RDA", 2, 1000, 3),
+            // One request every 500 ms stays under 120 per minute
+            Provider::Nomads => ("NOMADS", 2, 500, 2),
+            Provider::AwsOpenData => ("AWS open data", 8, 0, 5),
+        };
+        Politeness {
+            provider: provider.to_string(),
+            concurrency,
+            request_interval: Duration::from_millis(interval_ms),
+            max_attempts,
+        }
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn host_in(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+/// Host of a URL (or URL template), without port.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    authority.split(':').next().unwrap_or_default()
+}
+
+impl Politeness {
+    /// Limits of the provider serving `url`, if known.
+    pub fn for_url(url: &str) -> Option<Self> {
+        Provider::of_host(&host(url).to_ascii_lowercase()).map(Provider::politeness)
+    }
+
+    /// Strictest limits of the known providers among `urls` (a run reading several
+    /// sources), or `None` if none is known.
+    pub fn for_sources<'a>(urls: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let profiles: Vec<Self> = urls.into_iter().filter_map(Self::for_url).collect();
+        let mut providers: Vec<&str> = Vec::new();
+        for profile in &profiles {
+            if !providers.contains(&profile.provider.as_str()) {
+                providers.push(&profile.provider);
+            }
+        }
+        Some(Self {
+            provider: providers.join(", "),
+            concurrency: profiles.iter().map(|p| p.concurrency).min()?,
+            request_interval: profiles.iter().map(|p| p.request_interval).max()?,
+            max_attempts: profiles.iter().map(|p| p.max_attempts).min()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_by_host() {
+        let rda = Politeness::for_url(
+            "https://thredds.rda.ucar.edu/thredds/fileServer/files/g/d084001/{yyyy}/gfs.f{fff}.grib2",
+        )
+        .unwrap();
+        assert_eq!(rda.provider, "NCAR RDA");
+        assert_eq!(rda.concurrency, 2);
+        let nomads =
+            Politeness::for_url("https://NOMADS.ncep.noaa.gov:443/pub/data/gfs.f000").unwrap();
+        assert_eq!(nomads.request_interval, Duration::from_millis(500));
+        let aws = Politeness::for_url("https://noaa-gfs-bdp-pds.s3.amazonaws.com/gfs.20200101/00")
+            .unwrap();
+        assert_eq!((aws.concurrency, aws.max_attempts), (8, 5));
+        assert_eq!(Politeness::for_url("http://127.0.0.1:9001/gfs.f000"), None);
+        assert_eq!(
+            Politeness::for_url("https://notrda.ucar.edu.example.com/f000"),
+            None
+        );
+
+        // Several sources: the strictest of each limit
+        let both = Politeness::for_sources([
+            "https://noaa-nam-pds.s3.amazonaws.com/nam.f00",
+            "https://data.rda.ucar.edu/ds084.1/gfs.f000",
+            "https://noaa-rap-pds.s3.amazonaws.com/rap.f00",
+            "https://opendata.dwd.de/icon.grib2.bz2",
+        ])
+        .unwrap();
+        assert_eq!(both.provider, "AWS open data, NCAR RDA");
+        assert_eq!(both.concurrency, 2);
+        assert_eq!(both.request_interval, Duration::from_secs(1));
+        assert_eq!(both.max_attempts, 3);
+        assert_eq!(
+            Politeness::for_sources(["https://opendata.dwd.de/icon"]),
+            None
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Options that select a profile, replaced by the profile's options.
+pub const PROFILE_FLAG: &str = "--profile";
+pub const CONFIG_FLAG: &str = "--config";
+
+/// Config file holding named profiles (`--profile`), e.g.
+///
+/// ```toml
+/// [profiles.local-minio]
+/// bucket = "gfs-test"
+/// endpoint-url = "http://localhost:9000"
+/// aws-profile = "minio"
+/// ```
+///
+/// Each entry of a profile is a long option: strings and numbers are its value,
+/// `true` sets a flag and arrays repeat the option.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, toml::Table>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Command-line options of the profile `name`, keeping those `accepts` (the
+    /// long names of the command being run) so a destination profile can serve
+    /// the download run and the subcommands alike. Keys that no command `knows`
+    /// are rejected, rather than a typo being ignored.
+    pub fn args(
+        &self,
+        name: &str,
+        accepts: impl Fn(&str) -> bool,
+        knows: impl Fn(&str) -> bool,
+    ) -> Result<Vec<String>, String> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            format!("no profile '{name}' (profiles: {})", known.join(", "))
+        })?;
+        let mut args = Vec::new();
+        for (key, value) in profile {
+            let option = key.replace('_', "-");
+            if !knows(&option) {
+                return Err(format!("unknown option '{key}' in profile '{name}'"));
+            }
+            if !accepts(&option) {
+                continue;
+            }
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    toml::Value::Boolean(true) => args.push(format!("--{option}")),
+                    toml::Value::Boolean(false) => {}
+                    toml::Value::String(s) => args.extend([format!("--{option}"), s.clone()]),
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Datetime(_) => {
+                        args.extend([format!("--{option}"), value.to_string()])
+                    }
+                    toml::Value::Array(_) | toml::Value::Table(_) => {
+                        return Err(format!("invalid value for '{key}' in profile '{name}'"))
+                    }
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Default config file: `$XDG_CONFIG_HOME/gfs_wind_downloader/config.toml`, or
+/// under `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("gfs_wind_downloader").join("config.toml"))
+}
+
+/// Value of `--name VALUE` or `--name=VALUE` in `argv`, removed from it.
+pub fn take_option(argv: &mut Vec<String>, name: &str) -> Result<Option<(usize, String)>, String> {
+    let prefix = format!("{name}=");
+    let Some(index) = argv
+        .iter()
+        .position(|arg| arg == name || arg.starts_with(&prefix))
+    else {
+        return Ok(None);
+    };
+    let arg = argv.remove(index);
+    let value = match arg.strip_prefix(&prefix) {
+        Some(value) => value.to_string(),
+        None if index < argv.len() => argv.remove(index),
+        None => return Err(format!("{name} requires a value")),
+    };
+    Ok(Some((index, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_args() {
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.prod]
+            bucket = "gfs-archive"
+            storage-class = "glacier-ir"
+            concurrency = 4
+            s3_accelerate = true
+            s3-dualstack = false
+            model = ["gfs", "nam"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.args("prod", |_| true, |_| true).unwrap(),
+            [
+                "--bucket",
+                "gfs-archive",
+                "--concurrency",
+                "4",
+                "--model",
+                "gfs",
+                "--model",
+                "nam",
+                "--s3-accelerate",
+                "--storage-class",
+                "glacier-ir",
+            ]
+        );
+        assert_eq!(
+            config
+                .args("prod", |option| option == "bucket", |_| true)
+                .unwrap(),
+            ["--bucket", "gfs-archive"]
+        );
+        assert!(config
+            .args("staging", |_| true, |_| true)
+            .unwrap_err()
+            .contains("prod"));
+        // A typo no command knows fails, instead of being left out
+        assert_eq!(
+            config.args("prod", |_| true, |option| option != "s3-dualstack"),
+            Err("unknown option 's3-dualstack' in profile 'prod'".to_string())
+        );
+
+        let mut argv: Vec<String> = ["x", "verify", "--profile=prod", "--prefix", "wind/"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            take_option(&mut argv, PROFILE_FLAG),
+            Ok(Some((2, "prod".to_string())))
+        );
+        assert_eq!(argv, ["x", "verify", "--prefix", "wind/"]);
+        assert_eq!(take_option(&mut argv, CONFIG_FLAG), Ok(None));
+        assert!(take_option(&mut vec!["--config".to_string()], CONFIG_FLAG).is_err());
+    }
+}
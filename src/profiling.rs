@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::cycle::Task;
+
+/// Samples per second of each thread.
+const FREQUENCY: i32 = 997;
+
+/// CPU profile of the file being processed (`--profile-dir`), to tell whether
+/// decoding, filtering or S3 I/O dominates. Every thread of the process is
+/// sampled, so a profile only covers one file when files run one at a time.
+pub struct FileProfile {
+    guard: pprof::ProfilerGuard<'static>,
+}
+
+impl FileProfile {
+    /// Start sampling; fails if another profile is running.
+    pub fn start() -> Result<Self, String> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| format!("cannot start the profiler: {e}"))?;
+        Ok(Self { guard })
+    }
+
+    /// Stop sampling and write the samples as a flamegraph SVG to `path`. Fails
+    /// when the file took too little CPU time to be sampled.
+    pub fn write_flamegraph(self, path: &Path) -> Result<(), String> {
+        let report = self
+            .guard
+            .report()
+            .build()
+            .map_err(|e| format!("cannot build the profile: {e}"))?;
+        if report.data.is_empty() {
+            return Err("no CPU sample taken".to_string());
+        }
+        let file = File::create(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        report
+            .flamegraph(file)
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// File name of the profile of a task, e.g. `gfs_2020010100_f000.svg`.
+pub fn profile_name(task: &Task) -> String {
+    format!(
+        "{}_{}_f{:03}.svg",
+        task.cycle.model,
+        task.cycle.time.format("%Y%m%d%H"),
+        task.forecast_hour
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::Cycle;
+    use crate::model::Model;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_profile_of_a_file() {
+        let task = Task {
+            cycle: Cycle {
+                model: Model::Gfs,
+                time: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            },
+            forecast_hour: 3,
+        };
+        assert_eq!(profile_name(&task), "gfs_2020010106_f003.svg");
+
+        let dir = std::env::temp_dir().join(format!("gfs-profile-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(profile_name(&task));
+        let profile = FileProfile::start().unwrap();
+        assert!(FileProfile::start().is_err());
+        // Busy long enough to be sampled
+        let busy = std::time::Instant::now();
+        let mut x = 0u64;
+        while busy.elapsed() < std::time::Duration::from_millis(200) {
+            x = std::hint::black_box(x.wrapping_mul(31).wrapping_add(1));
+        }
+        profile.write_flamegraph(&path).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("<?xml"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::cycle::{Cycle, Target};
+
+/// Progress of a run, posted periodically with `--progress-webhook` so long
+/// backfills can be followed from a dashboard.
+pub struct Progress {
+    started: Instant,
+    files_total: usize,
+    files_done: AtomicUsize,
+    cycles_total: usize,
+    cycles_done: AtomicUsize,
+    /// Targets left in each cycle.
+    remaining: Mutex<HashMap<Cycle, usize>>,
+    /// Time and downloaded bytes of the previous report, for the throughput.
+    last: Mutex<(Instant, u64)>,
+}
+
+/// JSON body posted to the webhook.
+#[derive(Debug, Serialize)]
+pub struct ProgressReport {
+    pub cycles_done: usize,
+    pub cycles_total: usize,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub files_failed: usize,
+    pub downloaded_bytes: u64,
+    /// Bytes per second downloaded since the previous report.
+    pub throughput_bytes_per_second: u64,
+    pub elapsed_seconds: u64,
+    /// Estimated time left at the average pace so far, once a file is done.
+    pub eta_seconds: Option<u64>,
+    /// Whether this is the final report of the run.
+    pub finished: bool,
+}
+
+impl Progress {
+    pub fn new(targets: &[Target], started: Instant) -> Self {
+        let mut remaining = HashMap::new();
+        for target in targets {
+            *remaining.entry(target.primary().cycle).or_insert(0) += 1;
+        }
+        Self {
+            started,
+            files_total: targets.len(),
+            files_done: AtomicUsize::new(0),
+            cycles_total: remaining.len(),
+            cycles_done: AtomicUsize::new(0),
+            remaining: Mutex::new(remaining),
+            last: Mutex::new((started, 0)),
+        }
+    }
+
+    /// Record a finished target, whatever its outcome.
+    pub fn done(&self, target: &Target) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        let mut remaining = self.remaining.lock().unwrap();
+        if let Some(left) = remaining.get_mut(&target.primary().cycle) {
+            *left -= 1;
+            if *left == 0 {
+                self.cycles_done.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Report at `now`, given the bytes downloaded and the files failed so far.
+    pub fn report(
+        &self,
+        now: Instant,
+        downloaded_bytes: u64,
+        files_failed: usize,
+        finished: bool,
+    ) -> ProgressReport {
+        let elapsed = now.duration_since(self.started);
+        let files_done = self.files_done.load(Ordering::Relaxed);
+
+        let mut last = self.last.lock().unwrap();
+        let interval = now.duration_since(last.0).as_secs_f64();
+        let throughput = if interval > 0.0 {
+            (downloaded_bytes.saturating_sub(last.1) as f64 / interval) as u64
+        } else {
+            0
+        };
+        *last = (now, downloaded_bytes);
+
+        let eta_seconds = (files_done > 0).then(|| {
+            let left = self.files_total.saturating_sub(files_done) as f64;
+            (elapsed.as_secs_f64() * left / files_done as f64).round() as u64
+        });
+
+        ProgressReport {
+            cycles_done: self.cycles_done.load(Ordering::Relaxed),
+            cycles_total: self.cycles_total,
+            files_done,
+            files_total: self.files_total,
+            files_failed,
+            downloaded_bytes,
+            throughput_bytes_per_second: throughput,
+            elapsed_seconds: elapsed.as_secs(),
+            eta_seconds,
+            finished,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::plan_targets;
+    use crate::testing::task;
+    use std::time::Duration;
+
+    #[test]
+    fn test_progress_report() {
+        let targets = plan_targets(&[task(0, 0), task(0, 3), task(6, 0), task(6, 3)]);
+        let started = Instant::now();
+        let progress = Progress::new(&targets, started);
+
+        let report = progress.report(started + Duration::from_secs(10), 1000, 0, false);
+        assert_eq!((report.cycles_total, report.files_total), (2, 4));
+        assert_eq!(report.throughput_bytes_per_second, 100);
+        assert_eq!(report.eta_seconds, None);
+
+        progress.done(&targets[0]);
+        progress.done(&targets[1]);
+        progress.done(&targets[2]);
+        let report = progress.report(started + Duration::from_secs(30), 5000, 1, false);
+        assert_eq!((report.cycles_done, report.files_done), (1, 3));
+        // 4000 bytes over the 20s since the previous report
+        assert_eq!(report.throughput_bytes_per_second, 200);
+        assert_eq!(report.eta_seconds, Some(10));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["files_failed"], 1);
+        assert_eq!(json["finished"], false);
+    }
+}
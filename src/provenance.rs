@@ -0,0 +1,105 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::Location;
+use crate::filter::Origin;
+use crate::grib::ProductInfo;
+use crate::param::Param;
+
+/// Suffix appended to an object key to name its provenance log.
+pub const PROVENANCE_SUFFIX: &str = ".provenance.jsonl";
+
+/// One line of the provenance log of an object (`--provenance`): where the bytes
+/// of a product were read upstream and where they are stored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceRecord {
+    /// 1-based index of the message in the source file.
+    pub message: u64,
+    /// Source file URL.
+    pub source: String,
+    /// Byte offset and length of the message in the source file (decompressed).
+    pub source_offset: u64,
+    pub source_length: u64,
+    /// NCEP short name, or `discipline.category.number`.
+    pub parameter: String,
+    pub level: Option<String>,
+    pub valid_time: Option<NaiveDateTime>,
+    /// Object holding the message bytes: this one, or another one holding an
+    /// identical message with `--dedup-catalog`.
+    pub object: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ProvenanceRecord {
+    pub fn new(source: &str, origin: &Origin, product: &ProductInfo, stored: &Location) -> Self {
+        Self {
+            message: origin.index,
+            source: source.to_string(),
+            source_offset: origin.offset,
+            source_length: origin.length,
+            parameter: Param::of(product).to_string(),
+            level: product.level.as_ref().map(ToString::to_string),
+            valid_time: product.time.map(|t| t.valid_time()),
+            object: stored.key.clone(),
+            offset: stored.offset,
+            length: stored.length,
+        }
+    }
+
+    /// Key of the provenance log for a data object key.
+    pub fn key_for(object_key: &str) -> String {
+        format!("{object_key}{PROVENANCE_SUFFIX}")
+    }
+
+    /// Body of a provenance log: one JSON record per line.
+    pub fn to_jsonl(records: &[Self]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut body, record).expect("provenance record serializes");
+            body.push(b'\n');
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_jsonl() {
+        let product = ProductInfo {
+            discipline: 0,
+            template: 0,
+            category: 2,
+            number: 2,
+            time: None,
+            level: None,
+        };
+        let origin = Origin {
+            index: 3,
+            offset: 2048,
+            length: 512,
+        };
+        let stored = Location {
+            key: "wind/wind_20200101_00.grb2".to_string(),
+            offset: 0,
+            length: 512,
+        };
+        let record = ProvenanceRecord::new("https://example.com/f000", &origin, &product, &stored);
+        assert_eq!(record.parameter, "UGRD");
+        assert_eq!(
+            ProvenanceRecord::key_for(&stored.key),
+            "wind/wind_20200101_00.grb2.provenance.jsonl"
+        );
+
+        let body = ProvenanceRecord::to_jsonl(&[record.clone(), record.clone()]);
+        let lines: Vec<ProvenanceRecord> = body
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines, [record.clone(), record]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Python bindings (`--features python`), built as the `gfs_wind_downloader`
+//! extension module with maturin: the run and subcommands of the binary, and the
+//! archive reader.
+
+use chrono::NaiveDateTime;
+use numpy::ndarray::Array2;
+use numpy::{IntoPyArray, PyArray2};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::cli;
+use crate::error::Error;
+use crate::param::Param;
+use crate::reader::{self, Message, Selector};
+use crate::s3;
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        PyRuntimeError::new_err(e.to_string())
+    }
+}
+
+/// A message's manifest fields and its decoded values.
+type DecodedMessage<'py> = (Bound<'py, PyDict>, Bound<'py, PyArray2<f32>>);
+
+/// Reads messages of archived objects with S3 range requests.
+#[pyclass]
+struct ArchiveReader {
+    reader: reader::ArchiveReader,
+    runtime: tokio::runtime::Runtime,
+}
+
+fn selector(
+    param: Option<&str>,
+    level: Option<String>,
+    valid_time: Option<NaiveDateTime>,
+) -> PyResult<Selector> {
+    Ok(Selector {
+        param: param
+            .map(str::parse::<Param>)
+            .transpose()
+            .map_err(PyValueError::new_err)?,
+        level,
+        valid_time,
+    })
+}
+
+/// Manifest fields of a message as a dict.
+fn describe<'py>(py: Python<'py>, message: &Message) -> PyResult<Bound<'py, PyDict>> {
+    let entry = &message.entry;
+    let dict = PyDict::new(py);
+    let param = Param::new(entry.discipline, entry.category, entry.number);
+    dict.set_item("param", param.to_string())?;
+    dict.set_item("level", &entry.level)?;
+    dict.set_item("reference_time", entry.reference_time)?;
+    dict.set_item("valid_time", entry.valid_time)?;
+    dict.set_item("offset", entry.offset)?;
+    dict.set_item("length", entry.length)?;
+    Ok(dict)
+}
+
+impl ArchiveReader {
+    fn fetch_messages(
+        &self,
+        py: Python<'_>,
+        key: &str,
+        selector: &Selector,
+    ) -> PyResult<Vec<Message>> {
+        py.detach(|| self.runtime.block_on(self.reader.fetch(key, selector)))
+            .map_err(PyErr::from)
+    }
+}
+
+#[pymethods]
+impl ArchiveReader {
+    #[new]
+    #[pyo3(signature = (bucket, endpoint_url = None))]
+    fn new(bucket: &str, endpoint_url: Option<&str>) -> PyResult<Self> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let compat = s3::S3Compat::detect(endpoint_url);
+        let client = runtime.block_on(s3::client(endpoint_url, compat, &Default::default()));
+        Ok(Self {
+            reader: reader::ArchiveReader::new(client, bucket),
+            runtime,
+        })
+    }
+
+    /// Matching messages of `key` as dicts with their GRIB2 bytes under `data`.
+    #[pyo3(signature = (key, param = None, level = None, valid_time = None))]
+    fn fetch<'py>(
+        &self,
+        py: Python<'py>,
+        key: &str,
+        param: Option<&str>,
+        level: Option<String>,
+        valid_time: Option<NaiveDateTime>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let selector = selector(param, level, valid_time)?;
+        self.fetch_messages(py, key, &selector)?
+            .iter()
+            .map(|message| {
+                let dict = describe(py, message)?;
+                dict.set_item("data", PyBytes::new(py, &message.bytes))?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Matching messages of `key` decoded as `(dict, array)` pairs, each array
+    /// of shape `(nj, ni)` with NaN for missing points.
+    #[pyo3(signature = (key, param = None, level = None, valid_time = None))]
+    fn fetch_arrays<'py>(
+        &self,
+        py: Python<'py>,
+        key: &str,
+        param: Option<&str>,
+        level: Option<String>,
+        valid_time: Option<NaiveDateTime>,
+    ) -> PyResult<Vec<DecodedMessage<'py>>> {
+        let selector = selector(param, level, valid_time)?;
+        self.fetch_messages(py, key, &selector)?
+            .iter()
+            .map(|message| {
+                let field = message.decode()?;
+                let array = Array2::from_shape_vec((field.nj, field.ni), field.values)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+                Ok((describe(py, message)?, array.into_pyarray(py)))
+            })
+            .collect()
+    }
+}
+
+/// Run the command line of the binary (without the program name) in this process,
+/// e.g. `run(["--start-date", "2024-01-01", "--end-date", "2024-01-31", "--bucket",
+/// "my-bucket"])`, returning its exit code. Invalid arguments raise `ValueError`
+/// with clap's message; `--help` and `--version` print theirs and return 0.
+#[pyfunction]
+fn run(py: Python<'_>, args: Vec<String>) -> PyResult<u8> {
+    let argv: Vec<String> = std::iter::once("gfs_wind_downloader".to_string())
+        .chain(args)
+        .collect();
+    match py.detach(|| cli::embedded(argv)) {
+        Ok(code) => Ok(code),
+        Err(e) => match e.downcast::<clap::Error>() {
+            Ok(e) if !e.use_stderr() => {
+                print!("{e}");
+                Ok(0)
+            }
+            Ok(e) => Err(PyValueError::new_err(e.render().to_string())),
+            Err(e) => Err(PyRuntimeError::new_err(format!("{e:#}"))),
+        },
+    }
+}
+
+#[pymodule]
+fn gfs_wind_downloader(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ArchiveReader>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    Ok(())
+}
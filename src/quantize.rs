@@ -0,0 +1,189 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Result;
+use crate::field::decode;
+use crate::filter::MessageTransform;
+use crate::grib::{is_wind_product, sections, ProductInfo};
+use crate::pack::{bitmap, complex_packing};
+
+/// Precision wind fields are re-packed to (`--quantize`), a power of ten in m/s.
+///
+/// Values are rounded to the step (an error of half a step at most) and
+/// written with complex packing and second-order spatial differencing
+/// (template 5.3), whose size follows the number of steps between
+/// neighbouring points rather than the full range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quantize {
+    /// Decimal scale factor D of the packed values: the step is 10^-D.
+    decimal_scale: i16,
+}
+
+impl Quantize {
+    /// Step the values are rounded to, in m/s.
+    pub fn step(&self) -> f64 {
+        10f64.powi(-(self.decimal_scale as i32))
+    }
+
+    /// Re-pack the field of a message to the step. `None` keeps the message as
+    /// it is: several fields, packing that can't be decoded (JPEG 2000, PNG),
+    /// or no smaller once re-packed.
+    pub fn repack(&self, msg: &[u8]) -> Option<Vec<u8>> {
+        let parts: Vec<(u8, &[u8])> = sections(msg).collect();
+        if parts.iter().filter(|(number, _)| *number == 7).count() != 1 {
+            return None;
+        }
+        let field = decode(msg).ok()?;
+        let scale = 10f64.powi(self.decimal_scale as i32);
+        let scaled: Vec<i64> = field
+            .values
+            .iter()
+            .filter(|v| !v.is_nan())
+            .map(|&v| (v as f64 * scale).round() as i64)
+            .collect();
+        let (representation, data) = complex_packing(&scaled, self.decimal_scale)?;
+
+        let mut repacked = msg[..16].to_vec();
+        for (_, section) in parts.iter().filter(|(number, _)| *number < 5) {
+            repacked.extend_from_slice(section);
+        }
+        repacked.extend(representation);
+        repacked.extend(bitmap(&field.values));
+        repacked.extend(data);
+        repacked.extend_from_slice(b"7777");
+        let length = repacked.len() as u64;
+        repacked[8..16].copy_from_slice(&length.to_be_bytes());
+        (repacked.len() < msg.len()).then_some(repacked)
+    }
+}
+
+impl MessageTransform for Quantize {
+    fn transform(&self, message: Vec<u8>, products: &[ProductInfo]) -> Result<Option<Vec<u8>>> {
+        if products.is_empty() || !products.iter().all(is_wind_product) {
+            return Ok(Some(message));
+        }
+        Ok(Some(self.repack(&message).unwrap_or(message)))
+    }
+}
+
+impl FromStr for Quantize {
+    type Err = String;
+
+    /// Parse a step such as `0.1m/s`, `0.01` or `1 m/s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid quantization step '{s}' (expected e.g. 0.1m/s)");
+        let step: f64 = s
+            .trim()
+            .trim_end_matches("m/s")
+            .trim()
+            .parse()
+            .map_err(|_| invalid())?;
+        if step <= 0.0 || step.is_nan() {
+            return Err(invalid());
+        }
+        let decimal_scale = -step.log10().round();
+        if !(-3.0..=6.0).contains(&decimal_scale)
+            || (10f64.powf(-decimal_scale) - step).abs() > step * 1e-9
+        {
+            return Err(format!(
+                "quantization step '{s}' must be a power of ten between 0.000001 and 1000 m/s"
+            ));
+        }
+        Ok(Self {
+            decimal_scale: decimal_scale as i16,
+        })
+    }
+}
+
+impl fmt::Display for Quantize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = self.decimal_scale.max(0) as usize;
+        write!(f, "{:.decimals$} m/s", self.step())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assemble, grid_section, product_section, section};
+
+    /// UGRD message on a 16x8 grid with simple packing of 16-bit values at
+    /// 0.01 m/s, the points of `missing` masked by a bitmap.
+    fn message(values: &[f64], missing: &[usize]) -> Vec<u8> {
+        let points = values.len() as u32;
+        let present: Vec<f64> = (0..values.len())
+            .filter(|i| !missing.contains(i))
+            .map(|i| values[i])
+            .collect();
+        let mut sect5 = section(5, 21);
+        sect5[5..9].copy_from_slice(&(present.len() as u32).to_be_bytes());
+        sect5[11..15].copy_from_slice(&(-5000f32).to_be_bytes());
+        sect5[17..19].copy_from_slice(&2u16.to_be_bytes());
+        sect5[19] = 16;
+        let sect6 = if missing.is_empty() {
+            bitmap(&[])
+        } else {
+            let values: Vec<f32> = (0..values.len())
+                .map(|i| if missing.contains(&i) { f32::NAN } else { 0.0 })
+                .collect();
+            bitmap(&values)
+        };
+        let mut sect7 = ((5 + 2 * present.len()) as u32).to_be_bytes().to_vec();
+        sect7.push(7);
+        for v in present {
+            let x = (v * 100.0).round() as i64 + 5000;
+            sect7.extend_from_slice(&(x as u16).to_be_bytes());
+        }
+        assemble(
+            0,
+            &[
+                &grid_section(16, points / 16),
+                &product_section(2, 2),
+                &sect5,
+                &sect6,
+                &sect7,
+            ],
+        )
+    }
+
+    #[test]
+    fn test_quantize_repacks_within_half_a_step() {
+        let quantize: Quantize = "0.1m/s".parse().unwrap();
+        assert_eq!(quantize.to_string(), "0.1 m/s");
+        assert_eq!("1 m/s".parse::<Quantize>().unwrap().step(), 1.0);
+        assert!("0.2m/s".parse::<Quantize>().is_err());
+        assert!("-0.1".parse::<Quantize>().is_err());
+
+        // A smooth field, as wind is at 0.25 degrees
+        let values: Vec<f64> = (0..128)
+            .map(|i| (((i as f64 / 7.0).sin() * 12.0 - 3.0) * 100.0).round() / 100.0)
+            .collect();
+        for missing in [vec![], vec![5, 6, 100]] {
+            let msg = message(&values, &missing);
+            let original = decode(&msg).unwrap();
+            let repacked = quantize.repack(&msg).unwrap();
+            assert!(repacked.len() < msg.len() * 2 / 3, "{}", repacked.len());
+            let field = decode(&repacked).unwrap();
+            assert_eq!((field.ni, field.nj), (16, 8));
+            for (a, b) in original.values.iter().zip(&field.values) {
+                if a.is_nan() {
+                    assert!(b.is_nan());
+                } else {
+                    assert!((a - b).abs() <= 0.05 + 1e-4, "{a} vs {b}");
+                }
+            }
+        }
+
+        // Other parameters pass through
+        let gust = ProductInfo {
+            discipline: 0,
+            template: 0,
+            category: 2,
+            number: 22,
+            time: None,
+            level: None,
+        };
+        let msg = message(&values, &[]);
+        assert_eq!(quantize.transform(msg.clone(), &[gust]).unwrap(), Some(msg));
+    }
+}
@@ -0,0 +1,150 @@
+use aws_sdk_s3::Client;
+use chrono::NaiveDateTime;
+
+use crate::error::{Error, Result};
+use crate::field::{decode, Field};
+use crate::manifest::{Manifest, MessageEntry};
+use crate::naming::Naming;
+use crate::param::Param;
+use crate::s3::{get_object, get_range};
+
+/// Messages to read from an archived object; unset criteria match anything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selector {
+    pub param: Option<Param>,
+    /// Level as written in manifests, e.g. `10 m above ground` (case-insensitive).
+    pub level: Option<String>,
+    pub valid_time: Option<NaiveDateTime>,
+}
+
+impl Selector {
+    pub fn matches(&self, entry: &MessageEntry) -> bool {
+        let param = Param::new(entry.discipline, entry.category, entry.number);
+        self.param.is_none_or(|p| p == param)
+            && self.level.as_ref().is_none_or(|level| {
+                entry
+                    .level
+                    .as_ref()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(level.trim()))
+            })
+            && self.valid_time.is_none_or(|t| entry.valid_time == Some(t))
+    }
+}
+
+/// A GRIB2 message read back from the archive.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Manifest entry of the message (of its first product).
+    pub entry: MessageEntry,
+    pub bytes: Vec<u8>,
+}
+
+impl Message {
+    /// Decode the values of the message.
+    pub fn decode(&self) -> Result<Field> {
+        decode(&self.bytes)
+    }
+}
+
+/// Reads single messages of archived objects with S3 range requests, using
+/// their manifests.
+pub struct ArchiveReader {
+    client: Client,
+    bucket: String,
+    naming: Naming,
+}
+
+impl ArchiveReader {
+    pub fn new(client: Client, bucket: &str) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            naming: Naming::default(),
+        }
+    }
+
+    /// Read an archive whose manifests have another suffix (`--manifest-suffix`).
+    pub fn with_naming(mut self, naming: &Naming) -> Self {
+        self.naming = naming.clone();
+        self
+    }
+
+    /// Manifest of an object.
+    pub async fn manifest(&self, key: &str) -> Result<Manifest> {
+        let manifest_key = self.naming.manifest_key(key);
+        let bytes = get_object(&self.client, &self.bucket, &manifest_key).await?;
+        serde_json::from_slice(&bytes).map_err(|e| Error::SinkError {
+            key: manifest_key,
+            reason: format!("invalid manifest: {e}"),
+        })
+    }
+
+    /// Bytes of one message of `key`, from the object holding them.
+    pub async fn read(&self, key: &str, entry: &MessageEntry) -> Result<Vec<u8>> {
+        let key = entry.stored_in.as_deref().unwrap_or(key);
+        let bytes = get_range(&self.client, &self.bucket, key, entry.offset, entry.length).await?;
+        if bytes.len() as u64 != entry.length || !bytes.starts_with(b"GRIB") {
+            return Err(Error::ParseError {
+                offset: entry.offset,
+                reason: format!("no GRIB2 message of {} bytes in {key}", entry.length),
+            });
+        }
+        Ok(bytes)
+    }
+
+    /// Read the messages of `key` matching `selector`, one range request each.
+    pub async fn fetch(&self, key: &str, selector: &Selector) -> Result<Vec<Message>> {
+        let manifest = self.manifest(key).await?;
+        let mut messages: Vec<Message> = Vec::new();
+        for entry in manifest.messages.iter().filter(|e| selector.matches(e)) {
+            // Messages with several products have one entry per product
+            let location = (&entry.stored_in, entry.offset);
+            if messages
+                .iter()
+                .any(|m| (&m.entry.stored_in, m.entry.offset) == location)
+            {
+                continue;
+            }
+            let bytes = self.read(key, entry).await?;
+            messages.push(Message {
+                entry: entry.clone(),
+                bytes,
+            });
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{time, timed_entry};
+
+    /// Message of parameter 0.2.`number` at `level` from the `hour` analysis.
+    fn entry(number: u8, level: &str, hour: u32) -> MessageEntry {
+        MessageEntry {
+            level: Some(level.to_string()),
+            ..timed_entry(number, time(hour), 0, 100)
+        }
+    }
+
+    #[test]
+    fn test_selector_matches_param_level_and_time() {
+        let selector = Selector {
+            param: Some("UGRD".parse().unwrap()),
+            level: Some("10 M above ground".to_string()),
+            valid_time: None,
+        };
+        assert!(selector.matches(&entry(2, "10 m above ground", 0)));
+        assert!(selector.matches(&entry(2, "10 m above ground", 6)));
+        assert!(!selector.matches(&entry(3, "10 m above ground", 0)));
+        assert!(!selector.matches(&entry(2, "850 mb", 0)));
+
+        let at_six = Selector {
+            valid_time: Some(time(6)),
+            ..Selector::default()
+        };
+        assert!(at_six.matches(&entry(3, "850 mb", 6)));
+        assert!(!at_six.matches(&entry(3, "850 mb", 0)));
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use aws_sdk_s3::Client;
+
+use crate::catalog::message_hash;
+use crate::error::Result;
+use crate::grib::{END_MARKER, INDICATOR_LEN};
+use crate::manifest::Manifest;
+use crate::naming::Naming;
+use crate::s3::{head_metadata, put_object, S3Compat, S3MultipartUploader, StorageClass};
+
+/// A message of a re-framed object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reframe {
+    /// Offset of the message in the original object.
+    pub old_offset: u64,
+    /// Offset and length in the re-framed object.
+    pub offset: u64,
+    pub length: u64,
+    /// What was fixed, empty if the message was already well framed.
+    pub fixes: Vec<String>,
+}
+
+/// An object whose messages were re-framed: indicator lengths set to the
+/// sections actually present and `7777` markers restored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reframed {
+    pub bytes: Vec<u8>,
+    pub messages: Vec<Reframe>,
+}
+
+/// End of the sections of the message starting at `start`, and whether its
+/// `7777` marker follows them. The sections are walked by their own lengths,
+/// ignoring the length announced by the indicator; they end at the marker, at
+/// the next `GRIB` magic or at the end of the object.
+fn sections_end(object: &[u8], start: usize) -> Result<(usize, bool), String> {
+    let mut pos = start + INDICATOR_LEN;
+    let mut data = false;
+    loop {
+        let rest = &object[pos..];
+        let marker = rest.starts_with(END_MARKER);
+        if marker || rest.is_empty() || rest.starts_with(b"GRIB") {
+            if !data {
+                return Err(format!("message at byte {start} has no data section"));
+            }
+            return Ok((pos, marker));
+        }
+        if rest.len() < 5 {
+            return Err(format!("truncated section at byte {pos}"));
+        }
+        let length = u32::from_be_bytes(rest[..4].try_into().expect("4 bytes")) as usize;
+        let number = rest[4];
+        if length < 5 || !(1..=7).contains(&number) || length > rest.len() {
+            return Err(format!("invalid section at byte {pos}"));
+        }
+        data |= number == 7;
+        pos += length;
+    }
+}
+
+/// Re-frame the GRIB2 messages of an object written by an earlier version with
+/// inconsistent indicator lengths or missing `7777` markers.
+///
+/// Fails when the payload can't be recovered: bytes that aren't a GRIB2
+/// message, or sections that don't chain up.
+pub fn reframe(object: &[u8]) -> Result<Reframed, String> {
+    let mut reframed = Reframed {
+        bytes: Vec::with_capacity(object.len()),
+        messages: Vec::new(),
+    };
+    let mut start = 0;
+    while start < object.len() {
+        let indicator = &object[start..];
+        if indicator.len() < INDICATOR_LEN || !indicator.starts_with(b"GRIB") {
+            return Err(format!("no GRIB2 message at byte {start}"));
+        }
+        if indicator[7] != 2 {
+            return Err(format!(
+                "message at byte {start} has GRIB edition {}",
+                indicator[7]
+            ));
+        }
+        let (end, marker) = sections_end(object, start)?;
+        let length = (end - start + END_MARKER.len()) as u64;
+        let announced = u64::from_be_bytes(indicator[8..16].try_into().expect("8 bytes"));
+
+        let mut fixes = Vec::new();
+        if announced != length {
+            fixes.push(format!(
+                "header announced {announced} bytes, message has {length}"
+            ));
+        }
+        if !marker {
+            fixes.push("'7777' end marker added".to_string());
+        }
+        let offset = reframed.bytes.len() as u64;
+        reframed.bytes.extend_from_slice(&object[start..start + 8]);
+        reframed.bytes.extend_from_slice(&length.to_be_bytes());
+        reframed
+            .bytes
+            .extend_from_slice(&object[start + INDICATOR_LEN..end]);
+        reframed.bytes.extend_from_slice(END_MARKER);
+        reframed.messages.push(Reframe {
+            old_offset: start as u64,
+            offset,
+            length,
+            fixes,
+        });
+        start = end + if marker { END_MARKER.len() } else { 0 };
+    }
+    Ok(reframed)
+}
+
+impl Reframed {
+    /// Whether any message was fixed.
+    pub fn is_changed(&self) -> bool {
+        self.messages.iter().any(|m| !m.fixes.is_empty())
+    }
+
+    /// Fixes made, one line per message fixed.
+    pub fn fixes(&self) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter(|m| !m.fixes.is_empty())
+            .map(|m| format!("message at byte {}: {}", m.old_offset, m.fixes.join(", ")))
+            .collect()
+    }
+
+    /// Point the entries of `manifest` stored in the re-framed object (those
+    /// with `stored_in` equal to `stored_in`) to the re-framed messages, with
+    /// their hashes recomputed. Entries are matched by offset, or by rank when
+    /// the manifest offsets were also written wrong but list as many messages.
+    /// Returns the number of entries changed.
+    pub fn remap(&self, manifest: &mut Manifest, stored_in: Option<&str>) -> Result<usize, String> {
+        let by_offset: HashMap<u64, &Reframe> =
+            self.messages.iter().map(|m| (m.old_offset, m)).collect();
+        let mut offsets: Vec<u64> = manifest
+            .messages
+            .iter()
+            .filter(|e| e.stored_in.as_deref() == stored_in)
+            .map(|e| e.offset)
+            .collect();
+        offsets.sort();
+        offsets.dedup();
+        let by_rank = offsets.len() == self.messages.len();
+
+        let mut changed = 0;
+        for entry in &mut manifest.messages {
+            if entry.stored_in.as_deref() != stored_in {
+                continue;
+            }
+            let message = match by_offset.get(&entry.offset) {
+                Some(message) => *message,
+                None if by_rank => {
+                    let rank = offsets.binary_search(&entry.offset).expect("listed offset");
+                    &self.messages[rank]
+                }
+                None => {
+                    return Err(format!(
+                        "manifest lists a message at byte {}, which starts none",
+                        entry.offset
+                    ))
+                }
+            };
+            let sha256 = entry.sha256.as_ref().map(|_| {
+                let start = message.offset as usize;
+                message_hash(&self.bytes[start..start + message.length as usize])
+            });
+            if (entry.offset, entry.length, &entry.sha256)
+                != (message.offset, message.length, &sha256)
+            {
+                (entry.offset, entry.length, entry.sha256) =
+                    (message.offset, message.length, sha256);
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Replace an object by its re-framed bytes, keeping its user metadata, then
+/// upload its updated manifest.
+#[allow(clippy::too_many_arguments)]
+pub async fn rewrite_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    reframed: &Reframed,
+    manifest: &Manifest,
+    naming: &Naming,
+    storage_class: StorageClass,
+    compat: S3Compat,
+) -> Result<()> {
+    let mut uploader = S3MultipartUploader::new(client.clone(), bucket, key, storage_class, compat);
+    for (name, value) in head_metadata(client, bucket, key)
+        .await?
+        .unwrap_or_default()
+    {
+        uploader.set_metadata(&name, value);
+    }
+    if let Err(e) = uploader.write(&reframed.bytes).await {
+        uploader.abort().await?;
+        return Err(e);
+    }
+    uploader.complete().await?;
+
+    let body = serde_json::to_vec_pretty(manifest).expect("manifest serializes");
+    put_object(
+        client,
+        bucket,
+        &naming.manifest_key(key),
+        body,
+        "application/json",
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::MessageEntry;
+    use crate::testing::{assemble, entry, section};
+
+    /// GRIB2 message with a grid, product and data section, announcing `announced`
+    /// bytes (its length if `None`), with or without its end marker.
+    fn message(value: u8, announced: Option<u64>, marker: bool) -> Vec<u8> {
+        let mut data = section(7, 6);
+        data[5] = value;
+        let mut msg = assemble(0, &[&section(3, 8), &section(4, 6), &data]);
+        if let Some(announced) = announced {
+            msg[8..16].copy_from_slice(&announced.to_be_bytes());
+        }
+        if !marker {
+            msg.truncate(msg.len() - END_MARKER.len());
+        }
+        msg
+    }
+
+    #[test]
+    fn test_reframe_and_remap() {
+        let good = message(1, None, true);
+        let wrong_length = message(2, Some(1000), true);
+        let no_marker = message(3, Some(20), false);
+        let object = [good.clone(), wrong_length, no_marker, good.clone()].concat();
+        let reframed = reframe(&object).unwrap();
+        assert!(reframed.is_changed());
+        assert_eq!(
+            reframed.bytes,
+            [
+                good.clone(),
+                message(2, None, true),
+                message(3, None, true),
+                good.clone()
+            ]
+            .concat()
+        );
+        assert_eq!(
+            reframed.fixes(),
+            [
+                "message at byte 40: header announced 1000 bytes, message has 40",
+                "message at byte 80: header announced 20 bytes, message has 40, '7777' end marker added",
+            ]
+        );
+        assert!(!reframe(&good).unwrap().is_changed());
+
+        // The manifest recorded the announced lengths and hashes; a duplicate points to
+        // the object
+        let mut manifest = Manifest::new("https://example.com/f000", "wind.grb2");
+        manifest.messages = [entry(0, 40), entry(40, 1000), entry(80, 20), entry(116, 40)]
+            .into_iter()
+            .map(|entry| MessageEntry {
+                sha256: Some(String::new()),
+                ..entry
+            })
+            .collect();
+        assert_eq!(reframed.remap(&mut manifest, None), Ok(4));
+        let offsets: Vec<_> = manifest
+            .messages
+            .iter()
+            .map(|m| (m.offset, m.length))
+            .collect();
+        assert_eq!(offsets, [(0, 40), (40, 40), (80, 40), (120, 40)]);
+        assert_eq!(manifest.messages[3].sha256, Some(message_hash(&good)));
+        let mut other = Manifest::new("https://example.com/f001", "other.grb2");
+        other.messages = vec![entry(116, 40)];
+        other.messages[0].stored_in = Some("wind.grb2".to_string());
+        assert_eq!(reframed.remap(&mut other, Some("wind.grb2")), Ok(1));
+        assert_eq!(other.messages[0].offset, 120);
+        let mut unknown = Manifest::new("https://example.com/f000", "wind.grb2");
+        unknown.messages = vec![entry(10, 40)];
+        assert!(reframed.remap(&mut unknown, None).is_err());
+
+        // Unrecoverable payloads
+        assert!(reframe(&object[..object.len() - 45]).is_err());
+        assert!(reframe(&[good.clone(), b"junk".to_vec()].concat()).is_err());
+        let mut broken = good;
+        broken[19] = 0;
+        assert!(reframe(&broken).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::s3::{validate_bucket_name, validate_prefix};
+
+/// Bucket receiving a server-side copy of each completed object
+/// (`--replicate-to`), e.g. a disaster-recovery copy in another account or
+/// region, without processing the files a second time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaTarget {
+    pub bucket: String,
+    /// Replaces `--prefix` in the keys of the copies; `None` keeps the keys.
+    pub prefix: Option<String>,
+    /// Region of the bucket, when it differs from the primary one.
+    pub region: Option<String>,
+    /// AWS profile of the account writing the copies, when it differs from the
+    /// primary one.
+    pub profile: Option<String>,
+}
+
+impl ReplicaTarget {
+    /// Key of the copy of the object at `key`, written under `primary_prefix`.
+    pub fn key(&self, primary_prefix: &str, key: &str) -> String {
+        let Some(prefix) = &self.prefix else {
+            return key.to_string();
+        };
+        let name = match primary_prefix.trim_end_matches('/') {
+            "" => key,
+            primary => key
+                .strip_prefix(primary)
+                .and_then(|name| name.strip_prefix('/'))
+                .unwrap_or(key),
+        };
+        match prefix.trim_end_matches('/') {
+            "" => name.to_string(),
+            prefix => format!("{prefix}/{name}"),
+        }
+    }
+}
+
+impl FromStr for ReplicaTarget {
+    type Err = String;
+
+    /// Parse `BUCKET[/PREFIX][,region=REGION][,profile=PROFILE]`, e.g.
+    /// `gfs-dr/wind/,region=eu-west-1,profile=dr`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let location = parts.next().unwrap_or_default();
+        let (bucket, prefix) = match location.split_once('/') {
+            Some((bucket, prefix)) => (bucket, Some(prefix.to_string())),
+            None => (location, None),
+        };
+        validate_bucket_name(bucket).map_err(|e| format!("invalid replica '{s}': {e}"))?;
+        if let Some(prefix) = &prefix {
+            validate_prefix(prefix).map_err(|e| format!("invalid replica '{s}': {e}"))?;
+        }
+        let mut target = Self {
+            bucket: bucket.to_string(),
+            prefix,
+            region: None,
+            profile: None,
+        };
+        for option in parts {
+            match option.split_once('=') {
+                Some(("region", region)) if !region.is_empty() => {
+                    target.region = Some(region.to_string())
+                }
+                Some(("profile", profile)) if !profile.is_empty() => {
+                    target.profile = Some(profile.to_string())
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid replica '{s}': unknown option '{option}' (expected region=REGION or profile=PROFILE)"
+                    ))
+                }
+            }
+        }
+        Ok(target)
+    }
+}
+
+impl fmt::Display for ReplicaTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}/", self.bucket)?;
+        if let Some(prefix) = &self.prefix {
+            f.write_str(prefix)?;
+        }
+        if let Some(region) = &self.region {
+            write!(f, " ({region})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replica_targets() {
+        let dr: ReplicaTarget = "gfs-dr/wind/,region=eu-west-1,profile=dr".parse().unwrap();
+        assert_eq!(dr.bucket, "gfs-dr");
+        assert_eq!(dr.prefix.as_deref(), Some("wind/"));
+        assert_eq!(dr.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(dr.profile.as_deref(), Some("dr"));
+        assert_eq!(dr.to_string(), "s3://gfs-dr/wind/ (eu-west-1)");
+        assert_eq!(
+            dr.key("archive/", "archive/gust/wind_20200101_00.grb2"),
+            "wind/gust/wind_20200101_00.grb2"
+        );
+        assert_eq!(
+            dr.key("", "wind_20200101_00.grb2"),
+            "wind/wind_20200101_00.grb2"
+        );
+
+        // Without a prefix, the keys are kept
+        let same: ReplicaTarget = "gfs-copy".parse().unwrap();
+        assert_eq!(same.key("archive", "archive/a.grb2"), "archive/a.grb2");
+        let root: ReplicaTarget = "gfs-copy/".parse().unwrap();
+        assert_eq!(root.key("archive", "archive/a.grb2"), "a.grb2");
+        assert_eq!(root.key("archive", "archived/a.grb2"), "archived/a.grb2");
+
+        assert!("Not_A_Bucket".parse::<ReplicaTarget>().is_err());
+        assert!("gfs-dr//wind".parse::<ReplicaTarget>().is_err());
+        assert!("gfs-dr,zone=a".parse::<ReplicaTarget>().is_err());
+        assert!("gfs-dr,region=".parse::<ReplicaTarget>().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::grib::{Level, ProductInfo};
+use crate::param::Param;
+use crate::units::{format_size, SizeUnits};
+
+/// Messages seen for one parameter/level combination.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub messages: u64,
+    pub bytes: u64,
+    /// Messages matched by a route.
+    pub kept: u64,
+}
+
+/// Every parameter/level combination seen in the source, kept or dropped
+/// (`--filter-report`), to check the routes before a large backfill.
+#[derive(Debug, Default)]
+pub struct FilterReport {
+    entries: BTreeMap<(Param, Option<Level>), Tally>,
+}
+
+impl FilterReport {
+    /// Count a message of `bytes` bytes under each of its parameter/level combinations.
+    pub fn record(&mut self, products: &[ProductInfo], bytes: u64, kept: bool) {
+        let mut keys: Vec<_> = products.iter().map(|p| (Param::of(p), p.level)).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let tally = self.entries.entry(key).or_default();
+            tally.messages += 1;
+            tally.bytes += bytes;
+            tally.kept += kept as u64;
+        }
+    }
+
+    /// Add the counts of another report.
+    pub fn merge(&mut self, other: FilterReport) {
+        for (key, other) in other.entries {
+            let tally = self.entries.entry(key).or_default();
+            tally.messages += other.messages;
+            tally.bytes += other.bytes;
+            tally.kept += other.kept;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, param: Param, level: Option<Level>) -> Option<Tally> {
+        self.entries.get(&(param, level)).copied()
+    }
+
+    /// One line per combination, then the kept and dropped totals, with sizes in `units`.
+    pub fn render(&self, units: SizeUnits) -> String {
+        let mut text = format!(
+            "{:<10} {:<40} {:>9} {:>10}  Kept\n",
+            "Parameter", "Level", "Messages", "Size"
+        );
+        let mut kept = Tally::default();
+        let mut dropped = Tally::default();
+        for ((param, level), tally) in &self.entries {
+            let level = level.map_or_else(|| "-".to_string(), |l| l.to_string());
+            let status = match tally.kept {
+                0 => "no".to_string(),
+                n if n == tally.messages => "yes".to_string(),
+                n => format!("{n} of {}", tally.messages),
+            };
+            let _ = writeln!(
+                text,
+                "{:<10} {:<40} {:>9} {:>10}  {status}",
+                param.to_string(),
+                level,
+                tally.messages,
+                format_size(tally.bytes, units)
+            );
+
+            let share = |n: u64| tally.bytes * n / tally.messages;
+            kept.messages += tally.kept;
+            kept.bytes += share(tally.kept);
+            dropped.messages += tally.messages - tally.kept;
+            dropped.bytes += share(tally.messages - tally.kept);
+        }
+        let _ = writeln!(
+            text,
+            "Kept {} messages ({}), dropped {} messages ({})",
+            kept.messages,
+            format_size(kept.bytes, units),
+            dropped.messages,
+            format_size(dropped.bytes, units)
+        );
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grib::Surface;
+
+    fn product(number: u8, height: i32) -> ProductInfo {
+        ProductInfo {
+            discipline: 0,
+            template: 0,
+            category: 2,
+            number,
+            time: None,
+            level: Some(Level {
+                first: Surface {
+                    kind: 103,
+                    scale: 0,
+                    scaled_value: Some(height),
+                },
+                second: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_filter_report_tallies_and_merges() {
+        let mut report = FilterReport::default();
+        report.record(&[product(2, 10)], 100, true);
+        report.record(&[product(22, 10)], 50, false);
+
+        let mut other = FilterReport::default();
+        other.record(&[product(2, 10)], 120, true);
+        other.record(&[product(2, 100)], 80, false);
+        report.merge(other);
+
+        let ugrd = Param::new(0, 2, 2);
+        let tally = report.get(ugrd, product(2, 10).level).unwrap();
+        assert_eq!(
+            tally,
+            Tally {
+                messages: 2,
+                bytes: 220,
+                kept: 2
+            }
+        );
+        assert_eq!(report.get(ugrd, product(2, 100).level).unwrap().kept, 0);
+
+        // A message counts once under each combination, however its products are ordered
+        let mut message = FilterReport::default();
+        message.record(&[product(2, 10), product(3, 10), product(2, 10)], 10, true);
+        assert_eq!(message.get(ugrd, product(2, 10).level).unwrap().messages, 1);
+
+        let text = report.render(SizeUnits::Si);
+        assert!(text.contains("GUST"));
+        assert!(text.contains("100 m above ground"));
+        assert!(text.contains("Kept 2 messages (220 B), dropped 2 messages (130 B)"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::io;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Client, Response, StatusCode};
+
+use crate::limiter::RequestPacer;
+
+/// Reconnections allowed per source file before its download fails.
+pub const MAX_RESUMES: u32 = 5;
+
+/// Called with the offset and the error of each reconnection.
+type ResumeHook = dyn Fn(u64, &str) + Send + Sync;
+
+/// Download of a source file that, when the connection breaks, reconnects with a
+/// `Range` request for the bytes not received yet, so the file goes on from where
+/// it stopped rather than from zero. The bytes are yielded as the source sent
+/// them (compressed or not), the reconnection is invisible to what reads them.
+pub struct ResumableDownload {
+    http: Client,
+    url: String,
+    pacer: Option<Arc<RequestPacer>>,
+    /// `If-Range` value, for a file changed on the source to be sent whole
+    /// rather than spliced.
+    validator: Option<HeaderValue>,
+    total_size: Option<u64>,
+    max_resumes: u32,
+    on_resume: Box<ResumeHook>,
+}
+
+impl ResumableDownload {
+    pub fn new(http: Client, url: &str) -> Self {
+        Self {
+            http,
+            url: url.to_string(),
+            pacer: None,
+            validator: None,
+            total_size: None,
+            max_resumes: MAX_RESUMES,
+            on_resume: Box::new(|_, _| {}),
+        }
+    }
+
+    /// Wait for the slot of the pacer before reconnecting (`--request-interval`).
+    pub fn with_pacer(mut self, pacer: Option<Arc<RequestPacer>>) -> Self {
+        self.pacer = pacer;
+        self
+    }
+
+    /// Called with the offset and the error of each reconnection.
+    pub fn on_resume(mut self, f: impl Fn(u64, &str) + Send + Sync + 'static) -> Self {
+        self.on_resume = Box::new(f);
+        self
+    }
+
+    /// Stream the body of `response`, reconnecting on failure. A body ending
+    /// before its `Content-Length` counts as a failure.
+    pub fn stream(mut self, response: Response) -> BoxStream<'static, io::Result<Bytes>> {
+        self.validator = validator(response.headers());
+        self.total_size = response.content_length();
+        let state = State {
+            download: self,
+            body: response.bytes_stream().boxed(),
+            offset: 0,
+            resumes: 0,
+            done: false,
+        };
+        futures::stream::unfold(state, |mut state| async move {
+            if state.done {
+                return None;
+            }
+            loop {
+                let reason = match state.body.next().await {
+                    Some(Ok(chunk)) => {
+                        state.offset += chunk.len() as u64;
+                        return Some((Ok(chunk), state));
+                    }
+                    Some(Err(e)) => e.to_string(),
+                    None => match state.download.total_size {
+                        Some(total) if state.offset < total => {
+                            format!("connection closed at byte {} of {total}", state.offset)
+                        }
+                        _ => return None,
+                    },
+                };
+                if state.resumes >= state.download.max_resumes {
+                    state.done = true;
+                    return Some((Err(io::Error::other(reason)), state));
+                }
+                state.resumes += 1;
+                (state.download.on_resume)(state.offset, &reason);
+                match state.download.reopen(state.offset).await {
+                    Ok(body) => state.body = body,
+                    Err(e) => {
+                        state.done = true;
+                        let message =
+                            format!("{reason}; cannot resume at byte {}: {e}", state.offset);
+                        return Some((Err(io::Error::other(message)), state));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Request the file again from `offset`.
+    async fn reopen(
+        &self,
+        offset: u64,
+    ) -> Result<BoxStream<'static, reqwest::Result<Bytes>>, String> {
+        if let Some(pacer) = &self.pacer {
+            pacer.wait().await;
+        }
+        let mut request = self
+            .http
+            .get(&self.url)
+            .header(RANGE, format!("bytes={offset}-"));
+        if let Some(validator) = &self.validator {
+            request = request.header(IF_RANGE, validator.clone());
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {}
+            StatusCode::OK => return Err("the file changed on the source".to_string()),
+            status => return Err(format!("range request answered with {status}")),
+        }
+        let range = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range);
+        match range {
+            Some((start, total))
+                if start == offset && (total.is_none() || total == self.total_size) =>
+            {
+                Ok(response.bytes_stream().boxed())
+            }
+            _ => Err("the source answered another range".to_string()),
+        }
+    }
+}
+
+struct State {
+    download: ResumableDownload,
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+    /// Bytes received so far.
+    offset: u64,
+    resumes: u32,
+    done: bool,
+}
+
+/// `If-Range` value identifying the version of a file: its strong `ETag`, else
+/// its `Last-Modified` date (a weak `ETag` cannot be used for ranges).
+pub fn validator(headers: &HeaderMap) -> Option<HeaderValue> {
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .cloned()
+}
+
+/// First byte and complete length of a `Content-Range` header, e.g.
+/// `bytes 100-199/1000`; the length is `None` when unknown (`*`).
+pub fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let start: u64 = start.trim().parse().ok()?;
+    if end.trim().parse::<u64>().ok()? < start {
+        return None;
+    }
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_range() {
+        assert_eq!(
+            parse_content_range("bytes 400-999/1000"),
+            Some((400, Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 0-9/*"), Some((0, None)));
+        assert_eq!(parse_content_range("bytes 10-9/100"), None);
+        assert_eq!(parse_content_range("bytes */1000"), None);
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(validator(&headers), None);
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 01 Jan 2020 03:30:00 GMT".parse().unwrap(),
+        );
+        headers.insert(ETAG, "W/\"abc\"".parse().unwrap());
+        assert_eq!(
+            validator(&headers).unwrap(),
+            "Wed, 01 Jan 2020 03:30:00 GMT"
+        );
+        headers.insert(ETAG, "\"abc\"".parse().unwrap());
+        assert_eq!(validator(&headers).unwrap(), "\"abc\"");
+    }
+}
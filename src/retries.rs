@@ -0,0 +1,174 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// One retried attempt (`--retry-log`), a line of the JSON Lines log, to tell a
+/// run slowed by source throttling from one slowed by bandwidth.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryRecord {
+    pub time: DateTime<Utc>,
+    /// Task retried, e.g. `2020-01-01 00 f000`.
+    pub task: String,
+    /// 1-based number of the attempt that failed.
+    pub attempt: u32,
+    /// Category of the failure (`Error::kind()`).
+    pub cause: String,
+    /// HTTP status of the source, if it answered.
+    pub status: Option<u16>,
+    pub error: String,
+    /// Wait before the next attempt.
+    pub backoff_seconds: f64,
+    /// Cycles allowed in parallel after the failure (reduced when throttled).
+    pub concurrency: usize,
+}
+
+impl RetryRecord {
+    pub fn new(
+        task: String,
+        attempt: u32,
+        error: &Error,
+        backoff: Duration,
+        concurrency: usize,
+    ) -> Self {
+        Self {
+            time: Utc::now(),
+            task,
+            attempt,
+            cause: error.kind().to_string(),
+            status: error.status(),
+            error: error.to_string(),
+            backoff_seconds: backoff.as_secs_f64(),
+            concurrency,
+        }
+    }
+}
+
+/// A file failing for good once the end-of-run retries are spent, a line of the
+/// dead-letter file (`--dead-letter`), with what is needed to retry it by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub time: DateTime<Utc>,
+    /// Task that failed, e.g. `2020-01-01 00 f000`.
+    pub task: String,
+    /// URLs of its source files.
+    pub sources: Vec<String>,
+    /// Times the file was queued, the first pass included (`--requeue-rounds` + 1 at most).
+    pub rounds: u32,
+    /// Category of the failure (`Error::kind()`), `other` outside the pipeline.
+    pub cause: String,
+    pub status: Option<u16>,
+    /// The error with its context chain.
+    pub error: String,
+}
+
+impl DeadLetter {
+    pub fn new(task: String, sources: Vec<String>, rounds: u32, error: &anyhow::Error) -> Self {
+        let pipeline = error.downcast_ref::<Error>();
+        Self {
+            time: Utc::now(),
+            task,
+            sources,
+            rounds,
+            cause: pipeline.map_or("other", Error::kind).to_string(),
+            status: pipeline.and_then(Error::status),
+            error: format!("{error:#}"),
+        }
+    }
+}
+
+/// Append-only JSON Lines log (`--retry-log`, `--dead-letter`), shared by the
+/// tasks of a run.
+pub struct RetryLog {
+    file: Mutex<File>,
+    path: String,
+}
+
+impl RetryLog {
+    /// Open the log at `path`, appending to it if it exists.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            path: path.display().to_string(),
+        })
+    }
+
+    /// Append a record as one line.
+    pub fn record(&self, record: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_vec(record).expect("log record serializes");
+        line.push(b'\n');
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(&line)
+            .with_context(|| format!("Failed to write to {}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_log() {
+        let path = std::env::temp_dir().join(format!("retries-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let throttled = Error::Throttled {
+            url: "https://example.com/f000".to_string(),
+            status: 429,
+        };
+        let record = RetryRecord::new(
+            "2020-01-01 00 f000".to_string(),
+            2,
+            &throttled,
+            Duration::from_secs(60),
+            1,
+        );
+        assert_eq!(record.cause, "throttled");
+        assert_eq!(record.status, Some(429));
+
+        let log = RetryLog::open(&path).unwrap();
+        log.record(&record).unwrap();
+        log.record(&record).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<RetryRecord>(lines[1]).unwrap(),
+            record
+        );
+        assert!(
+            lines[0].contains(r#""backoff_seconds":60.0"#),
+            "{}",
+            lines[0]
+        );
+
+        let error = anyhow::Error::from(throttled).context("Failed to produce 2020-01-01 00 f000");
+        let letter = DeadLetter::new(
+            "2020-01-01 00 f000".to_string(),
+            vec!["https://example.com/f000".to_string()],
+            3,
+            &error,
+        );
+        assert_eq!(letter.cause, "throttled");
+        assert_eq!(letter.status, Some(429));
+        assert!(letter
+            .error
+            .starts_with("Failed to produce 2020-01-01 00 f000: "));
+        let other = DeadLetter::new(String::new(), Vec::new(), 1, &anyhow::anyhow!("boom"));
+        assert_eq!((other.cause.as_str(), other.status), ("other", None));
+    }
+}
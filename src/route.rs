@@ -0,0 +1,133 @@
+use std::fmt;
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256};
+
+use crate::grib::ProductInfo;
+use crate::param::Param;
+use crate::s3::validate_prefix;
+
+/// Route used when none is given: wind components at the top of `--prefix`.
+pub const DEFAULT_ROUTE: &str = "UGRD,VGRD=";
+
+/// Sends the messages of some parameters to their own objects under a key prefix,
+/// so one pass over a source file can populate several datasets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub params: Vec<Param>,
+    /// Prefix relative to `--prefix` (may be empty).
+    pub prefix: String,
+}
+
+impl Route {
+    /// Whether a message with these products belongs to the route.
+    pub fn matches(&self, products: &[ProductInfo]) -> bool {
+        products
+            .iter()
+            .any(|product| self.params.contains(&Param::of(product)))
+    }
+
+    /// Short hash of the parameters the route keeps, whatever their order, recorded
+    /// on its objects (`filter-hash`) to tell those produced with another selection.
+    pub fn filter_hash(&self) -> String {
+        self.transformed_hash(&[])
+    }
+
+    /// `filter_hash()` of objects whose messages are also transformed, e.g.
+    /// `["bbox=-10,35,30,60"]`, so another crop makes them stale too. Without
+    /// transforms, the same as `filter_hash()`.
+    pub fn transformed_hash(&self, transforms: &[String]) -> String {
+        let mut params = self.params.clone();
+        params.sort();
+        params.dedup();
+        let params: Vec<_> = params.iter().map(Param::to_string).collect();
+        let mut filter = params.join(",");
+        for transform in transforms {
+            filter.push(';');
+            filter.push_str(transform);
+        }
+        format!("{:x}", Sha256::digest(filter))[..16].to_string()
+    }
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    /// Parse `PARAM[,PARAM...]=PREFIX`, e.g. `UGRD,VGRD=wind/` or `GUST=gust/`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (params, prefix) = s.split_once('=').ok_or_else(|| {
+            format!("route '{s}' must be PARAM[,PARAM...]=PREFIX (e.g. UGRD,VGRD=wind/)")
+        })?;
+        let params = params
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Param>, _>>()?;
+        validate_prefix(prefix)?;
+
+        Ok(Self {
+            params,
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<_> = self.params.iter().map(Param::to_string).collect();
+        write!(f, "{}={}", params.join(","), self.prefix)
+    }
+}
+
+/// Check that no two routes write to the same prefix.
+pub fn validate_routes(routes: &[Route]) -> Result<(), String> {
+    for (i, route) in routes.iter().enumerate() {
+        let prefix = route.prefix.trim_end_matches('/');
+        if routes[..i]
+            .iter()
+            .any(|other| other.prefix.trim_end_matches('/') == prefix)
+        {
+            return Err(format!(
+                "several routes write to prefix '{}'; give each route its own prefix",
+                route.prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_parsing() {
+        let route: Route = "UGRD,VGRD=wind/".parse().unwrap();
+        assert_eq!(route.params, vec![Param::new(0, 2, 2), Param::new(0, 2, 3)]);
+        assert_eq!(route.prefix, "wind/");
+        assert_eq!(route.to_string(), "UGRD,VGRD=wind/");
+
+        let default: Route = DEFAULT_ROUTE.parse().unwrap();
+        assert_eq!(default.prefix, "");
+
+        assert!("UGRD".parse::<Route>().unwrap_err().contains("PARAM"));
+        assert!("GUST=/gust".parse::<Route>().is_err());
+
+        let gust: Route = "GUST=gust".parse().unwrap();
+        assert!(validate_routes(&[route.clone(), gust]).is_ok());
+        assert!(validate_routes(&[route.clone(), "GUST=wind".parse().unwrap()]).is_err());
+
+        let reordered: Route = "VGRD,UGRD,UGRD=".parse().unwrap();
+        assert_eq!(reordered.filter_hash(), route.filter_hash());
+        assert_ne!(
+            default.filter_hash(),
+            "GUST=".parse::<Route>().unwrap().filter_hash()
+        );
+        assert_eq!(route.transformed_hash(&[]), route.filter_hash());
+        let cropped = route.transformed_hash(&["bbox=-10,35,30,60".to_string()]);
+        assert_ne!(cropped, route.filter_hash());
+        assert_ne!(
+            cropped,
+            route.transformed_hash(&["bbox=0,35,30,60".to_string()])
+        );
+    }
+}
@@ -1,54 +1,920 @@
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::limiter::BandwidthLimiter;
+use crate::template::check_key_path;
+use crate::units::parse_duration;
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{RequestChecksumCalculation, ResponseChecksumValidation};
+use aws_sdk_s3::error::SdkError;
 use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, MetadataDirective};
 use aws_sdk_s3::Client;
 use bytes::Bytes;
 
 /// Minimum part size for S3 multipart upload (5 MB).
-const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// S3 storage class for uploaded objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum StorageClass {
+    #[default]
+    Standard,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+    GlacierIr,
+    Glacier,
+    DeepArchive,
+}
+
+impl StorageClass {
+    /// S3 API name of the storage class (e.g. `STANDARD_IA`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::StandardIa => "STANDARD_IA",
+            StorageClass::OnezoneIa => "ONEZONE_IA",
+            StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+            StorageClass::GlacierIr => "GLACIER_IR",
+            StorageClass::Glacier => "GLACIER",
+            StorageClass::DeepArchive => "DEEP_ARCHIVE",
+        }
+    }
+
+    fn to_sdk(self) -> aws_sdk_s3::types::StorageClass {
+        aws_sdk_s3::types::StorageClass::from(self.as_str())
+    }
+
+    /// Approximate us-east-1 list prices: (USD per GB-month, USD per 1,000 PUT requests).
+    pub fn list_prices_usd(self) -> (f64, f64) {
+        match self {
+            StorageClass::Standard => (0.023, 0.005),
+            StorageClass::StandardIa => (0.0125, 0.01),
+            StorageClass::OnezoneIa => (0.01, 0.01),
+            StorageClass::IntelligentTiering => (0.023, 0.005),
+            StorageClass::GlacierIr => (0.004, 0.02),
+            StorageClass::Glacier => (0.0036, 0.03),
+            StorageClass::DeepArchive => (0.00099, 0.05),
+        }
+    }
+}
+
+/// Storage class of each object by the age of its cycle (`--archive-after`):
+/// backfilled cycles go straight to an infrequent-access class instead of being
+/// moved there later by a lifecycle transition, billed per object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoragePolicy {
+    /// Class of the recent cycles (`--storage-class`).
+    pub recent: StorageClass,
+    /// Cycles initialized before this time go to this class.
+    pub archive: Option<(chrono::NaiveDateTime, StorageClass)>,
+}
+
+impl StoragePolicy {
+    /// Write every object with `class`.
+    pub fn fixed(class: StorageClass) -> Self {
+        Self {
+            recent: class,
+            archive: None,
+        }
+    }
+
+    /// Write the cycles older than `days` at `now` with `archive`, the others with
+    /// `recent`.
+    pub fn by_age(
+        recent: StorageClass,
+        days: u32,
+        archive: StorageClass,
+        now: chrono::NaiveDateTime,
+    ) -> Self {
+        Self {
+            recent,
+            archive: Some((now - chrono::TimeDelta::days(days.into()), archive)),
+        }
+    }
+
+    /// Class of the objects of the cycle initialized at `cycle_time`.
+    pub fn class_for(&self, cycle_time: chrono::NaiveDateTime) -> StorageClass {
+        match self.archive {
+            Some((cutoff, archive)) if cycle_time < cutoff => archive,
+            _ => self.recent,
+        }
+    }
+}
+
+/// Tags of the objects uploaded by a run, for cost allocation reports
+/// (`run-id`, and `cost-center` with `--cost-center`), and the bytes it uploaded.
+#[derive(Debug, Default)]
+pub struct RunTags {
+    tags: Vec<(String, String)>,
+    uploaded: AtomicU64,
+}
+
+impl RunTags {
+    pub fn new(run_id: &str, cost_center: Option<&str>) -> Self {
+        let mut tags = vec![("run-id".to_string(), run_id.to_string())];
+        if let Some(cost_center) = cost_center {
+            tags.push(("cost-center".to_string(), cost_center.to_string()));
+        }
+        Self {
+            tags,
+            uploaded: AtomicU64::new(0),
+        }
+    }
+
+    /// Count the uploaded bytes without tagging the objects (`--no-tags`, or a
+    /// service without object tagging).
+    pub fn untagged() -> Self {
+        Self::default()
+    }
+
+    /// Value of a tag, if set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `x-amz-tagging` header: URL-encoded `key=value` pairs joined by `&`.
+    fn header(&self) -> Option<String> {
+        (!self.tags.is_empty()).then(|| {
+            self.tags
+                .iter()
+                .map(|(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+                .collect::<Vec<_>>()
+                .join("&")
+        })
+    }
+
+    /// Bytes uploaded so far (object data, not counting manifests).
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded.load(Ordering::Relaxed)
+    }
+
+    fn add_uploaded(&self, bytes: usize) {
+        self.uploaded.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Same tags, counting bytes apart (uploads that aren't part of the run's data).
+    fn detached(&self) -> Self {
+        Self {
+            tags: self.tags.clone(),
+            uploaded: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Check a tag value (`--cost-center`, `--run-id`) against the S3 tagging rules.
+pub fn validate_tag_value(value: &str) -> Result<String, String> {
+    if value.chars().count() > 256 {
+        return Err(format!("tag value '{value}' is longer than 256 characters"));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|c| !(c.is_alphanumeric() || c.is_whitespace() || "+-=._:/@".contains(*c)))
+    {
+        return Err(format!(
+            "tag value '{value}' contains '{c}'; only letters, digits, spaces and + - = . _ : / @ are allowed"
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// S3-compatible service behind `--endpoint-url`, whose quirks the client and
+/// the uploader work around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum S3Compat {
+    /// AWS S3, MinIO and other close implementations.
+    #[default]
+    S3,
+    /// Cloudflare R2.
+    R2,
+    /// Backblaze B2.
+    B2,
+}
+
+impl S3Compat {
+    /// Service of an endpoint, from its host name.
+    pub fn detect(endpoint_url: Option<&str>) -> Self {
+        let host = endpoint_url
+            .map(|url| url.split("://").last().unwrap_or(url))
+            .and_then(|rest| rest.split(['/', ':']).next())
+            .unwrap_or_default();
+        if host.ends_with(".r2.cloudflarestorage.com") {
+            S3Compat::R2
+        } else if host.ends_with(".backblazeb2.com") {
+            S3Compat::B2
+        } else {
+            S3Compat::S3
+        }
+    }
+
+    /// Signing region for a custom endpoint: R2 expects `auto`, B2 the region
+    /// in its host name (`s3.<region>.backblazeb2.com`).
+    pub fn region(self, endpoint_url: &str) -> String {
+        match self {
+            S3Compat::S3 => "us-east-1".to_string(),
+            S3Compat::R2 => "auto".to_string(),
+            S3Compat::B2 => endpoint_url
+                .split("://")
+                .last()
+                .and_then(|host| host.strip_prefix("s3."))
+                .and_then(|host| host.split('.').next())
+                .unwrap_or("us-east-1")
+                .to_string(),
+        }
+    }
+
+    /// Check that the service offers a storage class: R2 has Standard and
+    /// Infrequent Access, B2 a single class.
+    pub fn check_storage_class(self, storage_class: StorageClass) -> Result<(), String> {
+        let supported = match self {
+            S3Compat::S3 => true,
+            S3Compat::R2 => matches!(
+                storage_class,
+                StorageClass::Standard | StorageClass::StandardIa
+            ),
+            S3Compat::B2 => storage_class == StorageClass::Standard,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(format!(
+                "storage class {} is not available on {self:?}",
+                storage_class.as_str()
+            ))
+        }
+    }
+
+    /// `x-amz-storage-class` to send, B2 rejecting the header.
+    fn storage_class(self, storage_class: StorageClass) -> Option<aws_sdk_s3::types::StorageClass> {
+        (self != S3Compat::B2).then(|| storage_class.to_sdk())
+    }
+
+    /// Whether objects can be tagged on upload (`x-amz-tagging`), which R2 and B2
+    /// do not implement.
+    pub fn supports_tagging(self) -> bool {
+        self == S3Compat::S3
+    }
+
+    /// Whether objects smaller than a part are sent with a single PutObject rather
+    /// than a one-part multipart upload (which may need an empty part).
+    fn put_small_objects(self) -> bool {
+        self != S3Compat::S3
+    }
+
+    /// Whether CompleteMultipartUpload honors `If-None-Match: *`, refusing to
+    /// replace an existing object.
+    fn supports_conditional_writes(self) -> bool {
+        self == S3Compat::S3
+    }
+}
+
+/// Check a bucket name against the S3 naming rules.
+pub fn validate_bucket_name(bucket: &str) -> Result<(), String> {
+    if bucket.len() < 3 || bucket.len() > 63 {
+        return Err(format!(
+            "bucket name '{bucket}' must be between 3 and 63 characters long"
+        ));
+    }
+    if let Some(c) = bucket
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '.' || *c == '-'))
+    {
+        return Err(format!(
+            "bucket name '{bucket}' contains '{c}'; only lowercase letters, digits, '.' and '-' are allowed"
+        ));
+    }
+    let first_last = [bucket.as_bytes()[0], bucket.as_bytes()[bucket.len() - 1]];
+    if !first_last.iter().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(format!(
+            "bucket name '{bucket}' must start and end with a letter or digit"
+        ));
+    }
+    if bucket.contains("..") {
+        return Err(format!(
+            "bucket name '{bucket}' must not contain consecutive dots"
+        ));
+    }
+    if bucket.split('.').count() == 4 && bucket.split('.').all(|p| p.parse::<u8>().is_ok()) {
+        return Err(format!(
+            "bucket name '{bucket}' must not be formatted as an IP address"
+        ));
+    }
+    Ok(())
+}
+
+/// Check a key prefix (e.g. `wind/2020/` or `model=gfs/`).
+pub fn validate_prefix(prefix: &str) -> Result<(), String> {
+    check_key_path("prefix", prefix)
+}
+
+/// Upload a small object in a single PUT.
+pub async fn put_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    body: Vec<u8>,
+    content_type: &str,
+) -> Result<()> {
+    client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .content_type(content_type)
+        .body(ByteStream::from(body))
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "PutObject", e))?;
+    Ok(())
+}
+
+/// How the SDK retries failed S3 requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RetryMode {
+    /// Exponential backoff with jitter.
+    #[default]
+    Standard,
+    /// Standard, plus client-side rate limiting once S3 throttles.
+    Adaptive,
+}
+
+/// Endpoint, retry and timeout settings of the S3 client, for uploads from far
+/// away or over flaky links. Unset, they are the SDK's (`AWS_RETRY_MODE`,
+/// `AWS_MAX_ATTEMPTS` or the AWS profile's, else its defaults).
+#[derive(Debug, Clone, PartialEq, Eq, Default, clap::Args)]
+pub struct ClientSettings {
+    /// SDK retry mode for S3 requests (default: AWS_RETRY_MODE or the AWS
+    /// profile's, else standard)
+    #[arg(
+        id = "s3_retry_mode",
+        value_name = "RETRY_MODE",
+        long = "s3-retry-mode",
+        value_enum
+    )]
+    pub retry_mode: Option<RetryMode>,
+
+    /// Attempts per S3 request, including the first one (default:
+    /// AWS_MAX_ATTEMPTS or the AWS profile's, else 3)
+    #[arg(id = "s3_max_attempts", value_name = "MAX_ATTEMPTS", long = "s3-max-attempts", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: Option<u32>,
+
+    /// Time limit of an S3 request, retries included (e.g. 5m)
+    #[arg(id = "s3_operation_timeout", value_name = "OPERATION_TIMEOUT", long = "s3-operation-timeout", value_parser = parse_duration)]
+    pub operation_timeout: Option<Duration>,
+
+    /// Time limit of each attempt of an S3 request (e.g. 90s), so a stalled
+    /// connection is retried instead of waited on
+    #[arg(id = "s3_attempt_timeout", value_name = "ATTEMPT_TIMEOUT", long = "s3-attempt-timeout", value_parser = parse_duration)]
+    pub attempt_timeout: Option<Duration>,
+
+    /// Go through S3 Transfer Acceleration (enabled on the bucket), e.g. when
+    /// uploading from another continent
+    #[arg(long = "s3-accelerate", conflicts_with = "endpoint_url")]
+    pub accelerate: bool,
+
+    /// Use the dual-stack S3 endpoints, reachable over IPv6
+    #[arg(long = "s3-dualstack", conflicts_with = "endpoint_url")]
+    pub dualstack: bool,
+
+    /// Named AWS profile (~/.aws/config) to take the credentials and region from,
+    /// instead of AWS_PROFILE or the default one
+    #[arg(long)]
+    pub aws_profile: Option<String>,
+}
+
+impl ClientSettings {
+    /// Check that the bucket can be reached through the chosen endpoint.
+    pub fn check(&self, bucket: &str) -> Result<(), String> {
+        if self.accelerate && bucket.contains('.') {
+            return Err(format!(
+                "bucket '{bucket}' has dots in its name, which Transfer Acceleration does not support"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Retries of `base` (from the environment or the AWS profile), with the
+    /// ones given on the command line set.
+    pub fn retry_config(&self, base: Option<&RetryConfig>) -> RetryConfig {
+        let mut retries = base.cloned().unwrap_or_else(RetryConfig::standard);
+        if let Some(mode) = self.retry_mode {
+            retries = retries.with_retry_mode(match mode {
+                RetryMode::Standard => aws_sdk_s3::config::retry::RetryMode::Standard,
+                RetryMode::Adaptive => aws_sdk_s3::config::retry::RetryMode::Adaptive,
+            });
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            retries = retries.with_max_attempts(max_attempts);
+        }
+        retries
+    }
+
+    /// Timeouts of `base` (the SDK defaults), with the operation ones set.
+    pub fn timeout_config(&self, base: Option<&TimeoutConfig>) -> TimeoutConfig {
+        let mut timeouts = base.map_or_else(TimeoutConfig::builder, TimeoutConfig::to_builder);
+        if let Some(timeout) = self.operation_timeout {
+            timeouts = timeouts.operation_timeout(timeout);
+        }
+        if let Some(timeout) = self.attempt_timeout {
+            timeouts = timeouts.operation_attempt_timeout(timeout);
+        }
+        timeouts.build()
+    }
+}
+
+/// S3 client from the default credential chain, or for a custom endpoint.
+pub async fn client(
+    endpoint_url: Option<&str>,
+    compat: S3Compat,
+    settings: &ClientSettings,
+) -> Client {
+    let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+    if let Some(profile) = &settings.aws_profile {
+        loader = loader.profile_name(profile);
+    }
+    let aws_config = loader.load().await;
+    let mut s3_config = aws_sdk_s3::config::Builder::from(&aws_config)
+        .retry_config(settings.retry_config(aws_config.retry_config()))
+        .timeout_config(settings.timeout_config(aws_config.timeout_config()))
+        .accelerate(settings.accelerate)
+        .use_dual_stack(settings.dualstack);
+    if let Some(endpoint) = endpoint_url {
+        s3_config = s3_config
+            .endpoint_url(endpoint)
+            .region(aws_sdk_s3::config::Region::new(compat.region(endpoint)))
+            .force_path_style(true);
+    }
+    if compat != S3Compat::S3 {
+        // R2 and B2 reject or mishandle the flexible checksums the SDK adds by default
+        s3_config = s3_config
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired);
+    }
+    Client::from_conf(s3_config.build())
+}
+
+/// List every key under a prefix, following continuation tokens.
+pub async fn list_keys(client: &Client, bucket: &str, prefix: &str) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut pages = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .prefix(prefix)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| Error::from_sdk(prefix, "ListObjectsV2", e))?;
+        keys.extend(
+            page.contents()
+                .iter()
+                .filter_map(|object| object.key().map(str::to_string)),
+        );
+    }
+    Ok(keys)
+}
+
+/// User metadata of an object (`x-amz-meta-*` names without the prefix), or
+/// `None` if it does not exist.
+pub async fn head_metadata(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<HashMap<String, String>>> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(head) => Ok(Some(head.metadata().cloned().unwrap_or_default())),
+        Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(None),
+        Err(e) => Err(Error::from_sdk(key, "HeadObject", e)),
+    }
+}
+
+/// Size of an object, or `None` if it does not exist.
+pub async fn head_size(client: &Client, bucket: &str, key: &str) -> Result<Option<u64>> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(head) => Ok(Some(head.content_length().unwrap_or_default().max(0) as u64)),
+        Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(None),
+        Err(e) => Err(Error::from_sdk(key, "HeadObject", e)),
+    }
+}
+
+/// Download a whole object.
+pub async fn get_object(client: &Client, bucket: &str, key: &str) -> Result<Vec<u8>> {
+    let object = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "GetObject", e))?;
+    let body = object.body.collect().await.map_err(|e| Error::SinkError {
+        key: key.to_string(),
+        reason: format!("GetObject body: {e}"),
+    })?;
+    Ok(body.to_vec())
+}
+
+/// Download `length` bytes of an object from `offset` (a Range request).
+pub async fn get_range(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    offset: u64,
+    length: u64,
+) -> Result<Vec<u8>> {
+    let object = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .range(format!("bytes={offset}-{}", offset + length.max(1) - 1))
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "GetObject", e))?;
+    let body = object.body.collect().await.map_err(|e| Error::SinkError {
+        key: key.to_string(),
+        reason: format!("GetObject body: {e}"),
+    })?;
+    Ok(body.to_vec())
+}
+
+/// Delete an object.
+pub async fn delete_object(client: &Client, bucket: &str, key: &str) -> Result<()> {
+    client
+        .delete_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "DeleteObject", e))?;
+    Ok(())
+}
+
+/// Largest object S3 copies with a single CopyObject.
+const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Parts of the multipart copies of larger objects (10,000 parts cover 5 TiB).
+const COPY_PART_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Server-side copy of `source_bucket/key` to `bucket/dest_key`, with its
+/// metadata and tags, written with `storage_class`. The credentials of `client`
+/// must be able to read the source. Objects over 5 GiB, more than a CopyObject
+/// takes, are copied in parts (UploadPartCopy).
+pub async fn copy_object(
+    client: &Client,
+    source_bucket: &str,
+    key: &str,
+    bucket: &str,
+    dest_key: &str,
+    storage_class: StorageClass,
+    compat: S3Compat,
+) -> Result<()> {
+    let head = client
+        .head_object()
+        .bucket(source_bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "HeadObject", e))?;
+    let size = head.content_length().unwrap_or_default().max(0) as u64;
+    if size > MAX_COPY_SIZE {
+        let tagging = if compat.supports_tagging() {
+            source_tagging(client, source_bucket, key).await?
+        } else {
+            None
+        };
+        let upload = client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(dest_key)
+            .set_storage_class(compat.storage_class(storage_class))
+            .set_metadata(head.metadata().cloned())
+            .set_content_type(head.content_type().map(str::to_string))
+            .set_tagging(tagging)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(dest_key, "CreateMultipartUpload", e))?;
+        let upload_id = upload.upload_id().ok_or_else(|| Error::SinkError {
+            key: dest_key.to_string(),
+            reason: "no upload ID returned".to_string(),
+        })?;
+        let copied = copy_parts(
+            client,
+            source_bucket,
+            key,
+            bucket,
+            dest_key,
+            upload_id,
+            size,
+        )
+        .await;
+        if copied.is_err() {
+            // The error of the copy matters, not that of its cleanup
+            let _ = client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(dest_key)
+                .upload_id(upload_id)
+                .send()
+                .await;
+        }
+        return copied;
+    }
+
+    client
+        .copy_object()
+        .bucket(bucket)
+        .key(dest_key)
+        .copy_source(copy_source(source_bucket, key))
+        .set_storage_class(compat.storage_class(storage_class))
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(dest_key, "CopyObject", e))?;
+    Ok(())
+}
+
+/// `x-amz-tagging` header with the tags of an object, `None` if it has none.
+async fn source_tagging(client: &Client, bucket: &str, key: &str) -> Result<Option<String>> {
+    let tagging = client
+        .get_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(key, "GetObjectTagging", e))?;
+    let tags: Vec<_> = tagging
+        .tag_set()
+        .iter()
+        .map(|tag| format!("{}={}", url_encode(tag.key()), url_encode(tag.value())))
+        .collect();
+    Ok((!tags.is_empty()).then(|| tags.join("&")))
+}
+
+/// Copy the `size` bytes of `source_bucket/key` into the multipart upload
+/// `upload_id` of `bucket/dest_key`, `COPY_PART_SIZE` at a time, and complete it.
+async fn copy_parts(
+    client: &Client,
+    source_bucket: &str,
+    key: &str,
+    bucket: &str,
+    dest_key: &str,
+    upload_id: &str,
+    size: u64,
+) -> Result<()> {
+    let mut parts = Vec::new();
+    for (offset, part_number) in (0..size).step_by(COPY_PART_SIZE as usize).zip(1..) {
+        let end = (offset + COPY_PART_SIZE).min(size) - 1;
+        let part = client
+            .upload_part_copy()
+            .bucket(bucket)
+            .key(dest_key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .copy_source(copy_source(source_bucket, key))
+            .copy_source_range(format!("bytes={offset}-{end}"))
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(dest_key, &format!("UploadPartCopy {part_number}"), e))?;
+        let e_tag = part
+            .copy_part_result()
+            .and_then(|result| result.e_tag())
+            .ok_or_else(|| Error::SinkError {
+                key: dest_key.to_string(),
+                reason: format!("no ETag returned for part {part_number}"),
+            })?;
+        parts.push(
+            CompletedPart::builder()
+                .e_tag(e_tag)
+                .part_number(part_number)
+                .build(),
+        );
+    }
+    client
+        .complete_multipart_upload()
+        .bucket(bucket)
+        .key(dest_key)
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk(dest_key, "CompleteMultipartUpload", e))?;
+    Ok(())
+}
+
+/// Percent-encode every byte but unreserved characters, and `/` if `keep_slash`.
+fn percent_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// URL-encoded component of a query string, e.g. a tag key or value.
+fn url_encode(s: &str) -> String {
+    percent_encode(s, false)
+}
+
+/// `x-amz-copy-source` value for an object: bucket and URL-encoded key.
+fn copy_source(bucket: &str, key: &str) -> String {
+    format!("{bucket}/{}", percent_encode(key, true))
+}
+
+/// Name of the object `preflight()` writes, under the checked prefix.
+pub const PREFLIGHT_NAME: &str = ".preflight";
+
+/// Check that the credentials may write under `prefix` with the requests a run
+/// makes, before its first download: a multipart upload aborted after one part
+/// (CreateMultipartUpload, UploadPart, AbortMultipartUpload), another one
+/// completed (CompleteMultipartUpload, or PutObject where small objects are
+/// sent whole) and a PutObject, with the run's storage class and tags.
+///
+/// Both write a few bytes to `<prefix>/.preflight`, deleted at the end when
+/// allowed (runs don't need DeleteObject). S3 has no dry-run mode for writes.
+pub async fn preflight(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+    storage_class: StorageClass,
+    compat: S3Compat,
+    tags: Option<&RunTags>,
+) -> Result<()> {
+    let prefix = prefix.trim_end_matches('/');
+    let key = if prefix.is_empty() {
+        PREFLIGHT_NAME.to_string()
+    } else {
+        format!("{prefix}/{PREFLIGHT_NAME}")
+    };
+    let tags = tags.map(|tags| Arc::new(tags.detached()));
+    let uploader = || {
+        S3MultipartUploader::new(client.clone(), bucket, &key, storage_class, compat)
+            .with_tags(tags.clone())
+    };
+
+    let mut aborted = uploader();
+    aborted.upload_id().await?;
+    aborted.buffer.push(0);
+    let part = aborted.flush_part(1).await;
+    aborted.abort().await?;
+    part?;
+
+    let mut completed = uploader();
+    completed.write(b"preflight").await?;
+    completed.complete().await?;
+
+    put_object(client, bucket, &key, b"preflight".to_vec(), "text/plain").await?;
+    let _ = delete_object(client, bucket, &key).await;
+    Ok(())
+}
 
 /// S3 multipart uploader that buffers data and uploads in chunks.
+///
+/// The multipart upload is created lazily when the first part is flushed,
+/// so object metadata can still be set after construction.
 pub struct S3MultipartUploader {
     client: Client,
     bucket: String,
     key: String,
-    upload_id: String,
+    storage_class: StorageClass,
+    compat: S3Compat,
+    metadata: HashMap<String, String>,
+    /// Metadata changed after the upload was created, to apply when completing.
+    late_metadata: bool,
+    upload_id: Option<String>,
     parts: Vec<CompletedPart>,
     buffer: Vec<u8>,
+    /// Largest `buffer` length so far.
+    peak_buffered: usize,
     part_number: i32,
+    bandwidth: Option<Arc<BandwidthLimiter>>,
+    tags: Option<Arc<RunTags>>,
 }
 
 impl S3MultipartUploader {
-    /// Create a new multipart upload.
-    pub async fn new(client: Client, bucket: &str, key: &str) -> Result<Self> {
-        let create = client
+    /// Prepare a new multipart upload.
+    pub fn new(
+        client: Client,
+        bucket: &str,
+        key: &str,
+        storage_class: StorageClass,
+        compat: S3Compat,
+    ) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            storage_class,
+            compat,
+            metadata: HashMap::new(),
+            late_metadata: false,
+            upload_id: None,
+            parts: Vec::new(),
+            buffer: Vec::with_capacity(MIN_PART_SIZE * 2),
+            peak_buffered: 0,
+            part_number: 1,
+            bandwidth: None,
+            tags: None,
+        }
+    }
+
+    /// Pace the uploads of parts (and single PUTs) through `limiter`.
+    pub fn with_bandwidth_limit(mut self, limiter: Option<Arc<BandwidthLimiter>>) -> Self {
+        self.bandwidth = limiter;
+        self
+    }
+
+    /// Tag the object with the run's tags and count its bytes in them.
+    pub fn with_tags(mut self, tags: Option<Arc<RunTags>>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    fn tagging(&self) -> Option<String> {
+        self.tags.as_ref().and_then(|tags| tags.header())
+    }
+
+    /// New upload to `key` with the same settings and the metadata set before
+    /// the first message.
+    pub fn continuation(&self, key: &str) -> Self {
+        let mut uploader = Self::new(
+            self.client.clone(),
+            &self.bucket,
+            key,
+            self.storage_class,
+            self.compat,
+        );
+        uploader.metadata = self.metadata.clone();
+        uploader
+            .with_bandwidth_limit(self.bandwidth.clone())
+            .with_tags(self.tags.clone())
+    }
+
+    /// Storage class the object is written with.
+    pub fn storage_class(&self) -> StorageClass {
+        self.storage_class
+    }
+
+    /// Set a user metadata entry (`x-amz-meta-<name>`) on the object. Once the
+    /// first part has been flushed, the change is applied by `complete()`, which
+    /// then copies the object onto itself.
+    pub fn set_metadata(&mut self, name: &str, value: String) {
+        let previous = self.metadata.insert(name.to_string(), value);
+        if self.upload_id.is_some() && previous.as_ref() != self.metadata.get(name) {
+            self.late_metadata = true;
+        }
+    }
+
+    /// Create the multipart upload if not done yet and return its ID.
+    async fn upload_id(&mut self) -> Result<String> {
+        if let Some(upload_id) = &self.upload_id {
+            return Ok(upload_id.clone());
+        }
+
+        let create = self
+            .client
             .create_multipart_upload()
-            .bucket(bucket)
-            .key(key)
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .set_storage_class(self.compat.storage_class(self.storage_class))
+            .set_metadata(Some(self.metadata.clone()).filter(|m| !m.is_empty()))
+            .set_tagging(self.tagging())
             .send()
             .await
-            .context("Failed to create multipart upload")?;
+            .map_err(|e| Error::from_sdk(&self.key, "CreateMultipartUpload", e))?;
 
         let upload_id = create
             .upload_id()
-            .context("No upload ID returned")?
+            .ok_or_else(|| self.sink_error("no upload ID returned"))?
             .to_string();
 
-        Ok(Self {
-            client,
-            bucket: bucket.to_string(),
-            key: key.to_string(),
-            upload_id,
-            parts: Vec::new(),
-            buffer: Vec::with_capacity(MIN_PART_SIZE * 2),
-            part_number: 1,
-        })
+        self.upload_id = Some(upload_id.clone());
+        Ok(upload_id)
+    }
+
+    fn sink_error(&self, reason: &str) -> Error {
+        Error::SinkError {
+            key: self.key.clone(),
+            reason: reason.to_string(),
+        }
     }
 
     /// Write data to the upload buffer.
     /// Automatically flushes parts when buffer exceeds minimum size.
     pub async fn write(&mut self, data: &[u8]) -> Result<()> {
         self.buffer.extend_from_slice(data);
+        self.peak_buffered = self.peak_buffered.max(self.buffer.len());
 
         while self.buffer.len() >= MIN_PART_SIZE {
             self.flush_part(MIN_PART_SIZE).await?;
@@ -56,23 +922,39 @@ impl S3MultipartUploader {
         Ok(())
     }
 
+    /// Largest number of bytes buffered at once, before a part was flushed.
+    pub fn peak_buffered(&self) -> usize {
+        self.peak_buffered
+    }
+
     /// Flush a part of the specified size from the buffer.
     async fn flush_part(&mut self, size: usize) -> Result<()> {
+        let upload_id = self.upload_id().await?;
         let part_data: Vec<u8> = self.buffer.drain(..size).collect();
+        if let Some(limiter) = &self.bandwidth {
+            limiter.acquire(part_data.len() as u64).await;
+        }
 
         let resp = self
             .client
             .upload_part()
             .bucket(&self.bucket)
             .key(&self.key)
-            .upload_id(&self.upload_id)
+            .upload_id(&upload_id)
             .part_number(self.part_number)
             .body(ByteStream::from(Bytes::from(part_data)))
             .send()
             .await
-            .with_context(|| format!("Failed to upload part {}", self.part_number))?;
+            .map_err(|e| {
+                Error::from_sdk(&self.key, &format!("UploadPart {}", self.part_number), e)
+            })?;
+        if let Some(tags) = &self.tags {
+            tags.add_uploaded(size);
+        }
 
-        let e_tag = resp.e_tag().context("No ETag returned for part")?;
+        let e_tag = resp
+            .e_tag()
+            .ok_or_else(|| self.sink_error("no ETag returned for part"))?;
 
         self.parts.push(
             CompletedPart::builder()
@@ -87,33 +969,53 @@ impl S3MultipartUploader {
 
     /// Complete the multipart upload.
     /// Flushes any remaining buffered data as the final part.
-    pub async fn complete(mut self) -> Result<()> {
-        // Flush remaining buffer as final part
-        if !self.buffer.is_empty() {
-            let remaining = self.buffer.len();
-            self.flush_part(remaining).await?;
+    pub async fn complete(self) -> Result<()> {
+        if self.upload_id.is_none() && self.compat.put_small_objects() {
+            return self.put().await;
+        }
+        if !self.late_metadata {
+            return self.complete_upload(false).await;
         }
 
-        // S3 requires at least one part
-        if self.parts.is_empty() {
-            // Upload an empty part if no data was written
-            self.client
-                .upload_part()
-                .bucket(&self.bucket)
-                .key(&self.key)
-                .upload_id(&self.upload_id)
-                .part_number(1)
-                .body(ByteStream::from(Bytes::new()))
-                .send()
-                .await
-                .context("Failed to upload empty part")?;
+        let client = self.client.clone();
+        let (bucket, key) = (self.bucket.clone(), self.key.clone());
+        let storage_class = self.compat.storage_class(self.storage_class);
+        let metadata = self.metadata.clone();
+        self.complete_upload(false).await?;
+        // Tags are copied along (default tagging directive)
+        client
+            .copy_object()
+            .bucket(&bucket)
+            .key(&key)
+            .copy_source(copy_source(&bucket, &key))
+            .metadata_directive(MetadataDirective::Replace)
+            .set_metadata(Some(metadata))
+            .set_storage_class(storage_class)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(&key, "CopyObject", e))?;
+        Ok(())
+    }
+
+    /// Complete the multipart upload, only if no object exists at its key when
+    /// `if_absent` (where the service supports conditional writes).
+    async fn complete_upload(mut self, if_absent: bool) -> Result<()> {
+        // Flush remaining buffer as final part.
+        // S3 requires at least one part, so an empty part is uploaded if no data was written.
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            let remaining = self.buffer.len();
+            self.flush_part(remaining).await?;
         }
 
+        let upload_id = self.upload_id().await?;
         self.client
             .complete_multipart_upload()
             .bucket(&self.bucket)
             .key(&self.key)
-            .upload_id(&self.upload_id)
+            .upload_id(upload_id)
+            .set_if_none_match(
+                (if_absent && self.compat.supports_conditional_writes()).then(|| "*".to_string()),
+            )
             .multipart_upload(
                 CompletedMultipartUpload::builder()
                     .set_parts(Some(self.parts))
@@ -121,23 +1023,282 @@ impl S3MultipartUploader {
             )
             .send()
             .await
-            .context("Failed to complete multipart upload")?;
+            .map_err(|e| Error::from_sdk(&self.key, "CompleteMultipartUpload", e))?;
 
         Ok(())
     }
 
+    /// Upload the buffered data as a whole object, for objects smaller than a part.
+    async fn put(self) -> Result<()> {
+        let (size, tagging) = (self.buffer.len(), self.tagging());
+        if let Some(limiter) = &self.bandwidth {
+            limiter.acquire(size as u64).await;
+        }
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .set_storage_class(self.compat.storage_class(self.storage_class))
+            .set_metadata(Some(self.metadata).filter(|m| !m.is_empty()))
+            .set_tagging(tagging)
+            .body(ByteStream::from(self.buffer))
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(&self.key, "PutObject", e))?;
+        if let Some(tags) = &self.tags {
+            tags.add_uploaded(size);
+        }
+        Ok(())
+    }
+
+    /// Keep what was uploaded before a late failure as `partial_key`, with
+    /// `extra_metadata` added, never replacing an object at the original key.
+    ///
+    /// Data not flushed yet goes straight to `partial_key`. Flushed parts belong
+    /// to the upload of the original key and S3 cannot rename them: they are
+    /// completed there only if no object exists (checked, then enforced with
+    /// If-None-Match), copied to `partial_key` and deleted. If an object exists,
+    /// the upload is aborted and nothing is kept.
+    pub async fn complete_as_partial(
+        mut self,
+        partial_key: &str,
+        extra_metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        self.metadata.extend(extra_metadata);
+        if self.upload_id.is_none() {
+            self.key = partial_key.to_string();
+            return self.put().await;
+        }
+
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let key = self.key.clone();
+        let storage_class = self.compat.storage_class(self.storage_class);
+        let metadata = self.metadata.clone();
+
+        if head_size(&client, &bucket, &key).await?.is_some() {
+            self.abort().await?;
+            return Err(Error::SinkError {
+                key: partial_key.to_string(),
+                reason: format!("{key} exists, the partial output would replace it"),
+            });
+        }
+        self.complete_upload(true).await?;
+
+        let head = client
+            .head_object()
+            .bucket(&bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(&key, "HeadObject", e))?;
+        client
+            .copy_object()
+            .bucket(&bucket)
+            .key(partial_key)
+            .copy_source(copy_source(&bucket, &key))
+            .metadata_directive(MetadataDirective::Replace)
+            .set_metadata(Some(metadata))
+            .set_content_type(head.content_type().map(str::to_string))
+            .set_storage_class(storage_class)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk(partial_key, "CopyObject", e))?;
+
+        delete_object(&client, &bucket, &key).await
+    }
+
     /// Abort the multipart upload.
     /// Call this if an error occurs to clean up incomplete uploads.
     pub async fn abort(self) -> Result<()> {
+        // Nothing to clean up if no part was ever flushed
+        let Some(upload_id) = self.upload_id else {
+            return Ok(());
+        };
+
         self.client
             .abort_multipart_upload()
             .bucket(&self.bucket)
             .key(&self.key)
-            .upload_id(&self.upload_id)
+            .upload_id(upload_id)
             .send()
             .await
-            .context("Failed to abort multipart upload")?;
+            .map_err(|e| Error::from_sdk(&self.key, "AbortMultipartUpload", e))?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_name_rules() {
+        assert!(validate_bucket_name("gfs-wind").is_ok());
+        assert!(validate_bucket_name("my.gfs.archive-2020").is_ok());
+
+        assert!(validate_bucket_name("ab").is_err());
+        assert!(validate_bucket_name("GFS_Wind")
+            .unwrap_err()
+            .contains("contains 'G'"));
+        assert!(validate_bucket_name("-gfs").is_err());
+        assert!(validate_bucket_name("gfs..wind").is_err());
+        assert!(validate_bucket_name("192.168.1.10").is_err());
+    }
+
+    #[test]
+    fn test_metadata_set_late() {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(aws_config::BehaviorVersion::latest())
+            .build();
+        let mut uploader = S3MultipartUploader::new(
+            Client::from_conf(config),
+            "gfs-wind",
+            "wind.grb2",
+            StorageClass::Standard,
+            S3Compat::S3,
+        );
+        uploader.set_metadata("model", "gfs".to_string());
+        assert!(!uploader.late_metadata);
+
+        // Once the upload exists, only an actual change is applied on completion
+        uploader.upload_id = Some("upload".to_string());
+        uploader.set_metadata("model", "gfs".to_string());
+        assert!(!uploader.late_metadata);
+        uploader.set_metadata("valid-time-max", "2020-01-01T06:00:00Z".to_string());
+        assert!(uploader.late_metadata);
+        assert_eq!(uploader.metadata.len(), 2);
+    }
+
+    #[test]
+    fn test_copy_source_encodes_key() {
+        assert_eq!(
+            copy_source("gfs-wind", "wind/2020/wind_20200101_00.grb2"),
+            "gfs-wind/wind/2020/wind_20200101_00.grb2"
+        );
+        assert_eq!(copy_source("b", "a b+c.grb2"), "b/a%20b%2Bc.grb2");
+        assert_eq!(
+            copy_source("b", "model=gfs/é.grb2"),
+            "b/model%3Dgfs/%C3%A9.grb2"
+        );
+    }
+
+    #[test]
+    fn test_run_tags() {
+        let tags = RunTags::new("20200101T000000Z", Some("team weather/gfs"));
+        assert_eq!(tags.get("cost-center"), Some("team weather/gfs"));
+        assert_eq!(
+            tags.header().unwrap(),
+            "run-id=20200101T000000Z&cost-center=team%20weather%2Fgfs"
+        );
+        assert_eq!(RunTags::untagged().header(), None);
+        tags.add_uploaded(100);
+        tags.add_uploaded(28);
+        assert_eq!(tags.uploaded(), 128);
+
+        assert!(validate_tag_value("ops:backfill-2020").is_ok());
+        assert!(validate_tag_value("a&b").is_err());
+        assert!(validate_tag_value(&"x".repeat(257)).is_err());
+    }
+
+    #[test]
+    fn test_compat_quirks() {
+        let r2 = "https://0123abcd.r2.cloudflarestorage.com";
+        let b2 = "https://s3.us-west-004.backblazeb2.com/";
+        assert_eq!(S3Compat::detect(Some(r2)), S3Compat::R2);
+        assert_eq!(S3Compat::detect(Some(b2)), S3Compat::B2);
+        assert_eq!(
+            S3Compat::detect(Some("http://localhost:9000")),
+            S3Compat::S3
+        );
+        assert_eq!(S3Compat::detect(None), S3Compat::S3);
+
+        assert_eq!(S3Compat::R2.region(r2), "auto");
+        assert_eq!(S3Compat::B2.region(b2), "us-west-004");
+        assert_eq!(S3Compat::S3.region("http://localhost:9000"), "us-east-1");
+
+        assert!(S3Compat::R2
+            .check_storage_class(StorageClass::StandardIa)
+            .is_ok());
+        assert!(S3Compat::R2
+            .check_storage_class(StorageClass::Glacier)
+            .is_err());
+        assert!(S3Compat::B2
+            .check_storage_class(StorageClass::StandardIa)
+            .is_err());
+        assert_eq!(S3Compat::B2.storage_class(StorageClass::Standard), None);
+        assert!(S3Compat::S3
+            .check_storage_class(StorageClass::DeepArchive)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_client_settings() {
+        let settings = ClientSettings {
+            retry_mode: Some(RetryMode::Adaptive),
+            max_attempts: None,
+            operation_timeout: None,
+            attempt_timeout: Some(Duration::from_secs(90)),
+            accelerate: true,
+            dualstack: false,
+            aws_profile: None,
+        };
+        assert!(settings.check("gfs-archive").is_ok());
+        assert!(settings.check("gfs.archive").unwrap_err().contains("dots"));
+        // The attempts not given stay those of the environment or profile
+        let base = RetryConfig::standard().with_max_attempts(8);
+        let retry = settings.retry_config(Some(&base));
+        assert_eq!(retry.mode(), aws_sdk_s3::config::retry::RetryMode::Adaptive);
+        assert_eq!(retry.max_attempts(), 8);
+        assert_eq!(ClientSettings::default().retry_config(Some(&base)), base);
+
+        let base = TimeoutConfig::builder()
+            .connect_timeout(Duration::from_secs(3))
+            .build();
+        let timeouts = settings.timeout_config(Some(&base));
+        assert_eq!(timeouts.connect_timeout(), Some(Duration::from_secs(3)));
+        assert_eq!(
+            timeouts.operation_attempt_timeout(),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(timeouts.operation_timeout(), None);
+    }
+
+    #[test]
+    fn test_storage_policy_by_age() {
+        let at = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let now = at("2024-03-31 12:00");
+        let policy =
+            StoragePolicy::by_age(StorageClass::Standard, 30, StorageClass::StandardIa, now);
+        assert_eq!(
+            policy.class_for(at("2024-03-30 00:00")),
+            StorageClass::Standard
+        );
+        assert_eq!(
+            policy.class_for(at("2024-03-01 12:00")),
+            StorageClass::Standard
+        );
+        assert_eq!(
+            policy.class_for(at("2024-03-01 06:00")),
+            StorageClass::StandardIa
+        );
+        let fixed = StoragePolicy::fixed(StorageClass::GlacierIr);
+        assert_eq!(
+            fixed.class_for(at("2000-01-01 00:00")),
+            StorageClass::GlacierIr
+        );
+    }
+
+    #[test]
+    fn test_prefix_rules() {
+        assert!(validate_prefix("").is_ok());
+        assert!(validate_prefix("wind/2020/").is_ok());
+        assert!(validate_prefix("/wind")
+            .unwrap_err()
+            .contains("must not start with '/'"));
+        assert!(validate_prefix("wind//2020").is_err());
+        assert!(validate_prefix("model=gfs/run date=2020-01-01/").is_ok());
+        assert!(validate_prefix("wind/./2020/").is_err());
+    }
+}
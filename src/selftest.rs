@@ -0,0 +1,135 @@
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::grib::Grib2StreamParser;
+use crate::manifest::Manifest;
+
+/// Columns and rows of the fixture's grid.
+const NI: u32 = 4;
+const NJ: u32 = 2;
+
+/// GRIB2 message of parameter 0.`category`.`number` at `height` m above ground,
+/// on a 4x2 lat/lon grid, reference time 2020-01-01 00:00: 8-bit simple
+/// packing of `values` in tenths.
+fn message(category: u8, number: u8, height: u32, values: [u8; 8]) -> Vec<u8> {
+    let mut sect1 = [0u8; 21];
+    sect1[..4].copy_from_slice(&21u32.to_be_bytes());
+    sect1[4] = 1;
+    sect1[12..19].copy_from_slice(&[0x07, 0xe4, 1, 1, 0, 0, 0]);
+    let mut sect3 = [0u8; 72];
+    sect3[..4].copy_from_slice(&72u32.to_be_bytes());
+    sect3[4] = 3;
+    sect3[6..10].copy_from_slice(&(NI * NJ).to_be_bytes());
+    sect3[30..34].copy_from_slice(&NI.to_be_bytes());
+    sect3[34..38].copy_from_slice(&NJ.to_be_bytes());
+    let mut sect4 = [0u8; 34];
+    sect4[..4].copy_from_slice(&34u32.to_be_bytes());
+    sect4[4] = 4;
+    sect4[9] = category;
+    sect4[10] = number;
+    sect4[17] = 1;
+    sect4[22] = 103;
+    sect4[24..28].copy_from_slice(&height.to_be_bytes());
+    sect4[28..34].fill(255);
+    let mut sect5 = [0u8; 21];
+    sect5[..4].copy_from_slice(&21u32.to_be_bytes());
+    sect5[4] = 5;
+    sect5[5..9].copy_from_slice(&(NI * NJ).to_be_bytes());
+    sect5[17..19].copy_from_slice(&1u16.to_be_bytes());
+    sect5[19] = 8;
+    let sect6 = [0, 0, 0, 6, 6, 255];
+    let mut sect7 = vec![0, 0, 0, 5 + values.len() as u8, 7];
+    sect7.extend_from_slice(&values);
+
+    let mut msg = b"GRIB\0\0\0\x02".to_vec();
+    msg.extend_from_slice(&[0; 8]);
+    for section in [&sect1[..], &sect3, &sect4, &sect5, &sect6, &sect7] {
+        msg.extend_from_slice(section);
+    }
+    msg.extend_from_slice(b"7777");
+    let length = msg.len() as u64;
+    msg[8..16].copy_from_slice(&length.to_be_bytes());
+    msg
+}
+
+/// Bundled source file of the `selftest` subcommand: 10 m UGRD and VGRD, kept
+/// by the default route, around a 2 m TMP it leaves out.
+pub fn fixture() -> Vec<u8> {
+    [
+        message(2, 2, 10, [12, 15, 18, 21, 24, 27, 30, 33]),
+        message(0, 0, 2, [0, 5, 10, 15, 20, 25, 30, 35]),
+        message(2, 3, 10, [40, 38, 36, 34, 32, 30, 28, 26]),
+    ]
+    .concat()
+}
+
+/// Run the filter stage over a whole source file, as a run does: the object
+/// made of the kept messages and its manifest. Fails on invalid or truncated
+/// GRIB2 data.
+pub fn filter_file(
+    filter: &Filter,
+    source: &str,
+    key: &str,
+    bytes: &[u8],
+) -> Result<(Vec<u8>, Manifest)> {
+    let filtered = filter.filter_chunk(Grib2StreamParser::new(), bytes);
+    if let Some(e) = filtered.error {
+        return Err(e);
+    }
+    if filtered.parser.pending() > 0 {
+        return Err(Error::Truncated {
+            url: source.to_string(),
+            received: bytes.len() as u64,
+            expected: None,
+        });
+    }
+    let mut object = Vec::new();
+    let mut manifest = Manifest::new(source, key);
+    for msg in &filtered.kept {
+        for product in &msg.products {
+            manifest.push(
+                object.len() as u64,
+                msg.bytes.len() as u64,
+                product,
+                msg.sha256.as_deref(),
+            );
+        }
+        object.extend_from_slice(&msg.bytes);
+    }
+    Ok((object, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::field::decode;
+    use crate::route::{Route, DEFAULT_ROUTE};
+
+    #[test]
+    fn test_fixture_through_the_filter() {
+        let routes: Arc<[Route]> = vec![DEFAULT_ROUTE.parse::<Route>().unwrap()].into();
+        let filter = Filter::new(routes).hashing(true);
+        let source = fixture();
+        let (object, manifest) = filter_file(&filter, "bundled", "selftest.grb2", &source).unwrap();
+        assert_eq!(manifest.messages.len(), 2);
+        assert_eq!(manifest.messages[1].number, 3);
+        assert_eq!(
+            manifest.messages[1].level.as_deref(),
+            Some("10 m above ground")
+        );
+        assert_eq!(
+            object.len() as u64,
+            manifest.messages.iter().map(|m| m.length).sum::<u64>()
+        );
+        let entry = &manifest.messages[1];
+        let field = decode(&object[entry.offset as usize..]).unwrap();
+        assert_eq!((field.ni, field.nj), (4, 2));
+        assert_eq!(field.values[0], 4.0);
+
+        assert!(matches!(
+            filter_file(&filter, "cut", "k", &source[..100]),
+            Err(Error::Truncated { received: 100, .. })
+        ));
+    }
+}
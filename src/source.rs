@@ -0,0 +1,141 @@
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+
+use crate::model::{Model, NAM_URL_TEMPLATE, RAP_URL_TEMPLATE};
+use crate::template::{parse_url_template, Template, DEFAULT_URL_TEMPLATE};
+
+/// GFS 0.25° files on NOMADS.
+pub const NOMADS_GFS_URL_TEMPLATE: &str = "https://nomads.ncep.noaa.gov/pub/data/nccf/com/gfs/prod/gfs.{yyyymmdd}/{hh}/atmos/gfs.t{hh}z.pgrb2.0p25.f{fff}";
+
+/// NAM 12 km CONUS files on NOMADS.
+pub const NOMADS_NAM_URL_TEMPLATE: &str = "https://nomads.ncep.noaa.gov/pub/data/nccf/com/nam/prod/nam.{yyyymmdd}/nam.t{hh}z.awphys{ff}.tm00.grib2";
+
+/// RAP 13 km CONUS files on NOMADS.
+pub const NOMADS_RAP_URL_TEMPLATE: &str = "https://nomads.ncep.noaa.gov/pub/data/nccf/com/rap/prod/rap.{yyyymmdd}/rap.t{hh}z.awp130pgrbf{ff}.grib2";
+
+/// GFS 0.25° files on the AWS open data bucket (GFS v16 layout, from the
+/// 2021-03-22 12Z cycle).
+pub const AWS_GFS_URL_TEMPLATE: &str = "https://noaa-gfs-bdp-pds.s3.amazonaws.com/gfs.{yyyymmdd}/{hh}/atmos/gfs.t{hh}z.pgrb2.0p25.f{fff}";
+
+/// Server the files of a model are downloaded from (`--source`), replacing the
+/// model's default one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Source {
+    /// NCAR RDA THREDDS (ds084.1): GFS since 2015, a few days behind real time
+    Rda,
+    /// NCEP NOMADS: GFS, NAM and RAP of the last 10 days, as soon as published
+    Nomads,
+    /// NOAA open data buckets on AWS (us-east-1): GFS since March 2021, NAM and RAP
+    Aws,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Rda => "rda",
+            Source::Nomads => "nomads",
+            Source::Aws => "aws",
+        }
+    }
+
+    /// URL templates of the files making up a task of `model`, `None` if the
+    /// source doesn't publish the model.
+    pub fn url_templates(&self, model: Model) -> Option<Vec<Template>> {
+        let template = match (self, model) {
+            (Source::Rda, Model::Gfs) => DEFAULT_URL_TEMPLATE,
+            (Source::Nomads, Model::Gfs) => NOMADS_GFS_URL_TEMPLATE,
+            (Source::Nomads, Model::Nam) => NOMADS_NAM_URL_TEMPLATE,
+            (Source::Nomads, Model::Rap) => NOMADS_RAP_URL_TEMPLATE,
+            (Source::Aws, Model::Gfs) => AWS_GFS_URL_TEMPLATE,
+            (Source::Aws, Model::Nam) => NAM_URL_TEMPLATE,
+            (Source::Aws, Model::Rap) => RAP_URL_TEMPLATE,
+            _ => return None,
+        };
+        Some(vec![
+            parse_url_template(template).expect("built-in URL templates are valid")
+        ])
+    }
+
+    /// Step between the forecast hours the source publishes for `model`: the RDA
+    /// archive has GFS every 3 hours only (f000, f003, ...).
+    pub fn forecast_hour_step(&self, model: Model) -> u32 {
+        match (self, model) {
+            (Source::Rda, Model::Gfs) => 3,
+            _ => 1,
+        }
+    }
+
+    /// Days of cycles the source keeps, `None` for an archive.
+    pub fn retention_days(&self) -> Option<u32> {
+        match self {
+            Source::Rda | Source::Aws => None,
+            Source::Nomads => Some(10),
+        }
+    }
+
+    /// First cycle of `model` laid out as `url_templates()` expects, `None` when
+    /// the source has all of them.
+    pub fn first_cycle(&self, model: Model) -> Option<NaiveDateTime> {
+        match (self, model) {
+            // Files moved under atmos/ with GFS v16
+            (Source::Aws, Model::Gfs) => {
+                NaiveDate::from_ymd_opt(2021, 3, 22)?.and_hms_opt(12, 0, 0)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycle::{Cycle, Task};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_source_url_layouts() {
+        let task = Task {
+            cycle: Cycle {
+                model: Model::Gfs,
+                time: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            },
+            forecast_hour: 3,
+        };
+        assert_eq!(
+            Source::Nomads.url_templates(Model::Gfs).unwrap()[0].render(&task),
+            "https://nomads.ncep.noaa.gov/pub/data/nccf/com/gfs/prod/gfs.20200101/06/atmos/gfs.t06z.pgrb2.0p25.f003"
+        );
+        assert_eq!(
+            Source::Rda.url_templates(Model::Gfs),
+            Some(Model::Gfs.url_templates())
+        );
+        assert!(Source::Nomads.url_templates(Model::Rap).unwrap()[0]
+            .render(&task)
+            .ends_with("/rap.20200101/rap.t06z.awp130pgrbf03.grib2"));
+        assert_eq!(
+            Source::Aws.url_templates(Model::Gfs).unwrap()[0].render(&task),
+            "https://noaa-gfs-bdp-pds.s3.amazonaws.com/gfs.20200101/06/atmos/gfs.t06z.pgrb2.0p25.f003"
+        );
+        assert_eq!(
+            Source::Aws.url_templates(Model::Nam),
+            Some(Model::Nam.url_templates())
+        );
+        assert!(Source::Aws.first_cycle(Model::Gfs) > Some(task.cycle.time));
+        assert_eq!(Source::Aws.first_cycle(Model::Nam), None);
+        assert_eq!(Source::Rda.url_templates(Model::Nam), None);
+        assert_eq!(Source::Rda.forecast_hour_step(Model::Gfs), 3);
+        assert_eq!(Source::Aws.forecast_hour_step(Model::Gfs), 1);
+        assert_eq!(Source::Nomads.url_templates(Model::Icon), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Instance metadata service of EC2, unless overridden by the variable the AWS
+/// SDKs also read.
+const DEFAULT_ENDPOINT: &str = "http://169.254.169.254";
+const ENDPOINT_VARIABLE: &str = "AWS_EC2_METADATA_SERVICE_ENDPOINT";
+
+/// How often the interruption notice is polled; AWS gives two minutes of warning.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watches the spot interruption notice of the EC2 instance the run is on
+/// (`--spot`), through the instance metadata service (IMDSv2).
+pub struct SpotWatcher {
+    http: reqwest::Client,
+    endpoint: String,
+}
+
+/// Body of `spot/instance-action`, e.g.
+/// `{"action": "terminate", "time": "2020-01-01T12:00:00Z"}`.
+#[derive(Debug, Deserialize)]
+struct InstanceAction {
+    action: String,
+    time: String,
+}
+
+impl SpotWatcher {
+    pub fn new(http: reqwest::Client) -> Self {
+        let endpoint =
+            std::env::var(ENDPOINT_VARIABLE).unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string());
+        Self {
+            http,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The interruption announced for the instance (e.g. `terminate at
+    /// 2020-01-01T12:00:00Z`), or `None` while there is none.
+    pub async fn notice(&self) -> reqwest::Result<Option<String>> {
+        let token = self
+            .http
+            .put(format!("{}/latest/api/token", self.endpoint))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let response = self
+            .http
+            .get(format!(
+                "{}/latest/meta-data/spot/instance-action",
+                self.endpoint
+            ))
+            .header("X-aws-ec2-metadata-token", token)
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response.error_for_status()?.text().await?;
+        Ok(Some(describe_notice(&body)))
+    }
+}
+
+/// Human-readable form of an instance action, or the raw body if it is not one.
+fn describe_notice(body: &str) -> String {
+    match serde_json::from_str::<InstanceAction>(body) {
+        Ok(notice) => format!("{} at {}", notice.action, notice.time),
+        Err(_) => body.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_notice() {
+        assert_eq!(
+            describe_notice(r#"{"action": "terminate", "time": "2020-01-01T12:00:00Z"}"#),
+            "terminate at 2020-01-01T12:00:00Z"
+        );
+        assert_eq!(describe_notice("stop\n"), "stop");
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::field::{decode, read_u16, read_u32};
+use crate::filter::MessageTransform;
+use crate::grib::{sections, ProductInfo};
+use crate::pack::{bitmap, simple_packing};
+
+/// Microdegrees in a full turn, the unit of lat/lon grid angles.
+const TURN: i64 = 360_000_000;
+
+/// Scanning modes of the grids that can be cropped: rows west to east, from
+/// north to south (GFS) or from south to north.
+const NORTH_TO_SOUTH: u8 = 0x00;
+const SOUTH_TO_NORTH: u8 = 0x40;
+
+fn subset_error(reason: impl Into<String>) -> Error {
+    Error::ParseError {
+        offset: 0,
+        reason: reason.into(),
+    }
+}
+
+/// Geographic box the fields are cropped to (`--bbox`), in degrees.
+///
+/// `west` may be greater than `east` for a box crossing the antimeridian
+/// (e.g. `170,-50,-170,-30`), whatever the longitude convention of the grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+/// A regular lat/lon grid (template 3.0), angles in microdegrees.
+struct LatLonGrid {
+    ni: usize,
+    nj: usize,
+    la1: i64,
+    lo1: i64,
+    di: i64,
+    dj: i64,
+    scanning: u8,
+}
+
+impl LatLonGrid {
+    fn parse(section: &[u8]) -> Result<Self> {
+        let short = || subset_error("short grid section");
+        let template = read_u16(section, 12).ok_or_else(short)?;
+        if template != 0 {
+            return Err(subset_error(format!(
+                "cannot crop grid template 3.{template}, only regular lat/lon grids"
+            )));
+        }
+        if section.get(10) != Some(&0) {
+            return Err(subset_error("cannot crop a quasi-regular grid"));
+        }
+        // Angles in microdegrees unless another basic angle is given
+        let basic_angle = read_u32(section, 38).ok_or_else(short)?;
+        if basic_angle != 0 && basic_angle != u32::MAX {
+            return Err(subset_error(format!(
+                "cannot crop a grid with basic angle {basic_angle}"
+            )));
+        }
+        let scanning = *section.get(71).ok_or_else(short)?;
+        if scanning != NORTH_TO_SOUTH && scanning != SOUTH_TO_NORTH {
+            return Err(subset_error(format!(
+                "cannot crop a grid with scanning mode {scanning:#04x}"
+            )));
+        }
+        Ok(Self {
+            ni: read_u32(section, 30).ok_or_else(short)? as usize,
+            nj: read_u32(section, 34).ok_or_else(short)? as usize,
+            la1: read_angle(section, 46).ok_or_else(short)?,
+            lo1: read_angle(section, 50).ok_or_else(short)?,
+            di: read_angle(section, 63).ok_or_else(short)?,
+            dj: read_angle(section, 67).ok_or_else(short)?,
+            scanning,
+        })
+    }
+
+    fn latitude(&self, j: usize) -> i64 {
+        match self.scanning {
+            SOUTH_TO_NORTH => self.la1 + j as i64 * self.dj,
+            _ => self.la1 - j as i64 * self.dj,
+        }
+    }
+
+    /// Longitude of column `i`, in [0, 360) degrees.
+    fn longitude(&self, i: usize) -> i64 {
+        (self.lo1 + i as i64 * self.di).rem_euclid(TURN)
+    }
+
+    /// Whether the columns go all around the globe.
+    fn is_global(&self) -> bool {
+        self.ni as i64 * self.di >= TURN
+    }
+}
+
+impl BoundingBox {
+    /// Crop the field of a message on a regular lat/lon grid to the box,
+    /// rebuilding its grid (section 3), bitmap (section 6) and data (sections 5
+    /// and 7, simple packing of the same values). Points missing from the
+    /// source stay missing.
+    pub fn crop(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        let parts: Vec<(u8, &[u8])> = sections(msg).collect();
+        let section = |number: u8| {
+            let mut found = parts.iter().filter(|(n, _)| *n == number);
+            match (found.next(), found.next()) {
+                (Some((_, section)), None) => Ok(*section),
+                _ => Err(subset_error(format!(
+                    "cannot crop a message without exactly one section {number}"
+                ))),
+            }
+        };
+        let (grid_section, representation) = (section(3)?, section(5)?);
+        section(7)?;
+        let grid = LatLonGrid::parse(grid_section)?;
+        let field = decode(msg)?;
+        if (field.ni, field.nj) != (grid.ni, grid.nj) {
+            return Err(subset_error(format!(
+                "{}x{} values for a {}x{} grid",
+                field.ni, field.nj, grid.ni, grid.nj
+            )));
+        }
+
+        let (south, north) = (microdegrees(self.south), microdegrees(self.north));
+        let rows: Vec<usize> = (0..grid.nj)
+            .filter(|&j| (south..=north).contains(&grid.latitude(j)))
+            .collect();
+        let columns = self.columns(&grid);
+        if rows.is_empty() || columns.is_empty() {
+            return Err(subset_error(format!("{self} is outside the grid")));
+        }
+        if columns.windows(2).any(|w| w[1] != (w[0] + 1) % grid.ni) {
+            return Err(subset_error(format!("{self} covers both ends of the grid")));
+        }
+        let values: Vec<f32> = rows
+            .iter()
+            .flat_map(|j| columns.iter().map(move |i| (j, i)))
+            .map(|(j, i)| field.values[j * grid.ni + i])
+            .collect();
+
+        let mut grid_cropped = grid_section.to_vec();
+        let (first_row, last_row) = (rows[0], rows[rows.len() - 1]);
+        let (first_column, last_column) = (columns[0], columns[columns.len() - 1]);
+        grid_cropped[6..10].copy_from_slice(&(values.len() as u32).to_be_bytes());
+        grid_cropped[30..34].copy_from_slice(&(columns.len() as u32).to_be_bytes());
+        grid_cropped[34..38].copy_from_slice(&(rows.len() as u32).to_be_bytes());
+        write_angle(&mut grid_cropped, 46, grid.latitude(first_row));
+        write_angle(&mut grid_cropped, 50, grid.longitude(first_column));
+        write_angle(&mut grid_cropped, 55, grid.latitude(last_row));
+        write_angle(&mut grid_cropped, 59, grid.longitude(last_column));
+
+        let present: Vec<f32> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+        let (representation, data) = simple_packing(&present, representation)
+            .ok_or_else(|| subset_error("short data representation section"))?;
+
+        let mut cropped = msg[..16].to_vec();
+        for (number, section) in &parts {
+            match number {
+                3 => cropped.extend_from_slice(&grid_cropped),
+                4 => cropped.extend_from_slice(section),
+                n if *n < 3 => cropped.extend_from_slice(section),
+                _ => {}
+            }
+        }
+        cropped.extend(representation);
+        cropped.extend(bitmap(&values));
+        cropped.extend(data);
+        cropped.extend_from_slice(b"7777");
+        let length = cropped.len() as u64;
+        cropped[8..16].copy_from_slice(&length.to_be_bytes());
+        Ok(cropped)
+    }
+
+    /// Columns of the grid within the box, west to east. On a global grid they
+    /// may wrap around its first column.
+    fn columns(&self, grid: &LatLonGrid) -> Vec<usize> {
+        let west = microdegrees(self.west).rem_euclid(TURN);
+        let width = if self.east - self.west >= 360.0 {
+            TURN
+        } else {
+            (microdegrees(self.east) - west).rem_euclid(TURN)
+        };
+        let inside = |i: usize| (grid.longitude(i) - west).rem_euclid(TURN) <= width;
+        if !grid.is_global() {
+            return (0..grid.ni).filter(|&i| inside(i)).collect();
+        }
+        // Start at the westernmost column inside the box
+        let start = (0..grid.ni)
+            .find(|&i| inside(i) && !inside((i + grid.ni - 1) % grid.ni))
+            .unwrap_or(0);
+        (0..grid.ni)
+            .map(|k| (start + k) % grid.ni)
+            .take_while(|&i| inside(i))
+            .collect()
+    }
+}
+
+impl MessageTransform for BoundingBox {
+    fn transform(&self, message: Vec<u8>, _products: &[ProductInfo]) -> Result<Option<Vec<u8>>> {
+        self.crop(&message).map(Some)
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    /// Parse `WEST,SOUTH,EAST,NORTH` in degrees, e.g. `-10,35,30,60`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("invalid box '{s}' (expected WEST,SOUTH,EAST,NORTH, e.g. -10,35,30,60)");
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|v| v.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let [west, south, east, north] = values[..] else {
+            return Err(invalid());
+        };
+        if !(-90.0..=90.0).contains(&south) || !(-90.0..=90.0).contains(&north) || south > north {
+            return Err(format!(
+                "invalid box '{s}': latitudes must be within -90..90, south first"
+            ));
+        }
+        if !(-180.0..=360.0).contains(&west) || !(-180.0..=360.0).contains(&east) {
+            return Err(format!(
+                "invalid box '{s}': longitudes must be within -180..360"
+            ));
+        }
+        Ok(Self {
+            west,
+            south,
+            east,
+            north,
+        })
+    }
+}
+
+impl fmt::Display for BoundingBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.west, self.south, self.east, self.north
+        )
+    }
+}
+
+fn microdegrees(degrees: f64) -> i64 {
+    (degrees * 1e6).round() as i64
+}
+
+/// Read a 4-octet sign-and-magnitude angle.
+fn read_angle(section: &[u8], index: usize) -> Option<i64> {
+    let raw = read_u32(section, index)?;
+    let magnitude = (raw & 0x7fff_ffff) as i64;
+    Some(if raw & 0x8000_0000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+fn write_angle(section: &mut [u8], index: usize, angle: i64) {
+    let raw = angle.unsigned_abs() as u32 | if angle < 0 { 0x8000_0000 } else { 0 };
+    section[index..index + 4].copy_from_slice(&raw.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assemble, grid_section, product_section, section};
+
+    /// Message on a lat/lon grid of `ni` x `nj` points from (`la1`, `lo1`) by
+    /// `step` degrees, simple packing of the values at 0.1, the points of
+    /// `missing` masked by a bitmap.
+    fn message(ni: u32, nj: u32, la1: f64, lo1: f64, step: f64, scanning: u8) -> Vec<u8> {
+        let missing = [3, 17, 24];
+        let mut sect3 = grid_section(ni, nj);
+        write_angle(&mut sect3, 46, microdegrees(la1));
+        write_angle(&mut sect3, 50, microdegrees(lo1));
+        write_angle(&mut sect3, 63, microdegrees(step));
+        write_angle(&mut sect3, 67, microdegrees(step));
+        sect3[71] = scanning;
+        let values: Vec<f32> = (0..ni * nj)
+            .map(|k| {
+                if missing.contains(&k) {
+                    f32::NAN
+                } else {
+                    k as f32
+                }
+            })
+            .collect();
+        let present: Vec<u16> = (0..ni * nj)
+            .filter(|k| !missing.contains(k))
+            .map(|k| k as u16 * 10)
+            .collect();
+        let mut sect5 = section(5, 21);
+        sect5[5..9].copy_from_slice(&(present.len() as u32).to_be_bytes());
+        sect5[17..19].copy_from_slice(&1u16.to_be_bytes());
+        sect5[19] = 16;
+        let mut sect7 = ((5 + 2 * present.len()) as u32).to_be_bytes().to_vec();
+        sect7.push(7);
+        for x in present {
+            sect7.extend_from_slice(&x.to_be_bytes());
+        }
+        assemble(
+            0,
+            &[
+                &sect3,
+                &product_section(2, 2),
+                &sect5,
+                &bitmap(&values),
+                &sect7,
+            ],
+        )
+    }
+
+    /// Values of the points (`i`, `j`) of the source grid, decoded.
+    fn source_values(msg: &[u8], points: &[(usize, usize)]) -> Vec<f32> {
+        let field = decode(msg).unwrap();
+        points
+            .iter()
+            .map(|&(i, j)| field.get(i, j).unwrap())
+            .collect()
+    }
+
+    fn assert_same(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!(x == y || (x.is_nan() && y.is_nan()), "{a:?} vs {b:?}");
+        }
+    }
+
+    #[test]
+    fn test_crop_rebuilds_grid_bitmap_and_data() {
+        // Global grid every 30 degrees, north to south from 90N, from 0E
+        let msg = message(12, 7, 90.0, 0.0, 30.0, NORTH_TO_SOUTH);
+        let bbox: BoundingBox = "-60,-30,30,60".parse().unwrap();
+        let cropped = bbox.crop(&msg).unwrap();
+        // Columns 300E, 330E, 0E and 30E; rows 60N to 30S
+        let points: Vec<_> = (1..5)
+            .flat_map(|j| [10, 11, 0, 1].map(|i| (i, j)))
+            .collect();
+        let field = decode(&cropped).unwrap();
+        assert_eq!((field.ni, field.nj), (4, 4));
+        assert_same(&field.values, &source_values(&msg, &points));
+        assert!(
+            field.get(2, 1).unwrap().is_nan(),
+            "masked point 24 kept masked"
+        );
+
+        let grid = LatLonGrid::parse(sections(&cropped).find(|s| s.0 == 3).unwrap().1).unwrap();
+        assert_eq!((grid.la1, grid.lo1), (60_000_000, 300_000_000));
+        assert_eq!(
+            (grid.latitude(3), grid.longitude(3)),
+            (-30_000_000, 30_000_000)
+        );
+        assert_eq!(
+            cropped.len() as u64,
+            u64::from_be_bytes(cropped[8..16].try_into().unwrap())
+        );
+
+        // Regional grid from south to north, no masked point in the box
+        let msg = message(8, 6, 20.0, -10.0, 5.0, SOUTH_TO_NORTH);
+        let cropped = "0,25,12,30"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .crop(&msg)
+            .unwrap();
+        let points: Vec<_> = (1..3).flat_map(|j| [2, 3, 4].map(|i| (i, j))).collect();
+        let field = decode(&cropped).unwrap();
+        assert_eq!((field.ni, field.nj), (3, 2));
+        assert_same(&field.values, &source_values(&msg, &points));
+        assert!(sections(&cropped).any(|(n, s)| n == 6 && s[5] == 255));
+
+        assert!("0,50,10,80"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .crop(&msg)
+            .is_err());
+        assert!("0,60,10,40".parse::<BoundingBox>().is_err());
+        assert!("0,40,10".parse::<BoundingBox>().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cycle::Task;
+
+/// Default S3 key template (relative to `--prefix`).
+pub const DEFAULT_KEY_TEMPLATE: &str = "wind_{valid_yyyymmdd}_{valid_hh}.grb2";
+
+/// Default key template when a run plans several lead times per cycle
+/// (`--hourly-coverage`), telling the analysis from the forecast steps.
+pub const DEFAULT_LEAD_KEY_TEMPLATE: &str = "wind_{valid_yyyymmdd}_{valid_hh}_{lead}.grb2";
+
+/// Default source URL template: NCAR THREDDS server (historical GFS data, no auth required).
+pub const DEFAULT_URL_TEMPLATE: &str = "https://thredds.rda.ucar.edu/thredds/fileServer/files/g/d084001/{yyyy}/{yyyymmdd}/gfs.0p25.{yyyymmdd}{hh}.f{fff}.grib2";
+
+/// Maximum length of an S3 key, in UTF-8 bytes.
+pub const MAX_KEY_LEN: usize = 1024;
+
+/// Placeholders available in templates, with their description.
+const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("yyyy", "cycle year"),
+    ("mm", "cycle month"),
+    ("dd", "cycle day"),
+    ("yyyymmdd", "cycle date"),
+    ("hh", "cycle hour"),
+    ("fff", "forecast hour, 3 digits"),
+    ("ff", "forecast hour, 2 digits"),
+    (
+        "arpege_steps",
+        "hours of the Arpège 0.1° file holding the forecast hour, e.g. 013H024H",
+    ),
+    ("lead", "anl for the analysis, else fNNN"),
+    ("valid_yyyymmdd", "valid date"),
+    ("valid_hh", "valid hour"),
+    ("model", "model name, e.g. gfs"),
+];
+
+/// A key or URL pattern with `{placeholder}` fields, validated on parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl Template {
+    /// Fill in the placeholders for a task.
+    pub fn render(&self, task: &Task) -> String {
+        let init = task.cycle.time;
+        let valid = task.valid_time();
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.clone(),
+                Part::Field(name) => match name.as_str() {
+                    "yyyy" => init.format("%Y").to_string(),
+                    "mm" => init.format("%m").to_string(),
+                    "dd" => init.format("%d").to_string(),
+                    "yyyymmdd" => init.format("%Y%m%d").to_string(),
+                    "hh" => init.format("%H").to_string(),
+                    "fff" => format!("{:03}", task.forecast_hour),
+                    "ff" => format!("{:02}", task.forecast_hour),
+                    "arpege_steps" => arpege_steps(task.forecast_hour),
+                    "lead" => task.lead(),
+                    "valid_yyyymmdd" => valid.format("%Y%m%d").to_string(),
+                    "valid_hh" => valid.format("%H").to_string(),
+                    "model" => task.cycle.model.to_string(),
+                    _ => unreachable!("placeholders are validated on parse"),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Forecast hours of the Arpège 0.1° file holding `hour`: 0 to 12, then 12 at a
+/// time up to the last one, 102.
+fn arpege_steps(hour: u32) -> String {
+    let first = match hour {
+        0..=12 => 0,
+        _ => (hour - 1) / 12 * 12 + 1,
+    };
+    let last = if first == 0 {
+        12
+    } else {
+        (first + 11).min(102)
+    };
+    format!("{first:03}H{last:03}H")
+}
+
+impl Template {
+    /// Whether the template contains a placeholder.
+    pub fn uses(&self, name: &str) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(field) if field == name))
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched '}}' in template '{s}'"));
+            }
+            let Some(close) = rest[open..].find('}') else {
+                return Err(format!("unclosed '{{' in template '{s}'"));
+            };
+            let name = &rest[open + 1..open + close];
+            if !PLACEHOLDERS.iter().any(|(known, _)| *known == name) {
+                let known: Vec<_> = PLACEHOLDERS
+                    .iter()
+                    .map(|(name, desc)| format!("{{{name}}} ({desc})"))
+                    .collect();
+                return Err(format!(
+                    "unknown placeholder {{{name}}} in template '{s}'; known placeholders: {}",
+                    known.join(", ")
+                ));
+            }
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            parts.push(Part::Field(name.to_string()));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(Self {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Check a key, or the part of one named `what` (a prefix, a template), against
+/// the S3 key rules. Any UTF-8 is allowed, `=` of Hive-style layouts (e.g.
+/// `model=gfs/`), spaces and non-ASCII letters included, but control characters;
+/// keys are escaped where they go into a URL or header. Empty (`//`) and
+/// relative (`.`, `..`) path segments are rejected, as clients and consoles
+/// resolve them to other keys.
+pub fn check_key_path(what: &str, s: &str) -> Result<(), String> {
+    if s.starts_with('/') {
+        return Err(format!("{what} '{s}' must not start with '/'"));
+    }
+    if s.contains("//") {
+        return Err(format!(
+            "{what} '{s}' must not contain empty path segments ('//')"
+        ));
+    }
+    if s.chars().any(char::is_control) {
+        return Err(format!("{what} {s:?} must not contain control characters"));
+    }
+    if s.split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err(format!(
+            "{what} '{s}' must not contain '.' or '..' path segments"
+        ));
+    }
+    if s.len() > MAX_KEY_LEN {
+        return Err(format!(
+            "{what} '{s}' is {} bytes long, S3 keys are limited to {MAX_KEY_LEN}",
+            s.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Check a full object key.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("key must not be empty".to_string());
+    }
+    check_key_path("key", key)
+}
+
+/// Parse and check a key template (relative to the prefix).
+pub fn parse_key_template(s: &str) -> Result<Template, String> {
+    check_key_path("key template", s)?;
+    if s.trim().is_empty() {
+        return Err("key template must not be empty".to_string());
+    }
+    s.parse()
+}
+
+/// S3 key of the object of a task: the prefixes (e.g. `--prefix`, then a route's)
+/// joined with single slashes, followed by the rendered key template. Prefixes
+/// are taken as is, `=` and all, with or without their trailing `/`.
+pub fn object_key(prefixes: &[&str], template: &Template, task: &Task) -> String {
+    let mut key = String::new();
+    for prefix in prefixes.iter().map(|p| p.trim_end_matches('/')) {
+        if !prefix.is_empty() {
+            key.push_str(prefix);
+            key.push('/');
+        }
+    }
+    key.push_str(&template.render(task));
+    key
+}
+
+/// Parse and check the key template of daily objects, which must not depend
+/// on the cycle hour or the forecast hour.
+pub fn parse_daily_key_template(s: &str) -> Result<Template, String> {
+    let template = parse_key_template(s)?;
+    if let Some(name) = ["hh", "fff", "ff", "lead", "valid_hh"]
+        .into_iter()
+        .find(|name| template.uses(name))
+    {
+        return Err(format!(
+            "daily key template '{s}' must not use the hourly placeholder {{{name}}}"
+        ));
+    }
+    Ok(template)
+}
+
+/// Parse and check a source URL template.
+pub fn parse_url_template(s: &str) -> Result<Template, String> {
+    if !(s.starts_with("http://") || s.starts_with("https://")) {
+        return Err(format!(
+            "URL template '{s}' must start with http:// or https://"
+        ));
+    }
+    s.parse()
+}
+
+/// Key of the `n`th continuation of an object split by `--max-object-size`: the
+/// number goes before the extension, e.g. `daily/wind_20200101.2.grb2` for n = 2.
+pub fn continuation_key(key: &str, n: u32) -> String {
+    let name_start = key.rfind('/').map_or(0, |i| i + 1);
+    match key[name_start..].rfind('.').filter(|&dot| dot > 0) {
+        Some(dot) => {
+            let (stem, extension) = key.split_at(name_start + dot);
+            format!("{stem}.{n}{extension}")
+        }
+        None => format!("{key}.{n}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::task;
+
+    #[test]
+    fn test_default_templates_render() {
+        let key = parse_key_template(DEFAULT_KEY_TEMPLATE).unwrap();
+        assert_eq!(key.render(&task(18, 9)), "wind_20200102_03.grb2");
+        assert!(!key.uses("model"));
+        let leads = parse_key_template(DEFAULT_LEAD_KEY_TEMPLATE).unwrap();
+        assert_eq!(leads.render(&task(18, 0)), "wind_20200101_18_anl.grb2");
+        assert_eq!(leads.render(&task(18, 9)), "wind_20200102_03_f009.grb2");
+
+        let namespaced = parse_key_template("{model}/{yyyy}/wind.grb2").unwrap();
+        assert!(namespaced.uses("model"));
+        assert_eq!(namespaced.render(&task(0, 0)), "gfs/2020/wind.grb2");
+
+        assert_eq!(
+            continuation_key("daily/wind_20200101.grb2", 2),
+            "daily/wind_20200101.2.grb2"
+        );
+        assert_eq!(continuation_key("v1.0/wind", 3), "v1.0/wind.3");
+        assert_eq!(continuation_key(".hidden", 2), ".hidden.2");
+
+        // Hive-style partitions, spaces and non-ASCII names are kept as is
+        let hive = parse_key_template("year={yyyy}/hour={hh}/vent é.grb2").unwrap();
+        assert_eq!(
+            object_key(&["archive=v2/", "param=wind"], &hive, &task(6, 0)),
+            "archive=v2/param=wind/year=2020/hour=06/vent é.grb2"
+        );
+        assert_eq!(
+            object_key(&["", "wind/"], &key, &task(0, 0)),
+            "wind/wind_20200101_00.grb2"
+        );
+
+        let url = parse_url_template(DEFAULT_URL_TEMPLATE).unwrap();
+        assert_eq!(
+            url.render(&task(6, 0)),
+            "https://thredds.rda.ucar.edu/thredds/fileServer/files/g/d084001/2020/20200101/gfs.0p25.2020010106.f000.grib2"
+        );
+
+        let arpege: Template = "{arpege_steps}".parse().unwrap();
+        let steps: Vec<_> = [0, 12, 13, 24, 100]
+            .map(|hour| arpege.render(&task(0, hour)))
+            .into();
+        assert_eq!(
+            steps,
+            ["000H012H", "000H012H", "013H024H", "013H024H", "097H102H"]
+        );
+    }
+
+    #[test]
+    fn test_template_errors_are_actionable() {
+        let err = parse_key_template("{yyy}/wind.grb2").unwrap_err();
+        assert!(err.contains("unknown placeholder {yyy}"), "{err}");
+        assert!(err.contains("{yyyy} (cycle year)"), "{err}");
+
+        assert!(parse_key_template("/wind.grb2")
+            .unwrap_err()
+            .contains("must not start with '/'"));
+        assert!(parse_key_template("../wind.grb2")
+            .unwrap_err()
+            .contains("'..'"));
+        assert!(parse_key_template("a\tb.grb2").is_err());
+        assert!(validate_key(&"x".repeat(MAX_KEY_LEN + 1))
+            .unwrap_err()
+            .contains("1025 bytes"));
+        assert!(validate_key("").is_err());
+        assert!(parse_key_template("wind_{hh.grb2")
+            .unwrap_err()
+            .contains("unclosed"));
+        assert!(
+            parse_daily_key_template("daily/wind_{valid_yyyymmdd}_{valid_hh}.grb2")
+                .unwrap_err()
+                .contains("{valid_hh}")
+        );
+        assert!(parse_url_template("ftp://example.com/{hh}")
+            .unwrap_err()
+            .contains("http://"));
+    }
+}
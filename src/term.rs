@@ -0,0 +1,112 @@
+use std::io::IsTerminal;
+
+/// Width of the status column, the longest status included.
+const STATUS_WIDTH: usize = 9;
+
+/// When to colorize the output (`--color`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// When the stream is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Outcome of a file, shown at the start of its status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Completed,
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Completed => "Completed",
+            Status::Skipped => "Skipped",
+            Status::Failed => "Failed",
+        }
+    }
+
+    /// ANSI color: green, yellow, red.
+    fn color(self) -> &'static str {
+        match self {
+            Status::Completed => "\x1b[32m",
+            Status::Skipped => "\x1b[33m",
+            Status::Failed => "\x1b[31m",
+        }
+    }
+}
+
+/// Formats status lines for one output stream, in color or plain text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Styler {
+    color: bool,
+}
+
+impl Styler {
+    /// Styler of a stream, given whether it is a terminal and `NO_COLOR` is set.
+    pub fn new(choice: ColorChoice, is_terminal: bool, no_color: bool) -> Self {
+        let color = match choice {
+            ColorChoice::Auto => is_terminal && !no_color,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        Self { color }
+    }
+
+    /// Styler of standard output.
+    pub fn stdout(choice: ColorChoice) -> Self {
+        Self::new(choice, std::io::stdout().is_terminal(), no_color())
+    }
+
+    /// Styler of standard error.
+    pub fn stderr(choice: ColorChoice) -> Self {
+        Self::new(choice, std::io::stderr().is_terminal(), no_color())
+    }
+
+    /// Status line of `subject`, the status padded so the subjects line up.
+    pub fn status(&self, status: Status, subject: &str) -> String {
+        let label = status.label();
+        let pad = " ".repeat(STATUS_WIDTH - label.len() + 1);
+        if self.color {
+            format!("  {}{label}\x1b[0m{pad}{subject}", status.color())
+        } else {
+            format!("  {label}{pad}{subject}")
+        }
+    }
+}
+
+/// Whether `NO_COLOR` is set to a non-empty value (https://no-color.org).
+fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_lines() {
+        let plain = Styler::new(ColorChoice::Auto, false, false);
+        assert_eq!(
+            plain.status(Status::Completed, "2020-01-01 00 f000: 20 messages"),
+            "  Completed 2020-01-01 00 f000: 20 messages"
+        );
+        assert_eq!(
+            plain.status(Status::Failed, "2020-01-01 06 f000: not found"),
+            "  Failed    2020-01-01 06 f000: not found"
+        );
+
+        let color = Styler::new(ColorChoice::Auto, true, false);
+        assert_eq!(
+            color.status(Status::Skipped, "2020-01-01 00 f000"),
+            "  \x1b[33mSkipped\x1b[0m   2020-01-01 00 f000"
+        );
+        assert_eq!(Styler::new(ColorChoice::Auto, true, true), plain);
+        assert_eq!(Styler::new(ColorChoice::Never, true, false), plain);
+        assert_eq!(Styler::new(ColorChoice::Always, false, true), color);
+    }
+}
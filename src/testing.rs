@@ -0,0 +1,117 @@
+//! Fixtures of the unit tests: GRIB2 messages built section by section, GFS
+//! tasks and manifest entries.
+// Each feature set builds the tests of its own modules only
+#![allow(dead_code)]
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::cycle::{Cycle, Task};
+use crate::grib::END_MARKER;
+use crate::manifest::MessageEntry;
+use crate::model::Model;
+
+/// 2020-01-01 at `hour`.
+pub fn time(hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2020, 1, 1)
+        .unwrap()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+}
+
+/// GFS task of the 2020-01-01 `hour` cycle, at `forecast_hour`.
+pub fn task(hour: u32, forecast_hour: u32) -> Task {
+    Task {
+        cycle: Cycle {
+            model: Model::Gfs,
+            time: time(hour),
+        },
+        forecast_hour,
+    }
+}
+
+/// Manifest entry of a 10 m wind component (parameter 0.2.2) of `length` bytes
+/// at `offset`, without level, times nor hash.
+pub fn entry(offset: u64, length: u64) -> MessageEntry {
+    MessageEntry {
+        offset,
+        length,
+        discipline: 0,
+        category: 2,
+        number: 2,
+        template: 0,
+        level: None,
+        reference_time: None,
+        valid_time: None,
+        period_start: None,
+        period_end: None,
+        statistic: None,
+        sha256: None,
+        stored_in: None,
+    }
+}
+
+/// `entry()` of parameter 0.2.`number` from the analysis at `time`: reference
+/// time, valid time and period all at `time`.
+pub fn timed_entry(number: u8, time: NaiveDateTime, offset: u64, length: u64) -> MessageEntry {
+    MessageEntry {
+        number,
+        reference_time: Some(time),
+        valid_time: Some(time),
+        period_start: Some(time),
+        period_end: Some(time),
+        ..entry(offset, length)
+    }
+}
+
+/// Section `number` of `length` zero bytes, but for its length and number.
+pub fn section(number: u8, length: usize) -> Vec<u8> {
+    let mut section = vec![0u8; length];
+    section[..4].copy_from_slice(&(length as u32).to_be_bytes());
+    section[4] = number;
+    section
+}
+
+/// Section `number` holding `body` after its length and number.
+pub fn body_section(number: u8, body: &[u8]) -> Vec<u8> {
+    let mut section = section(number, 5);
+    section[..4].copy_from_slice(&((5 + body.len()) as u32).to_be_bytes());
+    section.extend_from_slice(body);
+    section
+}
+
+/// Grid definition section of a regular lat/lon grid of `ni` x `nj` points
+/// (template 3.0), the coordinates left to the caller.
+pub fn grid_section(ni: u32, nj: u32) -> Vec<u8> {
+    let mut sect3 = section(3, 72);
+    sect3[6..10].copy_from_slice(&(ni * nj).to_be_bytes());
+    sect3[30..34].copy_from_slice(&ni.to_be_bytes());
+    sect3[34..38].copy_from_slice(&nj.to_be_bytes());
+    sect3
+}
+
+/// Product definition section (template 4.0) of parameter
+/// `0.category.number`.
+pub fn product_section(category: u8, number: u8) -> Vec<u8> {
+    let mut sect4 = section(4, 34);
+    sect4[9] = category;
+    sect4[10] = number;
+    sect4
+}
+
+/// GRIB2 message of `discipline` holding `sections`, between its indicator
+/// section (announcing its length) and its end marker.
+pub fn assemble(discipline: u8, sections: &[&[u8]]) -> Vec<u8> {
+    let length = 16 + sections.iter().map(|s| s.len()).sum::<usize>() + 4;
+    let mut msg = b"GRIB".to_vec();
+    msg.extend_from_slice(&[0, 0, discipline, 2]);
+    msg.extend_from_slice(&(length as u64).to_be_bytes());
+    for section in sections {
+        msg.extend_from_slice(section);
+    }
+    msg.extend_from_slice(END_MARKER);
+    msg
+}
+
+/// Minimal GRIB2 message with a section 4 for parameter 0.2.`number`.
+pub fn message(number: u8) -> Vec<u8> {
+    assemble(0, &[&product_section(2, number)])
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+/// Parse a human-readable byte size such as `500GB`, `1.5TiB` or `1048576`.
+///
+/// SI suffixes (KB, MB, GB, TB) are powers of 1000 and binary suffixes
+/// (KiB, MiB, GiB, TiB) powers of 1024. A bare number is a byte count.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{s}' (expected e.g. 500GB or 2GiB)"))?;
+
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1_000,
+        "mb" | "m" => 1_000_000,
+        "gb" | "g" => 1_000_000_000,
+        "tb" | "t" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => return Err(format!("unknown size unit '{other}' in '{s}'")),
+    };
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parse a positive duration such as `500ms`, `90s`, `10m`, `2h` or `1d`; a bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{s}' (expected e.g. 30s, 10m or 2h)"))?;
+
+    if value == 0 {
+        return Err(format!("duration '{s}' must be positive"));
+    }
+
+    let seconds: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "ms" => return Ok(Duration::from_millis(value)),
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        other => return Err(format!("unknown duration unit '{other}' in '{s}'")),
+    };
+
+    value
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{s}' is too large"))
+}
+
+/// Units of the sizes printed (`--size-units`): SI (MB, GB) or binary (MiB, GiB).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SizeUnits {
+    #[default]
+    Si,
+    Binary,
+}
+
+/// Format a byte count for display, e.g. `512 B`, `1.5 MB` or `2.0 GiB`.
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let (base, suffixes) = match units {
+        SizeUnits::Si => (1000.0, ["KB", "MB", "GB", "TB"]),
+        SizeUnits::Binary => (1024.0, ["KiB", "MiB", "GiB", "TiB"]),
+    };
+    let mut value = bytes as f64;
+    if value < base {
+        return format!("{bytes} B");
+    }
+    let mut suffix = suffixes[0];
+    for next in suffixes {
+        if value < base {
+            break;
+        }
+        value /= base;
+        suffix = next;
+    }
+    format!("{value:.1} {suffix}")
+}
+
+/// Format a duration for display with its two largest units, e.g. `45s`,
+/// `2m 05s`, `1h 02m` or `3d 04h`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{seconds}s"),
+        (0, 0, _) => format!("{minutes}m {:02}s", seconds % 60),
+        (0, _, _) => format!("{hours}h {minutes:02}m"),
+        _ => format!("{days}d {hours:02}h"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("1048576"), Ok(1_048_576));
+        assert_eq!(parse_size("500GB"), Ok(500_000_000_000));
+        assert_eq!(parse_size("2GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1.5 MB"), Ok(1_500_000));
+        assert!(parse_size("12XB").is_err());
+        assert!(parse_size("GB").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration("3w").is_err());
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("0ms").is_err());
+        assert!(parse_duration("999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_format_size_and_duration() {
+        assert_eq!(format_size(512, SizeUnits::Si), "512 B");
+        assert_eq!(format_size(1_500_000, SizeUnits::Si), "1.5 MB");
+        assert_eq!(format_size(1_500_000, SizeUnits::Binary), "1.4 MiB");
+        assert_eq!(format_size(2 << 30, SizeUnits::Binary), "2.0 GiB");
+        assert_eq!(
+            format_size(3_000_000_000_000_000, SizeUnits::Si),
+            "3000.0 TB"
+        );
+
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m 05s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 02m");
+        assert_eq!(format_duration(Duration::from_secs(273_600)), "3d 04h");
+    }
+}
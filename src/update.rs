@@ -0,0 +1,275 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+
+/// Repository whose releases `self-update` installs.
+pub const DEFAULT_REPOSITORY: &str = "etaque/gfs-wind-downloader";
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+/// Suffix of the asset holding the minisign signature of a binary, next to it in
+/// the release.
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+/// Minisign public key of the releases, pinned at build time by the release
+/// workflow (`GFS_WIND_DOWNLOADER_UPDATE_KEY`).
+pub const PUBLIC_KEY: Option<&str> = option_env!("GFS_WIND_DOWNLOADER_UPDATE_KEY");
+
+/// Release as described by the GitHub API.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    /// Tag of the release, e.g. `v0.2.0`.
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    pub fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Name of the release asset holding the binary for this platform, e.g.
+/// `gfs_wind_downloader-x86_64-linux`.
+pub fn asset_name() -> String {
+    format!(
+        "gfs_wind_downloader-{}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// Parse a `major.minor.patch` version, with or without a leading `v`.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.trim_start_matches('v').splitn(3, '.');
+    let mut next = || parts.next()?.parse().ok();
+    Some((next()?, next()?, next()?))
+}
+
+/// Whether the release tagged `tag` is newer than the `current` version.
+pub fn is_newer(tag: &str, current: &str) -> bool {
+    matches!(
+        (parse_version(tag), parse_version(current)),
+        (Some(tag), Some(current)) if tag > current
+    )
+}
+
+/// Minisign public key: an identifier and an Ed25519 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl FromStr for PublicKey {
+    type Err = String;
+
+    /// Parse a minisign public key, as the base64 line of its `.pub` file (the
+    /// comment line is allowed).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let line = s
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .unwrap_or_default();
+        let bytes = STANDARD
+            .decode(line)
+            .map_err(|e| format!("invalid minisign public key: {e}"))?;
+        match bytes.as_slice() {
+            [b'E', b'd', rest @ ..] if rest.len() == 40 => Ok(Self {
+                id: rest[..8].try_into().expect("8 bytes"),
+                key: rest[8..].try_into().expect("32 bytes"),
+            }),
+            _ => Err("invalid minisign public key: expected an Ed25519 key".to_string()),
+        }
+    }
+}
+
+/// Check a downloaded binary against its minisign signature (`.minisig`) by
+/// `key`: the signature of the file, then the one of the trusted comment.
+/// Only legacy signatures of the whole file are read (`minisign -S -l`), not
+/// the prehashed ones.
+pub fn verify_signature(bytes: &[u8], signature: &str, key: &PublicKey) -> Result<(), String> {
+    let mut lines = signature
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("untrusted comment:"));
+    let mut decode = |what: &str| {
+        lines
+            .next()
+            .ok_or_else(|| format!("signature without {what}"))
+            .map(str::to_string)
+    };
+    let signature = STANDARD
+        .decode(decode("a signature line")?)
+        .map_err(|e| format!("invalid signature: {e}"))?;
+    let trusted = decode("a trusted comment")?;
+    let trusted = trusted
+        .strip_prefix("trusted comment: ")
+        .ok_or("invalid signature: no trusted comment")?;
+    let global = STANDARD
+        .decode(decode("a global signature")?)
+        .map_err(|e| format!("invalid global signature: {e}"))?;
+
+    let (algorithm, id, signature) = match signature.as_slice() {
+        [a, b, rest @ ..] if rest.len() == 72 => ([*a, *b], &rest[..8], &rest[8..]),
+        _ => return Err("invalid signature: unexpected length".to_string()),
+    };
+    match &algorithm {
+        b"Ed" => {}
+        b"ED" => {
+            return Err(
+                "prehashed signature, only legacy ones (minisign -S -l) are supported".to_string(),
+            )
+        }
+        _ => return Err("invalid signature: unknown algorithm".to_string()),
+    }
+    if id != key.id {
+        return Err("signed by another key than the release key".to_string());
+    }
+    let public = UnparsedPublicKey::new(&ED25519, &key.key);
+    public
+        .verify(bytes, signature)
+        .map_err(|_| "signature does not match the binary".to_string())?;
+    let signed = [signature, trusted.as_bytes()].concat();
+    public
+        .verify(&signed, &global)
+        .map_err(|_| "signature of the trusted comment does not match".to_string())
+}
+
+/// Reads the releases of a repository from the GitHub API (`self-update`).
+pub struct Updater {
+    http: reqwest::Client,
+    api_url: String,
+    repository: String,
+}
+
+impl Updater {
+    pub fn new(http: reqwest::Client, api_url: &str, repository: &str) -> Self {
+        Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            repository: repository.to_string(),
+        }
+    }
+
+    /// Latest published release (drafts and pre-releases excluded).
+    pub async fn latest(&self) -> Result<Release> {
+        let url = format!("{}/repos/{}/releases/latest", self.api_url, self.repository);
+        let body = self
+            .http
+            .get(&url)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Failed to read the latest release from {url}"))?
+            .text()
+            .await
+            .with_context(|| format!("Failed to read the latest release from {url}"))?;
+        serde_json::from_str(&body)
+            .with_context(|| format!("Invalid release description from {url}"))
+    }
+
+    /// Download a release asset.
+    pub async fn download(&self, asset: &Asset) -> Result<Vec<u8>> {
+        let bytes = self
+            .http
+            .get(&asset.browser_download_url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Failed to download {}", asset.name))?
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to download {}", asset.name))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Replace the binary at `path` by `bytes`, keeping its permissions. The new
+/// binary is written next to the old one and renamed over it, so a failure
+/// leaves the old one in place.
+pub fn replace_binary(path: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = path.with_extension("new");
+    let permissions = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .permissions();
+    std::fs::write(&staged, bytes)
+        .and_then(|()| std::fs::set_permissions(&staged, permissions))
+        .and_then(|()| std::fs::rename(&staged, path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&staged);
+        })
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    /// Key pair, public key and legacy minisign signature of `bytes`.
+    fn sign(bytes: &[u8], trusted: &str) -> (PublicKey, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let id = *b"keyid-01";
+        let public = [b"Ed".as_slice(), &id, pair.public_key().as_ref()].concat();
+        let key: PublicKey = format!("untrusted comment: key\n{}\n", STANDARD.encode(public))
+            .parse()
+            .unwrap();
+        let signature = pair.sign(bytes);
+        let global = pair.sign(&[signature.as_ref(), trusted.as_bytes()].concat());
+        let line = STANDARD.encode([b"Ed".as_slice(), &id, signature.as_ref()].concat());
+        let text = format!(
+            "untrusted comment: signature\n{line}\ntrusted comment: {trusted}\n{}\n",
+            STANDARD.encode(global)
+        );
+        (key, text)
+    }
+
+    #[test]
+    fn test_release_checks() {
+        assert!(is_newer("v0.2.0", "0.1.0"));
+        assert!(is_newer("0.10.0", "0.9.3"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("v0.1.0", "0.2.0"));
+        assert!(!is_newer("nightly", "0.1.0"));
+
+        let (key, signature) = sign(b"binary", "file:gfs_wind_downloader-x86_64-linux");
+        assert_eq!(verify_signature(b"binary", &signature, &key), Ok(()));
+        assert!(verify_signature(b"tampered", &signature, &key)
+            .unwrap_err()
+            .contains("does not match the binary"));
+        // The trusted comment is covered by the global signature
+        let forged = signature.replace("x86_64", "aarch64");
+        assert!(verify_signature(b"binary", &forged, &key)
+            .unwrap_err()
+            .contains("trusted comment"));
+        // Signed by another key
+        let (other, _) = sign(b"binary", "");
+        let other = PublicKey {
+            id: *b"other-id",
+            ..other
+        };
+        assert!(verify_signature(b"binary", &signature, &other)
+            .unwrap_err()
+            .contains("another key"));
+        assert!("not a key".parse::<PublicKey>().is_err());
+
+        let release: Release = serde_json::from_str(
+            r#"{"tag_name": "v0.2.0", "assets": [{"name": "a", "browser_download_url": "https://example.com/a"}]}"#,
+        )
+        .unwrap();
+        assert!(release.asset("a").is_some());
+        assert!(release.asset("b").is_none());
+    }
+}
@@ -0,0 +1,217 @@
+use aws_sdk_s3::Client;
+
+use crate::error::Result;
+use crate::grib::END_MARKER;
+use crate::manifest::Manifest;
+use crate::s3::{get_range, head_size};
+
+/// Length of the GRIB2 indicator section, read at the start of each message.
+const INDICATOR_LEN: u64 = 16;
+
+/// Outcome of checking an archived object against its manifest.
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    /// Messages stored in the object.
+    pub messages: usize,
+    /// Range requests made.
+    pub requests: usize,
+    pub problems: Vec<String>,
+}
+
+/// Messages stored in the object described by `manifest`, as sorted (offset,
+/// length) pairs: one per message (not per product), without those stored in
+/// other objects.
+pub fn stored_messages(manifest: &Manifest) -> Vec<(u64, u64)> {
+    let mut messages: Vec<_> = manifest
+        .messages
+        .iter()
+        .filter(|m| m.stored_in.is_none())
+        .map(|m| (m.offset, m.length))
+        .collect();
+    messages.sort();
+    messages.dedup();
+    messages
+}
+
+/// Check that the messages follow each other from the start of the object to
+/// its end (`size` bytes).
+pub fn check_layout(messages: &[(u64, u64)], size: u64) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut end = 0;
+    for &(offset, length) in messages {
+        if offset != end {
+            problems.push(format!(
+                "message at byte {offset} does not follow the previous one (ending at {end})"
+            ));
+        }
+        end = offset + length;
+    }
+    if end != size {
+        problems.push(format!(
+            "messages end at byte {end} but the object has {size} bytes"
+        ));
+    }
+    problems
+}
+
+/// Check the indicator section of a message announced as `length` bytes long.
+pub fn check_indicator(bytes: &[u8], length: u64) -> Result<(), String> {
+    if bytes.len() < INDICATOR_LEN as usize || &bytes[..4] != b"GRIB" {
+        return Err("no GRIB header".to_string());
+    }
+    if bytes[7] != 2 {
+        return Err(format!("GRIB edition {} instead of 2", bytes[7]));
+    }
+    let announced = u64::from_be_bytes(bytes[8..16].try_into().expect("8 bytes"));
+    if announced != length {
+        return Err(format!(
+            "header announces {announced} bytes, the manifest {length}"
+        ));
+    }
+    Ok(())
+}
+
+/// Check an archived object with a HEAD request and one small range request per
+/// message: the first reads the indicator section of the first message, each
+/// next one the "7777" end of a message together with the indicator section of
+/// the following one. The message bodies are never downloaded.
+pub async fn verify_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    manifest: &Manifest,
+) -> Result<Verification> {
+    let messages = stored_messages(manifest);
+    let mut verification = Verification {
+        messages: messages.len(),
+        ..Default::default()
+    };
+    let Some(size) = head_size(client, bucket, key).await? else {
+        verification.problems.push("object missing".to_string());
+        return Ok(verification);
+    };
+    verification.problems = check_layout(&messages, size);
+    if !verification.problems.is_empty() {
+        return Ok(verification);
+    }
+    let Some(&(_, first_length)) = messages.first() else {
+        return Ok(verification);
+    };
+
+    let problem = |offset: u64, reason: String| format!("message at byte {offset}: {reason}");
+    let first = get_range(client, bucket, key, 0, INDICATOR_LEN.min(size)).await?;
+    verification.requests += 1;
+    if let Err(reason) = check_indicator(&first, first_length) {
+        verification.problems.push(problem(0, reason));
+    }
+
+    for (i, &(offset, length)) in messages.iter().enumerate() {
+        let end = offset + length;
+        let next = messages.get(i + 1);
+        let marker = END_MARKER.len() as u64;
+        let read = marker + next.map_or(0, |_| INDICATOR_LEN.min(size - end));
+        let bytes = get_range(client, bucket, key, end - marker.min(length), read).await?;
+        verification.requests += 1;
+
+        if !bytes.starts_with(END_MARKER) {
+            verification
+                .problems
+                .push(problem(offset, "does not end with '7777'".to_string()));
+        }
+        if let Some(&(next_offset, next_length)) = next {
+            if let Err(reason) =
+                check_indicator(&bytes[END_MARKER.len().min(bytes.len())..], next_length)
+            {
+                verification.problems.push(problem(next_offset, reason));
+            }
+        }
+    }
+    Ok(verification)
+}
+
+/// Check the ends of an object just uploaded with `written` bytes: `size` as
+/// reported by S3, `head` its first bytes and `tail` its last ones.
+pub fn check_read_back(size: Option<u64>, written: u64, head: &[u8], tail: &[u8]) -> Vec<String> {
+    let Some(size) = size else {
+        return vec!["object missing after completion".to_string()];
+    };
+    let mut problems = Vec::new();
+    if size != written {
+        problems.push(format!("object has {size} bytes, {written} were written"));
+    }
+    if written > 0 && !head.starts_with(b"GRIB") {
+        problems.push("does not start with 'GRIB'".to_string());
+    }
+    if written > 0 && !tail.ends_with(END_MARKER) {
+        problems.push("does not end with '7777'".to_string());
+    }
+    problems
+}
+
+/// Read back an object just uploaded with `written` bytes (`--verify-upload`):
+/// a HEAD request for its size, then range requests for the "GRIB" magic at its
+/// start and the "7777" marker at its end.
+pub async fn verify_upload(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    written: u64,
+) -> Result<Vec<String>> {
+    let size = head_size(client, bucket, key).await?;
+    let (mut head, mut tail) = (Vec::new(), Vec::new());
+    if let Some(size) = size.filter(|&size| size == written && size > 0) {
+        let marker = END_MARKER.len() as u64;
+        head = get_range(client, bucket, key, 0, marker.min(size)).await?;
+        tail = get_range(client, bucket, key, size - marker.min(size), marker).await?;
+    }
+    Ok(check_read_back(size, written, &head, &tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::MessageEntry;
+    use crate::testing::entry;
+
+    #[test]
+    fn test_layout_and_headers() {
+        let mut manifest = Manifest::new("https://example.com/f000", "wind.grb2");
+        // Two products of one message, then a message stored elsewhere
+        manifest.messages = vec![
+            entry(100, 50),
+            entry(0, 100),
+            entry(0, 100),
+            MessageEntry {
+                stored_in: Some("other.grb2".to_string()),
+                ..entry(0, 80)
+            },
+        ];
+        let messages = stored_messages(&manifest);
+        assert_eq!(messages, [(0, 100), (100, 50)]);
+        assert!(check_layout(&messages, 150).is_empty());
+        assert_eq!(check_layout(&messages, 200).len(), 1);
+        assert_eq!(check_layout(&[(0, 100), (120, 50)], 170).len(), 1);
+
+        let mut header = b"GRIB\0\0\0\x02".to_vec();
+        header.extend_from_slice(&100u64.to_be_bytes());
+        assert_eq!(check_indicator(&header, 100), Ok(()));
+        assert!(check_indicator(&header, 99)
+            .unwrap_err()
+            .contains("announces 100"));
+        header[7] = 1;
+        assert!(check_indicator(&header, 100).is_err());
+        assert!(check_indicator(b"GRIB", 100).is_err());
+    }
+
+    #[test]
+    fn test_read_back() {
+        assert!(check_read_back(Some(150), 150, b"GRIB", b"7777").is_empty());
+        assert!(check_read_back(Some(0), 0, b"", b"").is_empty());
+        assert_eq!(check_read_back(None, 150, b"", b"").len(), 1);
+        assert!(check_read_back(Some(100), 150, b"", b"")[0].contains("150 were written"));
+        assert_eq!(
+            check_read_back(Some(150), 150, b"GRIB", b"7770"),
+            ["does not end with '7777'"]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! JavaScript bindings (`--features wasm`) of the stream parser and filter,
+//! for browser tools and edge workers.
+
+use wasm_bindgen::prelude::*;
+
+use crate::grib::{products, Grib2StreamParser};
+use crate::param::Param;
+
+/// Splits a GRIB2 byte stream into messages and keeps those of some parameters.
+#[wasm_bindgen]
+pub struct StreamFilter {
+    parser: Grib2StreamParser,
+    params: Vec<Param>,
+    kept: u32,
+    failed: u32,
+    error: Option<String>,
+}
+
+#[wasm_bindgen]
+impl StreamFilter {
+    /// Filter keeping the comma-separated parameters (NCEP short names or
+    /// discipline.category.number), e.g. `"UGRD,VGRD"`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(params: &str) -> Result<StreamFilter, JsError> {
+        let params = params
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, String>>()
+            .map_err(|e| JsError::new(&e))?;
+        Ok(Self {
+            parser: Grib2StreamParser::new(),
+            params,
+            kept: 0,
+            failed: 0,
+            error: None,
+        })
+    }
+
+    /// Feed a chunk of the stream; returns the kept messages it completed,
+    /// concatenated (empty if none). Messages that don't parse are skipped and
+    /// reported by `failed` and `error` rather than losing the others.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.filter(chunk)
+    }
+
+    /// Number of messages read so far.
+    #[wasm_bindgen(getter)]
+    pub fn messages(&self) -> u32 {
+        self.parser.messages() as u32
+    }
+
+    /// Number of messages kept so far.
+    #[wasm_bindgen(getter)]
+    pub fn kept(&self) -> u32 {
+        self.kept
+    }
+
+    /// Number of messages skipped because they don't parse.
+    #[wasm_bindgen(getter)]
+    pub fn failed(&self) -> u32 {
+        self.failed
+    }
+
+    /// Why the last skipped message doesn't parse, if any was.
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+
+    /// Bytes of an incomplete message still buffered; non-zero once the
+    /// stream has ended means it was cut short.
+    #[wasm_bindgen(getter)]
+    pub fn pending(&self) -> usize {
+        self.parser.pending()
+    }
+}
+
+impl StreamFilter {
+    fn filter(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.parser.push(chunk);
+        loop {
+            // The parser is past a message that fails, ready for the next one
+            let msg = match self.parser.next_message() {
+                Ok(Some(msg)) => msg,
+                Ok(None) => return out,
+                Err(e) => {
+                    self.failed += 1;
+                    self.error = Some(e.to_string());
+                    continue;
+                }
+            };
+            if products(&msg)
+                .iter()
+                .any(|product| self.params.contains(&Param::of(product)))
+            {
+                out.extend_from_slice(&msg);
+                self.kept += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message;
+
+    #[test]
+    fn test_failed_messages_leave_the_others_kept() {
+        let mut broken = message(2);
+        let end = broken.len() - 4;
+        broken[end..].copy_from_slice(b"6666");
+        let stream = [message(2), broken, message(3), message(2)].concat();
+
+        let mut filter = StreamFilter::new("UGRD").unwrap();
+        let kept = filter.push(&stream);
+        assert_eq!(kept, [message(2), message(2)].concat());
+        assert_eq!(
+            (filter.messages(), filter.kept(), filter.failed()),
+            (4, 2, 1)
+        );
+        assert!(filter.error().is_some_and(|e| e.contains("7777")));
+    }
+}
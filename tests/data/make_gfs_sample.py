@@ -0,0 +1,167 @@
+"""Write gfs_sample.grb2, the GFS-like file of the golden tests.
+
+A 5° global grid of the fields a GFS pgrb2 file interleaves with the wind ones,
+scaled to 0.1 and packed like NCEP does: complex packing with second-order
+spatial differencing (template 5.3), in groups of varying length and width.
+The values are smooth, deterministic functions of the position, so the file is
+the same on every run.
+
+    python3 tests/data/make_gfs_sample.py tests/data/gfs_sample.grb2
+"""
+import datetime
+import math
+import struct
+import sys
+
+REF = datetime.datetime(2020, 1, 1, 0)
+HOUR = 6
+NI, NJ, STEP = 72, 37, 5.0
+# Lengths the groups of the complex packing cycle through
+GROUP_LENGTHS = [20, 36, 16]
+
+# (name, category, number, template, level type, level value, mean, amplitude)
+FIELDS = [
+    ("PRMSL", 3, 1, 0, 101, 0, 101325.0, 2500.0),
+    ("HGT", 3, 5, 0, 100, 50000, 5600.0, 250.0),
+    ("TMP", 0, 0, 0, 100, 85000, 275.0, 25.0),
+    ("UGRD", 2, 2, 0, 100, 85000, 5.0, 15.0),
+    ("VGRD", 2, 3, 0, 100, 85000, 0.0, 12.0),
+    ("UGRD", 2, 2, 0, 100, 25000, 20.0, 30.0),
+    ("VGRD", 2, 3, 0, 100, 25000, 0.0, 20.0),
+    ("GUST", 2, 22, 0, 1, 0, 8.0, 7.0),
+    ("TMP", 0, 0, 0, 103, 2, 283.0, 30.0),
+    ("RH", 1, 1, 0, 103, 2, 70.0, 25.0),
+    ("UGRD", 2, 2, 0, 103, 10, 2.0, 9.0),
+    ("VGRD", 2, 3, 0, 103, 10, 0.0, 8.0),
+    ("APCP", 1, 8, 8, 1, 0, 3.0, 3.0),
+    ("UGRD", 2, 2, 0, 103, 100, 3.0, 12.0),
+    ("VGRD", 2, 3, 0, 103, 100, 0.0, 10.0),
+]
+
+
+def section(number, body):
+    return struct.pack(">IB", 5 + len(body), number) + body
+
+
+def values(index, mean, amplitude):
+    out = []
+    for j in range(NJ):
+        lat = math.radians(90.0 - j * STEP)
+        for i in range(NI):
+            lon = math.radians(i * STEP)
+            wave = math.sin(2 * lon + index) * math.cos(lat) + 0.5 * math.cos(3 * lat + index)
+            out.append(mean + amplitude * wave / 1.5)
+    return out
+
+
+class BitWriter:
+    def __init__(self):
+        self.data, self.acc, self.used = bytearray(), 0, 0
+
+    def write(self, value, bits):
+        for shift in range(bits - 1, -1, -1):
+            self.acc = (self.acc << 1) | ((value >> shift) & 1)
+            self.used += 1
+            if self.used == 8:
+                self.data.append(self.acc)
+                self.acc, self.used = 0, 0
+
+    def align(self):
+        if self.used:
+            self.write(0, 8 - self.used)
+
+
+def sign_magnitude(value, bits):
+    return (1 << (bits - 1)) | -value if value < 0 else value
+
+
+def complex_packing(scaled):
+    """Sections 5 and 7 bodies of the scaled integers, template 5.3."""
+    reference = min(scaled)
+    x = [v - reference for v in scaled]
+    differences = [x[k] - 2 * x[k - 1] + x[k - 2] for k in range(2, len(x))]
+    minimum = min(differences)
+    # The first two values are given in the extra descriptors
+    packed = [0, 0] + [d - minimum for d in differences]
+
+    groups, start = [], 0
+    while start < len(packed):
+        length = GROUP_LENGTHS[len(groups) % len(GROUP_LENGTHS)]
+        groups.append(packed[start:start + length])
+        start += length
+    references = [min(g) for g in groups]
+    widths = [(max(g) - r).bit_length() for g, r in zip(groups, references)]
+    lengths = [len(g) for g in groups]
+    reference_bits = max(references).bit_length()
+    width_reference = min(widths)
+    width_bits = (max(widths) - width_reference).bit_length()
+    length_reference = min(lengths[:-1])
+    length_bits = (max(lengths[:-1]) - length_reference).bit_length()
+    descriptor = max(abs(v) for v in (x[0], x[1], minimum))
+    extra_octets = (descriptor.bit_length() + 1 + 7) // 8
+
+    data = BitWriter()
+    for value in (x[0], x[1], minimum):
+        data.write(sign_magnitude(value, extra_octets * 8), extra_octets * 8)
+    for r in references:
+        data.write(r, reference_bits)
+    data.align()
+    for w in widths:
+        data.write(w - width_reference, width_bits)
+    data.align()
+    for length in lengths:
+        data.write(length - length_reference, length_bits)
+    data.align()
+    for g, r, w in zip(groups, references, widths):
+        for value in g:
+            data.write(value - r, w)
+    data.align()
+
+    representation = struct.pack(">IH", len(scaled), 3)
+    representation += struct.pack(">fhhBB", float(reference), 0, 1, reference_bits, 0)
+    # General group splitting, no missing values
+    representation += struct.pack(">BBII", 1, 0, 0, 0)
+    representation += struct.pack(">IBBIBIB", len(groups), width_reference, width_bits,
+                                  length_reference, 1, lengths[-1], length_bits)
+    representation += struct.pack(">BB", 2, extra_octets)
+    return representation, bytes(data.data)
+
+
+def message(index, field):
+    _, category, number, template, level_type, level, mean, amplitude = field
+    ident = struct.pack(
+        ">HHBBBHBBBBBBB", 7, 0, 2, 1, 1, REF.year, REF.month, REF.day, REF.hour, 0, 0, 0, 1
+    )
+    grid = struct.pack(">BIBBH", 0, NI * NJ, 0, 0, 0)
+    grid += struct.pack(
+        ">BBIBIBIIIIIiiBiiIIB",
+        6, 0, 0, 0, 0, 0, 0, NI, NJ, 0, 0xFFFFFFFF,
+        90000000, 0, 48, -90000000, int((NI - 1) * STEP * 1e6), int(STEP * 1e6), int(STEP * 1e6), 0,
+    )
+    # Accumulations cover the period up to the forecast hour
+    start = 0 if template == 8 else HOUR
+    product = struct.pack(">HH", 0, template)
+    product += struct.pack(">BBBBBHBBI", category, number, 2, 0, 96, 0, 0, 1, start)
+    product += struct.pack(">BBIBBI", level_type, 0, level, 255, 0, 0)
+    if template == 8:
+        end = REF + datetime.timedelta(hours=HOUR)
+        product += struct.pack(">HBBBBBBI", end.year, end.month, end.day, end.hour, 0, 0, 1, 0)
+        product += struct.pack(">BBBIBI", 1, 2, 1, HOUR, 255, 0)
+    scaled = [round(v * 10) for v in values(index, mean, amplitude)]
+    representation, packed = complex_packing(scaled)
+    body = (
+        section(1, ident)
+        + section(3, grid)
+        + section(4, product)
+        + section(5, representation)
+        + section(6, b"\xff")
+        + section(7, packed)
+    )
+    total = 16 + len(body) + 4
+    return b"GRIB" + bytes([0, 0, 0, 2]) + struct.pack(">Q", total) + body + b"7777"
+
+
+if __name__ == "__main__":
+    with open(sys.argv[1], "wb") as out:
+        for index, field in enumerate(FIELDS):
+            out.write(message(index, field))
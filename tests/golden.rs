@@ -0,0 +1,164 @@
+//! Golden-file regression tests: the objects produced from a bundled GFS-like
+//! sample (`data/gfs_sample.grb2`, written by `data/make_gfs_sample.py`) must
+//! stay the same byte for byte, whatever the chunking of the stream.
+//!
+//! A change of output that is intended (a new packing, a manifest field) shows
+//! up here: check the new values, then update the hashes below and, with
+//! `GOLDEN_UPDATE=1 cargo test --test golden`, the manifests under `data/`.
+#![cfg(feature = "pipeline")]
+
+use std::path::Path;
+use std::sync::Arc;
+
+use gfs_wind_downloader::catalog::message_hash;
+use gfs_wind_downloader::field::decode;
+use gfs_wind_downloader::filter::{Filter, KeptMessage};
+use gfs_wind_downloader::grib::Grib2StreamParser;
+use gfs_wind_downloader::manifest::Manifest;
+use gfs_wind_downloader::quantize::Quantize;
+use gfs_wind_downloader::route::{Route, DEFAULT_ROUTE};
+use gfs_wind_downloader::subset::BoundingBox;
+
+const SAMPLE: &[u8] = include_bytes!("data/gfs_sample.grb2");
+const SOURCE: &str = "https://example.com/gfs.t00z.pgrb2.5p00.f006";
+
+fn routes(specs: &[&str]) -> Arc<[Route]> {
+    specs.iter().map(|spec| spec.parse().unwrap()).collect()
+}
+
+/// Run the sample through `filter` in chunks of `chunk_size` bytes; returns the
+/// number of messages read and those kept.
+fn filter_sample(filter: &Filter, chunk_size: usize) -> (u64, Vec<KeptMessage>) {
+    let mut parser = Grib2StreamParser::new();
+    let (mut messages, mut kept) = (0, Vec::new());
+    for chunk in SAMPLE.chunks(chunk_size) {
+        let filtered = filter.clone().filter_chunk(parser, chunk);
+        assert!(filtered.error.is_none());
+        assert!(filtered.undecodable.is_empty());
+        parser = filtered.parser;
+        messages += filtered.messages;
+        kept.extend(filtered.kept);
+    }
+    assert_eq!(parser.pending(), 0);
+    (messages, kept)
+}
+
+/// Object and manifest a route writes from the kept messages.
+fn route_object(kept: &[KeptMessage], route: usize, key: &str) -> (Vec<u8>, Manifest) {
+    let mut manifest = Manifest::new(SOURCE, key);
+    let mut object = Vec::new();
+    for msg in kept.iter().filter(|msg| msg.routes.contains(&route)) {
+        for product in &msg.products {
+            manifest.push(
+                object.len() as u64,
+                msg.bytes.len() as u64,
+                product,
+                msg.sha256.as_deref(),
+            );
+        }
+        object.extend_from_slice(&msg.bytes);
+    }
+    (object, manifest)
+}
+
+/// Compare a manifest with its golden JSON under `data/`, rewritten instead
+/// with `GOLDEN_UPDATE=1`.
+fn assert_golden_manifest(manifest: &Manifest, name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name);
+    let json = serde_json::to_string_pretty(manifest).unwrap() + "\n";
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        std::fs::write(&path, &json).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(json, golden, "{} differs", path.display());
+}
+
+#[test]
+fn test_golden_default_route() {
+    let filter = Filter::new(routes(&[DEFAULT_ROUTE])).hashing(true);
+    let (messages, kept) = filter_sample(&filter, SAMPLE.len());
+    assert_eq!(messages, 15);
+    assert_eq!(kept.len(), 8);
+
+    // Messages are kept as they are, in the order of the file
+    let (object, manifest) = route_object(&kept, 0, "wind_20200101_00.grb2");
+    assert_eq!(object.len(), 11518);
+    assert_eq!(
+        message_hash(&object),
+        "c8b8a6e7696bf00617bf3532fc7fbc118f1b9763929621eba8a87afd4260a603"
+    );
+    assert_golden_manifest(&manifest, "gfs_sample.wind.manifest.json");
+
+    // Chunk boundaries anywhere, down to single bytes, change nothing
+    for chunk_size in [1, 7, 4096] {
+        let (_, chunked) = filter_sample(&filter, chunk_size);
+        assert_eq!(route_object(&chunked, 0, "wind_20200101_00.grb2").0, object);
+    }
+}
+
+#[test]
+fn test_golden_routes() {
+    let filter = Filter::new(routes(&[
+        "UGRD,VGRD=wind/",
+        "GUST,TMP=gust/",
+        "APCP=precip/",
+    ]));
+    let (_, kept) = filter_sample(&filter, 4096);
+    let hashes: Vec<_> = (0..3)
+        .map(|route| {
+            let (object, _) = route_object(&kept, route, "x");
+            (object.len(), message_hash(&object))
+        })
+        .collect();
+    assert_eq!(
+        hashes,
+        [
+            (
+                11518,
+                "c8b8a6e7696bf00617bf3532fc7fbc118f1b9763929621eba8a87afd4260a603".to_string()
+            ),
+            (
+                4718,
+                "ccfb7b6a70ec941a72206b1047c2eb18bfd69a0711785892e1efeb7b7ad65fb9".to_string()
+            ),
+            (
+                1088,
+                "aa1cb22d7ec9b91467358d98665da9dd9a36d0da304ee5bc6a6e949f0a206bb6".to_string()
+            ),
+        ]
+    );
+    let (_, precip) = route_object(&kept, 2, "precip/wind_20200101_00.grb2");
+    assert_golden_manifest(&precip, "gfs_sample.precip.manifest.json");
+}
+
+#[test]
+fn test_golden_transforms() {
+    let bbox: BoundingBox = "-10,35,30,60".parse().unwrap();
+    let quantize: Quantize = "1m/s".parse().unwrap();
+    let filter = Filter::new(routes(&[DEFAULT_ROUTE]))
+        .with_transform(Arc::new(bbox))
+        .with_transform(Arc::new(quantize));
+    let (_, kept) = filter_sample(&filter, 4096);
+    let (object, _) = route_object(&kept, 0, "x");
+    assert_eq!(object.len(), 1792);
+    assert_eq!(
+        message_hash(&object),
+        "e8a5064efbd8906f4ccffc148a82a1b83d10770f0f1693e7b920c0505dfd4fd2"
+    );
+
+    // The 10 m wind of the sample, cropped, then within half a step of it
+    let cropped = Filter::new(routes(&[DEFAULT_ROUTE])).with_transform(Arc::new(bbox));
+    let (_, exact) = filter_sample(&cropped, 4096);
+    let exact = decode(&exact[4].bytes).unwrap();
+    let rounded = decode(&kept[4].bytes).unwrap();
+    assert_eq!((exact.ni, exact.nj), (9, 6));
+    assert_eq!((rounded.ni, rounded.nj), (9, 6));
+    for (exact, rounded) in exact.values.iter().zip(&rounded.values) {
+        assert!(
+            (exact - rounded).abs() <= 0.5 + 1e-3,
+            "{exact} -> {rounded}"
+        );
+    }
+}